@@ -0,0 +1,107 @@
+//! Long-lived API tokens for CI and scripts.
+//!
+//! Tokens look like `dky_<43 url-safe base64 chars>` and are shown once at
+//! creation. Only their SHA-256 is stored: they carry 256 bits of entropy,
+//! so a slow password hash would add cost without adding security.
+
+use anyhow::Result;
+use base64::Engine;
+use ring::rand::SecureRandom;
+use rusqlite::OptionalExtension;
+
+use crate::db::models::TokenScope;
+use crate::AppState;
+
+pub const PREFIX: &str = "dky_";
+
+/// Characters kept in the clear (`dky_` plus a few) to recognise a token.
+const DISPLAY_LEN: usize = 12;
+
+pub struct NewToken {
+    pub token: String,
+    pub token_hash: String,
+    pub display_prefix: String,
+}
+
+pub fn generate() -> Result<NewToken> {
+    let token = random_token(PREFIX)?;
+    Ok(NewToken {
+        token_hash: hash(&token),
+        display_prefix: token[..DISPLAY_LEN].to_string(),
+        token,
+    })
+}
+
+/// `prefix` followed by 256 random bits, URL-safe base64 encoded.
+pub fn random_token(prefix: &str) -> Result<String> {
+    let mut bytes = [0u8; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate random token"))?;
+    Ok(format!(
+        "{}{}",
+        prefix,
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    ))
+}
+
+pub fn hash(token: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub struct AuthenticatedToken {
+    pub id: i64,
+    pub username: String,
+    pub scope: TokenScope,
+    pub repo_ids: Option<Vec<i64>>,
+    /// Unix timestamp.
+    pub expires_at: Option<i64>,
+}
+
+/// Look up an unexpired token and record its use. `None` when unknown or
+/// expired.
+pub fn authenticate(state: &AppState, token: &str) -> Result<Option<AuthenticatedToken>> {
+    state.db.with_conn(|conn| {
+        let found = conn
+            .query_row(
+                "SELECT t.id, u.username, t.scope, t.repo_ids, t.expires_at
+                 FROM api_tokens t JOIN users u ON u.id = t.user_id
+                 WHERE t.token_hash = ?1
+                   AND (t.expires_at IS NULL OR t.expires_at > datetime('now'))",
+                [hash(token)],
+                |row| {
+                    let scope: String = row.get(2)?;
+                    let repo_ids: Option<String> = row.get(3)?;
+                    let expires_at: Option<String> = row.get(4)?;
+                    Ok(AuthenticatedToken {
+                        id: row.get(0)?,
+                        username: row.get(1)?,
+                        // Unknown scopes from a hand-edited database get the least access.
+                        scope: TokenScope::parse(&scope).unwrap_or(TokenScope::Read),
+                        repo_ids: repo_ids.and_then(|ids| serde_json::from_str(&ids).ok()),
+                        expires_at: expires_at.and_then(|ts| {
+                            chrono::NaiveDateTime::parse_from_str(&ts, "%Y-%m-%d %H:%M:%S")
+                                .ok()
+                                .map(|t| t.and_utc().timestamp())
+                        }),
+                    })
+                },
+            )
+            .optional()?;
+
+        if let Some(ref found) = found {
+            // At most one write per minute for busy CI tokens.
+            conn.execute(
+                "UPDATE api_tokens SET last_used_at = datetime('now')
+                 WHERE id = ?1
+                   AND (last_used_at IS NULL OR last_used_at < datetime('now', '-60 seconds'))",
+                [found.id],
+            )?;
+        }
+        Ok(found)
+    })
+}
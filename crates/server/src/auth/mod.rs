@@ -1,30 +1,202 @@
 use axum::{
-    extract::Request,
-    http::StatusCode,
+    extract::{FromRequestParts, MatchedPath, RawPathParams, Request},
+    http::{Method, StatusCode},
     middleware::Next,
     response::Response,
 };
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::AppState;
 
+pub mod api_tokens;
+pub mod sessions;
+pub mod totp;
+
+/// Access level of a user, ordered from least to most privileged.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read-only access.
+    Viewer,
+    /// Can deploy, manage containers and open terminals.
+    Deployer,
+    /// Full access, including users, credentials and notification channels.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Deployer => "deployer",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "viewer" => Some(Role::Viewer),
+            "deployer" => Some(Role::Deployer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String,
+    pub role: Role,
     pub exp: usize,
+    /// Unique id of the access token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Login session the access token belongs to; `None` for API tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+/// What the caller may reach, resolved by [`auth_middleware`] on every
+/// request so grant changes apply without a new login.
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub role: Role,
+    /// Granted repositories when the user is scoped; `None` means all.
+    pub repos: Option<HashMap<i64, Role>>,
+    /// Set when authenticated with an API token rather than a login.
+    pub token_id: Option<i64>,
+}
+
+impl Access {
+    /// Effective role on a repository: the grant, capped by the global role.
+    pub fn repo_role(&self, repo_id: i64) -> Option<Role> {
+        match &self.repos {
+            None => Some(self.role),
+            Some(grants) => grants.get(&repo_id).map(|r| (*r).min(self.role)),
+        }
+    }
+
+    pub fn can_see(&self, repo_id: i64) -> bool {
+        self.repo_role(repo_id).is_some()
+    }
+
+    pub fn is_scoped(&self) -> bool {
+        self.repos.is_some()
+    }
+
+    /// Whether an engine object (container, volume, network) with `labels`
+    /// is visible: scoped users only see ones labelled for their repositories.
+    pub fn can_see_labels(&self, labels: &HashMap<String, String>) -> bool {
+        !self.is_scoped()
+            || labels
+                .get("dockyy.repo_id")
+                .and_then(|id| id.parse().ok())
+                .is_some_and(|id| self.can_see(id))
+    }
+
+    /// Whether the caller may change an engine object with `labels`. Ones no
+    /// repository owns, such as the proxy or containers started outside
+    /// dockyy, are shared infrastructure, so only admins may.
+    pub fn can_change_labels(&self, labels: &HashMap<String, String>) -> bool {
+        self.role == Role::Admin
+            || labels
+                .get("dockyy.repo_id")
+                .and_then(|id| id.parse().ok())
+                .and_then(|id| self.repo_role(id))
+                .is_some_and(|role| role >= Role::Deployer)
+    }
+}
+
+/// Load a user's current role and grants. `cap` (the role in the JWT or the
+/// API token's scope) limits the stored role, and the stored role limits the
+/// cap, so demotions apply immediately. `None` when the account no longer
+/// exists.
+fn load_access(state: &AppState, username: &str, cap: Role) -> anyhow::Result<Option<Access>> {
+    state.db.with_conn(|conn| {
+        let user: Option<(i64, String, bool)> = conn
+            .query_row(
+                "SELECT id, role, scoped FROM users WHERE username = ?1",
+                [username],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let Some((user_id, role, scoped)) = user else {
+            return Ok(None);
+        };
+        let role = Role::parse(&role).unwrap_or(Role::Viewer).min(cap);
+
+        // Admins always have global access.
+        let repos = if scoped && role < Role::Admin {
+            let mut stmt =
+                conn.prepare("SELECT repo_id, role FROM repository_grants WHERE user_id = ?1")?;
+            let grants = stmt
+                .query_map([user_id], |row| {
+                    let role: String = row.get(1)?;
+                    Ok((row.get(0)?, Role::parse(&role).unwrap_or(Role::Viewer)))
+                })?
+                .collect::<Result<HashMap<_, _>, _>>()?;
+            Some(grants)
+        } else {
+            None
+        };
+        Ok(Some(Access {
+            role,
+            repos,
+            token_id: None,
+        }))
+    })
 }
 
-pub fn create_token(secret: &str, username: &str) -> anyhow::Result<String> {
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    use argon2::password_hash::SaltString;
+    use argon2::{Argon2, PasswordHasher};
+    let salt = SaltString::generate(argon2::password_hash::rand_core::OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?
+        .to_string())
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    use argon2::{Argon2, PasswordHash, PasswordVerifier};
+    // Single sign-on accounts have no password.
+    if hash.is_empty() {
+        return false;
+    }
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(e) => {
+            tracing::error!(error = %e, "password hash parse failed");
+            false
+        }
+    }
+}
+
+/// Issue a short-lived access token for a session.
+pub fn create_token(
+    secret: &str,
+    username: &str,
+    role: Role,
+    session_id: &str,
+    ttl: chrono::Duration,
+) -> anyhow::Result<String> {
     let exp = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(ttl)
         .unwrap()
         .timestamp() as usize;
 
     let claims = Claims {
         sub: username.to_string(),
+        role,
         exp,
+        jti: Some(uuid::Uuid::new_v4().to_string()),
+        sid: Some(session_id.to_string()),
     };
 
     let token = encode(
@@ -53,16 +225,318 @@ pub async fn auth_middleware(
         .headers()
         .get("Authorization")
         .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("Bearer "));
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string);
 
-    let token = match auth_header {
+    // Browsers can't set headers on WebSocket handshakes, so upgrades may pass
+    // a session token as `?token=` instead.
+    let token = match auth_header.or_else(|| websocket_query_token(&req)) {
         Some(t) => t,
         None => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    let claims = verify_token(&state.config.jwt_secret, token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let (claims, access) = if token.starts_with(api_tokens::PREFIX) {
+        api_token_access(&state, &token)?
+    } else {
+        let mut claims = verify_token(&state.config.jwt_secret, &token)
+            .map_err(|_| StatusCode::UNAUTHORIZED)?;
+        // Tokens from before sessions existed carry no `sid` and can't be revoked.
+        let sid = claims.sid.as_deref().ok_or(StatusCode::UNAUTHORIZED)?;
+        match sessions::is_active(&state, sid) {
+            Ok(true) => {}
+            Ok(false) => return Err(StatusCode::UNAUTHORIZED),
+            Err(e) => {
+                tracing::error!("Failed to check session for {}: {}", claims.sub, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+        let access = match load_access(&state, &claims.sub, claims.role) {
+            Ok(Some(access)) => access,
+            Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+            Err(e) => {
+                tracing::error!("Failed to load access for {}: {}", claims.sub, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+        claims.role = access.role;
+        (claims, access)
+    };
 
     req.extensions_mut().insert(claims);
+    req.extensions_mut().insert(access);
+    Ok(next.run(req).await)
+}
+
+/// Resolve a `dky_` API token to its owner, limited by the token's scope and
+/// repository list.
+fn api_token_access(state: &AppState, token: &str) -> Result<(Claims, Access), StatusCode> {
+    let internal = |e: anyhow::Error| {
+        tracing::error!("Failed to authenticate API token: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let api_token = api_tokens::authenticate(state, token)
+        .map_err(internal)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let mut access = load_access(state, &api_token.username, api_token.scope.role())
+        .map_err(internal)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if let Some(repo_ids) = api_token.repo_ids {
+        let repos = repo_ids
+            .into_iter()
+            .filter_map(|id| access.repo_role(id).map(|role| (id, role)))
+            .collect();
+        access.repos = Some(repos);
+    }
+    access.token_id = Some(api_token.id);
+
+    let claims = Claims {
+        sub: api_token.username,
+        role: access.role,
+        exp: api_token.expires_at.map_or(usize::MAX, |ts| ts as usize),
+        jti: None,
+        sid: None,
+    };
+    Ok((claims, access))
+}
+
+/// Route group layer: viewers may read, every mutation needs at least the
+/// deployer role. Runs after [`auth_middleware`].
+pub async fn writes_require_deployer(req: Request, next: Next) -> Result<Response, StatusCode> {
+    let read_only = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    match req.extensions().get::<Claims>() {
+        Some(claims) if read_only || claims.role >= Role::Deployer => Ok(next.run(req).await),
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Repository a request targets, from its path: repository routes directly,
+/// deployments via their row and containers via the `dockyy.repo_id` label.
+/// `Ok(None)` means the route isn't tied to a repository.
+async fn target_repo(state: &AppState, route: &str, params: &RawPathParams) -> Result<Option<i64>, ()> {
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let route = route.strip_prefix("/api").unwrap_or(route);
+
+    if route.starts_with("/repositories/{id}") || route.starts_with("/deployments/repo/{repo_id}") {
+        let id = param("id").or_else(|| param("repo_id")).ok_or(())?;
+        return id.parse().map(Some).map_err(|_| ());
+    }
+    if route.starts_with("/deployments/{id}") {
+        let id: i64 = param("id").ok_or(())?.parse().map_err(|_| ())?;
+        let repo_id = state
+            .db
+            .with_conn(|conn| {
+                Ok(conn.query_row("SELECT repo_id FROM deployments WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })?)
+            })
+            .map_err(|_| ())?;
+        return Ok(Some(repo_id));
+    }
+    if route.starts_with("/containers/{id}") {
+        let id = param("id").ok_or(())?;
+        let info = state.docker.inspect_container(&id).await.map_err(|_| ())?;
+        return info
+            .config
+            .and_then(|c| c.labels)
+            .and_then(|labels| labels.get("dockyy.repo_id").and_then(|v| v.parse().ok()))
+            .map(Some)
+            .ok_or(());
+    }
+    Ok(None)
+}
+
+/// Route group layer enforcing repository grants for scoped users. Targets
+/// they can't see answer 404 so their existence isn't revealed; routes not
+/// tied to a repository are read-only for them. Listing endpoints filter
+/// through [`Access`] themselves. Runs after [`auth_middleware`].
+pub async fn repo_scope_middleware(
+    axum::extract::State(state): axum::extract::State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let Some(access) = req.extensions().get::<Access>().cloned() else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    if !access.is_scoped() {
+        return Ok(next.run(req).await);
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let route = parts
+        .extensions
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    let params = RawPathParams::from_request_parts(&mut parts, &state)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let read_only = matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS);
+
+    match target_repo(&state, &route, &params).await {
+        Ok(Some(repo_id)) => {
+            let group = parts.extensions.get::<RequiredRole>().map_or(Role::Viewer, |r| r.0);
+            let needed = if read_only { group } else { group.max(Role::Deployer) };
+            match access.repo_role(repo_id) {
+                None => return Err(StatusCode::NOT_FOUND),
+                Some(role) if role < needed => return Err(StatusCode::FORBIDDEN),
+                Some(_) => {}
+            }
+        }
+        Ok(None) if !read_only => return Err(StatusCode::FORBIDDEN),
+        Ok(None) => {}
+        Err(()) => return Err(StatusCode::NOT_FOUND),
+    }
+
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+/// Minimum role set by a route group layer, also applied per repository by
+/// [`repo_scope_middleware`].
+#[derive(Debug, Clone, Copy)]
+struct RequiredRole(Role);
+
+fn require(role: Role, req: &mut Request) -> Result<(), StatusCode> {
+    match req.extensions().get::<Claims>() {
+        Some(claims) if claims.role >= role => {
+            req.extensions_mut().insert(RequiredRole(role));
+            Ok(())
+        }
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Route group layer for endpoints that need the deployer role even to read,
+/// such as terminals. Runs after [`auth_middleware`] and before
+/// [`repo_scope_middleware`].
+pub async fn require_deployer(mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    require(Role::Deployer, &mut req)?;
+    Ok(next.run(req).await)
+}
+
+/// Route group layer for admin-only endpoints. These span every repository,
+/// so callers limited to some (admin API tokens with `repo_ids`) are refused.
+/// Runs after [`auth_middleware`].
+pub async fn require_admin(mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    require(Role::Admin, &mut req)?;
+    if req.extensions().get::<Access>().is_some_and(Access::is_scoped) {
+        return Err(StatusCode::FORBIDDEN);
+    }
     Ok(next.run(req).await)
 }
+
+/// `?token=` of a WebSocket upgrade. URLs end up in proxy and access logs, so
+/// only short-lived session tokens are accepted there, never `dky_` API tokens.
+fn websocket_query_token(req: &Request) -> Option<String> {
+    let is_upgrade = req
+        .headers()
+        .get("Upgrade")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if !is_upgrade {
+        return None;
+    }
+
+    req.uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .filter(|token| !token.starts_with(api_tokens::PREFIX))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoped_users_only_see_their_repositories_objects() {
+        let labels = |id: &str| -> HashMap<String, String> { [("dockyy.repo_id".to_string(), id.to_string())].into() };
+        let scoped = Access {
+            role: Role::Deployer,
+            repos: Some([(1, Role::Viewer)].into()),
+            token_id: None,
+        };
+        assert!(scoped.can_see_labels(&labels("1")));
+        assert!(!scoped.can_see_labels(&labels("2")));
+        assert!(!scoped.can_see_labels(&HashMap::new()));
+        assert_eq!(scoped.repo_role(1), Some(Role::Viewer));
+
+        let admin = Access {
+            role: Role::Admin,
+            repos: None,
+            token_id: None,
+        };
+        assert!(admin.can_see_labels(&labels("2")));
+        assert!(admin.can_see_labels(&HashMap::new()));
+    }
+
+    #[tokio::test]
+    async fn scoped_users_only_reach_their_repositories() {
+        use crate::test_support::{self, call, MockDocker};
+
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let shop = test_support::add_repo(&state, "shop");
+        let billing = test_support::add_repo(&state, "billing");
+        docker.add_container("shop", &[("dockyy.repo_id", &shop.to_string())]);
+        docker.add_container("billing", &[("dockyy.repo_id", &billing.to_string())]);
+        docker.add_container("postgres", &[]);
+        let deployments: Vec<i64> = [shop, billing]
+            .iter()
+            .map(|repo_id| {
+                state
+                    .db
+                    .with_conn(|conn| {
+                        conn.execute("INSERT INTO deployments (repo_id, status) VALUES (?1, 'running')", [repo_id])?;
+                        Ok(conn.last_insert_rowid())
+                    })
+                    .unwrap()
+            })
+            .collect();
+        test_support::add_user(&state, "contractor", Role::Deployer, Some(&[(shop, Role::Deployer)]));
+        test_support::add_user(&state, "auditor", Role::Deployer, Some(&[(shop, Role::Viewer)]));
+        let contractor = test_support::login(&state, "contractor");
+        let auditor = test_support::login(&state, "auditor");
+
+        let cases = [
+            (Method::GET, format!("/api/repositories/{}", shop), StatusCode::OK),
+            (Method::GET, format!("/api/repositories/{}", billing), StatusCode::NOT_FOUND),
+            (Method::GET, format!("/api/deployments/{}", deployments[0]), StatusCode::OK),
+            (Method::GET, format!("/api/deployments/{}", deployments[1]), StatusCode::NOT_FOUND),
+            (Method::GET, "/api/deployments/999".to_string(), StatusCode::NOT_FOUND),
+            (Method::POST, "/api/containers/shop/restart".to_string(), StatusCode::OK),
+            (Method::POST, "/api/containers/billing/restart".to_string(), StatusCode::NOT_FOUND),
+            // Unlabeled containers don't belong to any repository they can see.
+            (Method::POST, "/api/containers/postgres/restart".to_string(), StatusCode::NOT_FOUND),
+            // Writes not tied to a repository are refused, reads pass through.
+            (Method::POST, "/api/images/pull".to_string(), StatusCode::FORBIDDEN),
+            (Method::GET, "/api/repositories".to_string(), StatusCode::OK),
+        ];
+        for (method, uri, expected) in cases {
+            let body = (method == Method::POST).then(|| serde_json::json!({"image": "nginx"}));
+            let (status, _) = call(&app, method, &uri, Some(&contractor), body).await;
+            assert_eq!(status, expected, "{}", uri);
+        }
+
+        let (_, repos) = call(&app, Method::GET, "/api/repositories", Some(&contractor), None).await;
+        let ids: Vec<i64> = repos.as_array().unwrap().iter().map(|r| r["id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, [shop]);
+
+        // A viewer grant caps the user's global role on that repository.
+        let (status, _) = call(&app, Method::GET, &format!("/api/repositories/{}", shop), Some(&auditor), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::POST, "/api/containers/shop/restart", Some(&auditor), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
@@ -0,0 +1,184 @@
+//! Dashboard login sessions.
+//!
+//! A login yields a short-lived access JWT carrying the session id (`sid`)
+//! and a `dkr_` refresh token. Each refresh rotates the refresh token and
+//! slides the session's expiry. Presenting a token that was already rotated
+//! revokes the whole session, since only a copy could still hold it.
+//! Revoking a session cuts off its access tokens on their next request.
+
+use anyhow::Result;
+use rusqlite::OptionalExtension;
+
+use super::{api_tokens, Role};
+use crate::AppState;
+
+pub const PREFIX: &str = "dkr_";
+
+/// Revoked and expired sessions are purged after this many days.
+const RETENTION_DAYS: i64 = 7;
+
+/// Sessions are touched at most this often, to spare a write per request.
+const TOUCH_INTERVAL_SECS: i64 = 60;
+
+pub struct NewSession {
+    pub id: String,
+    pub refresh_token: String,
+}
+
+/// Start a session for a user who just logged in.
+pub fn create(
+    state: &AppState,
+    user_id: i64,
+    ip: &str,
+    user_agent: Option<&str>,
+) -> Result<NewSession> {
+    let session = NewSession {
+        id: uuid::Uuid::new_v4().to_string(),
+        refresh_token: api_tokens::random_token(PREFIX)?,
+    };
+    state.db.with_conn(|conn| {
+        conn.execute(
+            "DELETE FROM sessions
+             WHERE expires_at < datetime('now', '-' || ?1 || ' days')
+                OR revoked_at < datetime('now', '-' || ?1 || ' days')",
+            [RETENTION_DAYS],
+        )?;
+        conn.execute(
+            "INSERT INTO sessions (id, user_id, refresh_hash, ip_address, user_agent, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, datetime('now', '+' || ?6 || ' days'))",
+            rusqlite::params![
+                session.id,
+                user_id,
+                api_tokens::hash(&session.refresh_token),
+                ip,
+                user_agent,
+                state.config.refresh_token_ttl_days,
+            ],
+        )?;
+        Ok(())
+    })?;
+    Ok(session)
+}
+
+pub struct Refreshed {
+    pub session_id: String,
+    pub username: String,
+    pub role: Role,
+    pub refresh_token: String,
+}
+
+/// Exchange a refresh token for its replacement. `None` when the token is
+/// unknown, expired or revoked; a replayed old token also revokes its
+/// session.
+pub fn refresh(state: &AppState, token: &str) -> Result<Option<Refreshed>> {
+    let token_hash = api_tokens::hash(token);
+    let next = api_tokens::random_token(PREFIX)?;
+
+    state.db.with_conn(|conn| {
+        let found = conn
+            .query_row(
+                "SELECT s.id, u.username, u.role
+                 FROM sessions s JOIN users u ON u.id = s.user_id
+                 WHERE s.refresh_hash = ?1
+                   AND s.revoked_at IS NULL
+                   AND s.expires_at > datetime('now')",
+                [&token_hash],
+                |row| {
+                    let role: String = row.get(2)?;
+                    Ok(Refreshed {
+                        session_id: row.get(0)?,
+                        username: row.get(1)?,
+                        role: Role::parse(&role).unwrap_or(Role::Viewer),
+                        refresh_token: next.clone(),
+                    })
+                },
+            )
+            .optional()?;
+
+        let Some(refreshed) = found else {
+            let revoked = conn.execute(
+                "UPDATE sessions SET revoked_at = datetime('now')
+                 WHERE previous_hash = ?1 AND revoked_at IS NULL",
+                [&token_hash],
+            )?;
+            if revoked > 0 {
+                tracing::warn!("Rotated refresh token was reused; session revoked");
+            }
+            return Ok(None);
+        };
+
+        conn.execute(
+            "UPDATE sessions
+             SET previous_hash = refresh_hash, refresh_hash = ?2,
+                 last_used_at = datetime('now'),
+                 expires_at = datetime('now', '+' || ?3 || ' days')
+             WHERE id = ?1",
+            rusqlite::params![
+                refreshed.session_id,
+                api_tokens::hash(&next),
+                state.config.refresh_token_ttl_days,
+            ],
+        )?;
+        Ok(Some(refreshed))
+    })
+}
+
+/// Whether a session is neither revoked nor expired, recording its use.
+pub fn is_active(state: &AppState, session_id: &str) -> Result<bool> {
+    state.db.with_conn(|conn| {
+        let active: bool = conn.query_row(
+            "SELECT EXISTS(
+                 SELECT 1 FROM sessions
+                 WHERE id = ?1 AND revoked_at IS NULL AND expires_at > datetime('now'))",
+            [session_id],
+            |row| row.get(0),
+        )?;
+        if active {
+            conn.execute(
+                "UPDATE sessions SET last_used_at = datetime('now')
+                 WHERE id = ?1 AND last_used_at < datetime('now', '-' || ?2 || ' seconds')",
+                rusqlite::params![session_id, TOUCH_INTERVAL_SECS],
+            )?;
+        }
+        Ok(active)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, MockDocker};
+
+    #[tokio::test]
+    async fn rotates_and_revokes_on_replay() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        state.db.seed_admin_user("admin", "longpassword").unwrap();
+        let user_id: i64 = state
+            .db
+            .with_conn(|conn| Ok(conn.query_row("SELECT id FROM users", [], |row| row.get(0))?))
+            .unwrap();
+
+        let session = create(&state, user_id, "127.0.0.1", Some("test")).unwrap();
+        assert!(session.refresh_token.starts_with(PREFIX));
+        assert!(is_active(&state, &session.id).unwrap());
+
+        let first = refresh(&state, &session.refresh_token).unwrap().expect("rotated");
+        assert_eq!(first.session_id, session.id);
+        assert_eq!(first.username, "admin");
+        assert_eq!(first.role, Role::Admin);
+        assert_ne!(first.refresh_token, session.refresh_token);
+
+        let second = refresh(&state, &first.refresh_token).unwrap().expect("rotated again");
+        assert!(is_active(&state, &session.id).unwrap());
+
+        // Replaying the token `second` replaced revokes the session, so even
+        // the newest token stops working.
+        assert!(refresh(&state, &first.refresh_token).unwrap().is_none());
+        assert!(!is_active(&state, &session.id).unwrap());
+        assert!(refresh(&state, &second.refresh_token).unwrap().is_none());
+        assert!(refresh(&state, "dkr_unknown").unwrap().is_none());
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
@@ -35,7 +35,6 @@ impl Database {
                 description TEXT,
                 webhook_url TEXT,
                 filesystem_path TEXT,
-                ssh_password TEXT,
                 is_private  INTEGER NOT NULL DEFAULT 0,
                 default_branch TEXT NOT NULL DEFAULT 'main',
                 created_at  TEXT NOT NULL DEFAULT (datetime('now')),
@@ -79,6 +78,235 @@ impl Database {
             );
 
             CREATE INDEX IF NOT EXISTS idx_login_attempts_ip ON login_attempts(ip_address, attempted_at);
+
+            CREATE TABLE IF NOT EXISTS previews (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                repo_id     INTEGER NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+                pr_number   INTEGER NOT NULL,
+                head_ref    TEXT,
+                head_sha    TEXT,
+                container_name TEXT NOT NULL,
+                container_id TEXT,
+                image_name  TEXT,
+                domain      TEXT,
+                status      TEXT NOT NULL DEFAULT 'pending',
+                build_log   TEXT,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE (repo_id, pr_number)
+            );
+
+            CREATE TABLE IF NOT EXISTS environments (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                repo_id     INTEGER NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+                name        TEXT NOT NULL,
+                branch      TEXT,
+                domain      TEXT,
+                proxy_port  INTEGER,
+                env_vars    TEXT,
+                memory_limit INTEGER,
+                cpu_limit   REAL,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                UNIQUE (repo_id, name)
+            );
+
+            CREATE TABLE IF NOT EXISTS exec_sessions (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                container_id TEXT NOT NULL,
+                container_name TEXT,
+                username    TEXT NOT NULL,
+                shell       TEXT NOT NULL,
+                remote_addr TEXT,
+                exit_code   INTEGER,
+                bytes_in    INTEGER NOT NULL DEFAULT 0,
+                bytes_out   INTEGER NOT NULL DEFAULT 0,
+                error       TEXT,
+                started_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                ended_at    TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_exec_sessions_container ON exec_sessions(container_id);
+
+            -- resolution is seconds per row: 30 for raw samples, 300 for rollups.
+            CREATE TABLE IF NOT EXISTS container_stats (
+                ts          INTEGER NOT NULL,
+                resolution  INTEGER NOT NULL,
+                container_name TEXT NOT NULL,
+                repo_id     INTEGER,
+                environment TEXT,
+                cpu_pct     REAL NOT NULL DEFAULT 0,
+                mem_bytes   INTEGER NOT NULL DEFAULT 0,
+                mem_limit   INTEGER NOT NULL DEFAULT 0,
+                net_rx_bps  REAL NOT NULL DEFAULT 0,
+                net_tx_bps  REAL NOT NULL DEFAULT 0,
+                blk_read_bps REAL NOT NULL DEFAULT 0,
+                blk_write_bps REAL NOT NULL DEFAULT 0,
+                PRIMARY KEY (resolution, container_name, ts)
+            ) WITHOUT ROWID;
+
+            CREATE INDEX IF NOT EXISTS idx_container_stats_repo ON container_stats(repo_id, resolution, ts);
+
+            -- resolution is seconds per row: 30 raw, 300 and 3600 rollups.
+            CREATE TABLE IF NOT EXISTS host_metrics (
+                ts          INTEGER NOT NULL,
+                resolution  INTEGER NOT NULL,
+                cpu_pct     REAL NOT NULL DEFAULT 0,
+                mem_used_bytes  INTEGER NOT NULL DEFAULT 0,
+                mem_total_bytes INTEGER NOT NULL DEFAULT 0,
+                swap_used_bytes  INTEGER NOT NULL DEFAULT 0,
+                swap_total_bytes INTEGER NOT NULL DEFAULT 0,
+                disk_used_bytes  INTEGER NOT NULL DEFAULT 0,
+                disk_total_bytes INTEGER NOT NULL DEFAULT 0,
+                load_avg_1  REAL NOT NULL DEFAULT 0,
+                load_avg_5  REAL NOT NULL DEFAULT 0,
+                load_avg_15 REAL NOT NULL DEFAULT 0,
+                net_rx_bps  REAL NOT NULL DEFAULT 0,
+                net_tx_bps  REAL NOT NULL DEFAULT 0,
+                PRIMARY KEY (resolution, ts)
+            ) WITHOUT ROWID;
+
+            CREATE TABLE IF NOT EXISTS notification_channels (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                name        TEXT NOT NULL UNIQUE,
+                kind        TEXT NOT NULL,
+                config      TEXT NOT NULL DEFAULT '{}',
+                enabled     INTEGER NOT NULL DEFAULT 1,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS alert_rules (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                name        TEXT NOT NULL,
+                metric      TEXT NOT NULL,
+                operator    TEXT NOT NULL DEFAULT 'gt',
+                threshold   REAL NOT NULL DEFAULT 0,
+                duration_secs INTEGER NOT NULL DEFAULT 0,
+                container   TEXT,
+                severity    TEXT NOT NULL DEFAULT 'warning',
+                enabled     INTEGER NOT NULL DEFAULT 1,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            -- rule_name/metric are copied so history survives rule deletion.
+            CREATE TABLE IF NOT EXISTS alerts (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                rule_id     INTEGER REFERENCES alert_rules(id) ON DELETE SET NULL,
+                rule_name   TEXT NOT NULL,
+                metric      TEXT NOT NULL,
+                subject     TEXT NOT NULL,
+                severity    TEXT NOT NULL,
+                state       TEXT NOT NULL DEFAULT 'firing',
+                value       REAL NOT NULL DEFAULT 0,
+                message     TEXT NOT NULL DEFAULT '',
+                fired_at    TEXT NOT NULL DEFAULT (datetime('now')),
+                resolved_at TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS notification_deliveries (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id  INTEGER NOT NULL REFERENCES notification_channels(id) ON DELETE CASCADE,
+                event       TEXT NOT NULL,
+                payload     TEXT NOT NULL,
+                status      TEXT NOT NULL DEFAULT 'pending',
+                attempts    INTEGER NOT NULL DEFAULT 0,
+                last_error  TEXT,
+                next_attempt_at TEXT,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                delivered_at TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_notification_deliveries_pending
+                ON notification_deliveries(status, next_attempt_at);
+
+            CREATE TABLE IF NOT EXISTS users (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                username    TEXT NOT NULL UNIQUE,
+                password_hash TEXT NOT NULL,
+                role        TEXT NOT NULL DEFAULT 'viewer',
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            -- Repository access for users with `scoped` set.
+            CREATE TABLE IF NOT EXISTS repository_grants (
+                user_id     INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                repo_id     INTEGER NOT NULL REFERENCES repositories(id) ON DELETE CASCADE,
+                role        TEXT NOT NULL DEFAULT 'viewer',
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                PRIMARY KEY (user_id, repo_id)
+            );
+
+            -- Only the SHA-256 of each token is stored.
+            CREATE TABLE IF NOT EXISTS api_tokens (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id     INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                name        TEXT NOT NULL,
+                token_hash  TEXT NOT NULL UNIQUE,
+                prefix      TEXT NOT NULL,
+                scope       TEXT NOT NULL DEFAULT 'read',
+                repo_ids    TEXT,
+                expires_at  TEXT,
+                last_used_at TEXT,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            -- Dashboard logins. The refresh token rotates on every use; the
+            -- previous hash is kept to detect a replayed (stolen) token.
+            CREATE TABLE IF NOT EXISTS sessions (
+                id          TEXT PRIMARY KEY,
+                user_id     INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                refresh_hash TEXT NOT NULL UNIQUE,
+                previous_hash TEXT,
+                ip_address  TEXT,
+                user_agent  TEXT,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now')),
+                last_used_at TEXT NOT NULL DEFAULT (datetime('now')),
+                expires_at  TEXT NOT NULL,
+                revoked_at  TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_sessions_previous ON sessions(previous_hash);
+
+            -- Single sign-on logins in progress; rows live for a few minutes.
+            CREATE TABLE IF NOT EXISTS oidc_states (
+                state       TEXT PRIMARY KEY,
+                nonce       TEXT NOT NULL,
+                code_verifier TEXT NOT NULL,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            -- One-time TOTP recovery codes, stored as SHA-256.
+            CREATE TABLE IF NOT EXISTS recovery_codes (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id     INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                code_hash   TEXT NOT NULL,
+                used_at     TEXT,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            -- Who changed what. Users and tokens are stored by name/id
+            -- without foreign keys so entries outlive them.
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                username    TEXT,
+                token_id    INTEGER,
+                ip_address  TEXT,
+                action      TEXT NOT NULL,
+                target      TEXT,
+                summary     TEXT,
+                outcome     TEXT NOT NULL,
+                status      INTEGER,
+                created_at  TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log(created_at);
+
+            -- At most one firing alert per rule and subject.
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_alerts_firing
+                ON alerts(rule_id, subject) WHERE state = 'firing';
             "
         )?;
 
@@ -89,6 +317,58 @@ impl Database {
             "ALTER TABLE repositories ADD COLUMN proxy_port INTEGER DEFAULT 3000",
             [],
         );
+        let _ = conn.execute(
+            "ALTER TABLE repositories ADD COLUMN previews_enabled INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN preview_domain TEXT", []);
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN preview_env TEXT", []);
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN resource_limits TEXT", []);
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN mounts TEXT", []);
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN networks TEXT", []);
+        // Unlabeled volumes and networks an admin attached.
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN adopted TEXT", []);
+        // Sealed by `services::crypto`; replaces the plaintext `ssh_password`,
+        // which `crypto::reencrypt_ssh_keys` migrates and drops.
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN ssh_key_encrypted TEXT", []);
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN ssh_key_fingerprint TEXT", []);
+        // Verifies `X-Hub-Signature-256`; sealed like the SSH key.
+        let _ = conn.execute("ALTER TABLE repositories ADD COLUMN webhook_secret_encrypted TEXT", []);
+        let _ = conn.execute(
+            "ALTER TABLE repositories ADD COLUMN preview_forks INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        let _ = conn.execute("ALTER TABLE notification_channels ADD COLUMN events TEXT", []);
+        let _ = conn.execute(
+            "ALTER TABLE users ADD COLUMN scoped INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        // Set by `/auth/totp/setup`; only enforced once `totp_enabled`.
+        let _ = conn.execute("ALTER TABLE users ADD COLUMN totp_secret TEXT", []);
+        let _ = conn.execute(
+            "ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0",
+            [],
+        );
+        let _ = conn.execute("ALTER TABLE users ADD COLUMN totp_last_step INTEGER", []);
+        // Repository of the container, so scoped users only see their own sessions.
+        let _ = conn.execute("ALTER TABLE exec_sessions ADD COLUMN repo_id INTEGER", []);
+        // Subject of the single sign-on identity; such users have no password.
+        let _ = conn.execute("ALTER TABLE users ADD COLUMN oidc_subject TEXT", []);
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_users_oidc_subject ON users(oidc_subject)",
+            [],
+        )?;
+        let _ = conn.execute(
+            "ALTER TABLE deployments ADD COLUMN environment_id INTEGER REFERENCES environments(id) ON DELETE SET NULL",
+            [],
+        );
+
+        // Every repository gets a default environment that inherits its settings.
+        conn.execute(
+            "INSERT OR IGNORE INTO environments (repo_id, name)
+             SELECT id, ?1 FROM repositories",
+            [models::DEFAULT_ENVIRONMENT],
+        )?;
 
         tracing::info!("Database migrations complete");
         Ok(())
@@ -103,6 +383,42 @@ impl Database {
         f(&conn)
     }
 
+    /// Create an admin account with the given credentials when no users
+    /// exist yet. Returns whether one was created.
+    pub fn seed_admin_user(&self, username: &str, password: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))?;
+        if count > 0 {
+            return Ok(false);
+        }
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role) VALUES (?1, ?2, 'admin')",
+            rusqlite::params![username, crate::auth::hash_password(password)?],
+        )?;
+        Ok(true)
+    }
+
+    /// Whether any account exists yet.
+    pub fn has_users(&self) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row("SELECT EXISTS(SELECT 1 FROM users)", [], |row| row.get(0))?)
+    }
+
+    /// Usernames of admins still using the old `admin` default password.
+    pub fn admins_with_default_password(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT username, password_hash FROM users WHERE role = 'admin'")?;
+        let admins = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(admins
+            .into_iter()
+            .filter(|(_, hash)| crate::auth::verify_password(hash, "admin"))
+            .map(|(username, _)| username)
+            .collect())
+    }
+
     /// Record a login attempt (success or failure) for the given IP.
     pub fn record_login_attempt(&self, ip: &str, success: bool) -> Result<()> {
         let conn = self.conn.lock().unwrap();
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+
+use crate::auth::Role;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Repository {
@@ -9,17 +12,113 @@ pub struct Repository {
     pub description: Option<String>,
     pub webhook_url: Option<String>,
     pub filesystem_path: Option<String>,
-    pub ssh_password: Option<String>,
+    /// Deploy key sealed by [`crate::services::crypto`]; never serialized.
+    #[serde(skip)]
+    pub ssh_key_encrypted: Option<String>,
+    pub has_ssh_key: bool,
+    /// `SHA256:...` fingerprint of the deploy key, when its format allows.
+    pub ssh_key_fingerprint: Option<String>,
     pub is_private: bool,
     pub default_branch: String,
     /// Domain used for automatic reverse-proxy routing via Traefik.
     pub domain: Option<String>,
     /// Internal port the container listens on (used by Traefik; default 3000).
     pub proxy_port: Option<i64>,
+    /// Build and run pull requests as ephemeral preview environments.
+    pub previews_enabled: bool,
+    /// Base domain for previews; PR `n` is routed at `pr-<n>.<preview_domain>`.
+    pub preview_domain: Option<String>,
+    /// JSON object of env vars injected into preview containers only.
+    pub preview_env: Option<String>,
+    /// Also preview pull requests opened from forks. Their code runs on the
+    /// host with `preview_env`, so this is off by default.
+    pub preview_forks: bool,
+    /// Secret GitHub signs webhook payloads with, sealed by
+    /// [`crate::services::crypto`]; never serialized.
+    #[serde(skip)]
+    pub webhook_secret_encrypted: Option<String>,
+    pub has_webhook_secret: bool,
+    /// Limits, restart policy and logging applied to every container of the app.
+    pub resource_limits: Option<ResourceLimits>,
+    /// Named volumes and host bind mounts attached on every run.
+    pub mounts: Vec<MountSpec>,
+    /// Extra (typically private) networks app containers are attached to, in
+    /// addition to the proxy network.
+    pub networks: Vec<String>,
+    /// Existing volumes and networks dockyy didn't create that an admin
+    /// attached, as `volume:<name>` or `network:<name>`.
+    pub adopted: Vec<String>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+impl Repository {
+    /// Column list matching the field order expected by [`Repository::from_row`].
+    pub const COLUMNS: &'static str = "id, name, owner, url, description, webhook_url, filesystem_path,
+        ssh_key_encrypted, ssh_key_fingerprint, is_private, default_branch, domain, proxy_port,
+        previews_enabled, preview_domain, preview_env, resource_limits, mounts,
+        networks, created_at, updated_at, webhook_secret_encrypted, preview_forks, adopted";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let ssh_key_encrypted: Option<String> = row.get(7)?;
+        let webhook_secret_encrypted: Option<String> = row.get(21)?;
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            owner: row.get(2)?,
+            url: row.get(3)?,
+            description: row.get(4)?,
+            webhook_url: row.get(5)?,
+            filesystem_path: row.get(6)?,
+            has_ssh_key: ssh_key_encrypted.is_some(),
+            ssh_key_encrypted,
+            ssh_key_fingerprint: row.get(8)?,
+            is_private: row.get::<_, i64>(9)? != 0,
+            default_branch: row.get(10)?,
+            domain: row.get(11)?,
+            proxy_port: row.get(12)?,
+            previews_enabled: row.get::<_, Option<i64>>(13)?.unwrap_or(0) != 0,
+            preview_domain: row.get(14)?,
+            preview_env: row.get(15)?,
+            preview_forks: row.get::<_, Option<i64>>(22)?.unwrap_or(0) != 0,
+            has_webhook_secret: webhook_secret_encrypted.is_some(),
+            webhook_secret_encrypted,
+            resource_limits: row
+                .get::<_, Option<String>>(16)?
+                .and_then(|raw| serde_json::from_str(&raw).ok()),
+            mounts: row
+                .get::<_, Option<String>>(17)?
+                .and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or_default(),
+            networks: row
+                .get::<_, Option<String>>(18)?
+                .and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or_default(),
+            adopted: row
+                .get::<_, Option<String>>(23)?
+                .and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or_default(),
+            created_at: row.get(19)?,
+            updated_at: row.get(20)?,
+        })
+    }
+
+    /// Docker-safe slug used for container, image and compose project names.
+    pub fn slug(&self) -> String {
+        self.name.to_lowercase().replace("/", "-")
+    }
+
+    /// Whether an admin attached the existing volume or network `name`.
+    pub fn adopts(&self, kind: &str, name: &str) -> bool {
+        self.adopted.contains(&adoption(kind, name))
+    }
+}
+
+/// Entry of [`Repository::adopted`] for the volume or network `name`.
+pub fn adoption(kind: &str, name: &str) -> String {
+    format!("{}:{}", kind.to_ascii_lowercase(), name)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateRepository {
     pub name: String,
@@ -28,13 +127,28 @@ pub struct CreateRepository {
     pub description: Option<String>,
     pub webhook_url: Option<String>,
     pub filesystem_path: Option<String>,
-    pub ssh_password: Option<String>,
+    /// Private deploy key; stored encrypted and never returned.
+    #[serde(alias = "ssh_password")]
+    pub ssh_key: Option<String>,
     #[serde(default)]
     pub is_private: bool,
     #[serde(default = "default_branch")]
     pub default_branch: String,
     pub domain: Option<String>,
     pub proxy_port: Option<i64>,
+    #[serde(default)]
+    pub previews_enabled: bool,
+    pub preview_domain: Option<String>,
+    pub preview_env: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub preview_forks: bool,
+    /// Secret for `X-Hub-Signature-256`; stored encrypted and never returned.
+    pub webhook_secret: Option<String>,
+    pub resource_limits: Option<ResourceLimits>,
+    #[serde(default)]
+    pub mounts: Vec<MountSpec>,
+    #[serde(default)]
+    pub networks: Vec<String>,
 }
 
 fn default_branch() -> String {
@@ -49,17 +163,222 @@ pub struct UpdateRepository {
     pub description: Option<String>,
     pub webhook_url: Option<String>,
     pub filesystem_path: Option<String>,
-    pub ssh_password: Option<String>,
+    /// Replaces the deploy key; an empty string removes it.
+    #[serde(alias = "ssh_password")]
+    pub ssh_key: Option<String>,
     pub is_private: Option<bool>,
     pub default_branch: Option<String>,
     pub domain: Option<String>,
     pub proxy_port: Option<i64>,
+    pub previews_enabled: Option<bool>,
+    pub preview_domain: Option<String>,
+    pub preview_env: Option<HashMap<String, String>>,
+    pub preview_forks: Option<bool>,
+    /// Replaces the webhook secret; an empty string removes it.
+    pub webhook_secret: Option<String>,
+    pub resource_limits: Option<ResourceLimits>,
+    pub mounts: Option<Vec<MountSpec>>,
+    pub networks: Option<Vec<String>>,
+}
+
+/// Networks that can't be created, removed or used as an app's extra network.
+pub const RESERVED_NETWORKS: &[&str] = &[
+    "bridge",
+    "host",
+    "none",
+    crate::services::traefik::TRAEFIK_NETWORK,
+];
+
+/// Check `name` is a valid user-defined network name.
+pub fn validate_network_name(name: &str) -> Result<(), String> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !valid {
+        return Err(format!("Invalid network name '{}'", name));
+    }
+    if RESERVED_NETWORKS.contains(&name) {
+        return Err(format!("Network '{}' is reserved", name));
+    }
+    Ok(())
+}
+
+/// Check `name` is a volume name the engine accepts.
+pub fn validate_volume_name(name: &str) -> Result<(), String> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !valid {
+        return Err(format!("Invalid volume name '{}'", name));
+    }
+    Ok(())
+}
+
+/// `dockyy.*` labels attribute engine objects to repositories, so callers
+/// can't set them on objects they create.
+pub fn validate_labels(labels: &HashMap<String, String>) -> Result<(), String> {
+    match labels.keys().find(|key| key.starts_with("dockyy.")) {
+        Some(key) => Err(format!("Label '{}' is reserved for dockyy", key)),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MountKind {
+    Volume,
+    Bind,
+}
+
+/// A persistent mount for an application container.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MountSpec {
+    pub kind: MountKind,
+    /// Volume name, or absolute host path for bind mounts.
+    pub source: String,
+    /// Absolute path inside the container.
+    pub target: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl MountSpec {
+    /// Check the mount is well-formed and, for bind mounts, that the host path
+    /// lies under one of the `allowlist` prefixes.
+    pub fn validate(&self, allowlist: &[String]) -> Result<(), String> {
+        let target = std::path::Path::new(&self.target);
+        if !target.is_absolute() || target == std::path::Path::new("/") {
+            return Err(format!("Mount target '{}' must be an absolute path other than /", self.target));
+        }
+
+        match self.kind {
+            MountKind::Volume => validate_volume_name(&self.source)?,
+            MountKind::Bind => {
+                let source = std::path::Path::new(&self.source);
+                if !source.is_absolute()
+                    || source
+                        .components()
+                        .any(|c| matches!(c, std::path::Component::ParentDir))
+                {
+                    return Err(format!("Bind source '{}' must be an absolute path without '..'", self.source));
+                }
+                // Resolve symlinks when the path exists so they can't escape the allowlist.
+                let resolved = std::fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+                if !allowlist
+                    .iter()
+                    .any(|prefix| resolved.starts_with(std::path::Path::new(prefix)))
+                {
+                    return Err(format!(
+                        "Bind source '{}' is not under an allowed host path ({})",
+                        self.source,
+                        if allowlist.is_empty() { "none configured".to_string() } else { allowlist.join(", ") }
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check repository `repo_id` (`None` while it is being created) may use an
+/// existing volume or network carrying `labels`. Objects created for another
+/// repository are always refused; ones dockyy didn't create for any
+/// repository only unless `adopt` is set.
+pub fn check_owner(
+    kind: &str,
+    name: &str,
+    labels: &HashMap<String, String>,
+    repo_id: Option<i64>,
+    adopt: bool,
+) -> Result<(), String> {
+    match labels.get("dockyy.repo_id") {
+        Some(owner) if repo_id.is_some_and(|id| *owner == id.to_string()) => Ok(()),
+        Some(_) => Err(format!("{} '{}' belongs to another application", kind, name)),
+        None if adopt => Ok(()),
+        None => Err(format!(
+            "{} '{}' was not created by dockyy for this application; only admins can attach it",
+            kind, name
+        )),
+    }
+}
+
+/// Per-application container limits. Every field is optional; unset fields
+/// leave the engine default in place.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// Hard memory limit in bytes.
+    pub memory_limit: Option<i64>,
+    /// Soft memory limit (reservation) in bytes.
+    pub memory_reservation: Option<i64>,
+    /// Number of CPUs (fractional, e.g. 0.5).
+    pub cpus: Option<f64>,
+    /// Relative CPU weight (default 1024).
+    pub cpu_shares: Option<i64>,
+    /// CPU CFS quota in microseconds per `cpu_period`.
+    pub cpu_quota: Option<i64>,
+    /// CPU CFS period in microseconds (default 100000).
+    pub cpu_period: Option<i64>,
+    pub pids_limit: Option<i64>,
+    /// `no`, `always`, `unless-stopped` or `on-failure[:N]` (default `always`).
+    pub restart_policy: Option<String>,
+    /// Logging driver, e.g. `json-file`, `local`, `journald`.
+    pub log_driver: Option<String>,
+    #[serde(default)]
+    pub log_options: HashMap<String, String>,
+}
+
+impl ResourceLimits {
+    /// Parse `restart_policy` into a policy name and optional retry count.
+    pub fn restart_policy(&self) -> Result<(&str, Option<i64>), String> {
+        let raw = self.restart_policy.as_deref().unwrap_or("always");
+        match raw.split_once(':') {
+            Some(("on-failure", n)) => n
+                .parse::<i64>()
+                .ok()
+                .filter(|n| *n >= 0)
+                .map(|n| ("on-failure", Some(n)))
+                .ok_or_else(|| format!("Invalid retry count in restart policy '{}'", raw)),
+            None if matches!(raw, "no" | "always" | "unless-stopped" | "on-failure") => {
+                Ok((raw, None))
+            }
+            _ => Err(format!(
+                "Invalid restart policy '{}' (expected no, always, unless-stopped or on-failure[:N])",
+                raw
+            )),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.restart_policy()?;
+        let positive = [
+            ("memory_limit", self.memory_limit),
+            ("memory_reservation", self.memory_reservation),
+            ("cpu_shares", self.cpu_shares),
+            ("cpu_quota", self.cpu_quota),
+            ("cpu_period", self.cpu_period),
+        ];
+        if let Some((name, _)) = positive.iter().find(|(_, v)| v.is_some_and(|v| v <= 0)) {
+            return Err(format!("{} must be positive", name));
+        }
+        if self.cpus.is_some_and(|c| c <= 0.0) {
+            return Err("cpus must be positive".into());
+        }
+        if let (Some(limit), Some(reservation)) = (self.memory_limit, self.memory_reservation)
+            && reservation > limit
+        {
+            return Err("memory_reservation must not exceed memory_limit".into());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Deployment {
     pub id: i64,
     pub repo_id: i64,
+    pub environment_id: Option<i64>,
     pub status: String,
     pub commit_sha: Option<String>,
     pub image_name: Option<String>,
@@ -71,6 +390,236 @@ pub struct Deployment {
     pub updated_at: String,
 }
 
+impl Deployment {
+    /// Column list matching the field order expected by [`Deployment::from_row`].
+    pub const COLUMNS: &'static str = "id, repo_id, environment_id, status, commit_sha, image_name,
+        container_id, domain, port, build_log, created_at, updated_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            repo_id: row.get(1)?,
+            environment_id: row.get(2)?,
+            status: row.get(3)?,
+            commit_sha: row.get(4)?,
+            image_name: row.get(5)?,
+            container_id: row.get(6)?,
+            domain: row.get(7)?,
+            port: row.get(8)?,
+            build_log: row.get(9)?,
+            created_at: row.get(10)?,
+            updated_at: row.get(11)?,
+        })
+    }
+}
+
+/// Name of the environment every repository starts with. It keeps the legacy
+/// `dockyy-<name>` container and checkout paths so existing deploys are adopted.
+pub const DEFAULT_ENVIRONMENT: &str = "production";
+
+/// A named deployment target (production, staging, ...) under a repository.
+///
+/// Unset fields fall back to the repository's own settings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Environment {
+    pub id: i64,
+    pub repo_id: i64,
+    pub name: String,
+    pub branch: Option<String>,
+    pub domain: Option<String>,
+    pub proxy_port: Option<i64>,
+    /// JSON object of env vars injected into this environment's containers.
+    pub env_vars: Option<String>,
+    /// Hard memory limit in bytes.
+    pub memory_limit: Option<i64>,
+    /// Number of CPUs (fractional, e.g. 0.5).
+    pub cpu_limit: Option<f64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Environment {
+    /// Column list matching the field order expected by [`Environment::from_row`].
+    pub const COLUMNS: &'static str = "id, repo_id, name, branch, domain, proxy_port, env_vars,
+        memory_limit, cpu_limit, created_at, updated_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            repo_id: row.get(1)?,
+            name: row.get(2)?,
+            branch: row.get(3)?,
+            domain: row.get(4)?,
+            proxy_port: row.get(5)?,
+            env_vars: row.get(6)?,
+            memory_limit: row.get(7)?,
+            cpu_limit: row.get(8)?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
+        })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_ENVIRONMENT
+    }
+
+    pub fn branch<'a>(&'a self, repo: &'a Repository) -> &'a str {
+        self.branch.as_deref().unwrap_or(&repo.default_branch)
+    }
+
+    /// Only the default environment inherits the repository's domain; others
+    /// are routed only at a domain of their own, so they never share a Traefik
+    /// rule with production.
+    pub fn domain<'a>(&'a self, repo: &'a Repository) -> Option<&'a str> {
+        if self.is_default() {
+            self.domain.as_deref().or(repo.domain.as_deref())
+        } else {
+            self.domain.as_deref()
+        }
+    }
+
+    pub fn proxy_port(&self, repo: &Repository) -> Option<i64> {
+        self.proxy_port.or(repo.proxy_port)
+    }
+
+    /// Container name, also used as the compose project name. Other
+    /// environments include the repository id with `_` separators, so they
+    /// can't collide with another repository's `dockyy-<slug>` or its previews.
+    pub fn container_name(&self, repo: &Repository) -> String {
+        if self.is_default() {
+            format!("dockyy-{}", repo.slug())
+        } else {
+            format!("dockyy-{}_{}_{}", repo.slug(), self.repo_id, self.name)
+        }
+    }
+
+    /// Name non-default environments' containers had before
+    /// [`Environment::container_name`] included the repository id.
+    pub fn legacy_container_name(&self, repo: &Repository) -> Option<String> {
+        (!self.is_default()).then(|| format!("dockyy-{}-{}", repo.slug(), self.name))
+    }
+
+    /// Labels attributing this environment's containers (built images and
+    /// compose services alike) to it.
+    pub fn labels(&self) -> Vec<(String, String)> {
+        vec![
+            ("dockyy.repo_id".to_string(), self.repo_id.to_string()),
+            ("dockyy.environment".to_string(), self.name.clone()),
+        ]
+    }
+
+    pub fn image_tag(&self, repo: &Repository) -> String {
+        if self.is_default() {
+            format!("dockyy-{}:latest", repo.slug())
+        } else {
+            format!("dockyy-{}:{}", repo.slug(), self.name)
+        }
+    }
+
+    /// Working tree the environment's branch is checked out into.
+    pub fn checkout_dir(&self, data_dir: &str) -> String {
+        if self.is_default() {
+            format!("{}/repos/{}", data_dir, self.repo_id)
+        } else {
+            format!("{}/repos/{}-{}", data_dir, self.repo_id, self.name)
+        }
+    }
+
+    /// The repository's mounts as seen by this environment. Named volumes are
+    /// suffixed with the environment name outside the default environment so
+    /// staging never writes into production's data.
+    pub fn mounts(&self, repo: &Repository) -> Vec<MountSpec> {
+        repo.mounts
+            .iter()
+            .cloned()
+            .map(|mut m| {
+                if m.kind == MountKind::Volume && !self.is_default() {
+                    m.source = format!("{}-{}", m.source, self.name);
+                }
+                m
+            })
+            .collect()
+    }
+
+    /// The repository's limits with this environment's memory/CPU overrides applied.
+    pub fn resource_limits(&self, repo: &Repository) -> ResourceLimits {
+        let mut limits = repo.resource_limits.clone().unwrap_or_default();
+        if let Some(memory) = self.memory_limit {
+            limits.memory_limit = Some(memory);
+        }
+        if let Some(cpus) = self.cpu_limit {
+            limits.cpus = Some(cpus);
+        }
+        limits
+    }
+
+    pub fn env_vars(&self) -> HashMap<String, String> {
+        self.env_vars
+            .as_deref()
+            .and_then(|raw| serde_json::from_str(raw).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateEnvironment {
+    pub name: String,
+    pub branch: Option<String>,
+    pub domain: Option<String>,
+    pub proxy_port: Option<i64>,
+    pub env_vars: Option<HashMap<String, String>>,
+    pub memory_limit: Option<i64>,
+    pub cpu_limit: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateEnvironment {
+    pub branch: Option<String>,
+    pub domain: Option<String>,
+    pub proxy_port: Option<i64>,
+    pub env_vars: Option<HashMap<String, String>>,
+    /// `null` clears the override back to the repository's limit.
+    #[serde(default, deserialize_with = "nullable")]
+    pub memory_limit: Option<Option<i64>>,
+    /// `null` clears the override back to the repository's limit.
+    #[serde(default, deserialize_with = "nullable")]
+    pub cpu_limit: Option<Option<f64>>,
+}
+
+/// Tell an explicit `null` (`Some(None)`) apart from an omitted field (`None`).
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromoteRequest {
+    /// Environment to deploy the image into (defaults to production).
+    pub target: Option<String>,
+}
+
+/// Ephemeral environment built from a pull request head.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Preview {
+    pub id: i64,
+    pub repo_id: i64,
+    pub pr_number: i64,
+    pub head_ref: Option<String>,
+    pub head_sha: Option<String>,
+    pub container_name: String,
+    pub container_id: Option<String>,
+    pub image_name: Option<String>,
+    pub domain: Option<String>,
+    /// pending | building | running | failed | closed
+    pub status: String,
+    pub build_log: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Job {
     pub id: i64,
@@ -84,6 +633,37 @@ pub struct Job {
     pub updated_at: String,
 }
 
+/// One line of container output.
+#[derive(Debug, Serialize, Clone)]
+pub struct LogLine {
+    /// `stdout`, `stderr`, or `console` for TTY containers (streams are merged).
+    pub stream: &'static str,
+    /// RFC 3339 timestamp, when requested.
+    pub timestamp: Option<String>,
+    pub line: String,
+}
+
+/// Audit record of an interactive exec session into a container.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExecSession {
+    pub id: i64,
+    pub container_id: String,
+    pub container_name: Option<String>,
+    /// Repository the container belonged to, when it was a dockyy app.
+    pub repo_id: Option<i64>,
+    pub username: String,
+    pub shell: String,
+    pub remote_addr: Option<String>,
+    pub exit_code: Option<i64>,
+    /// Bytes received from the client (keystrokes).
+    pub bytes_in: i64,
+    /// Bytes sent to the client (terminal output).
+    pub bytes_out: i64,
+    pub error: Option<String>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ContainerInfo {
     pub id: String,
@@ -93,6 +673,214 @@ pub struct ContainerInfo {
     pub state: String,
     pub ports: Vec<PortMapping>,
     pub created: i64,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// Point-in-time resource usage of a container. Network and block I/O are
+/// cumulative byte counters since the container started.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ContainerStats {
+    pub cpu_pct: f64,
+    pub mem_bytes: u64,
+    pub mem_limit: u64,
+    pub net_rx_bytes: u64,
+    pub net_tx_bytes: u64,
+    pub blk_read_bytes: u64,
+    pub blk_write_bytes: u64,
+    pub pids: u64,
+}
+
+/// A stored stats sample: a raw 30s sample or the average over a rollup bucket.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatsSample {
+    /// Unix seconds (bucket start for rollups).
+    pub ts: i64,
+    pub cpu_pct: f64,
+    pub mem_bytes: i64,
+    pub mem_limit: i64,
+    /// Throughput in bytes per second.
+    pub net_rx_bps: f64,
+    pub net_tx_bps: f64,
+    pub blk_read_bps: f64,
+    pub blk_write_bps: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ContainerStatsSeries {
+    pub container_name: String,
+    pub environment: Option<String>,
+    /// Seconds between samples.
+    pub resolution: i64,
+    pub samples: Vec<StatsSample>,
+}
+
+/// Resource usage of one application (all its containers) over a time range.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppUsage {
+    pub repo_id: i64,
+    pub name: String,
+    pub avg_cpu_pct: f64,
+    pub max_cpu_pct: f64,
+    pub avg_mem_bytes: i64,
+    pub max_mem_bytes: i64,
+    pub avg_net_rx_bps: f64,
+    pub avg_net_tx_bps: f64,
+    pub avg_blk_read_bps: f64,
+    pub avg_blk_write_bps: f64,
+}
+
+/// A stored host metrics sample: a raw 30s sample or the average over a
+/// rollup bucket.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HostMetricsSample {
+    /// Unix seconds (bucket start for rollups).
+    pub ts: i64,
+    pub cpu_pct: f64,
+    pub mem_used_bytes: i64,
+    pub mem_total_bytes: i64,
+    pub swap_used_bytes: i64,
+    pub swap_total_bytes: i64,
+    pub disk_used_bytes: i64,
+    pub disk_total_bytes: i64,
+    pub load_avg_1: f64,
+    pub load_avg_5: f64,
+    pub load_avg_15: f64,
+    /// Throughput in bytes per second.
+    pub net_rx_bps: f64,
+    pub net_tx_bps: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HostMetricsHistory {
+    pub from: i64,
+    pub to: i64,
+    /// Seconds between samples.
+    pub resolution: i64,
+    pub samples: Vec<HostMetricsSample>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub driver: String,
+    pub mountpoint: String,
+    pub scope: Option<String>,
+    pub created_at: Option<String>,
+    pub labels: HashMap<String, String>,
+    /// Created by dockyy (`dockyy.managed=true`); protected from removal.
+    pub managed: bool,
+    /// Bytes used, when the engine reports it (`-1`/missing when unknown).
+    pub size_bytes: Option<i64>,
+    /// Names of containers (running or not) that mount this volume.
+    pub containers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateVolumeRequest {
+    pub name: String,
+    pub driver: Option<String>,
+    #[serde(default)]
+    pub driver_opts: HashMap<String, String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NetworkInfo {
+    pub id: String,
+    pub name: String,
+    pub driver: Option<String>,
+    pub scope: Option<String>,
+    /// No external connectivity (containers can only reach each other).
+    pub internal: bool,
+    pub subnets: Vec<String>,
+    pub gateways: Vec<String>,
+    pub created: Option<String>,
+    pub labels: HashMap<String, String>,
+    /// Created by dockyy (`dockyy.managed=true`).
+    pub managed: bool,
+    /// Names of containers (running or not) attached to this network.
+    pub containers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateNetworkRequest {
+    pub name: String,
+    /// `bridge` (default) or another driver supported by the engine.
+    pub driver: Option<String>,
+    #[serde(default)]
+    pub internal: bool,
+    /// CIDR, e.g. `172.30.0.0/24`.
+    pub subnet: Option<String>,
+    pub gateway: Option<String>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkConnectRequest {
+    /// Container id or name.
+    pub container: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkDisconnectRequest {
+    pub container: String,
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageInfo {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub digests: Vec<String>,
+    pub size_bytes: i64,
+    /// Unix timestamp the image was created at.
+    pub created: i64,
+    /// Untagged image left behind by a rebuild.
+    pub dangling: bool,
+    pub labels: HashMap<String, String>,
+    /// Names of containers (running or not) created from this image.
+    pub containers: Vec<String>,
+    /// Deployments whose recorded `image_name` is one of this image's tags.
+    pub deployments: Vec<ImageDeployment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageDeployment {
+    pub id: i64,
+    pub repo_id: i64,
+    pub environment_id: Option<i64>,
+    pub status: String,
+    pub image_name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PullImageRequest {
+    /// `name[:tag]` or `name@digest`; the tag defaults to `latest`.
+    pub image: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PruneImagesRequest {
+    /// Also remove tagged images not used by any container.
+    #[serde(default)]
+    pub all: bool,
+    /// Prune the build cache too (default true).
+    pub build_cache: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PruneReport {
+    pub images_deleted: Vec<String>,
+    pub space_reclaimed: i64,
+    /// Reclaimed-space summary, when the build cache was pruned.
+    pub build_cache: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -111,15 +899,798 @@ pub struct LoginRequest {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginResponse {
+    /// Short-lived access token.
     pub token: String,
+    /// Exchanged at `/auth/refresh` for a new pair; rotates on every use.
+    pub refresh_token: String,
+    /// Seconds until `token` expires.
+    pub expires_in: i64,
+    pub username: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+/// Creates the first admin with the setup token from the server log.
+#[derive(Debug, Deserialize)]
+pub struct SetupRequest {
+    pub token: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Returned by `/auth/login` instead of a [`LoginResponse`] when the user
+/// has TOTP enabled.
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    /// Intermediate token for `/auth/login/mfa`; not an access token.
+    pub mfa_token: String,
+    /// Seconds left to complete the second step.
+    pub expires_in: i64,
+}
+
+/// Second login step for users with TOTP enabled.
+#[derive(Debug, Deserialize)]
+pub struct MfaLoginRequest {
+    /// Intermediate token returned by `/auth/login`.
+    pub mfa_token: String,
+    /// Current TOTP code or an unused recovery code.
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisableTotpRequest {
+    pub password: String,
+}
+
+/// A dashboard account. The password hash never leaves the database layer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct User {
+    pub id: i64,
     pub username: String,
+    pub role: Role,
+    /// Restricted to the repositories granted in `repository_grants`.
+    /// Ignored for admins.
+    pub scoped: bool,
+    /// Login requires a TOTP code.
+    pub totp_enabled: bool,
+    /// Created by single sign-on; its role follows the provider's claims.
+    pub sso: bool,
+    pub created_at: String,
+    pub updated_at: String,
 }
+
+impl User {
+    pub const COLUMNS: &'static str =
+        "id, username, role, scoped, totp_enabled, oidc_subject IS NOT NULL, created_at, updated_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let role: String = row.get(2)?;
+        Ok(Self {
+            id: row.get(0)?,
+            username: row.get(1)?,
+            // Unknown roles from a hand-edited database get the least access.
+            role: Role::parse(&role).unwrap_or(Role::Viewer),
+            scoped: row.get(3)?,
+            totp_enabled: row.get(4)?,
+            sso: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUser {
+    pub username: String,
+    pub password: String,
+    pub role: Option<Role>,
+    pub scoped: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUser {
+    pub password: Option<String>,
+    pub role: Option<Role>,
+    pub scoped: Option<bool>,
+}
+
+/// A scoped user's access to one repository. The effective role is capped
+/// by the user's global role.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepositoryGrant {
+    pub repo_id: i64,
+    pub repo_name: String,
+    pub role: Role,
+    pub created_at: String,
+}
+
+impl RepositoryGrant {
+    pub const COLUMNS: &'static str = "g.repo_id, r.name, g.role, g.created_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let role: String = row.get(2)?;
+        Ok(Self {
+            repo_id: row.get(0)?,
+            repo_name: row.get(1)?,
+            role: Role::parse(&role).unwrap_or(Role::Viewer),
+            created_at: row.get(3)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRepositoryGrant {
+    pub role: Role,
+}
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// What an API token may do, on top of its owner's own permissions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    Read,
+    Deploy,
+    Admin,
+}
+
+impl TokenScope {
+    /// Highest role a token with this scope can act with.
+    pub fn role(&self) -> Role {
+        match self {
+            TokenScope::Read => Role::Viewer,
+            TokenScope::Deploy => Role::Deployer,
+            TokenScope::Admin => Role::Admin,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Deploy => "deploy",
+            TokenScope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(TokenScope::Read),
+            "deploy" => Some(TokenScope::Deploy),
+            "admin" => Some(TokenScope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// An API token as listed; the secret itself is only returned on creation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub id: i64,
+    pub username: String,
+    pub name: String,
+    /// First characters of the token, to tell tokens apart.
+    pub prefix: String,
+    pub scope: TokenScope,
+    /// Repositories the token is limited to; `None` means all the owner can reach.
+    pub repo_ids: Option<Vec<i64>>,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+impl ApiToken {
+    pub const COLUMNS: &'static str = "t.id, u.username, t.name, t.prefix, t.scope, t.repo_ids,
+        t.expires_at, t.last_used_at, t.created_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let scope: String = row.get(4)?;
+        let repo_ids: Option<String> = row.get(5)?;
+        Ok(Self {
+            id: row.get(0)?,
+            username: row.get(1)?,
+            name: row.get(2)?,
+            prefix: row.get(3)?,
+            scope: TokenScope::parse(&scope).unwrap_or(TokenScope::Read),
+            repo_ids: repo_ids.and_then(|ids| serde_json::from_str(&ids).ok()),
+            expires_at: row.get(6)?,
+            last_used_at: row.get(7)?,
+            created_at: row.get(8)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiToken {
+    pub name: String,
+    /// Defaults to `read`.
+    pub scope: Option<TokenScope>,
+    pub repo_ids: Option<Vec<i64>>,
+    /// Never expires when omitted.
+    pub expires_in_days: Option<i64>,
+}
+
+/// A login session as listed; refresh token hashes stay in the database.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub id: String,
+    pub username: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    pub expires_at: String,
+    /// The session making the request.
+    pub current: bool,
+}
+
+impl Session {
+    pub const COLUMNS: &'static str = "s.id, u.username, s.ip_address, s.user_agent,
+        s.created_at, s.last_used_at, s.expires_at";
+
+    /// `current_id` marks the caller's own session.
+    pub fn from_row(row: &rusqlite::Row<'_>, current_id: Option<&str>) -> rusqlite::Result<Self> {
+        let id: String = row.get(0)?;
+        Ok(Self {
+            current: current_id == Some(id.as_str()),
+            id,
+            username: row.get(1)?,
+            ip_address: row.get(2)?,
+            user_agent: row.get(3)?,
+            created_at: row.get(4)?,
+            last_used_at: row.get(5)?,
+            expires_at: row.get(6)?,
+        })
+    }
+}
+
+/// One recorded action, as listed by `/audit`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// Acting user; `None` for unauthenticated events such as webhooks.
+    pub username: Option<String>,
+    /// Set when the action used an API token.
+    pub token_id: Option<i64>,
+    pub ip_address: Option<String>,
+    /// `METHOD /api/route/{param}` for API calls, or a named event such
+    /// as `login` or `webhook.deploy`.
+    pub action: String,
+    pub target: Option<String>,
+    /// Request parameters with secrets redacted.
+    pub summary: Option<serde_json::Value>,
+    /// `success`, `denied` or `failure`.
+    pub outcome: String,
+    /// HTTP status of the response, when there was one.
+    pub status: Option<u16>,
+    pub created_at: String,
+}
+
+impl AuditEntry {
+    pub const COLUMNS: &'static str =
+        "id, username, token_id, ip_address, action, target, summary, outcome, status, created_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let summary: Option<String> = row.get(6)?;
+        Ok(Self {
+            id: row.get(0)?,
+            username: row.get(1)?,
+            token_id: row.get(2)?,
+            ip_address: row.get(3)?,
+            action: row.get(4)?,
+            target: row.get(5)?,
+            summary: summary.and_then(|s| serde_json::from_str(&s).ok()),
+            outcome: row.get(7)?,
+            status: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DockerComposeUpRequest {
     pub compose_file: Option<String>,
+    /// Environment to bring up (defaults to production).
+    pub environment: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SaveComposeOverrideRequest {
     pub content: String,
 }
+
+/// Events a notification channel can subscribe to.
+pub const NOTIFICATION_EVENTS: &[&str] = &[
+    "deploy.started",
+    "deploy.succeeded",
+    "deploy.failed",
+    "healthcheck.failed",
+    "container.died",
+    "alert.firing",
+    "alert.resolved",
+];
+
+/// Placeholder returned instead of secrets; sending it back keeps the stored value.
+pub const REDACTED: &str = "********";
+
+/// Delivery settings of a notification channel, tagged by `kind`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", content = "config", rename_all = "lowercase")]
+pub enum ChannelConfig {
+    /// JSON `POST` of the notification to an arbitrary URL, signed with
+    /// `X-Dockyy-Signature: sha256=<hmac>` when `secret` is set.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        #[serde(default)]
+        secret: Option<String>,
+    },
+    /// Slack incoming webhook.
+    Slack { url: String },
+    /// Discord channel webhook.
+    Discord { url: String },
+    /// Mattermost incoming webhook.
+    Mattermost { url: String },
+    Email {
+        host: String,
+        #[serde(default = "default_smtp_port")]
+        port: u16,
+        #[serde(default)]
+        security: crate::services::smtp::SmtpSecurity,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    Telegram { bot_token: String, chat_id: String },
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+impl ChannelConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChannelConfig::Webhook { .. } => "webhook",
+            ChannelConfig::Slack { .. } => "slack",
+            ChannelConfig::Discord { .. } => "discord",
+            ChannelConfig::Mattermost { .. } => "mattermost",
+            ChannelConfig::Email { .. } => "email",
+            ChannelConfig::Telegram { .. } => "telegram",
+        }
+    }
+
+    /// The `config` object as stored in the database.
+    pub fn config_json(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.get("config").cloned())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    pub fn from_columns(kind: &str, config: &str) -> Result<Self, serde_json::Error> {
+        let config: serde_json::Value = serde_json::from_str(config)?;
+        serde_json::from_value(serde_json::json!({"kind": kind, "config": config}))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ChannelConfig::Webhook { url, .. }
+            | ChannelConfig::Slack { url }
+            | ChannelConfig::Discord { url }
+            | ChannelConfig::Mattermost { url } => validate_http_url(url),
+            ChannelConfig::Email { host, from, to, .. } => {
+                if host.is_empty() {
+                    return Err("SMTP host is required".to_string());
+                }
+                if to.is_empty() {
+                    return Err("At least one recipient is required".to_string());
+                }
+                for addr in std::iter::once(from).chain(to) {
+                    if !addr.contains('@') || addr.contains(['\r', '\n', '<', '>']) {
+                        return Err(format!("Invalid email address '{}'", addr));
+                    }
+                }
+                Ok(())
+            }
+            ChannelConfig::Telegram { bot_token, chat_id } => {
+                if bot_token.is_empty() || chat_id.is_empty() {
+                    return Err("bot_token and chat_id are required".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn secrets_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            ChannelConfig::Webhook { secret, .. } => secret.iter_mut().collect(),
+            // The webhook URL itself is the credential for these.
+            ChannelConfig::Slack { url }
+            | ChannelConfig::Discord { url }
+            | ChannelConfig::Mattermost { url } => vec![url],
+            ChannelConfig::Email { password, .. } => password.iter_mut().collect(),
+            ChannelConfig::Telegram { bot_token, .. } => vec![bot_token],
+        }
+    }
+
+    /// Copy with secrets replaced by [`REDACTED`], for API responses.
+    pub fn redacted(&self) -> Self {
+        let mut copy = self.clone();
+        for secret in copy.secrets_mut() {
+            *secret = REDACTED.to_string();
+        }
+        copy
+    }
+
+    /// Restore secrets the client sent back as [`REDACTED`] from `stored`.
+    /// Only applies when the kind is unchanged.
+    pub fn keep_secrets(&mut self, stored: &ChannelConfig) {
+        if self.kind() != stored.kind() {
+            return;
+        }
+        let mut stored = stored.clone();
+        for (new, old) in self.secrets_mut().into_iter().zip(stored.secrets_mut()) {
+            if new == REDACTED {
+                *new = std::mem::take(old);
+            }
+        }
+    }
+
+    /// Checked after [`ChannelConfig::keep_secrets`]: a placeholder left over
+    /// means the client sent one without a stored value to restore.
+    pub fn has_placeholder(&self) -> bool {
+        self.clone().secrets_mut().iter().any(|s| s.as_str() == REDACTED)
+    }
+}
+
+fn validate_http_url(url: &str) -> Result<(), String> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not an http(s) URL", url))
+    }
+}
+
+pub fn validate_events(events: &[String]) -> Result<(), String> {
+    match events
+        .iter()
+        .find(|e| !NOTIFICATION_EVENTS.contains(&e.as_str()))
+    {
+        Some(e) => Err(format!(
+            "Unknown event '{}' (expected one of: {})",
+            e,
+            NOTIFICATION_EVENTS.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotificationChannel {
+    pub id: i64,
+    pub name: String,
+    pub channel: ChannelConfig,
+    /// Subscribed events; empty means all.
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl NotificationChannel {
+    pub const COLUMNS: &'static str =
+        "id, name, kind, config, events, enabled, created_at, updated_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        let kind: String = row.get(2)?;
+        let config: String = row.get(3)?;
+        let channel = ChannelConfig::from_columns(&kind, &config).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?;
+        let events: Option<String> = row.get(4)?;
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            channel,
+            events: events
+                .and_then(|e| serde_json::from_str(&e).ok())
+                .unwrap_or_default(),
+            enabled: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+
+    pub fn subscribed(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+}
+
+/// Secrets are redacted whenever a channel is serialized for the API.
+impl Serialize for NotificationChannel {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct View<'a> {
+            id: i64,
+            name: &'a str,
+            #[serde(flatten)]
+            channel: ChannelConfig,
+            events: &'a [String],
+            enabled: bool,
+            created_at: &'a str,
+            updated_at: &'a str,
+        }
+        View {
+            id: self.id,
+            name: &self.name,
+            channel: self.channel.redacted(),
+            events: &self.events,
+            enabled: self.enabled,
+            created_at: &self.created_at,
+            updated_at: &self.updated_at,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNotificationChannel {
+    pub name: String,
+    #[serde(flatten)]
+    pub channel: ChannelConfig,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationChannel {
+    pub name: Option<String>,
+    /// Replaces the whole kind/config pair when present.
+    #[serde(flatten)]
+    pub channel: Option<ChannelConfig>,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+/// One attempt series to deliver an event to a channel.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationDelivery {
+    pub id: i64,
+    pub channel_id: i64,
+    pub channel_name: Option<String>,
+    pub event: String,
+    /// `pending`, `sent` or `failed` (gave up after the last retry).
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<String>,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+impl NotificationDelivery {
+    pub const COLUMNS: &'static str = "d.id, d.channel_id, c.name, d.event, d.status, d.attempts,
+        d.last_error, d.next_attempt_at, d.created_at, d.delivered_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            channel_id: row.get(1)?,
+            channel_name: row.get(2)?,
+            event: row.get(3)?,
+            status: row.get(4)?,
+            attempts: row.get(5)?,
+            last_error: row.get(6)?,
+            next_attempt_at: row.get(7)?,
+            created_at: row.get(8)?,
+            delivered_at: row.get(9)?,
+        })
+    }
+}
+
+/// Metrics an alert rule can watch. Host metrics have the subject `host`;
+/// container metrics are evaluated per dockyy-managed container.
+pub const ALERT_METRICS: &[&str] = &[
+    "cpu_pct",
+    "mem_pct",
+    "swap_pct",
+    "disk_pct",
+    "load_1",
+    "docker_down",
+    "traefik_down",
+    "container_cpu_pct",
+    "container_mem_pct",
+    "container_restarts",
+];
+
+pub const ALERT_SEVERITIES: &[&str] = &["info", "warning", "critical"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertRule {
+    pub id: i64,
+    pub name: String,
+    pub metric: String,
+    /// `gt` or `lt`.
+    pub operator: String,
+    pub threshold: f64,
+    /// How long the condition must hold before firing. For
+    /// `container_restarts` this is the window restarts are counted over.
+    pub duration_secs: i64,
+    /// Limit a container rule to one container name.
+    pub container: Option<String>,
+    pub severity: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl AlertRule {
+    pub const COLUMNS: &'static str = "id, name, metric, operator, threshold, duration_secs,
+        container, severity, enabled, created_at, updated_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            metric: row.get(2)?,
+            operator: row.get(3)?,
+            threshold: row.get(4)?,
+            duration_secs: row.get(5)?,
+            container: row.get(6)?,
+            severity: row.get(7)?,
+            enabled: row.get(8)?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAlertRule {
+    pub name: String,
+    pub metric: String,
+    pub operator: Option<String>,
+    pub threshold: Option<f64>,
+    pub duration_secs: Option<i64>,
+    pub container: Option<String>,
+    pub severity: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAlertRule {
+    pub name: Option<String>,
+    pub operator: Option<String>,
+    pub threshold: Option<f64>,
+    pub duration_secs: Option<i64>,
+    pub container: Option<String>,
+    pub severity: Option<String>,
+    pub enabled: Option<bool>,
+}
+
+pub fn validate_alert_rule(
+    metric: &str,
+    operator: &str,
+    duration_secs: i64,
+    severity: &str,
+    container: Option<&str>,
+) -> Result<(), String> {
+    if !ALERT_METRICS.contains(&metric) {
+        return Err(format!(
+            "Unknown metric '{}' (expected one of: {})",
+            metric,
+            ALERT_METRICS.join(", ")
+        ));
+    }
+    if container.is_some_and(|c| !c.is_empty()) && !metric.starts_with("container_") {
+        return Err("'container' only applies to container_* metrics".to_string());
+    }
+    if !matches!(operator, "gt" | "lt") {
+        return Err("Operator must be 'gt' or 'lt'".to_string());
+    }
+    if duration_secs < 0 {
+        return Err("duration_secs cannot be negative".to_string());
+    }
+    if !ALERT_SEVERITIES.contains(&severity) {
+        return Err(format!("Severity must be one of: {}", ALERT_SEVERITIES.join(", ")));
+    }
+    Ok(())
+}
+
+/// One firing (or since resolved) occurrence of a rule for a subject.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Alert {
+    pub id: i64,
+    pub rule_id: Option<i64>,
+    pub rule_name: String,
+    pub metric: String,
+    /// `host` or a container name.
+    pub subject: String,
+    pub severity: String,
+    /// `firing` or `resolved`.
+    pub state: String,
+    pub value: f64,
+    pub message: String,
+    pub fired_at: String,
+    pub resolved_at: Option<String>,
+}
+
+impl Alert {
+    pub const COLUMNS: &'static str = "id, rule_id, rule_name, metric, subject, severity, state,
+        value, message, fired_at, resolved_at";
+
+    pub fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            rule_id: row.get(1)?,
+            rule_name: row.get(2)?,
+            metric: row.get(3)?,
+            subject: row.get(4)?,
+            severity: row.get(5)?,
+            state: row.get(6)?,
+            value: row.get(7)?,
+            message: row.get(8)?,
+            fired_at: row.get(9)?,
+            resolved_at: row.get(10)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_objects_belong_to_their_repository() {
+        let labels: HashMap<String, String> = [("dockyy.repo_id".to_string(), "7".to_string())].into();
+        assert!(check_owner("Volume", "data", &labels, Some(7), false).is_ok());
+        assert!(check_owner("Volume", "data", &labels, Some(8), true).is_err());
+        assert!(check_owner("Volume", "data", &labels, None, true).is_err());
+
+        let unlabeled = HashMap::new();
+        assert!(check_owner("Volume", "pgdata", &unlabeled, Some(7), true).is_ok());
+        assert!(check_owner("Volume", "pgdata", &unlabeled, Some(7), false).is_err());
+    }
+
+    #[test]
+    fn created_objects_cannot_claim_a_repository() {
+        for name in ["data", "pg_data.v2", "0cache-1"] {
+            assert!(validate_volume_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", "-data", ".data", "data/../etc", "my data", "dätä"] {
+            assert!(validate_volume_name(name).is_err(), "{}", name);
+        }
+
+        let labels = |key: &str| -> HashMap<String, String> { [(key.to_string(), "1".to_string())].into() };
+        assert!(validate_labels(&labels("team")).is_ok());
+        assert!(validate_labels(&labels("com.example.dockyy")).is_ok());
+        assert!(validate_labels(&labels("dockyy.repo_id")).is_err());
+        assert!(validate_labels(&labels("dockyy.managed")).is_err());
+    }
+
+    #[test]
+    fn alert_rules_are_validated() {
+        assert!(validate_alert_rule("cpu_pct", "gt", 60, "warning", None).is_ok());
+        assert!(validate_alert_rule("container_mem_pct", "lt", 0, "critical", Some("dockyy-shop")).is_ok());
+        assert!(validate_alert_rule("gpu_pct", "gt", 0, "warning", None).is_err());
+        assert!(validate_alert_rule("cpu_pct", "gt", 0, "warning", Some("dockyy-shop")).is_err());
+        assert!(validate_alert_rule("cpu_pct", "eq", 0, "warning", None).is_err());
+        assert!(validate_alert_rule("cpu_pct", "gt", -1, "warning", None).is_err());
+        assert!(validate_alert_rule("cpu_pct", "gt", 0, "panic", None).is_err());
+    }
+}
@@ -8,6 +8,8 @@ mod auth;
 mod db;
 mod routes;
 mod services;
+#[cfg(test)]
+mod test_support;
 
 pub struct AppState {
     pub db: db::Database,
@@ -15,12 +17,15 @@ pub struct AppState {
     pub traefik: services::traefik::TraefikService,
     pub config: AppConfig,
     pub metrics: services::monitor::MetricsState,
+    pub telemetry: services::telemetry::Telemetry,
+    /// Hash of the first-run setup token, until the first admin is created.
+    pub setup_token: std::sync::Mutex<Option<String>>,
+    /// Encrypts repository SSH keys and webhook secrets at rest.
+    pub keyring: services::crypto::Keyring,
 }
 
 pub struct AppConfig {
     pub jwt_secret: String,
-    pub admin_username: String,
-    pub admin_password_hash: String,
     pub host: String,
     pub port: u16,
     pub data_dir: String,
@@ -29,8 +34,38 @@ pub struct AppConfig {
     pub disable_rate_limit: bool,
     /// Absolute path to the git binary.
     pub git_bin: String,
+    /// Host path prefixes that application bind mounts may use.
+    pub bind_allowlist: Vec<String>,
+    /// Deployment images kept per repository for rollback (0 disables cleanup).
+    pub image_retention: usize,
+    /// Disk usage percentage above which dangling images and build cache are pruned.
+    pub image_prune_disk_pct: f64,
+    /// Shell preferred for container exec sessions (falls back to bash, then sh).
+    pub exec_shell: String,
+    /// Bearer token for the Prometheus `/metrics` endpoint (disabled when unset).
+    pub metrics_token: Option<String>,
+    /// Lifetime of access JWTs in minutes.
+    pub access_token_ttl_mins: i64,
+    /// Days a session survives without a refresh.
+    pub refresh_token_ttl_days: i64,
+    /// Single sign-on provider (disabled when `OIDC_ISSUER` is unset).
+    pub oidc: Option<services::oidc::OidcConfig>,
+    /// Whether `/auth/login` accepts passwords.
+    pub password_login: bool,
+    /// Days audit entries are kept (0 keeps them forever).
+    pub audit_retention_days: i64,
+    /// Pull request previews that may exist at once across all repositories
+    /// (0 for no limit).
+    pub max_previews: i64,
 }
 
+/// Example values from `.env.example` and the docs.
+const PLACEHOLDER_JWT_SECRETS: &[&str] = &[
+    "change-me",
+    "change-me-to-a-secure-random-string",
+    "your-secret-here",
+];
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
@@ -48,45 +83,97 @@ async fn main() -> Result<()> {
     let data_dir = std::env::var("DOCKYY_DATA_DIR").unwrap_or_else(|_| "./data".into());
     std::fs::create_dir_all(&data_dir)?;
 
-    let jwt_secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| {
-        tracing::warn!("JWT_SECRET not set, using random secret (sessions will not survive restarts)");
-        uuid::Uuid::new_v4().to_string()
-    });
-
-    let admin_username = std::env::var("ADMIN_USERNAME").unwrap_or_else(|_| "admin".into());
-    let admin_password = std::env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "admin".into());
-    let admin_password_hash = {
-        use argon2::password_hash::SaltString;
-        use argon2::{Argon2, PasswordHasher};
-        let salt = SaltString::generate(argon2::password_hash::rand_core::OsRng);
-        Argon2::default()
-            .hash_password(admin_password.as_bytes(), &salt)
-            .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?
-            .to_string()
-    };
-
     let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".into());
     let port: u16 = std::env::var("PORT")
         .unwrap_or_else(|_| "3000".into())
         .parse()?;
+    // Default credentials are only tolerated when nobody else can connect.
+    let loopback = host == "localhost"
+        || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback());
+
+    // Without JWT_SECRET, a secret is generated once and kept in data_dir so
+    // sessions survive restarts.
+    let jwt_secret = match std::env::var("JWT_SECRET").ok().filter(|s| !s.trim().is_empty()) {
+        Some(secret) => {
+            if !loopback && PLACEHOLDER_JWT_SECRETS.contains(&secret.as_str()) {
+                anyhow::bail!(
+                    "JWT_SECRET is still the example value; unset it to generate one, or bind HOST to 127.0.0.1"
+                );
+            }
+            secret
+        }
+        None => services::secrets::load_or_create(&data_dir, "jwt_secret")?,
+    };
 
     let traefik_http_port: u16 = std::env::var("TRAEFIK_HTTP_PORT")
         .unwrap_or_else(|_| "80".into())
         .parse()?;
 
+    // Like the JWT secret, the encryption key is generated once when unset.
+    let encryption_key = match std::env::var("ENCRYPTION_KEY").ok().filter(|s| !s.trim().is_empty()) {
+        Some(key) => key,
+        None => services::secrets::load_or_create(&data_dir, "encryption_key")?,
+    };
+    let previous_keys: Vec<String> = std::env::var("PREVIOUS_ENCRYPTION_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .collect();
+    let keyring = services::crypto::Keyring::new(&encryption_key, &previous_keys)?;
+
     // Initialize database
     let db_path = format!("{}/dockyy.db", &data_dir);
     let database = db::Database::new(&db_path)?;
     database.run_migrations()?;
+    services::crypto::reencrypt_secrets(&database, &keyring)?;
+
     tracing::info!("Database initialized at {}", db_path);
 
-    // Initialize Docker service
-    let docker = services::docker::DockerService::new().await?;
-    tracing::info!("Docker client connected");
+    // The env credentials only seed the first admin; further users are
+    // managed through the API.
+    let admin_username = std::env::var("ADMIN_USERNAME").unwrap_or_else(|_| "admin".into());
+    let admin_password = std::env::var("ADMIN_PASSWORD").ok().filter(|p| !p.is_empty());
+    if !loopback {
+        if admin_password.as_deref() == Some("admin") {
+            anyhow::bail!("Refusing to start on {} with ADMIN_PASSWORD=admin; choose another password", host);
+        }
+        let defaults = database.admins_with_default_password()?;
+        if !defaults.is_empty() {
+            anyhow::bail!(
+                "Refusing to start on {}: admin account(s) {} still use the password 'admin'. \
+                 Start once with HOST=127.0.0.1 and change it.",
+                host,
+                defaults.join(", ")
+            );
+        }
+    }
+    if let Some(ref password) = admin_password
+        && database.seed_admin_user(&admin_username, password)?
+    {
+        tracing::info!("Created initial admin user '{}'", admin_username);
+    }
 
-    // Initialize Traefik service (shares the Docker socket)
+    // Initialize Docker service (DOCKER_HOST or auto-detected Docker/Podman socket)
+    let endpoint = services::engine::DockerEndpoint::from_env()?;
+    let docker = services::docker::DockerService::new(endpoint, &data_dir).await?;
+    match docker.capabilities().await {
+        Ok(caps) => tracing::info!(
+            engine = %caps.engine,
+            version = caps.version.as_deref().unwrap_or("unknown"),
+            endpoint = %caps.endpoint,
+            networks = caps.networks,
+            compose = caps.compose.as_deref().unwrap_or("unavailable"),
+            "Container engine connected"
+        ),
+        Err(e) => tracing::warn!("Container engine connected but capability probe failed: {}", e),
+    }
+
+    // Initialize Traefik service (shares the engine connection)
     let traefik = services::traefik::TraefikService::new(
-        bollard::Docker::connect_with_local_defaults()?,
+        docker.client(),
+        docker.endpoint().host_socket_path(),
     );
 
     let disable_rate_limit = std::env::var("DISABLE_RATE_LIMIT")
@@ -103,16 +190,89 @@ async fn main() -> Result<()> {
     });
     tracing::info!("Using git binary: {}", git_bin);
 
+    // Bind mounts are opt-in per host path; default to a directory under data_dir.
+    let bind_allowlist: Vec<String> = std::env::var("DOCKYY_BIND_ALLOWLIST")
+        .unwrap_or_else(|_| format!("{}/binds", data_dir))
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            std::fs::canonicalize(p)
+                .map(|c| c.to_string_lossy().to_string())
+                .unwrap_or_else(|_| p.to_string())
+        })
+        .collect();
+
+    let image_retention: usize = std::env::var("IMAGE_RETENTION")
+        .unwrap_or_else(|_| "5".into())
+        .parse()?;
+    let image_prune_disk_pct: f64 = std::env::var("IMAGE_PRUNE_DISK_PCT")
+        .unwrap_or_else(|_| "80".into())
+        .parse()?;
+
+    let exec_shell = std::env::var("EXEC_SHELL").unwrap_or_else(|_| "bash".into());
+
+    let metrics_token = std::env::var("METRICS_TOKEN").ok().filter(|t| !t.is_empty());
+
+    let access_token_ttl_mins: i64 = std::env::var("ACCESS_TOKEN_TTL_MINS")
+        .unwrap_or_else(|_| "15".into())
+        .parse()?;
+    let refresh_token_ttl_days: i64 = std::env::var("REFRESH_TOKEN_TTL_DAYS")
+        .unwrap_or_else(|_| "30".into())
+        .parse()?;
+
+    let audit_retention_days: i64 = std::env::var("AUDIT_RETENTION_DAYS")
+        .unwrap_or_else(|_| "90".into())
+        .parse()?;
+
+    let max_previews: i64 = std::env::var("MAX_PREVIEWS")
+        .unwrap_or_else(|_| "5".into())
+        .parse()?;
+
+    let oidc = services::oidc::OidcConfig::from_env()?;
+    let password_login = !std::env::var("DISABLE_PASSWORD_LOGIN")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    match (&oidc, password_login) {
+        (Some(oidc), _) => tracing::info!("Single sign-on enabled with issuer {}", oidc.issuer),
+        (None, false) => tracing::warn!(
+            "DISABLE_PASSWORD_LOGIN is set without OIDC_ISSUER; nobody can sign in to the dashboard"
+        ),
+        (None, true) => {}
+    }
+
+    // With no accounts, the first admin is created through `/auth/setup`
+    // using a one-time token that only the server's operator can read.
+    let setup_token = if password_login && !database.has_users()? {
+        let token = auth::api_tokens::random_token("")?;
+        tracing::warn!(
+            "No users exist yet. Create the first admin in the dashboard with setup token: {}",
+            token
+        );
+        Some(auth::api_tokens::hash(&token))
+    } else {
+        None
+    };
+
     let config = AppConfig {
         jwt_secret,
-        admin_username,
-        admin_password_hash,
         host: host.clone(),
         port,
         data_dir,
         traefik_http_port,
         disable_rate_limit,
         git_bin,
+        bind_allowlist,
+        image_retention,
+        image_prune_disk_pct,
+        exec_shell,
+        metrics_token,
+        access_token_ttl_mins,
+        refresh_token_ttl_days,
+        oidc,
+        password_login,
+        audit_retention_days,
+        max_previews,
     };
 
     let state = Arc::new(AppState {
@@ -121,6 +281,9 @@ async fn main() -> Result<()> {
         traefik,
         config,
         metrics: services::monitor::new_metrics_state(),
+        telemetry: services::telemetry::Telemetry::default(),
+        setup_token: std::sync::Mutex::new(setup_token),
+        keyring,
     });
 
     // Ensure Traefik sidecar is running (non-fatal — log and continue)
@@ -139,6 +302,15 @@ async fn main() -> Result<()> {
     // Spawn health monitor
     tokio::spawn(services::monitor::run_monitor(state.clone()));
 
+    // Spawn image cleanup
+    tokio::spawn(services::cleanup::run_cleanup(state.clone()));
+
+    // Spawn notification retries
+    tokio::spawn(services::notifications::run_retries(state.clone()));
+
+    // Spawn audit log retention
+    tokio::spawn(services::audit::run_retention(state.clone()));
+
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
@@ -146,6 +318,7 @@ async fn main() -> Result<()> {
 
     let app = Router::new()
         .nest("/api", routes::api_routes(state.clone()))
+        .merge(routes::prometheus::routes())
         .fallback(routes::static_files::serve_static)
         .with_state(state)
         .layer(cors);
@@ -0,0 +1,258 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::db::models::{validate_alert_rule, Alert, AlertRule, CreateAlertRule, UpdateAlertRule};
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/alerts", get(list_alerts))
+        .route("/alerts/rules", get(list_rules).post(create_rule))
+        .route(
+            "/alerts/rules/{id}",
+            get(get_rule).put(update_rule).delete(delete_rule),
+        )
+}
+
+fn load_rule(state: &AppState, id: i64) -> Result<AlertRule, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let rule = conn.query_row(
+                &format!("SELECT {} FROM alert_rules WHERE id = ?1", AlertRule::COLUMNS),
+                [id],
+                AlertRule::from_row,
+            )?;
+            Ok(rule)
+        })
+        .map_err(|_| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Alert rule not found"})),
+            )
+        })
+}
+
+#[derive(Deserialize)]
+struct AlertsQuery {
+    /// `firing` or `resolved`; all when omitted.
+    state: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Alert history, newest first.
+async fn list_alerts(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AlertsQuery>,
+) -> Result<Json<Vec<Alert>>, (StatusCode, Json<Value>)> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM alerts WHERE ?1 IS NULL OR state = ?1
+                 ORDER BY id DESC LIMIT ?2",
+                Alert::COLUMNS
+            ))?;
+            let alerts = stmt
+                .query_map(rusqlite::params![query.state, limit], Alert::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(alerts)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn list_rules(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<AlertRule>>, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM alert_rules ORDER BY id",
+                AlertRule::COLUMNS
+            ))?;
+            let rules = stmt
+                .query_map([], AlertRule::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rules)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn get_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<AlertRule>, (StatusCode, Json<Value>)> {
+    load_rule(&state, id).map(Json)
+}
+
+async fn create_rule(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<CreateAlertRule>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let operator = body.operator.unwrap_or_else(|| "gt".to_string());
+    let duration_secs = body.duration_secs.unwrap_or(0);
+    let severity = body.severity.unwrap_or_else(|| "warning".to_string());
+    validate_alert_rule(
+        &body.metric,
+        &operator,
+        duration_secs,
+        &severity,
+        body.container.as_deref(),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO alert_rules (name, metric, operator, threshold, duration_secs,
+                    container, severity, enabled)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    body.name,
+                    body.metric,
+                    operator,
+                    body.threshold.unwrap_or(0.0),
+                    duration_secs,
+                    body.container,
+                    severity,
+                    body.enabled.unwrap_or(true),
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map(|id| {
+            (
+                StatusCode::CREATED,
+                Json(json!({"id": id, "message": "Alert rule created"})),
+            )
+        })
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn update_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Json(body): Json<UpdateAlertRule>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let rule = load_rule(&state, id)?;
+    validate_alert_rule(
+        &rule.metric,
+        body.operator.as_deref().unwrap_or(&rule.operator),
+        body.duration_secs.unwrap_or(rule.duration_secs),
+        body.severity.as_deref().unwrap_or(&rule.severity),
+        body.container.as_deref(),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut sets = Vec::new();
+            let mut params: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
+
+            if let Some(ref name) = body.name {
+                sets.push("name = ?");
+                params.push(Box::new(name.clone()));
+            }
+            if let Some(ref operator) = body.operator {
+                sets.push("operator = ?");
+                params.push(Box::new(operator.clone()));
+            }
+            if let Some(threshold) = body.threshold {
+                sets.push("threshold = ?");
+                params.push(Box::new(threshold));
+            }
+            if let Some(duration) = body.duration_secs {
+                sets.push("duration_secs = ?");
+                params.push(Box::new(duration));
+            }
+            if let Some(ref container) = body.container {
+                // An empty string clears the container filter.
+                sets.push("container = ?");
+                params.push(Box::new(Some(container.clone()).filter(|c| !c.is_empty())));
+            }
+            if let Some(ref severity) = body.severity {
+                sets.push("severity = ?");
+                params.push(Box::new(severity.clone()));
+            }
+            if let Some(enabled) = body.enabled {
+                sets.push("enabled = ?");
+                params.push(Box::new(enabled));
+            }
+
+            if sets.is_empty() {
+                anyhow::bail!("No fields to update");
+            }
+
+            sets.push("updated_at = datetime('now')");
+            params.push(Box::new(id));
+
+            let sql = format!("UPDATE alert_rules SET {} WHERE id = ?", sets.join(", "));
+            let param_refs: Vec<&dyn rusqlite::types::ToSql> =
+                params.iter().map(|p| p.as_ref()).collect();
+            conn.execute(&sql, param_refs.as_slice())?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Alert rule updated"})))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Delete a rule. Its open alerts are resolved; history is kept.
+async fn delete_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    load_rule(&state, id)?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "UPDATE alerts SET state = 'resolved', resolved_at = datetime('now')
+                 WHERE rule_id = ?1 AND state = 'firing'",
+                [id],
+            )?;
+            conn.execute("DELETE FROM alert_rules WHERE id = ?1", [id])?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Alert rule deleted"})))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
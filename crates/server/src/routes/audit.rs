@@ -0,0 +1,85 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::db::models::AuditEntry;
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/audit", get(list_audit))
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    username: Option<String>,
+    token_id: Option<i64>,
+    /// Substring of the action, e.g. `DELETE` or `/repositories`.
+    action: Option<String>,
+    /// Prefix of the target path.
+    target: Option<String>,
+    /// `success`, `denied` or `failure`.
+    outcome: Option<String>,
+    /// Only entries at or after this UTC time (`YYYY-MM-DD HH:MM:SS`).
+    since: Option<String>,
+    /// Only entries before this UTC time.
+    until: Option<String>,
+    /// Only entries with a smaller id, to page backwards.
+    before: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Audit entries, newest first.
+async fn list_audit(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, Json<Value>)> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM audit_log
+                 WHERE (?1 IS NULL OR username = ?1)
+                   AND (?2 IS NULL OR token_id = ?2)
+                   AND (?3 IS NULL OR instr(action, ?3) > 0)
+                   AND (?4 IS NULL OR substr(target, 1, length(?4)) = ?4)
+                   AND (?5 IS NULL OR outcome = ?5)
+                   AND (?6 IS NULL OR created_at >= ?6)
+                   AND (?7 IS NULL OR created_at < ?7)
+                   AND (?8 IS NULL OR id < ?8)
+                 ORDER BY id DESC LIMIT ?9",
+                AuditEntry::COLUMNS
+            ))?;
+            let entries = stmt
+                .query_map(
+                    rusqlite::params![
+                        query.username,
+                        query.token_id,
+                        query.action,
+                        query.target,
+                        query.outcome,
+                        query.since,
+                        query.until,
+                        query.before,
+                        limit,
+                    ],
+                    AuditEntry::from_row,
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
@@ -1,99 +1,386 @@
 use axum::{
     extract::{ConnectInfo, State},
-    http::StatusCode,
-    routing::post,
+    http::{header, HeaderMap, StatusCode},
+    routing::{get, post},
     Json, Router,
 };
 use serde_json::{json, Value};
 use std::net::SocketAddr;
+use rusqlite::OptionalExtension;
 use std::sync::Arc;
 
-use crate::auth as jwt;
-use crate::db::models::{LoginRequest, LoginResponse};
+use crate::auth::{self as jwt, api_tokens, sessions, totp, Role};
+use crate::db::models::{
+    LoginRequest, LoginResponse, MfaChallenge, MfaLoginRequest, RefreshRequest, SetupRequest,
+};
+use crate::services::audit;
 use crate::AppState;
 
 pub fn routes() -> Router<Arc<AppState>> {
     Router::new()
+        .route("/auth/methods", get(methods))
+        .route("/auth/setup", post(setup))
         .route("/auth/login", post(login))
+        .route("/auth/login/mfa", post(login_mfa))
+        .route("/auth/refresh", post(refresh))
         .route("/auth/verify", post(verify))
 }
 
+/// Access token for a session, wrapped in the login response.
+fn issue_tokens(
+    state: &AppState,
+    session_id: &str,
+    refresh_token: String,
+    username: String,
+    role: Role,
+) -> Result<Json<LoginResponse>, (StatusCode, Json<Value>)> {
+    let ttl = chrono::Duration::minutes(state.config.access_token_ttl_mins);
+    let token = jwt::create_token(&state.config.jwt_secret, &username, role, session_id, ttl)
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to create token"})),
+            )
+        })?;
+
+    Ok(Json(LoginResponse {
+        token,
+        refresh_token,
+        expires_in: ttl.num_seconds(),
+        username,
+        role,
+    }))
+}
+
+/// Sign-in options for the login page.
+async fn methods(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(json!({
+        "password": state.config.password_login,
+        "setup_required": state.setup_token.lock().unwrap().is_some(),
+        "oidc": state.config.oidc.as_ref().map(|oidc| json!({
+            "name": oidc.display_name,
+            "login_url": "/api/auth/oidc/login",
+        })),
+    }))
+}
+
+/// Record a sign-in step in the audit log.
+fn audit_auth(
+    state: &AppState,
+    ip: &str,
+    action: &str,
+    username: Option<&str>,
+    status: StatusCode,
+    reason: Option<&str>,
+) {
+    audit::record(
+        state,
+        audit::Event {
+            username,
+            ip: Some(ip),
+            action,
+            summary: reason.map(|reason| json!({"reason": reason})),
+            outcome: audit::Outcome::from_status(status),
+            status: Some(status),
+            ..Default::default()
+        },
+    );
+}
+
+/// Refuse an IP with too many consecutive failures (skipped when
+/// DISABLE_RATE_LIMIT is set).
+fn check_rate_limit(state: &AppState, ip: &str, action: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    if state.config.disable_rate_limit {
+        return Ok(());
+    }
+    let (attempts, wait_seconds) = state.db.check_login_rate_limit(ip).unwrap_or((0, 0));
+
+    if wait_seconds > 0 {
+        state.telemetry.record_login_failure("rate_limited");
+        tracing::warn!(
+            ip = %ip,
+            attempts = attempts,
+            wait_seconds = wait_seconds,
+            "Login attempt rate-limited"
+        );
+        audit_auth(state, ip, action, None, StatusCode::TOO_MANY_REQUESTS, Some("rate_limited"));
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({
+                "error": format!("Too many login attempts. Try again in {} seconds.", wait_seconds)
+            })),
+        ));
+    }
+    Ok(())
+}
+
+/// Record a successful login and open a session for it.
+fn start_session(
+    state: &AppState,
+    action: &str,
+    ip: &str,
+    headers: &HeaderMap,
+    user_id: i64,
+    username: String,
+    role: Role,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let _ = state.db.record_login_attempt(ip, true);
+    tracing::info!(ip = %ip, username = %username, "Successful login");
+    audit_auth(state, ip, action, Some(&username), StatusCode::OK, None);
+
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    let session = sessions::create(state, user_id, ip, user_agent).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    let Json(response) = issue_tokens(state, &session.id, session.refresh_token, username, role)?;
+    Ok(Json(json!(response)))
+}
+
+/// Password step. Users with TOTP enabled get an [`MfaChallenge`] to
+/// complete at `/auth/login/mfa` instead of a session.
 async fn login(
     State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(body): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, Json<Value>)> {
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if !state.config.password_login {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Password login is disabled; use single sign-on"})),
+        ));
+    }
     let ip = addr.ip().to_string();
+    check_rate_limit(&state, &ip, "login")?;
 
-    // Check rate limit (skipped when DISABLE_RATE_LIMIT is set)
-    if !state.config.disable_rate_limit {
-        let (attempts, wait_seconds) = state
-            .db
-            .check_login_rate_limit(&ip)
-            .unwrap_or((0, 0));
-
-        if wait_seconds > 0 {
-            tracing::warn!(
-                ip = %ip,
-                attempts = attempts,
-                wait_seconds = wait_seconds,
-                "Login attempt rate-limited"
-            );
-            return Err((
-                StatusCode::TOO_MANY_REQUESTS,
-                Json(json!({
-                    "error": format!("Too many login attempts. Try again in {} seconds.", wait_seconds)
-                })),
-            ));
-        }
-    }
+    let user: Option<(i64, String, String, bool)> = state
+        .db
+        .with_conn(|conn| {
+            let user = conn
+                .query_row(
+                    "SELECT id, password_hash, role, totp_enabled FROM users WHERE username = ?1",
+                    [&body.username],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .optional()?;
+            Ok(user)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
 
-    if body.username != state.config.admin_username {
+    let Some((user_id, password_hash, role, totp_enabled)) = user else {
         let _ = state.db.record_login_attempt(&ip, false);
+        state.telemetry.record_login_failure("invalid_username");
         tracing::warn!(ip = %ip, username = %body.username, "Failed login attempt: invalid username");
+        audit_auth(&state, &ip, "login", Some(&body.username), StatusCode::UNAUTHORIZED, Some("invalid_username"));
         return Err((
             StatusCode::UNAUTHORIZED,
             Json(json!({"error": "Invalid credentials"})),
         ));
-    }
-
-    let valid = {
-        use argon2::{Argon2, PasswordVerifier, PasswordHash};
-        match PasswordHash::new(&state.config.admin_password_hash) {
-            Ok(parsed_hash) => Argon2::default()
-                .verify_password(body.password.as_bytes(), &parsed_hash)
-                .is_ok(),
-            Err(e) => {
-                tracing::error!(ip = %ip, username = %body.username, error = %e, "password hash parse failed");
-                false
-            }
-        }
     };
 
-    if !valid {
+    if !jwt::verify_password(&password_hash, &body.password) {
         let _ = state.db.record_login_attempt(&ip, false);
+        state.telemetry.record_login_failure("invalid_password");
         tracing::warn!(ip = %ip, username = %body.username, "Failed login attempt: invalid password");
+        audit_auth(&state, &ip, "login", Some(&body.username), StatusCode::UNAUTHORIZED, Some("invalid_password"));
         return Err((
             StatusCode::UNAUTHORIZED,
             Json(json!({"error": "Invalid credentials"})),
         ));
     }
+    let role = Role::parse(&role).unwrap_or(Role::Viewer);
 
-    let _ = state.db.record_login_attempt(&ip, true);
-    tracing::info!(ip = %ip, username = %body.username, "Successful login");
+    if totp_enabled {
+        // Not recorded as a success yet: that would reset the failure count
+        // and let the code be guessed indefinitely.
+        let mfa_token = totp::create_mfa_token(&state.config.jwt_secret, &body.username)
+            .map_err(|_| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to create token"})),
+                )
+            })?;
+        return Ok(Json(json!(MfaChallenge {
+            mfa_required: true,
+            mfa_token,
+            expires_in: totp::MFA_TOKEN_TTL_MINS * 60,
+        })));
+    }
+
+    start_session(&state, "login", &ip, &headers, user_id, body.username, role)
+}
 
-    let token = jwt::create_token(&state.config.jwt_secret, &body.username)
+/// Second step for users with TOTP: a current code or a recovery code.
+async fn login_mfa(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<MfaLoginRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let ip = addr.ip().to_string();
+    check_rate_limit(&state, &ip, "login.mfa")?;
+
+    let username = totp::verify_mfa_token(&state.config.jwt_secret, &body.mfa_token)
         .map_err(|_| {
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Login expired; sign in again"})),
+            )
+        })?;
+    let internal = |e: anyhow::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    };
+
+    let user: Option<(i64, String)> = state
+        .db
+        .with_conn(|conn| {
+            let user = conn
+                .query_row(
+                    "SELECT id, role FROM users WHERE username = ?1 AND totp_enabled = 1",
+                    [&username],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            Ok(user)
+        })
+        .map_err(internal)?;
+    let Some((user_id, role)) = user else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Login expired; sign in again"})),
+        ));
+    };
+
+    if !totp::check_user_code(&state, user_id, &body.code, true).map_err(internal)? {
+        let _ = state.db.record_login_attempt(&ip, false);
+        state.telemetry.record_login_failure("invalid_mfa_code");
+        tracing::warn!(ip = %ip, username = %username, "Failed login attempt: invalid MFA code");
+        audit_auth(&state, &ip, "login.mfa", Some(&username), StatusCode::UNAUTHORIZED, Some("invalid_mfa_code"));
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid code"})),
+        ));
+    }
+    let role = Role::parse(&role).unwrap_or(Role::Viewer);
+
+    start_session(&state, "login.mfa", &ip, &headers, user_id, username, role)
+}
+
+/// First-run setup: create the initial admin with the token printed to the
+/// server log, and sign them in.
+async fn setup(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<SetupRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let ip = addr.ip().to_string();
+    check_rate_limit(&state, &ip, "setup")?;
+
+    let Some(expected) = state.setup_token.lock().unwrap().clone() else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Setup is already complete"})),
+        ));
+    };
+    if api_tokens::hash(body.token.trim()) != expected {
+        let _ = state.db.record_login_attempt(&ip, false);
+        state.telemetry.record_login_failure("invalid_setup_token");
+        tracing::warn!(ip = %ip, "Failed setup attempt: invalid setup token");
+        audit_auth(&state, &ip, "setup", None, StatusCode::UNAUTHORIZED, Some("invalid_setup_token"));
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid setup token"})),
+        ));
+    }
+
+    let username = body.username.trim().to_string();
+    if username.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Username is required"})),
+        ));
+    }
+    crate::routes::users::validate_password(&body.password)?;
+    let password_hash = jwt::hash_password(&body.password).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    // Conditional so only the first of two concurrent requests wins.
+    let user_id: Option<i64> = state
+        .db
+        .with_conn(|conn| {
+            let id = conn
+                .query_row(
+                    "INSERT INTO users (username, password_hash, role)
+                     SELECT ?1, ?2, 'admin' WHERE NOT EXISTS (SELECT 1 FROM users)
+                     RETURNING id",
+                    rusqlite::params![username, password_hash],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(id)
+        })
+        .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to create token"})),
+                Json(json!({"error": e.to_string()})),
             )
         })?;
+    *state.setup_token.lock().unwrap() = None;
+    let Some(user_id) = user_id else {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Setup is already complete"})),
+        ));
+    };
+    tracing::info!(ip = %ip, username = %username, "Created initial admin user through setup");
 
-    Ok(Json(LoginResponse {
-        token,
-        username: body.username,
-    }))
+    start_session(&state, "setup", &ip, &headers, user_id, username, Role::Admin)
+}
+
+/// Trade a refresh token for a new access token and refresh token. The old
+/// refresh token stops working.
+async fn refresh(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<RefreshRequest>,
+) -> Result<Json<LoginResponse>, (StatusCode, Json<Value>)> {
+    let refreshed = sessions::refresh(&state, &body.refresh_token)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid or expired refresh token"})),
+        ))?;
+
+    issue_tokens(
+        &state,
+        &refreshed.session_id,
+        refreshed.refresh_token,
+        refreshed.username,
+        refreshed.role,
+    )
 }
 
 async fn verify(
@@ -104,11 +391,19 @@ async fn verify(
         .as_str()
         .ok_or((StatusCode::BAD_REQUEST, Json(json!({"error": "Missing token"}))))?;
 
-    let claims = jwt::verify_token(&state.config.jwt_secret, token)
-        .map_err(|_| (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid token"}))))?;
+    let invalid = || (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid token"})));
+    let claims = jwt::verify_token(&state.config.jwt_secret, token).map_err(|_| invalid())?;
+    let active = claims
+        .sid
+        .as_deref()
+        .is_some_and(|sid| sessions::is_active(&state, sid).unwrap_or(false));
+    if !active {
+        return Err(invalid());
+    }
 
     Ok(Json(json!({
         "valid": true,
-        "username": claims.sub
+        "username": claims.sub,
+        "role": claims.role,
     })))
 }
@@ -1,15 +1,29 @@
 use axum::{
+    body::Body,
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
     routing::{delete, get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
+use futures_util::stream::{Stream, StreamExt};
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::convert::Infallible;
 use std::sync::Arc;
 
+use crate::auth::{Access, Role};
+use crate::db::models::LogLine;
+use crate::services::docker::LogStreamOptions;
 use crate::AppState;
 
+/// Lines buffered between the Docker log reader and a slow client. When full,
+/// the reader stops pulling from Docker until the client catches up.
+const LOG_CHANNEL_CAPACITY: usize = 256;
+
 pub fn routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/containers", get(list_containers))
@@ -18,6 +32,22 @@ pub fn routes() -> Router<Arc<AppState>> {
         .route("/containers/{id}/restart", post(restart_container))
         .route("/containers/{id}", delete(remove_container))
         .route("/containers/{id}/logs", get(get_logs))
+        .route("/containers/{id}/logs/stream", get(stream_logs))
+        .route("/containers/{id}/logs/download", get(download_logs))
+        .route("/docker/capabilities", get(engine_capabilities))
+}
+
+/// Report the connected engine (Docker or Podman) and which features it supports.
+async fn engine_capabilities(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let caps = state.docker.capabilities().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    Ok(Json(json!(caps)))
 }
 
 #[derive(Deserialize)]
@@ -27,6 +57,7 @@ struct ListQuery {
 
 async fn list_containers(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
     Query(query): Query<ListQuery>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
     let all = query.all.unwrap_or(true);
@@ -41,13 +72,47 @@ async fn list_containers(
             )
         })?;
 
+    // Scoped users only see containers of repositories granted to them.
+    let containers: Vec<_> = containers
+        .into_iter()
+        .filter(|c| access.can_see_labels(&c.labels))
+        .collect();
+
     Ok(Json(json!(containers)))
 }
 
+/// Refuse changes to container `id` unless `access` may change it: containers
+/// no repository owns, including the proxy, are admin-only.
+pub(crate) async fn check_can_change(
+    state: &AppState,
+    access: &Access,
+    id: &str,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    if access.role == Role::Admin {
+        return Ok(());
+    }
+    let info = state.docker.inspect_container(id).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let labels = info.config.and_then(|c| c.labels).unwrap_or_default();
+    if !access.can_change_labels(&labels) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only admins can change containers that don't belong to a repository"})),
+        ));
+    }
+    Ok(())
+}
+
 async fn start_container(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
     Path(id): Path<String>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    check_can_change(&state, &access, &id).await?;
     state.docker.start_container(&id).await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -59,8 +124,10 @@ async fn start_container(
 
 async fn stop_container(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
     Path(id): Path<String>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    check_can_change(&state, &access, &id).await?;
     state.docker.stop_container(&id).await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -72,8 +139,10 @@ async fn stop_container(
 
 async fn restart_container(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
     Path(id): Path<String>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    check_can_change(&state, &access, &id).await?;
     state.docker.restart_container(&id).await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -85,8 +154,10 @@ async fn restart_container(
 
 async fn remove_container(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
     Path(id): Path<String>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    check_can_change(&state, &access, &id).await?;
     state
         .docker
         .remove_container(&id, true)
@@ -124,3 +195,291 @@ async fn get_logs(
 
     Ok(Json(json!({"logs": logs})))
 }
+
+#[derive(Deserialize)]
+struct LogStreamQuery {
+    /// Unix seconds, RFC 3339, or a relative duration such as `15m`, `2h`, `7d`.
+    since: Option<String>,
+    until: Option<String>,
+    tail: Option<usize>,
+    /// Keep following new output (stream only; default true).
+    follow: Option<bool>,
+    timestamps: Option<bool>,
+    stdout: Option<bool>,
+    stderr: Option<bool>,
+    /// Only lines containing this substring.
+    grep: Option<String>,
+    ignore_case: Option<bool>,
+}
+
+/// Parse a `since`/`until` value into a Unix timestamp.
+pub(crate) fn parse_log_time(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if let Ok(ts) = value.parse::<i64>() {
+        return Ok(ts);
+    }
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.timestamp());
+    }
+
+    let Some(unit) = value.chars().last() else {
+        return Err("Empty time value".to_string());
+    };
+    let invalid = || format!("Invalid time '{}'", value);
+    let num = value[..value.len() - unit.len_utf8()]
+        .parse::<i64>()
+        .map_err(|_| invalid())?;
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return Err(invalid()),
+    };
+    num.checked_mul(scale)
+        .and_then(|secs| chrono::Utc::now().timestamp().checked_sub(secs))
+        .ok_or_else(invalid)
+}
+
+struct LineFilter {
+    needle: Option<String>,
+    ignore_case: bool,
+}
+
+impl LineFilter {
+    fn matches(&self, line: &LogLine) -> bool {
+        match &self.needle {
+            None => true,
+            Some(needle) if self.ignore_case => line.line.to_lowercase().contains(needle),
+            Some(needle) => line.line.contains(needle.as_str()),
+        }
+    }
+}
+
+impl LogStreamQuery {
+    fn options(&self, follow_default: bool) -> Result<(LogStreamOptions, LineFilter), (StatusCode, Json<Value>)> {
+        let parse = |v: &Option<String>| {
+            v.as_deref()
+                .map(parse_log_time)
+                .transpose()
+                .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))
+        };
+
+        let ignore_case = self.ignore_case.unwrap_or(false);
+        let options = LogStreamOptions {
+            follow: self.follow.unwrap_or(follow_default),
+            since: parse(&self.since)?,
+            until: parse(&self.until)?,
+            tail: self.tail,
+            timestamps: self.timestamps.unwrap_or(true),
+            stdout: self.stdout.unwrap_or(true),
+            stderr: self.stderr.unwrap_or(true),
+        };
+        let filter = LineFilter {
+            needle: self
+                .grep
+                .as_ref()
+                .filter(|g| !g.is_empty())
+                .map(|g| if ignore_case { g.to_lowercase() } else { g.clone() }),
+            ignore_case,
+        };
+        Ok((options, filter))
+    }
+}
+
+/// Follow container logs as Server-Sent Events. Each event's data is a JSON
+/// [`LogLine`]; a `done` event is sent when the container stops or `until` is
+/// reached.
+async fn stream_logs(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<LogStreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Value>)> {
+    let (options, filter) = query.options(true)?;
+    state.docker.inspect_container(&id).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Result<LogLine, String>>(LOG_CHANNEL_CAPACITY);
+    let logs = state.docker.stream_logs(&id, options);
+
+    tokio::spawn(async move {
+        futures_util::pin_mut!(logs);
+        loop {
+            // A quiet container (or a filter nothing matches) may not yield a
+            // line for hours, so watch for the client going away as well;
+            // dropping the Docker stream ends the follow.
+            let item = tokio::select! {
+                item = logs.next() => item,
+                _ = tx.closed() => return,
+            };
+            let Some(item) = item else {
+                return;
+            };
+            let item = match item {
+                Ok(line) if !filter.matches(&line) => continue,
+                Ok(line) => Ok(line),
+                Err(e) => Err(e.to_string()),
+            };
+            let failed = item.is_err();
+            if tx.send(item).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    let stream = async_stream::stream! {
+        while let Some(item) = rx.recv().await {
+            match item {
+                Ok(line) => match Event::default().json_data(&line) {
+                    Ok(event) => yield Ok::<_, Infallible>(event),
+                    Err(e) => tracing::debug!("Skipping unserialisable log line: {}", e),
+                },
+                Err(e) => {
+                    yield Ok(Event::default().event("error").data(e));
+                    return;
+                }
+            }
+        }
+        yield Ok(Event::default().event("done").data("Log stream ended"));
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Download a (possibly large) range of logs as a plain-text file, streamed
+/// without buffering it in memory.
+async fn download_logs(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<LogStreamQuery>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let (mut options, filter) = query.options(false)?;
+    options.follow = false;
+
+    let info = state.docker.inspect_container(&id).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let name = info
+        .name
+        .map(|n| n.trim_start_matches('/').to_string())
+        .unwrap_or_else(|| id.clone());
+
+    let logs = state.docker.stream_logs(&id, options);
+    let body = async_stream::stream! {
+        futures_util::pin_mut!(logs);
+        while let Some(item) = logs.next().await {
+            match item {
+                Ok(line) if filter.matches(&line) => {
+                    let text = match &line.timestamp {
+                        Some(ts) => format!("{} [{}] {}\n", ts, line.stream, line.line),
+                        None => format!("[{}] {}\n", line.stream, line.line),
+                    };
+                    yield Ok::<_, std::io::Error>(text);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    yield Err(std::io::Error::other(e.to_string()));
+                    return;
+                }
+            }
+        }
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}-logs.txt\"", name),
+        )
+        .body(Body::from_stream(body))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_log_times() {
+        assert_eq!(parse_log_time("1700000000"), Ok(1_700_000_000));
+        assert_eq!(parse_log_time("2023-11-14T22:13:20Z"), Ok(1_700_000_000));
+
+        let now = chrono::Utc::now().timestamp();
+        for (value, secs) in [("30s", 30), ("5m", 300), ("2h", 7200), ("1d", 86400)] {
+            let ts = parse_log_time(value).unwrap();
+            assert!((now - secs..=now - secs + 5).contains(&ts), "{}", value);
+        }
+
+        for value in ["", "m", "5x", "1.5h", "soon", "9223372036854775807m", "-9223372036854775807d", "-9223372036854775807s"] {
+            assert!(parse_log_time(value).is_err(), "{}", value);
+        }
+    }
+
+    #[tokio::test]
+    async fn roles_gate_writes_and_shared_containers() {
+        use crate::test_support::{self, call, MockDocker};
+        use axum::http::Method;
+
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let repo = test_support::add_repo(&state, "shop");
+        let repo_id = repo.to_string();
+        docker.add_container("shop", &[("dockyy.repo_id", &repo_id)]);
+        docker.add_container("dockyy-traefik", &[("dockyy.managed", "true")]);
+        docker.add_container("postgres", &[]);
+        let mut tokens = std::collections::HashMap::new();
+        for (name, role) in [("viewer", Role::Viewer), ("deployer", Role::Deployer), ("admin", Role::Admin)] {
+            test_support::add_user(&state, name, role, None);
+            tokens.insert(name, test_support::login(&state, name));
+        }
+
+        for (user, read, write) in [
+            ("viewer", StatusCode::OK, StatusCode::FORBIDDEN),
+            ("deployer", StatusCode::OK, StatusCode::OK),
+            ("admin", StatusCode::OK, StatusCode::OK),
+        ] {
+            let token = Some(tokens[user].as_str());
+            let (status, _) = call(&app, Method::GET, "/api/repositories", token, None).await;
+            assert_eq!(status, read, "{} reading", user);
+            let (status, _) = call(&app, Method::POST, "/api/containers/shop/restart", token, None).await;
+            assert_eq!(status, write, "{} restarting", user);
+        }
+
+        // The proxy and containers started outside dockyy are admin-only.
+        let deployer = Some(tokens["deployer"].as_str());
+        let admin = Some(tokens["admin"].as_str());
+        for (method, uri, body) in [
+            (Method::POST, "/api/containers/dockyy-traefik/stop", None),
+            (Method::POST, "/api/containers/postgres/restart", None),
+            (Method::DELETE, "/api/containers/postgres", None),
+            (Method::POST, "/api/networks/backend/connect", Some(json!({"container": "postgres"}))),
+            (Method::POST, "/api/images/prune", None),
+            (Method::POST, "/api/images/cleanup", None),
+        ] {
+            let (status, _) = call(&app, method, uri, deployer, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", uri);
+        }
+        let (status, _) = call(&app, Method::POST, "/api/containers/dockyy-traefik/stop", admin, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let connect = Some(json!({"container": "postgres"}));
+        let (status, _) = call(&app, Method::POST, "/api/networks/backend/connect", admin, connect).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, Method::DELETE, "/api/containers/postgres", admin, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
@@ -2,11 +2,12 @@ use axum::{
     extract::{Path, State},
     http::StatusCode,
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde_json::{json, Value};
 use std::sync::Arc;
 
+use crate::auth::Access;
 use crate::db::models::Deployment;
 use crate::AppState;
 
@@ -20,32 +21,27 @@ pub fn routes() -> Router<Arc<AppState>> {
 
 async fn list_deployments(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
 ) -> Result<Json<Vec<Deployment>>, (StatusCode, Json<Value>)> {
+    // Scoped users get the latest 50 of their repositories, not a filtered
+    // subset of the global latest 50.
+    let repo_ids = access
+        .repos
+        .as_ref()
+        .map(|grants| json!(grants.keys().collect::<Vec<_>>()).to_string());
+
     state
         .db
         .with_conn(|conn| {
-            let mut stmt = conn.prepare(
-                "SELECT id, repo_id, status, commit_sha, image_name, container_id,
-                        domain, port, build_log, created_at, updated_at
-                 FROM deployments ORDER BY created_at DESC LIMIT 50",
-            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM deployments
+                 WHERE ?1 IS NULL OR repo_id IN (SELECT value FROM json_each(?1))
+                 ORDER BY created_at DESC LIMIT 50",
+                Deployment::COLUMNS
+            ))?;
 
             let deployments = stmt
-                .query_map([], |row| {
-                    Ok(Deployment {
-                        id: row.get(0)?,
-                        repo_id: row.get(1)?,
-                        status: row.get(2)?,
-                        commit_sha: row.get(3)?,
-                        image_name: row.get(4)?,
-                        container_id: row.get(5)?,
-                        domain: row.get(6)?,
-                        port: row.get(7)?,
-                        build_log: row.get(8)?,
-                        created_at: row.get(9)?,
-                        updated_at: row.get(10)?,
-                    })
-                })?
+                .query_map([repo_ids], Deployment::from_row)?
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(deployments)
@@ -66,28 +62,13 @@ async fn list_by_repo(
     state
         .db
         .with_conn(|conn| {
-            let mut stmt = conn.prepare(
-                "SELECT id, repo_id, status, commit_sha, image_name, container_id,
-                        domain, port, build_log, created_at, updated_at
-                 FROM deployments WHERE repo_id = ?1 ORDER BY created_at DESC LIMIT 20",
-            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM deployments WHERE repo_id = ?1 ORDER BY created_at DESC LIMIT 20",
+                Deployment::COLUMNS
+            ))?;
 
             let deployments = stmt
-                .query_map([repo_id], |row| {
-                    Ok(Deployment {
-                        id: row.get(0)?,
-                        repo_id: row.get(1)?,
-                        status: row.get(2)?,
-                        commit_sha: row.get(3)?,
-                        image_name: row.get(4)?,
-                        container_id: row.get(5)?,
-                        domain: row.get(6)?,
-                        port: row.get(7)?,
-                        build_log: row.get(8)?,
-                        created_at: row.get(9)?,
-                        updated_at: row.get(10)?,
-                    })
-                })?
+                .query_map([repo_id], Deployment::from_row)?
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(deployments)
@@ -109,25 +90,9 @@ async fn get_deployment(
         .db
         .with_conn(|conn| {
             let deployment = conn.query_row(
-                "SELECT id, repo_id, status, commit_sha, image_name, container_id,
-                        domain, port, build_log, created_at, updated_at
-                 FROM deployments WHERE id = ?1",
+                &format!("SELECT {} FROM deployments WHERE id = ?1", Deployment::COLUMNS),
                 [id],
-                |row| {
-                    Ok(Deployment {
-                        id: row.get(0)?,
-                        repo_id: row.get(1)?,
-                        status: row.get(2)?,
-                        commit_sha: row.get(3)?,
-                        image_name: row.get(4)?,
-                        container_id: row.get(5)?,
-                        domain: row.get(6)?,
-                        port: row.get(7)?,
-                        build_log: row.get(8)?,
-                        created_at: row.get(9)?,
-                        updated_at: row.get(10)?,
-                    })
-                },
+                Deployment::from_row,
             )?;
             Ok(deployment)
         })
@@ -144,16 +109,18 @@ async fn redeploy(
     State(state): State<Arc<AppState>>,
     Path(id): Path<i64>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    // Get the original deployment's repo_id, then create a new job
-    let repo_id = state
+    // Get the original deployment's repo and environment, then create a new job
+    let (repo_id, environment) = state
         .db
         .with_conn(|conn| {
-            let repo_id: i64 = conn.query_row(
-                "SELECT repo_id FROM deployments WHERE id = ?1",
+            let target = conn.query_row(
+                "SELECT d.repo_id, e.name FROM deployments d
+                 LEFT JOIN environments e ON e.id = d.environment_id
+                 WHERE d.id = ?1",
                 [id],
-                |row| row.get(0),
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
             )?;
-            Ok(repo_id)
+            Ok(target)
         })
         .map_err(|e| {
             (
@@ -166,7 +133,7 @@ async fn redeploy(
     let job_id = state
         .db
         .with_conn(|conn| {
-            let payload = json!({"repo_id": repo_id}).to_string();
+            let payload = json!({"repo_id": repo_id, "environment": environment}).to_string();
             conn.execute(
                 "INSERT INTO jobs (job_type, payload, status) VALUES ('deploy', ?1, 'pending')",
                 [&payload],
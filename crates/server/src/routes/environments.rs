@@ -0,0 +1,474 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::db::models::{
+    CreateEnvironment, Deployment, Environment, PromoteRequest, UpdateEnvironment,
+    DEFAULT_ENVIRONMENT,
+};
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/repositories/{id}/environments",
+            get(list_environments).post(create_environment),
+        )
+        .route(
+            "/repositories/{id}/environments/{env}",
+            get(get_environment)
+                .put(update_environment)
+                .delete(delete_environment),
+        )
+        .route(
+            "/repositories/{id}/environments/{env}/deployments",
+            get(list_environment_deployments),
+        )
+        .route("/repositories/{id}/environments/{env}/deploy", post(deploy_environment))
+        .route("/repositories/{id}/environments/{env}/promote", post(promote_environment))
+}
+
+/// Environment names end up in container names and image tags, so keep them
+/// to lowercase alphanumerics and dashes, and away from the tags other
+/// images of the repository use (`latest`, `pr-<n>`, `d<deployment id>`).
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-');
+    if !valid {
+        return Err("Environment name must be lowercase letters, digits or '-'".into());
+    }
+    let deployment_tag = name
+        .strip_prefix('d')
+        .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()));
+    if name == "latest" || name == DEFAULT_ENVIRONMENT || name.starts_with("pr-") || deployment_tag {
+        return Err(format!("Environment name '{}' is reserved", name));
+    }
+    Ok(())
+}
+
+/// Check an environment's memory/CPU overrides together with the
+/// repository's other limits, e.g. a memory reservation above the override.
+fn validate_limits(
+    state: &AppState,
+    repo_id: i64,
+    memory_limit: Option<i64>,
+    cpu_limit: Option<f64>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let repo = crate::services::worker::load_repository(state, repo_id).map_err(|e| {
+        (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()})))
+    })?;
+    let mut limits = repo.resource_limits.unwrap_or_default();
+    limits.memory_limit = memory_limit.or(limits.memory_limit);
+    limits.cpus = cpu_limit.or(limits.cpus);
+    limits
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))
+}
+
+fn load(state: &AppState, repo_id: i64, name: &str) -> Result<Environment, (StatusCode, Json<Value>)> {
+    crate::services::worker::load_environment(state, repo_id, name)
+        .map_err(|e| (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()}))))
+}
+
+async fn list_environments(
+    State(state): State<Arc<AppState>>,
+    Path(repo_id): Path<i64>,
+) -> Result<Json<Vec<Environment>>, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM environments WHERE repo_id = ?1
+                 ORDER BY name = ?2 DESC, name ASC",
+                Environment::COLUMNS
+            ))?;
+
+            let envs = stmt
+                .query_map(rusqlite::params![repo_id, DEFAULT_ENVIRONMENT], Environment::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(envs)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn get_environment(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, name)): Path<(i64, String)>,
+) -> Result<Json<Environment>, (StatusCode, Json<Value>)> {
+    crate::services::worker::find_environment(&state, repo_id, &name)
+        .map(Json)
+        .map_err(|e| (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()}))))
+}
+
+async fn create_environment(
+    State(state): State<Arc<AppState>>,
+    Path(repo_id): Path<i64>,
+    Json(body): Json<CreateEnvironment>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    validate_name(&body.name)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    validate_limits(&state, repo_id, body.memory_limit, body.cpu_limit)?;
+
+    let env_vars = body.env_vars.as_ref().map(|env| json!(env).to_string());
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO environments (repo_id, name, branch, domain, proxy_port,
+                    env_vars, memory_limit, cpu_limit)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    repo_id,
+                    body.name,
+                    body.branch,
+                    body.domain,
+                    body.proxy_port,
+                    env_vars,
+                    body.memory_limit,
+                    body.cpu_limit,
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map(|id| {
+            (
+                StatusCode::CREATED,
+                Json(json!({"id": id, "message": "Environment created"})),
+            )
+        })
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn update_environment(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, name)): Path<(i64, String)>,
+    Json(body): Json<UpdateEnvironment>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let env = load(&state, repo_id, &name)?;
+    validate_limits(
+        &state,
+        repo_id,
+        body.memory_limit.unwrap_or(env.memory_limit),
+        body.cpu_limit.unwrap_or(env.cpu_limit),
+    )?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut sets = Vec::new();
+            let mut params: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
+
+            if let Some(ref branch) = body.branch {
+                sets.push("branch = ?");
+                params.push(Box::new(branch.clone()));
+            }
+            if let Some(ref d) = body.domain {
+                sets.push("domain = ?");
+                params.push(Box::new(d.clone()));
+            }
+            if let Some(pp) = body.proxy_port {
+                sets.push("proxy_port = ?");
+                params.push(Box::new(pp));
+            }
+            if let Some(ref vars) = body.env_vars {
+                sets.push("env_vars = ?");
+                params.push(Box::new(json!(vars).to_string()));
+            }
+            if let Some(mem) = body.memory_limit {
+                sets.push("memory_limit = ?");
+                params.push(Box::new(mem));
+            }
+            if let Some(cpu) = body.cpu_limit {
+                sets.push("cpu_limit = ?");
+                params.push(Box::new(cpu));
+            }
+
+            if sets.is_empty() {
+                anyhow::bail!("No fields to update");
+            }
+
+            sets.push("updated_at = datetime('now')");
+            params.push(Box::new(env.id));
+
+            let sql = format!("UPDATE environments SET {} WHERE id = ?", sets.join(", "));
+            let param_refs: Vec<&dyn rusqlite::types::ToSql> =
+                params.iter().map(|p| p.as_ref()).collect();
+            conn.execute(&sql, param_refs.as_slice())?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Environment updated"})))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn delete_environment(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, name)): Path<(i64, String)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if name == DEFAULT_ENVIRONMENT {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "The default environment cannot be deleted"})),
+        ));
+    }
+
+    let env = load(&state, repo_id, &name)?;
+    let repo = crate::services::worker::load_repository(&state, repo_id).map_err(|e| {
+        (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()})))
+    })?;
+
+    // Stop the environment's container before forgetting about it.
+    let container_name = env.container_name(&repo);
+    let _ = state.docker.stop_container(&container_name).await;
+    let _ = state.docker.remove_container(&container_name, true).await;
+    crate::services::worker::retire_legacy_container(&state, &repo, &env).await;
+    let _ = std::fs::remove_dir_all(env.checkout_dir(&state.config.data_dir));
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute("DELETE FROM environments WHERE id = ?1", [env.id])?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Environment deleted"})))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn list_environment_deployments(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, name)): Path<(i64, String)>,
+) -> Result<Json<Vec<Deployment>>, (StatusCode, Json<Value>)> {
+    let env = load(&state, repo_id, &name)?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM deployments WHERE environment_id = ?1
+                 ORDER BY created_at DESC LIMIT 20",
+                Deployment::COLUMNS
+            ))?;
+
+            let deployments = stmt
+                .query_map([env.id], Deployment::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(deployments)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+fn queue_deploy(state: &AppState, payload: Value) -> Result<i64, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO jobs (job_type, payload, status) VALUES ('deploy', ?1, 'pending')",
+                [payload.to_string()],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn deploy_environment(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, name)): Path<(i64, String)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let env = load(&state, repo_id, &name)?;
+    let job_id = queue_deploy(&state, json!({"repo_id": repo_id, "environment": env.name}))?;
+
+    Ok(Json(json!({"message": "Deployment queued", "job_id": job_id})))
+}
+
+/// Deploy the image currently running in `{env}` to the target environment
+/// (production by default) without rebuilding it.
+async fn promote_environment(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, name)): Path<(i64, String)>,
+    Json(body): Json<PromoteRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let target = body.target.unwrap_or_else(|| DEFAULT_ENVIRONMENT.to_string());
+    if target == name {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Source and target environments are the same"})),
+        ));
+    }
+
+    let source = load(&state, repo_id, &name)?;
+    let target_env = load(&state, repo_id, &target)?;
+    let repo = crate::services::worker::load_repository(&state, repo_id).map_err(|e| {
+        (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()})))
+    })?;
+
+    // Resolve the exact image id so a concurrent rebuild of the source can't race us.
+    let info = state
+        .docker
+        .inspect_container(&source.container_name(&repo))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::CONFLICT,
+                Json(json!({"error": format!("Environment '{}' has no running container: {}", name, e)})),
+            )
+        })?;
+    let image = info.image.ok_or_else(|| {
+        (
+            StatusCode::CONFLICT,
+            Json(json!({"error": format!("Environment '{}' has no image", name)})),
+        )
+    })?;
+
+    let job_id = queue_deploy(
+        &state,
+        json!({
+            "repo_id": repo_id,
+            "environment": target_env.name,
+            "image": image,
+            "promoted_from": source.name,
+        }),
+    )?;
+
+    Ok(Json(json!({
+        "message": format!("Promotion from '{}' to '{}' queued", source.name, target_env.name),
+        "job_id": job_id,
+        "image": image,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::{json, Value};
+
+    use crate::auth::Role;
+    use crate::test_support::{self, call, MockDocker};
+
+    #[tokio::test]
+    async fn limit_overrides_are_validated_and_clearable() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let repo = test_support::add_repo(&state, "shop");
+        test_support::add_user(&state, "root", Role::Admin, None);
+        let token = test_support::login(&state, "root");
+        let envs = format!("/api/repositories/{}/environments", repo);
+        let staging = format!("{}/staging", envs);
+
+        for bad in [json!({"memory_limit": -1}), json!({"memory_limit": 0}), json!({"cpu_limit": 0.0})] {
+            let mut body = bad.clone();
+            body["name"] = json!("staging");
+            let (status, _) = call(&app, Method::POST, &envs, Some(&token), Some(body)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", bad);
+        }
+        let (status, _) = call(
+            &app,
+            Method::POST,
+            &envs,
+            Some(&token),
+            Some(json!({"name": "staging", "memory_limit": 268435456, "cpu_limit": 0.5})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Overrides are checked against the repository's other limits.
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute(
+                    "UPDATE repositories SET resource_limits = ?1",
+                    [json!({"memory_reservation": 536870912}).to_string()],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+        let (status, body) = call(&app, Method::PUT, &staging, Some(&token), Some(json!({"cpu_limit": 1.0}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("memory_reservation"));
+        let (status, _) = call(&app, Method::PUT, &staging, Some(&token), Some(json!({"cpu_limit": -2.0}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // `null` falls back to the repository's limits; omitted fields stay.
+        let (status, _) = call(&app, Method::PUT, &staging, Some(&token), Some(json!({"memory_limit": null}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, env) = call(&app, Method::GET, &staging, Some(&token), None).await;
+        assert_eq!(env["memory_limit"], Value::Null);
+        assert_eq!(env["cpu_limit"], json!(0.5));
+        let (status, _) = call(&app, Method::PUT, &staging, Some(&token), Some(json!({"cpu_limit": null}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, env) = call(&app, Method::GET, &staging, Some(&token), None).await;
+        assert_eq!(env["cpu_limit"], Value::Null);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    #[tokio::test]
+    async fn reading_an_environment_creates_nothing() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let repo = test_support::add_repo(&state, "shop");
+        test_support::add_user(&state, "root", Role::Admin, None);
+        let token = test_support::login(&state, "root");
+        let count = || -> i64 {
+            state
+                .db
+                .with_conn(|conn| Ok(conn.query_row("SELECT COUNT(*) FROM environments", [], |row| row.get(0))?))
+                .unwrap()
+        };
+        state
+            .db
+            .with_conn(|conn| Ok(conn.execute("DELETE FROM environments", [])?))
+            .unwrap();
+
+        let path = format!("/api/repositories/{}/environments/production", repo);
+        let (status, _) = call(&app, Method::GET, &path, Some(&token), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(count(), 0);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
@@ -0,0 +1,468 @@
+//! Interactive terminal into a container over WebSocket.
+//!
+//! `GET /containers/{id}/exec` upgrades to a WebSocket backed by a `docker exec`
+//! with a TTY. Binary frames from the client are written to the shell's stdin
+//! and terminal output is sent back as binary frames. Text frames carry JSON
+//! control messages:
+//!
+//! - `{"type": "input", "data": "ls\r"}` — stdin as text
+//! - `{"type": "resize", "cols": 120, "rows": 40}` — resize the TTY
+//!
+//! When the shell exits the server sends `{"type": "exit", "code": N}` and
+//! closes the socket. Every session is recorded in `exec_sessions`.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Extension, Path, Query, State,
+    },
+    http::StatusCode,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+use crate::auth::{Access, Claims};
+use crate::db::models::ExecSession;
+use crate::services::audit;
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/containers/{id}/exec", get(exec_websocket))
+        .route("/exec-sessions", get(list_sessions))
+}
+
+#[derive(Deserialize)]
+struct ExecQuery {
+    /// Preferred shell; falls back to `bash`, then `sh`.
+    shell: Option<String>,
+    cols: Option<u16>,
+    rows: Option<u16>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ControlMessage {
+    Input { data: String },
+    Resize { cols: u16, rows: u16 },
+}
+
+/// Shell names are interpolated into a `sh -c` script, so keep them to plain
+/// command names or paths.
+fn valid_shell(shell: &str) -> bool {
+    !shell.is_empty()
+        && shell.len() <= 64
+        && shell
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '.'))
+}
+
+/// Command that execs the first available shell of `preferred`, `bash`, `sh`.
+fn shell_command(preferred: &str) -> Vec<String> {
+    let mut shells = vec![preferred];
+    for fallback in ["bash", "sh"] {
+        if !shells.contains(&fallback) {
+            shells.push(fallback);
+        }
+    }
+    let script = format!(
+        "for s in {}; do command -v \"$s\" >/dev/null 2>&1 && exec \"$s\"; done; \
+         echo 'No shell found in container' >&2; exit 127",
+        shells.join(" ")
+    );
+    vec!["/bin/sh".to_string(), "-c".to_string(), script]
+}
+
+async fn exec_websocket(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<ExecQuery>,
+    Extension(claims): Extension<Claims>,
+    Extension(access): Extension<Access>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let shell = query
+        .shell
+        .unwrap_or_else(|| state.config.exec_shell.clone());
+    if !valid_shell(&shell) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Invalid shell '{}'", shell)})),
+        ));
+    }
+
+    let info = state.docker.inspect_container(&id).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let running = info
+        .state
+        .as_ref()
+        .and_then(|s| s.running)
+        .unwrap_or(false);
+    if !running {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Container is not running"})),
+        ));
+    }
+
+    let repo_id: Option<i64> = info
+        .config
+        .as_ref()
+        .and_then(|c| c.labels.as_ref())
+        .and_then(|labels| labels.get("dockyy.repo_id"))
+        .and_then(|id| id.parse().ok());
+    let container_id = info.id.unwrap_or(id);
+    let container_name = info.name.map(|n| n.trim_start_matches('/').to_string());
+    let remote_addr = addr.ip().to_string();
+
+    let session_id = state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO exec_sessions (container_id, container_name, username, shell, remote_addr, repo_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![container_id, container_name, claims.sub, shell, remote_addr, repo_id],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    tracing::info!(
+        session = session_id,
+        container = container_name.as_deref().unwrap_or(&container_id),
+        user = %claims.sub,
+        ip = %remote_addr,
+        "Exec session opened"
+    );
+    audit::record(
+        &state,
+        audit::Event {
+            username: Some(&claims.sub),
+            token_id: access.token_id,
+            ip: Some(&remote_addr),
+            action: "exec.open",
+            target: Some(&format!("/api/containers/{}", container_id)),
+            summary: Some(json!({
+                "session_id": session_id,
+                "container_name": container_name,
+                "shell": shell,
+            })),
+            ..Default::default()
+        },
+    );
+
+    let size = query.cols.zip(query.rows);
+    Ok(ws.on_upgrade(move |socket| async move {
+        let outcome = run_session(&state, socket, &container_id, &shell, size).await;
+
+        let (exit_code, bytes_in, bytes_out, error) = match outcome {
+            Ok(s) => (s.exit_code, s.bytes_in, s.bytes_out, None),
+            Err(e) => (None, 0, 0, Some(e.to_string())),
+        };
+        tracing::info!(
+            session = session_id,
+            exit_code,
+            error = error.as_deref(),
+            "Exec session closed"
+        );
+
+        let _ = state.db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE exec_sessions
+                 SET exit_code = ?2, bytes_in = ?3, bytes_out = ?4, error = ?5,
+                     ended_at = datetime('now')
+                 WHERE id = ?1",
+                rusqlite::params![session_id, exit_code, bytes_in, bytes_out, error],
+            )?;
+            Ok(())
+        });
+    }))
+}
+
+struct SessionOutcome {
+    exit_code: Option<i64>,
+    bytes_in: i64,
+    bytes_out: i64,
+}
+
+/// Pipe the WebSocket to a new exec instance until either side hangs up.
+async fn run_session(
+    state: &AppState,
+    socket: WebSocket,
+    container_id: &str,
+    shell: &str,
+    size: Option<(u16, u16)>,
+) -> anyhow::Result<SessionOutcome> {
+    let (mut ws_tx, mut ws_rx) = socket.split();
+
+    let exec = match state
+        .docker
+        .exec_interactive(container_id, shell_command(shell))
+        .await
+    {
+        Ok(exec) => exec,
+        Err(e) => {
+            let msg = json!({"type": "error", "message": e.to_string()}).to_string();
+            let _ = ws_tx.send(Message::Text(msg.into())).await;
+            let _ = ws_tx.send(Message::Close(None)).await;
+            return Err(e);
+        }
+    };
+    let (exec_id, mut output, mut input) = (exec.id, exec.output, exec.input);
+
+    if let Some((cols, rows)) = size {
+        let _ = state.docker.resize_exec(&exec_id, cols, rows).await;
+    }
+
+    let mut outcome = SessionOutcome {
+        exit_code: None,
+        bytes_in: 0,
+        bytes_out: 0,
+    };
+    let mut shell_exited = false;
+
+    loop {
+        tokio::select! {
+            chunk = output.next() => match chunk {
+                Some(Ok(chunk)) => {
+                    let bytes = chunk.into_bytes();
+                    outcome.bytes_out += bytes.len() as i64;
+                    if ws_tx.send(Message::Binary(bytes)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
+                    tracing::warn!("Exec output stream failed: {}", e);
+                    break;
+                }
+                None => {
+                    shell_exited = true;
+                    break;
+                }
+            },
+            msg = ws_rx.next() => match msg {
+                Some(Ok(Message::Binary(data))) => {
+                    outcome.bytes_in += data.len() as i64;
+                    input.write_all(&data).await?;
+                }
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ControlMessage>(&text) {
+                    Ok(ControlMessage::Input { data }) => {
+                        outcome.bytes_in += data.len() as i64;
+                        input.write_all(data.as_bytes()).await?;
+                    }
+                    Ok(ControlMessage::Resize { cols, rows }) => {
+                        if let Err(e) = state.docker.resize_exec(&exec_id, cols, rows).await {
+                            tracing::debug!("Exec resize failed: {}", e);
+                        }
+                    }
+                    Err(_) => tracing::debug!("Ignoring unknown exec control message"),
+                },
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    if !shell_exited {
+        // Client went away: send EOT so an idle shell exits instead of lingering.
+        let _ = input.write_all(&[0x04]).await;
+        let _ = input.shutdown().await;
+    }
+
+    outcome.exit_code = state.docker.exec_exit_code(&exec_id).await.unwrap_or(None);
+
+    if shell_exited {
+        let msg = json!({"type": "exit", "code": outcome.exit_code}).to_string();
+        let _ = ws_tx.send(Message::Text(msg.into())).await;
+        let _ = ws_tx.send(Message::Close(None)).await;
+    }
+
+    Ok(outcome)
+}
+
+#[derive(Deserialize)]
+struct SessionsQuery {
+    container: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Audit trail of exec sessions, newest first. Scoped users only see
+/// sessions on their repositories' containers.
+async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<SessionsQuery>,
+) -> Result<Json<Vec<ExecSession>>, (StatusCode, Json<Value>)> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000) as usize;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, container_id, container_name, username, shell, remote_addr,
+                        exit_code, bytes_in, bytes_out, error, started_at, ended_at, repo_id
+                 FROM exec_sessions
+                 WHERE ?1 IS NULL OR container_id = ?1 OR container_name = ?1
+                 ORDER BY id DESC",
+            )?;
+
+            let rows = stmt.query_map(rusqlite::params![query.container], |row| {
+                Ok(ExecSession {
+                    id: row.get(0)?,
+                    container_id: row.get(1)?,
+                    container_name: row.get(2)?,
+                    username: row.get(3)?,
+                    shell: row.get(4)?,
+                    remote_addr: row.get(5)?,
+                    exit_code: row.get(6)?,
+                    bytes_in: row.get(7)?,
+                    bytes_out: row.get(8)?,
+                    error: row.get(9)?,
+                    started_at: row.get(10)?,
+                    ended_at: row.get(11)?,
+                    repo_id: row.get(12)?,
+                })
+            })?;
+
+            let mut sessions = Vec::new();
+            for session in rows {
+                let session = session?;
+                if access.is_scoped() && !session.repo_id.is_some_and(|id| access.can_see(id)) {
+                    continue;
+                }
+                sessions.push(session);
+                if sessions.len() == limit {
+                    break;
+                }
+            }
+            Ok(sessions)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use serde_json::json;
+
+    use crate::auth::Role;
+    use crate::test_support::{self, call, send, MockDocker};
+
+    fn upgrade(uri: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header("Connection", "upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn terminals_need_deploy_access_to_the_container() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let shop = test_support::add_repo(&state, "shop");
+        let billing = test_support::add_repo(&state, "billing");
+        docker.add_container("shop", &[("dockyy.repo_id", &shop.to_string())]);
+        docker.add_container("billing", &[("dockyy.repo_id", &billing.to_string())]);
+        test_support::add_user(&state, "viewer", Role::Viewer, None);
+        test_support::add_user(&state, "deployer", Role::Deployer, None);
+        test_support::add_user(&state, "auditor", Role::Deployer, Some(&[(shop, Role::Viewer)]));
+        test_support::add_user(&state, "contractor", Role::Deployer, Some(&[(shop, Role::Deployer)]));
+
+        // The handshake can't complete without a real connection, so reaching
+        // the handler answers 426.
+        for (user, container, expected) in [
+            ("viewer", "shop", StatusCode::FORBIDDEN),
+            ("auditor", "shop", StatusCode::FORBIDDEN),
+            ("contractor", "billing", StatusCode::NOT_FOUND),
+            ("contractor", "shop", StatusCode::UPGRADE_REQUIRED),
+            ("deployer", "billing", StatusCode::UPGRADE_REQUIRED),
+        ] {
+            let token = test_support::login(&state, user);
+            let uri = format!("/api/containers/{}/exec?token={}", container, token);
+            let (status, _) = send(&app, upgrade(&uri)).await;
+            assert_eq!(status, expected, "{} on {}", user, container);
+        }
+
+        // API tokens are long-lived, so they aren't accepted in the URL.
+        let session = test_support::login(&state, "deployer");
+        let body = Some(json!({"name": "ci", "scope": "deploy"}));
+        let (_, created) = call(&app, Method::POST, "/api/auth/tokens", Some(&session), body).await;
+        let api_token = created["token"].as_str().unwrap();
+        let uri = format!("/api/containers/shop/exec?token={}", api_token);
+        let (status, _) = send(&app, upgrade(&uri)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&app, Method::GET, "/api/exec-sessions", Some(api_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    #[tokio::test]
+    async fn scoped_users_only_list_their_repositories_sessions() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let shop = test_support::add_repo(&state, "shop");
+        let billing = test_support::add_repo(&state, "billing");
+        state
+            .db
+            .with_conn(|conn| {
+                for (container, repo_id) in [("shop", Some(shop)), ("billing", Some(billing)), ("postgres", None)] {
+                    conn.execute(
+                        "INSERT INTO exec_sessions (container_id, username, shell, repo_id) VALUES (?1, 'root', 'sh', ?2)",
+                        rusqlite::params![container, repo_id],
+                    )?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        test_support::add_user(&state, "root", Role::Admin, None);
+        test_support::add_user(&state, "contractor", Role::Deployer, Some(&[(shop, Role::Deployer)]));
+
+        let containers = |sessions: serde_json::Value| -> Vec<String> {
+            let sessions = sessions.as_array().unwrap().iter();
+            sessions.map(|s| s["container_id"].as_str().unwrap().to_string()).collect()
+        };
+        let root = test_support::login(&state, "root");
+        let (_, sessions) = call(&app, Method::GET, "/api/exec-sessions", Some(&root), None).await;
+        assert_eq!(containers(sessions), ["postgres", "billing", "shop"]);
+
+        let contractor = test_support::login(&state, "contractor");
+        let (_, sessions) = call(&app, Method::GET, "/api/exec-sessions", Some(&contractor), None).await;
+        assert_eq!(containers(sessions), ["shop"]);
+        let uri = "/api/exec-sessions?container=billing";
+        let (_, sessions) = call(&app, Method::GET, uri, Some(&contractor), None).await;
+        assert!(containers(sessions).is_empty());
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
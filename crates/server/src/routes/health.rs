@@ -1,8 +1,17 @@
-use axum::{extract::State, routing::get, Json, Router};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
 use serde_json::{json, Value};
 use std::sync::Arc;
 use sysinfo::System;
 
+use crate::db::models::HostMetricsHistory;
+use crate::routes::containers::parse_log_time;
+use crate::services::metrics_history;
 use crate::services::monitor::SystemMetrics;
 use crate::AppState;
 
@@ -10,9 +19,11 @@ pub fn routes() -> Router<Arc<AppState>> {
     Router::new().route("/health", get(health_check))
 }
 
-/// Protected route: `GET /api/metrics`
+/// Protected routes: `GET /api/metrics`, `GET /api/metrics/history`
 pub fn metrics_routes() -> Router<Arc<AppState>> {
-    Router::new().route("/metrics", get(server_metrics))
+    Router::new()
+        .route("/metrics", get(server_metrics))
+        .route("/metrics/history", get(metrics_history))
 }
 
 async fn health_check(State(state): State<Arc<AppState>>) -> Json<Value> {
@@ -43,3 +54,47 @@ async fn server_metrics(State(state): State<Arc<AppState>>) -> Json<SystemMetric
     let metrics = state.metrics.read().await.clone();
     Json(metrics)
 }
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Unix, RFC 3339 or relative (`6h`, `7d`). Defaults to 24 hours ago.
+    from: Option<String>,
+    to: Option<String>,
+    /// `raw`, `5m` or `1h`; picked from the range when omitted.
+    resolution: Option<String>,
+}
+
+async fn metrics_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HostMetricsHistory>, (StatusCode, Json<Value>)> {
+    let bad = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+
+    let now = chrono::Utc::now().timestamp();
+    let from = match &query.from {
+        Some(from) => parse_log_time(from).map_err(bad)?,
+        None => now - 24 * 3600,
+    };
+    let to = match &query.to {
+        Some(to) => parse_log_time(to).map_err(bad)?,
+        None => now,
+    };
+    if from > to {
+        return Err(bad("'from' must be before 'to'".to_string()));
+    }
+    let resolution = query
+        .resolution
+        .as_deref()
+        .map(metrics_history::parse_resolution)
+        .transpose()
+        .map_err(|e| bad(e.to_string()))?;
+
+    metrics_history::history(&state.db, from, to, resolution)
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
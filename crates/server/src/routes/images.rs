@@ -0,0 +1,280 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::db::models::{
+    ImageDeployment, ImageInfo, PruneImagesRequest, PruneReport, PullImageRequest,
+};
+use crate::auth::{Access, Role};
+use crate::services::cleanup::{self, CleanupReport};
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/images", get(list_images))
+        .route("/images/pull", post(pull_image))
+        .route("/images/prune", post(prune_images))
+        .route("/images/cleanup", post(run_cleanup))
+        // Image references contain '/' (registry paths), hence the wildcard.
+        .route("/images/{*name}", get(inspect_image).delete(remove_image))
+}
+
+/// Deployments keyed by the image reference they recorded.
+fn deployments_by_image(
+    state: &AppState,
+) -> Result<HashMap<String, Vec<ImageDeployment>>, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, repo_id, environment_id, status, image_name, created_at
+                 FROM deployments WHERE image_name IS NOT NULL
+                 ORDER BY created_at DESC",
+            )?;
+
+            let mut map: HashMap<String, Vec<ImageDeployment>> = HashMap::new();
+            let rows = stmt.query_map([], |row| {
+                Ok(ImageDeployment {
+                    id: row.get(0)?,
+                    repo_id: row.get(1)?,
+                    environment_id: row.get(2)?,
+                    status: row.get(3)?,
+                    image_name: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?;
+            for d in rows {
+                let d = d?;
+                map.entry(d.image_name.clone()).or_default().push(d);
+            }
+            Ok(map)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+fn attach_deployments(image: &mut ImageInfo, deployments: &HashMap<String, Vec<ImageDeployment>>) {
+    image.deployments = image
+        .tags
+        .iter()
+        .filter_map(|t| deployments.get(t))
+        .flatten()
+        .cloned()
+        .collect();
+}
+
+/// Whether `access` may see `image`. Scoped users see images deployed for
+/// (or labelled with) their repositories, and only those deployments of them.
+fn scope_image(image: &mut ImageInfo, access: &Access) -> bool {
+    if !access.is_scoped() {
+        return true;
+    }
+    image.deployments.retain(|d| access.can_see(d.repo_id));
+    !image.deployments.is_empty() || access.can_see_labels(&image.labels)
+}
+
+async fn list_images(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+) -> Result<Json<Vec<ImageInfo>>, (StatusCode, Json<Value>)> {
+    let mut images = state.docker.list_images().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    let deployments = deployments_by_image(&state)?;
+    for image in &mut images {
+        attach_deployments(image, &deployments);
+    }
+    images.retain_mut(|image| scope_image(image, &access));
+
+    Ok(Json(images))
+}
+
+async fn inspect_image(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Path(name): Path<String>,
+) -> Result<Json<ImageInfo>, (StatusCode, Json<Value>)> {
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("No such image: {}", name)})),
+        )
+    };
+    let mut image = state.docker.inspect_image(&name).await.map_err(|_| not_found())?;
+
+    attach_deployments(&mut image, &deployments_by_image(&state)?);
+    if !scope_image(&mut image, &access) {
+        return Err(not_found());
+    }
+    Ok(Json(image))
+}
+
+async fn pull_image(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<PullImageRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let image = body.image.trim();
+    if image.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Image reference is required"})),
+        ));
+    }
+
+    let output = state.docker.pull_image(image).await.map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    tracing::info!(image = %image, "Image pulled");
+    Ok(Json(json!({"status": "pulled", "image": image, "output": output})))
+}
+
+#[derive(Deserialize)]
+struct RemoveQuery {
+    force: Option<bool>,
+}
+
+/// Whether `access` may remove `image`: admins always, others only images
+/// labelled with or deployed for repositories they can deploy. Anything else
+/// is shared, such as the proxy's image.
+fn can_remove_image(image: &ImageInfo, access: &Access) -> bool {
+    if access.role == Role::Admin {
+        return true;
+    }
+    let can_deploy = |repo_id: i64| access.repo_role(repo_id).is_some_and(|r| r >= Role::Deployer);
+    if image.deployments.is_empty() {
+        access.can_change_labels(&image.labels)
+    } else {
+        image.deployments.iter().all(|d| can_deploy(d.repo_id))
+    }
+}
+
+/// Remove an image or tag. Images used by a container are refused unless
+/// `?force=true`.
+async fn remove_image(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Path(name): Path<String>,
+    Query(query): Query<RemoveQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let force = query.force.unwrap_or(false);
+    let mut image = state.docker.inspect_image(&name).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    attach_deployments(&mut image, &deployments_by_image(&state)?);
+    if !can_remove_image(&image, &access) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only admins can remove images that don't belong to your repositories"})),
+        ));
+    }
+
+    if !force && !image.containers.is_empty() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!("Image is in use by: {}", image.containers.join(", ")),
+                "containers": image.containers,
+            })),
+        ));
+    }
+
+    let removed = state.docker.delete_image(&name, force).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    tracing::info!(image = %name, force, "Image removed");
+    Ok(Json(json!({"status": "removed", "image": name, "removed": removed})))
+}
+
+/// Prune dangling images (all unused ones with `all`) and, by default, the
+/// build cache. This spans every repository, so it's admin-only.
+async fn prune_images(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    body: Option<Json<PruneImagesRequest>>,
+) -> Result<Json<PruneReport>, (StatusCode, Json<Value>)> {
+    require_admin(&access)?;
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+
+    let mut report = state.docker.prune_images(body.all).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    if body.build_cache.unwrap_or(true) {
+        report.build_cache = Some(state.docker.prune_build_cache().await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?);
+    }
+
+    tracing::info!(
+        images = report.images_deleted.len(),
+        space_reclaimed = report.space_reclaimed,
+        "Images pruned"
+    );
+    Ok(Json(report))
+}
+
+fn require_admin(access: &Access) -> Result<(), (StatusCode, Json<Value>)> {
+    if access.role == Role::Admin {
+        return Ok(());
+    }
+    Err((
+        StatusCode::FORBIDDEN,
+        Json(json!({"error": "Only admins can prune images"})),
+    ))
+}
+
+#[derive(Deserialize)]
+struct CleanupQuery {
+    prune: Option<bool>,
+}
+
+/// Run the scheduled cleanup now. `?prune=true` prunes regardless of disk
+/// usage. Like pruning, it's admin-only.
+async fn run_cleanup(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<CleanupQuery>,
+) -> Result<Json<CleanupReport>, (StatusCode, Json<Value>)> {
+    require_admin(&access)?;
+    cleanup::run_once(&state, query.prune.unwrap_or(false))
+        .await
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
@@ -3,33 +3,101 @@ use std::sync::Arc;
 
 use crate::AppState;
 
+pub mod alerts;
+pub mod audit;
 pub mod auth;
 pub mod containers;
 pub mod deployments;
+pub mod environments;
+pub mod exec;
 pub mod health;
+pub mod images;
+pub mod networks;
+pub mod notifications;
+pub mod oidc;
+pub mod previews;
+pub mod prometheus;
 pub mod proxy;
 pub mod repositories;
+pub mod sessions;
 pub mod static_files;
+pub mod stats;
+pub mod tokens;
+pub mod totp;
+pub mod users;
+pub mod volumes;
 pub mod webhooks;
 
 pub fn api_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
     let public_routes = Router::new()
         .merge(health::routes())
         .merge(auth::routes())
+        .merge(oidc::routes())
         .merge(webhooks::routes());
 
-    let protected_routes = Router::new()
+    // Readable by every role; mutations need the deployer role.
+    let read_routes = Router::new()
         .merge(containers::routes())
         .merge(repositories::routes())
         .merge(deployments::routes())
+        .merge(environments::routes())
+        .merge(previews::routes())
         .merge(proxy::routes())
+        .merge(volumes::routes())
+        .merge(images::routes())
+        .merge(networks::routes())
+        .merge(stats::routes())
+        .merge(alerts::routes())
         .merge(health::metrics_routes())
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::auth::repo_scope_middleware,
+        ))
+        .route_layer(axum::middleware::from_fn(crate::auth::writes_require_deployer));
+
+    // Every signed-in user manages their own API tokens, sessions and
+    // two-factor authentication.
+    let account_routes = Router::new()
+        .merge(tokens::routes())
+        .merge(sessions::routes())
+        .merge(totp::routes());
+
+    // Terminals give shell access to containers, so even opening one counts
+    // as a deployer action.
+    let deployer_routes = Router::new()
+        .merge(exec::routes())
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::auth::repo_scope_middleware,
+        ))
+        .route_layer(axum::middleware::from_fn(crate::auth::require_deployer));
+
+    let admin_routes = Router::new()
+        .merge(users::routes())
+        .merge(notifications::routes())
+        .merge(audit::routes())
+        .route_layer(axum::middleware::from_fn(crate::auth::require_admin));
+
+    let protected_routes = Router::new()
+        .merge(read_routes)
+        .merge(account_routes)
+        .merge(deployer_routes)
+        .merge(admin_routes)
+        // Inside the auth layer so entries name the caller.
         .layer(axum::middleware::from_fn_with_state(
-            state,
+            state.clone(),
+            crate::services::audit::audit_middleware,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
             crate::auth::auth_middleware,
         ));
 
     Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            crate::services::telemetry::track_http,
+        ))
 }
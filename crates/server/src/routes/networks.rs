@@ -0,0 +1,176 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::db::models::{
+    CreateNetworkRequest, NetworkConnectRequest, NetworkDisconnectRequest, NetworkInfo,
+    RESERVED_NETWORKS, validate_labels, validate_network_name,
+};
+use crate::auth::Access;
+use crate::routes::containers::check_can_change;
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/networks", get(list_networks).post(create_network))
+        .route("/networks/{name}", get(inspect_network).delete(remove_network))
+        .route("/networks/{name}/connect", post(connect_container))
+        .route("/networks/{name}/disconnect", post(disconnect_container))
+}
+
+/// Scoped users only see networks created for their repositories.
+async fn list_networks(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+) -> Result<Json<Vec<NetworkInfo>>, (StatusCode, Json<Value>)> {
+    let mut networks = state.docker.list_networks().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    networks.retain(|n| access.can_see_labels(&n.labels));
+    Ok(Json(networks))
+}
+
+async fn inspect_network(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Path(name): Path<String>,
+) -> Result<Json<NetworkInfo>, (StatusCode, Json<Value>)> {
+    state
+        .docker
+        .inspect_network(&name)
+        .await
+        .ok()
+        .filter(|n| access.can_see_labels(&n.labels))
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": format!("No such network: {}", name)})),
+            )
+        })
+}
+
+async fn create_network(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<CreateNetworkRequest>,
+) -> Result<(StatusCode, Json<NetworkInfo>), (StatusCode, Json<Value>)> {
+    validate_network_name(&body.name)
+        .and_then(|_| validate_labels(&body.labels))
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+
+    state
+        .docker
+        .create_network(body)
+        .await
+        .map(|n| (StatusCode::CREATED, Json(n)))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Remove a network. The proxy network and the engine's built-in networks are
+/// refused, as are networks that still have containers attached.
+async fn remove_network(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let network = state.docker.inspect_network(&name).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    if RESERVED_NETWORKS.contains(&network.name.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Network '{}' is reserved", network.name)})),
+        ));
+    }
+    if !network.containers.is_empty() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!("Network is in use by: {}", network.containers.join(", ")),
+                "containers": network.containers,
+            })),
+        ));
+    }
+
+    state.docker.remove_network(&name).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    tracing::info!(network = %network.name, "Network removed");
+    Ok(Json(json!({"status": "removed", "network": network.name})))
+}
+
+/// Connect a container. Containers no repository owns, such as the proxy, can
+/// only be connected by admins.
+async fn connect_container(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Path(name): Path<String>,
+    Json(body): Json<NetworkConnectRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    check_can_change(&state, &access, &body.container).await?;
+
+    state
+        .docker
+        .connect_network(&name, &body.container, body.aliases)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    tracing::info!(network = %name, container = %body.container, "Container connected");
+    Ok(Json(json!({"status": "connected", "network": name, "container": body.container})))
+}
+
+/// Disconnect a container. Disconnecting from the proxy network would take the
+/// app offline, so it's refused; containers no repository owns are admin-only.
+async fn disconnect_container(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Path(name): Path<String>,
+    Json(body): Json<NetworkDisconnectRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if name == crate::services::traefik::TRAEFIK_NETWORK {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Containers cannot be disconnected from the proxy network"})),
+        ));
+    }
+    check_can_change(&state, &access, &body.container).await?;
+
+    state
+        .docker
+        .disconnect_network(&name, &body.container, body.force)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    tracing::info!(network = %name, container = %body.container, "Container disconnected");
+    Ok(Json(json!({"status": "disconnected", "network": name, "container": body.container})))
+}
@@ -0,0 +1,282 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::db::models::{
+    validate_events, CreateNotificationChannel, NotificationChannel, NotificationDelivery,
+    UpdateNotificationChannel,
+};
+use crate::services::notifications;
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/notifications/channels",
+            get(list_channels).post(create_channel),
+        )
+        .route(
+            "/notifications/channels/{id}",
+            get(get_channel).put(update_channel).delete(delete_channel),
+        )
+        .route("/notifications/channels/{id}/test", post(test_channel))
+        .route("/notifications/deliveries", get(list_deliveries))
+}
+
+fn load_channel(state: &AppState, id: i64) -> Result<NotificationChannel, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let channel = conn.query_row(
+                &format!(
+                    "SELECT {} FROM notification_channels WHERE id = ?1",
+                    NotificationChannel::COLUMNS
+                ),
+                [id],
+                NotificationChannel::from_row,
+            )?;
+            Ok(channel)
+        })
+        .map_err(|_| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Notification channel not found"})),
+            )
+        })
+}
+
+async fn list_channels(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<NotificationChannel>>, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM notification_channels ORDER BY name",
+                NotificationChannel::COLUMNS
+            ))?;
+            let channels = stmt
+                .query_map([], NotificationChannel::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(channels)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn get_channel(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<NotificationChannel>, (StatusCode, Json<Value>)> {
+    load_channel(&state, id).map(Json)
+}
+
+async fn create_channel(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<CreateNotificationChannel>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    body.channel
+        .validate()
+        .and_then(|_| validate_events(body.events.as_deref().unwrap_or_default()))
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    if body.channel.has_placeholder() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Secrets must not be the redacted placeholder"})),
+        ));
+    }
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO notification_channels (name, kind, config, events, enabled)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![
+                    body.name,
+                    body.channel.kind(),
+                    body.channel.config_json(),
+                    serde_json::to_string(&body.events.unwrap_or_default())?,
+                    body.enabled.unwrap_or(true),
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map(|id| {
+            (
+                StatusCode::CREATED,
+                Json(json!({"id": id, "message": "Notification channel created"})),
+            )
+        })
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn update_channel(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Json(mut body): Json<UpdateNotificationChannel>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let stored = load_channel(&state, id)?;
+    if let Some(ref mut channel) = body.channel {
+        // Clients send back the redacted config they were given.
+        channel.keep_secrets(&stored.channel);
+        channel
+            .validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+        if channel.has_placeholder() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Secrets must not be the redacted placeholder"})),
+            ));
+        }
+    }
+    if let Some(ref events) = body.events {
+        validate_events(events).map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    }
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut sets = Vec::new();
+            let mut params: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
+
+            if let Some(ref name) = body.name {
+                sets.push("name = ?");
+                params.push(Box::new(name.clone()));
+            }
+            if let Some(ref channel) = body.channel {
+                sets.push("kind = ?");
+                params.push(Box::new(channel.kind()));
+                sets.push("config = ?");
+                params.push(Box::new(channel.config_json()));
+            }
+            if let Some(ref events) = body.events {
+                sets.push("events = ?");
+                params.push(Box::new(serde_json::to_string(events)?));
+            }
+            if let Some(enabled) = body.enabled {
+                sets.push("enabled = ?");
+                params.push(Box::new(enabled));
+            }
+
+            if sets.is_empty() {
+                anyhow::bail!("No fields to update");
+            }
+
+            sets.push("updated_at = datetime('now')");
+            params.push(Box::new(id));
+
+            let sql = format!(
+                "UPDATE notification_channels SET {} WHERE id = ?",
+                sets.join(", ")
+            );
+            let param_refs: Vec<&dyn rusqlite::types::ToSql> =
+                params.iter().map(|p| p.as_ref()).collect();
+            conn.execute(&sql, param_refs.as_slice())?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Notification channel updated"})))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn delete_channel(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    load_channel(&state, id)?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute("DELETE FROM notification_channels WHERE id = ?1", [id])?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Notification channel deleted"})))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Send a test notification synchronously and report the outcome.
+async fn test_channel(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let channel = load_channel(&state, id)?;
+
+    notifications::send_test(&state, &channel)
+        .await
+        .map(|_| Json(json!({"message": "Test notification sent"})))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"error": format!("{:#}", e)})),
+            )
+        })
+}
+
+#[derive(Deserialize)]
+struct DeliveriesQuery {
+    channel: Option<i64>,
+    /// `pending`, `sent` or `failed`; all when omitted.
+    status: Option<String>,
+    limit: Option<i64>,
+}
+
+/// Delivery log, newest first.
+async fn list_deliveries(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<NotificationDelivery>>, (StatusCode, Json<Value>)> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM notification_deliveries d
+                 LEFT JOIN notification_channels c ON c.id = d.channel_id
+                 WHERE (?1 IS NULL OR d.channel_id = ?1) AND (?2 IS NULL OR d.status = ?2)
+                 ORDER BY d.id DESC LIMIT ?3",
+                NotificationDelivery::COLUMNS
+            ))?;
+            let deliveries = stmt
+                .query_map(
+                    rusqlite::params![query.channel, query.status, limit],
+                    NotificationDelivery::from_row,
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(deliveries)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
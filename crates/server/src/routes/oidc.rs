@@ -0,0 +1,381 @@
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Redirect,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::auth::{sessions, Role};
+use crate::services::{audit, http, oidc};
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/auth/oidc/login", get(oidc_login))
+        .route("/auth/oidc/callback", get(oidc_callback))
+}
+
+/// Send the browser to the provider's sign-in page.
+async fn oidc_login(
+    State(state): State<Arc<AppState>>,
+) -> Result<Redirect, (StatusCode, Json<Value>)> {
+    let Some(ref config) = state.config.oidc else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Single sign-on is not configured"})),
+        ));
+    };
+    oidc::begin(&state, config)
+        .await
+        .map(|url| Redirect::to(&url))
+        .map_err(|e| {
+            tracing::error!("Failed to start single sign-on: {:#}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({"error": format!("{:#}", e)})),
+            )
+        })
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// Back to the dashboard with an error to show on the login page.
+fn fail(message: &str) -> Redirect {
+    Redirect::to(&format!("/#/?sso_error={}", http::form_encode(message)))
+}
+
+/// Record a single sign-on attempt in the audit log.
+fn audit_sso(state: &AppState, ip: &str, username: Option<&str>, error: Option<&str>) {
+    audit::record(
+        state,
+        audit::Event {
+            username,
+            ip: Some(ip),
+            action: "sso.login",
+            summary: error.map(|error| json!({"error": error})),
+            outcome: if error.is_some() {
+                audit::Outcome::Denied
+            } else {
+                audit::Outcome::Success
+            },
+            ..Default::default()
+        },
+    );
+}
+
+/// The provider redirects here. On success the dashboard receives a
+/// refresh token in the URL fragment, which it immediately exchanges (and
+/// so invalidates) at `/auth/refresh`.
+async fn oidc_callback(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<CallbackQuery>,
+) -> Redirect {
+    let ip = addr.ip().to_string();
+    let Some(ref config) = state.config.oidc else {
+        return fail("Single sign-on is not configured");
+    };
+    if let Some(error) = query.error {
+        tracing::warn!("Single sign-on refused by provider: {}", error);
+        audit_sso(&state, &ip, None, Some(&error));
+        return fail(query.error_description.as_deref().unwrap_or(&error));
+    }
+    let (Some(code), Some(login_state)) = (query.code, query.state) else {
+        return fail("Missing code or state");
+    };
+
+    let identity = match oidc::complete(&state, config, &code, &login_state).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::warn!("Single sign-on failed: {:#}", e);
+            audit_sso(&state, &ip, None, Some(&format!("{:#}", e)));
+            return fail(&format!("{:#}", e));
+        }
+    };
+    let Some(role) = identity.role else {
+        tracing::warn!(username = %identity.username, "Single sign-on user has no dockyy role");
+        audit_sso(&state, &ip, Some(&identity.username), Some("no_role"));
+        return fail("Your account has not been granted access to Dockyy");
+    };
+
+    let user_id = match provision_user(&state, &identity.subject, &identity.username, role) {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::warn!(username = %identity.username, "Single sign-on provisioning failed: {}", e);
+            audit_sso(&state, &ip, Some(&identity.username), Some(&e.to_string()));
+            return fail(&e.to_string());
+        }
+    };
+
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    match sessions::create(&state, user_id, &ip, user_agent) {
+        Ok(session) => {
+            tracing::info!(ip = %ip, username = %identity.username, "Successful single sign-on login");
+            audit_sso(&state, &ip, Some(&identity.username), None);
+            Redirect::to(&format!("/#/?refresh_token={}", session.refresh_token))
+        }
+        Err(e) => {
+            tracing::error!("Failed to create session: {}", e);
+            fail("Failed to create session")
+        }
+    }
+}
+
+/// Find the user linked to `subject`, creating it on first login. The role
+/// follows the provider's claims on every login.
+fn provision_user(state: &AppState, subject: &str, username: &str, role: Role) -> anyhow::Result<i64> {
+    use rusqlite::OptionalExtension;
+
+    state.db.with_conn(|conn| {
+        let existing: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM users WHERE oidc_subject = ?1",
+                [subject],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(id) = existing {
+            conn.execute(
+                "UPDATE users SET role = ?2, updated_at = datetime('now') WHERE id = ?1",
+                rusqlite::params![id, role.as_str()],
+            )?;
+            return Ok(id);
+        }
+
+        // Never link to a local account by name: the provider may let users
+        // pick their own usernames.
+        let taken: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE username = ?1)",
+            [username],
+            |row| row.get(0),
+        )?;
+        if taken {
+            anyhow::bail!("A local account named '{}' already exists", username);
+        }
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role, oidc_subject) VALUES (?1, '', ?2, ?3)",
+            rusqlite::params![username, role.as_str(), subject],
+        )?;
+        tracing::info!(username = %username, role = role.as_str(), "Created single sign-on user");
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::http::{header, Request};
+    use axum::{routing::post, Form, Json, Router};
+    use base64::Engine;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    use crate::auth::Role;
+    use crate::services::oidc::OidcConfig;
+    use crate::test_support::{self, MockDocker};
+
+    const CLIENT_ID: &str = "dockyy";
+    const CODE: &str = "good-code";
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// A P-256 signing key and its public JWK.
+    fn signing_key(kid: &str) -> (EncodingKey, Value) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        // Uncompressed point: 0x04 || x || y.
+        let point = pair.public_key().as_ref();
+        let jwk = json!({
+            "kty": "EC", "crv": "P-256", "alg": "ES256", "use": "sig", "kid": kid,
+            "x": b64(&point[1..33]), "y": b64(&point[33..]),
+        });
+        (EncodingKey::from_ec_der(pkcs8.as_ref()), jwk)
+    }
+
+    /// What the mock issuer hands out: the PKCE challenge it expects and the
+    /// ID token its token endpoint returns.
+    #[derive(Default)]
+    struct Issuer {
+        challenge: String,
+        id_token: String,
+    }
+
+    /// Serve discovery, JWKS and token endpoints for `jwk`, returning the
+    /// issuer URL.
+    async fn start_issuer(issuer: Arc<Mutex<Issuer>>, jwk: Value) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let discovery = json!({
+            "issuer": url,
+            "authorization_endpoint": format!("{}/authorize", url),
+            "token_endpoint": format!("{}/token", url),
+            "jwks_uri": format!("{}/jwks", url),
+        });
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", axum::routing::get(move || async move { Json(discovery) }))
+            .route("/jwks", axum::routing::get(move || async move { Json(json!({"keys": [jwk]})) }))
+            .route(
+                "/token",
+                post(move |Form(form): Form<HashMap<String, String>>| async move {
+                    let issuer = issuer.lock().unwrap();
+                    let verifier = form.get("code_verifier").map(String::as_str).unwrap_or_default();
+                    let digest = ring::digest::digest(&ring::digest::SHA256, verifier.as_bytes());
+                    if form.get("code").map(String::as_str) != Some(CODE)
+                        || form.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+                        || b64(digest.as_ref()) != issuer.challenge
+                    {
+                        return Err(axum::http::StatusCode::BAD_REQUEST);
+                    }
+                    Ok(Json(json!({"id_token": issuer.id_token, "token_type": "Bearer"})))
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn config(issuer: &str) -> OidcConfig {
+        OidcConfig {
+            issuer: issuer.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://dockyy.test/api/auth/oidc/callback".to_string(),
+            scopes: "openid profile".to_string(),
+            display_name: "SSO".to_string(),
+            username_claim: "preferred_username".to_string(),
+            roles_claim: "groups".to_string(),
+            admin_values: vec!["admins".to_string()],
+            deployer_values: vec!["ops".to_string()],
+            viewer_values: Vec::new(),
+            default_role: Some(Role::Viewer),
+        }
+    }
+
+    /// The `Location` a GET to `uri` redirects to.
+    async fn redirect(app: &Router, uri: &str) -> String {
+        let mut req = Request::get(uri).body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res.status().is_redirection(), "{} answered {}", uri, res.status());
+        res.headers()[header::LOCATION].to_str().unwrap().to_string()
+    }
+
+    /// A parameter of the authorization URL; the random values need no decoding.
+    fn query_param(url: &str, name: &str) -> String {
+        let query = url.split_once('?').unwrap().1;
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(&format!("{}=", name)))
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn signs_in_through_the_provider() {
+        let (key, jwk) = signing_key("k1");
+        let issuer_state = Arc::new(Mutex::new(Issuer::default()));
+        let issuer = start_issuer(issuer_state.clone(), jwk).await;
+        let docker = MockDocker::start().await;
+        let mut state = test_support::state(&docker).await;
+        Arc::get_mut(&mut state).unwrap().config.oidc = Some(config(&issuer));
+        let app = test_support::app(&state);
+        test_support::add_user(&state, "bob", Role::Admin, None);
+
+        // Start a login, then have the provider return an ID token built from
+        // `claims` (on top of valid defaults), signed with `key`.
+        let sign_in = |claims: Value, key: EncodingKey, alg: Algorithm| {
+            let app = app.clone();
+            let issuer = issuer.clone();
+            let issuer_state = issuer_state.clone();
+            async move {
+                let login = redirect(&app, "/api/auth/oidc/login").await;
+                assert!(login.starts_with(&format!("{}/authorize?", issuer)), "{}", login);
+                let login_state = query_param(&login, "state");
+                let now = chrono::Utc::now().timestamp();
+                let mut token = json!({
+                    "iss": issuer, "aud": CLIENT_ID, "sub": "u-1", "preferred_username": "ann",
+                    "nonce": query_param(&login, "nonce"), "iat": now, "exp": now + 300,
+                });
+                token.as_object_mut().unwrap().extend(claims.as_object().unwrap().clone());
+                let mut header = Header::new(alg);
+                header.kid = Some("k1".to_string());
+                *issuer_state.lock().unwrap() = Issuer {
+                    challenge: query_param(&login, "code_challenge"),
+                    id_token: jsonwebtoken::encode(&header, &token, &key).unwrap(),
+                };
+                let callback = format!("/api/auth/oidc/callback?code={}&state={}", CODE, login_state);
+                (redirect(&app, &callback).await, callback)
+            }
+        };
+        let user = |username: &str| -> Option<(String, Option<String>)> {
+            state
+                .db
+                .with_conn(|conn| {
+                    use rusqlite::OptionalExtension;
+                    Ok(conn
+                        .query_row(
+                            "SELECT role, oidc_subject FROM users WHERE username = ?1",
+                            [username],
+                            |row| Ok((row.get(0)?, row.get(1)?)),
+                        )
+                        .optional()?)
+                })
+                .unwrap()
+        };
+
+        let (landing, callback) = sign_in(json!({"groups": ["ops"]}), key.clone(), Algorithm::ES256).await;
+        assert!(landing.starts_with("/#/?refresh_token="), "{}", landing);
+        assert_eq!(user("ann"), Some(("deployer".to_string(), Some("u-1".to_string()))));
+        // States are single-use.
+        assert!(redirect(&app, &callback).await.contains("sso_error"));
+
+        // The role follows the provider's claims on every login.
+        let (landing, _) = sign_in(json!({"groups": []}), key.clone(), Algorithm::ES256).await;
+        assert!(landing.starts_with("/#/?refresh_token="), "{}", landing);
+        assert_eq!(user("ann").unwrap().0, "viewer");
+
+        let (other_key, _) = signing_key("k1");
+        let hmac_key = EncodingKey::from_secret(b"client-secret");
+        for (claims, key, alg, error) in [
+            (json!({"nonce": "replayed"}), key.clone(), Algorithm::ES256, "nonce"),
+            (json!({"aud": "another-client"}), key.clone(), Algorithm::ES256, "Invalid%20ID%20token"),
+            (json!({"iss": "https://evil.test"}), key.clone(), Algorithm::ES256, "Invalid%20ID%20token"),
+            (json!({}), other_key, Algorithm::ES256, "Invalid%20ID%20token"),
+            (json!({}), hmac_key, Algorithm::HS256, "Unsupported"),
+        ] {
+            let (landing, _) = sign_in(claims.clone(), key, alg).await;
+            assert!(landing.contains("sso_error") && landing.contains(error), "{}: {}", claims, landing);
+        }
+
+        // Provider users are never linked to local accounts by name.
+        let (landing, _) = sign_in(json!({"sub": "u-2", "preferred_username": "bob"}), key, Algorithm::ES256).await;
+        assert!(landing.contains("already%20exists"), "{}", landing);
+        assert_eq!(user("bob"), Some(("admin".to_string(), None)));
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
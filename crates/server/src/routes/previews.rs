@@ -0,0 +1,96 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::db::models::Preview;
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/repositories/{id}/previews", get(list_previews))
+        .route("/repositories/{id}/previews/{pr}", delete(teardown_preview))
+}
+
+async fn list_previews(
+    State(state): State<Arc<AppState>>,
+    Path(repo_id): Path<i64>,
+) -> Result<Json<Vec<Preview>>, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, repo_id, pr_number, head_ref, head_sha, container_name,
+                        container_id, image_name, domain, status, build_log,
+                        created_at, updated_at
+                 FROM previews WHERE repo_id = ?1 ORDER BY pr_number DESC",
+            )?;
+
+            let previews = stmt
+                .query_map([repo_id], |row| {
+                    Ok(Preview {
+                        id: row.get(0)?,
+                        repo_id: row.get(1)?,
+                        pr_number: row.get(2)?,
+                        head_ref: row.get(3)?,
+                        head_sha: row.get(4)?,
+                        container_name: row.get(5)?,
+                        container_id: row.get(6)?,
+                        image_name: row.get(7)?,
+                        domain: row.get(8)?,
+                        status: row.get(9)?,
+                        build_log: row.get(10)?,
+                        created_at: row.get(11)?,
+                        updated_at: row.get(12)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(previews)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Manually tear down a preview (e.g. when the `closed` webhook was missed).
+async fn teardown_preview(
+    State(state): State<Arc<AppState>>,
+    Path((repo_id, pr_number)): Path<(i64, i64)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let job_id = state
+        .db
+        .with_conn(|conn| {
+            let exists: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM previews WHERE repo_id = ?1 AND pr_number = ?2",
+                [repo_id, pr_number],
+                |row| row.get(0),
+            )?;
+            if exists == 0 {
+                anyhow::bail!("Preview not found");
+            }
+
+            let payload = json!({"repo_id": repo_id, "pr_number": pr_number}).to_string();
+            conn.execute(
+                "INSERT INTO jobs (job_type, payload, status) VALUES ('preview_teardown', ?1, 'pending')",
+                [&payload],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    Ok(Json(json!({"message": "Preview teardown queued", "job_id": job_id})))
+}
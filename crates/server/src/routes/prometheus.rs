@@ -0,0 +1,246 @@
+//! `GET /metrics` in Prometheus text format, outside `/api` and authenticated
+//! with `METRICS_TOKEN` rather than a user JWT so scrapers don't need to log in.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde_json::json;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::services::container_stats::RAW_RESOLUTION;
+use crate::services::telemetry::{header as family, labels};
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/metrics", get(scrape))
+}
+
+/// Compare without short-circuiting so the token can't be guessed by timing.
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+async fn scrape(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let Some(expected) = state.config.metrics_token.as_deref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Metrics endpoint is disabled (set METRICS_TOKEN)"})),
+        )
+            .into_response();
+    };
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    if !token_matches(given, expected) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"error": "Invalid metrics token"})),
+        )
+            .into_response();
+    }
+
+    let mut out = String::new();
+    render_host(&state, &mut out).await;
+    if let Err(e) = render_containers(&state, &mut out) {
+        tracing::warn!("Could not export container stats: {}", e);
+    }
+    if let Err(e) = render_jobs(&state, &mut out) {
+        tracing::warn!("Could not export job queue depth: {}", e);
+    }
+    state.telemetry.render(&mut out);
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        out,
+    )
+        .into_response()
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    family(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+async fn render_host(state: &AppState, out: &mut String) {
+    let m = state.metrics.read().await.clone();
+
+    gauge(out, "dockyy_host_cpu_usage_percent", "Host CPU usage", m.cpu_usage_pct);
+    gauge(out, "dockyy_host_memory_used_bytes", "Host memory in use", m.mem_used_bytes);
+    gauge(out, "dockyy_host_memory_total_bytes", "Host memory size", m.mem_total_bytes);
+    gauge(out, "dockyy_host_swap_used_bytes", "Swap in use", m.swap_used_bytes);
+    gauge(out, "dockyy_host_swap_total_bytes", "Swap size", m.swap_total_bytes);
+    gauge(out, "dockyy_host_disk_used_bytes", "Root filesystem used", m.disk_used_bytes);
+    gauge(out, "dockyy_host_disk_total_bytes", "Root filesystem size", m.disk_total_bytes);
+    gauge(out, "dockyy_host_load1", "1-minute load average", m.load_avg_1);
+    gauge(out, "dockyy_host_load5", "5-minute load average", m.load_avg_5);
+    gauge(out, "dockyy_host_load15", "15-minute load average", m.load_avg_15);
+
+    family(out, "dockyy_host_network_bytes_total", "counter", "Bytes on non-loopback interfaces");
+    let _ = writeln!(out, "dockyy_host_network_bytes_total{{direction=\"rx\"}} {}", m.net_rx_bytes);
+    let _ = writeln!(out, "dockyy_host_network_bytes_total{{direction=\"tx\"}} {}", m.net_tx_bytes);
+
+    gauge(out, "dockyy_docker_up", "Whether the Docker engine is reachable", m.docker_ok as u8);
+}
+
+/// Export the latest monitor round from `container_stats`, so scrapes don't
+/// hit the Docker API.
+fn render_containers(state: &AppState, out: &mut String) -> anyhow::Result<()> {
+    type Row = (String, Option<i64>, Option<String>, [f64; 7]);
+
+    let rows: Vec<Row> = state.db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT container_name, repo_id, environment, cpu_pct, mem_bytes, mem_limit,
+                    net_rx_bps, net_tx_bps, blk_read_bps, blk_write_bps
+             FROM container_stats
+             WHERE resolution = ?1
+               AND ts = (SELECT MAX(ts) FROM container_stats WHERE resolution = ?1)
+               AND ts >= strftime('%s', 'now') - ?2",
+        )?;
+        let rows = stmt
+            .query_map(rusqlite::params![RAW_RESOLUTION, RAW_RESOLUTION * 3], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    [
+                        row.get(3)?,
+                        row.get::<_, i64>(4)? as f64,
+                        row.get::<_, i64>(5)? as f64,
+                        row.get(6)?,
+                        row.get(7)?,
+                        row.get(8)?,
+                        row.get(9)?,
+                    ],
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    })?;
+
+    let families = [
+        ("dockyy_container_cpu_usage_percent", "Container CPU usage (100 = one core)"),
+        ("dockyy_container_memory_used_bytes", "Container memory in use"),
+        ("dockyy_container_memory_limit_bytes", "Container memory limit"),
+        ("dockyy_container_network_receive_bytes_per_second", "Container network receive rate"),
+        ("dockyy_container_network_transmit_bytes_per_second", "Container network transmit rate"),
+        ("dockyy_container_block_read_bytes_per_second", "Container block read rate"),
+        ("dockyy_container_block_write_bytes_per_second", "Container block write rate"),
+    ];
+    for (i, (name, help)) in families.iter().enumerate() {
+        family(out, name, "gauge", help);
+        for (container, repo_id, environment, values) in &rows {
+            let repo_id = repo_id.map(|id| id.to_string()).unwrap_or_default();
+            let set = labels(&[
+                ("container", container),
+                ("repo_id", &repo_id),
+                ("environment", environment.as_deref().unwrap_or("")),
+            ]);
+            let _ = writeln!(out, "{}{{{}}} {}", name, set, values[i]);
+        }
+    }
+    Ok(())
+}
+
+fn render_jobs(state: &AppState, out: &mut String) -> anyhow::Result<()> {
+    let counts: Vec<(String, i64)> = state.db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT s.status, COUNT(j.id)
+             FROM (SELECT 'pending' AS status UNION ALL SELECT 'running') s
+             LEFT JOIN jobs j ON j.status = s.status
+             GROUP BY s.status",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(counts)
+    })?;
+
+    family(out, "dockyy_jobs", "gauge", "Jobs in the queue by status");
+    for (status, count) in counts {
+        let _ = writeln!(out, "dockyy_jobs{{{}}} {}", labels(&[("status", &status)]), count);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, MockDocker};
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn scrape_with(app: &Router, token: Option<&str>) -> (StatusCode, String) {
+        let mut req = Request::get("/metrics");
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let res = app.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn exports_metrics_to_scrapers_with_the_token() {
+        let docker = MockDocker::start().await;
+        let mut state = test_support::state(&docker).await;
+        let disabled = routes().with_state(state.clone());
+        assert_eq!(scrape_with(&disabled, Some("scrape-secret")).await.0, StatusCode::NOT_FOUND);
+        drop(disabled);
+
+        Arc::get_mut(&mut state).unwrap().config.metrics_token = Some("scrape-secret".to_string());
+        let app = routes().with_state(state.clone());
+        assert_eq!(scrape_with(&app, None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(scrape_with(&app, Some("scrape-secreT")).await.0, StatusCode::UNAUTHORIZED);
+
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute("INSERT INTO jobs (job_type, payload, status) VALUES ('deploy', '{}', 'pending')", [])?;
+                conn.execute(
+                    "INSERT INTO container_stats (ts, resolution, container_name, repo_id, environment, cpu_pct, mem_bytes)
+                     VALUES (strftime('%s', 'now'), ?1, 'dockyy-shop', 1, 'production', 12.5, 2048)",
+                    [RAW_RESOLUTION],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+        state.telemetry.record_webhook("push", 202);
+
+        let (status, body) = scrape_with(&app, Some("scrape-secret")).await;
+        assert_eq!(status, StatusCode::OK);
+        for line in [
+            "# TYPE dockyy_host_cpu_usage_percent gauge",
+            "dockyy_jobs{status=\"pending\"} 1",
+            "dockyy_jobs{status=\"running\"} 0",
+            "dockyy_container_cpu_usage_percent{container=\"dockyy-shop\",repo_id=\"1\",environment=\"production\"} 12.5",
+            "dockyy_container_memory_used_bytes{container=\"dockyy-shop\",repo_id=\"1\",environment=\"production\"} 2048",
+            "dockyy_webhook_deliveries_total{event=\"push\",status=\"202\"} 1",
+        ] {
+            assert!(body.lines().any(|l| l == line), "missing {}:\n{}", line, body);
+        }
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    #[test]
+    fn compares_tokens_exactly() {
+        assert!(token_matches("scrape-secret", "scrape-secret"));
+        assert!(!token_matches("scrape-secre", "scrape-secret"));
+        assert!(!token_matches("", "scrape-secret"));
+    }
+}
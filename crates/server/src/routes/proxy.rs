@@ -2,11 +2,12 @@ use axum::{
     extract::State,
     http::StatusCode,
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde_json::{json, Value};
 use std::sync::Arc;
 
+use crate::auth::Access;
 use crate::AppState;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -37,15 +38,18 @@ async fn proxy_status(
     })))
 }
 
+/// Routed containers; scoped users only see their repositories' routes.
 async fn list_routes(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    let routes = state.traefik.list_routes().await.map_err(|e| {
+    let mut routes = state.traefik.list_routes().await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": e.to_string()})),
         )
     })?;
+    routes.retain(|r| !access.is_scoped() || r.repo_id.is_some_and(|id| access.can_see(id)));
 
     Ok(Json(json!(routes)))
 }
@@ -3,20 +3,24 @@ use axum::{
     http::StatusCode,
     response::sse::{Event, Sse},
     routing::{get, post, put},
-    Json, Router,
+    Extension, Json, Router,
 };
 use futures_util::stream::Stream;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::process::Stdio;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, BufReader};
 
 use crate::db::models::{
-    CreateRepository, DockerComposeUpRequest, Repository, SaveComposeOverrideRequest,
-    UpdateRepository,
+    CreateRepository, DockerComposeUpRequest, Environment, MountKind, MountSpec, Repository,
+    ResourceLimits,
+    SaveComposeOverrideRequest, UpdateRepository, DEFAULT_ENVIRONMENT, adoption, check_owner, validate_network_name,
 };
+use crate::auth::{Access, Claims, Role};
+use crate::services::crypto;
 use crate::AppState;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -31,6 +35,7 @@ pub fn routes() -> Router<Arc<AppState>> {
         .route("/repositories/{id}/filesystem-status", get(get_filesystem_status))
         .route("/repositories/{id}/readme", get(get_readme))
         .route("/repositories/{id}/compose-files", get(get_compose_files))
+        .route("/repositories/{id}/volumes", get(list_repository_volumes))
         .route("/repositories/{id}/clone", post(clone_repository))
         .route("/repositories/{id}/pull", post(pull_repository))
         .route("/repositories/{id}/fetch", post(fetch_repository))
@@ -42,43 +47,68 @@ pub fn routes() -> Router<Arc<AppState>> {
         )
 }
 
+/// SSH keys are write-only and only admins may set them. A non-empty key
+/// must be a PEM-armoured private key.
+fn check_ssh_key(ssh_key: &Option<String>, claims: &Claims) -> Result<(), (StatusCode, Json<Value>)> {
+    let Some(key) = ssh_key else {
+        return Ok(());
+    };
+    if claims.role < Role::Admin {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Only admins can set SSH keys"})),
+        ));
+    }
+    let key = key.trim();
+    let is_private_key = key.starts_with("-----BEGIN ") && key.contains("PRIVATE KEY-----");
+    if !key.is_empty() && !is_private_key {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "SSH key must be a private key in OpenSSH or PEM format"})),
+        ));
+    }
+    Ok(())
+}
+
+/// Encrypted key and fingerprint to store for `repo_id`; both `None` when
+/// the key is empty (removed).
+fn seal_ssh_key(state: &AppState, repo_id: i64, key: &str) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Ok((None, None));
+    }
+    let sealed = state.keyring.encrypt(key, &crypto::ssh_key_context(repo_id))?;
+    Ok((Some(sealed), crypto::ssh_key_fingerprint(key)))
+}
+
+/// Encrypted webhook secret to store for `repo_id`; `None` when empty.
+fn seal_webhook_secret(state: &AppState, repo_id: i64, secret: &str) -> anyhow::Result<Option<String>> {
+    let secret = secret.trim();
+    if secret.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(state.keyring.encrypt(secret, &crypto::webhook_secret_context(repo_id))?))
+}
+
 async fn list_repositories(
     State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
 ) -> Result<Json<Vec<Repository>>, (StatusCode, Json<Value>)> {
     state
         .db
         .with_conn(|conn| {
-            let mut stmt = conn.prepare(
-                "SELECT id, name, owner, url, description, webhook_url, filesystem_path,
-                        ssh_password, is_private, default_branch, domain, proxy_port,
-                        created_at, updated_at
-                 FROM repositories ORDER BY updated_at DESC",
-            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM repositories ORDER BY updated_at DESC",
+                Repository::COLUMNS
+            ))?;
 
             let repos = stmt
-                .query_map([], |row| {
-                    Ok(Repository {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        owner: row.get(2)?,
-                        url: row.get(3)?,
-                        description: row.get(4)?,
-                        webhook_url: row.get(5)?,
-                        filesystem_path: row.get(6)?,
-                        ssh_password: row.get(7)?,
-                        is_private: row.get::<_, i64>(8)? != 0,
-                        default_branch: row.get(9)?,
-                        domain: row.get(10)?,
-                        proxy_port: row.get(11)?,
-                        created_at: row.get(12)?,
-                        updated_at: row.get(13)?,
-                    })
-                })?
+                .query_map([], Repository::from_row)?
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(repos)
         })
-        .map(Json)
+        .map(|repos| Json(repos.into_iter().filter(|r| access.can_see(r.id)).collect()))
         .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -87,41 +117,17 @@ async fn list_repositories(
         })
 }
 
-async fn get_repository(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<i64>,
-) -> Result<Json<Repository>, (StatusCode, Json<Value>)> {
+fn find_repository(state: &AppState, id: i64) -> Result<Repository, (StatusCode, Json<Value>)> {
     state
         .db
         .with_conn(|conn| {
             let repo = conn.query_row(
-                "SELECT id, name, owner, url, description, webhook_url, filesystem_path,
-                        ssh_password, is_private, default_branch, domain, proxy_port,
-                        created_at, updated_at
-                 FROM repositories WHERE id = ?1",
+                &format!("SELECT {} FROM repositories WHERE id = ?1", Repository::COLUMNS),
                 [id],
-                |row| {
-                    Ok(Repository {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        owner: row.get(2)?,
-                        url: row.get(3)?,
-                        description: row.get(4)?,
-                        webhook_url: row.get(5)?,
-                        filesystem_path: row.get(6)?,
-                        ssh_password: row.get(7)?,
-                        is_private: row.get::<_, i64>(8)? != 0,
-                        default_branch: row.get(9)?,
-                        domain: row.get(10)?,
-                        proxy_port: row.get(11)?,
-                        created_at: row.get(12)?,
-                        updated_at: row.get(13)?,
-                    })
-                },
+                Repository::from_row,
             )?;
             Ok(repo)
         })
-        .map(Json)
         .map_err(|e| {
             (
                 StatusCode::NOT_FOUND,
@@ -130,18 +136,47 @@ async fn get_repository(
         })
 }
 
+async fn get_repository(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Repository>, (StatusCode, Json<Value>)> {
+    find_repository(&state, id).map(Json)
+}
+
 async fn create_repository(
     State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
     Json(body): Json<CreateRepository>,
 ) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    check_ssh_key(&body.ssh_key, &claims)?;
+    let preview_env = body
+        .preview_env
+        .as_ref()
+        .map(|env| json!(env).to_string());
+    let resource_limits = match body.resource_limits {
+        Some(ref limits) => {
+            limits
+                .validate()
+                .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+            Some(json!(limits).to_string())
+        }
+        None => None,
+    };
+    let mut adopted = validate_mounts(&state, &claims, None, &body.mounts).await?;
+    let mounts = json!(body.mounts).to_string();
+    adopted.extend(validate_networks(&state, &claims, None, &body.networks).await?);
+    let networks = json!(body.networks).to_string();
+    let adopted = json!(adopted).to_string();
+
     state
         .db
         .with_conn(|conn| {
             conn.execute(
                 "INSERT INTO repositories (name, owner, url, description, webhook_url,
-                    filesystem_path, ssh_password, is_private, default_branch,
-                    domain, proxy_port)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    filesystem_path, is_private, default_branch,
+                    domain, proxy_port, previews_enabled, preview_domain, preview_env,
+                    preview_forks, resource_limits, mounts, networks, adopted)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
                 rusqlite::params![
                     body.name,
                     body.owner,
@@ -149,14 +184,40 @@ async fn create_repository(
                     body.description,
                     body.webhook_url,
                     body.filesystem_path,
-                    body.ssh_password,
                     body.is_private as i64,
                     body.default_branch,
                     body.domain,
                     body.proxy_port,
+                    body.previews_enabled as i64,
+                    body.preview_domain,
+                    preview_env,
+                    body.preview_forks as i64,
+                    resource_limits,
+                    mounts,
+                    networks,
+                    adopted,
                 ],
             )?;
             let id = conn.last_insert_rowid();
+            // Sealed once the id is known, since the key is bound to it.
+            if let Some(ref key) = body.ssh_key {
+                let (sealed, fingerprint) = seal_ssh_key(&state, id, key)?;
+                conn.execute(
+                    "UPDATE repositories SET ssh_key_encrypted = ?2, ssh_key_fingerprint = ?3
+                     WHERE id = ?1",
+                    rusqlite::params![id, sealed, fingerprint],
+                )?;
+            }
+            if let Some(ref secret) = body.webhook_secret {
+                conn.execute(
+                    "UPDATE repositories SET webhook_secret_encrypted = ?2 WHERE id = ?1",
+                    rusqlite::params![id, seal_webhook_secret(&state, id, secret)?],
+                )?;
+            }
+            conn.execute(
+                "INSERT INTO environments (repo_id, name) VALUES (?1, ?2)",
+                rusqlite::params![id, DEFAULT_ENVIRONMENT],
+            )?;
             Ok(id)
         })
         .map(|id| {
@@ -173,11 +234,107 @@ async fn create_repository(
         })
 }
 
+/// Check mounts are well-formed and that named volumes are free for `repo`
+/// (`None` while it is being created) to use. Existing volumes dockyy didn't
+/// create can be attached by admins, and stay attached when others edit the
+/// mounts; returns their [`Repository::adopted`] entries.
+async fn validate_mounts(
+    state: &AppState,
+    claims: &Claims,
+    repo: Option<&Repository>,
+    mounts: &[MountSpec],
+) -> Result<Vec<String>, (StatusCode, Json<Value>)> {
+    let mut targets = std::collections::HashSet::new();
+    let mut adopted = Vec::new();
+    for mount in mounts {
+        mount
+            .validate(&state.config.bind_allowlist)
+            .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+        if !targets.insert(mount.target.as_str()) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("Duplicate mount target '{}'", mount.target)})),
+            ));
+        }
+        if mount.kind == MountKind::Volume {
+            let labels = state.docker.volume_labels(&mount.source).await.map_err(|e| {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
+            })?;
+            if let Some(labels) = labels {
+                check_attachment(claims, repo, "Volume", &mount.source, &labels, &mut adopted)?;
+            }
+        }
+    }
+    Ok(adopted)
+}
+
+/// Check extra networks are valid names that `repo` (`None` while it is
+/// being created) may join. Returns the [`Repository::adopted`] entries, as
+/// [`validate_mounts`] does.
+async fn validate_networks(
+    state: &AppState,
+    claims: &Claims,
+    repo: Option<&Repository>,
+    networks: &[String],
+) -> Result<Vec<String>, (StatusCode, Json<Value>)> {
+    let mut adopted = Vec::new();
+    for name in networks {
+        validate_network_name(name)
+            .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+        let labels = state.docker.network_labels(name).await.map_err(|e| {
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
+        })?;
+        if let Some(labels) = labels {
+            check_attachment(claims, repo, "Network", name, &labels, &mut adopted)?;
+        }
+    }
+    Ok(adopted)
+}
+
+/// Check `repo` may use the existing volume or network `name`, recording it
+/// in `adopted` when dockyy didn't create it for any repository.
+fn check_attachment(
+    claims: &Claims,
+    repo: Option<&Repository>,
+    kind: &str,
+    name: &str,
+    labels: &HashMap<String, String>,
+    adopted: &mut Vec<String>,
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let adopt = claims.role >= Role::Admin || repo.is_some_and(|r| r.adopts(kind, name));
+    check_owner(kind, name, labels, repo.map(|r| r.id), adopt)
+        .map_err(|e| (StatusCode::FORBIDDEN, Json(json!({"error": e}))))?;
+    if !labels.contains_key("dockyy.repo_id") {
+        adopted.push(adoption(kind, name));
+    }
+    Ok(())
+}
+
 async fn update_repository(
     State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
     Path(id): Path<i64>,
     Json(body): Json<UpdateRepository>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    check_ssh_key(&body.ssh_key, &claims)?;
+    if let Some(ref limits) = body.resource_limits {
+        limits
+            .validate()
+            .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    }
+    let current = find_repository(&state, id)?;
+    let mut adopted = current.adopted.clone();
+    if let Some(ref mounts) = body.mounts {
+        let volumes = validate_mounts(&state, &claims, Some(&current), mounts).await?;
+        adopted.retain(|entry| !entry.starts_with("volume:"));
+        adopted.extend(volumes);
+    }
+    if let Some(ref networks) = body.networks {
+        let networks = validate_networks(&state, &claims, Some(&current), networks).await?;
+        adopted.retain(|entry| !entry.starts_with("network:"));
+        adopted.extend(networks);
+    }
+
     state
         .db
         .with_conn(|conn| {
@@ -209,9 +366,12 @@ async fn update_repository(
                 sets.push("filesystem_path = ?");
                 params.push(Box::new(fp.clone()));
             }
-            if let Some(ref sp) = body.ssh_password {
-                sets.push("ssh_password = ?");
-                params.push(Box::new(sp.clone()));
+            if let Some(ref key) = body.ssh_key {
+                let (sealed, fingerprint) = seal_ssh_key(&state, id, key)?;
+                sets.push("ssh_key_encrypted = ?");
+                params.push(Box::new(sealed));
+                sets.push("ssh_key_fingerprint = ?");
+                params.push(Box::new(fingerprint));
             }
             if let Some(is_priv) = body.is_private {
                 sets.push("is_private = ?");
@@ -229,6 +389,42 @@ async fn update_repository(
                 sets.push("proxy_port = ?");
                 params.push(Box::new(pp));
             }
+            if let Some(enabled) = body.previews_enabled {
+                sets.push("previews_enabled = ?");
+                params.push(Box::new(enabled as i64));
+            }
+            if let Some(ref pd) = body.preview_domain {
+                sets.push("preview_domain = ?");
+                params.push(Box::new(pd.clone()));
+            }
+            if let Some(ref env) = body.preview_env {
+                sets.push("preview_env = ?");
+                params.push(Box::new(json!(env).to_string()));
+            }
+            if let Some(forks) = body.preview_forks {
+                sets.push("preview_forks = ?");
+                params.push(Box::new(forks as i64));
+            }
+            if let Some(ref secret) = body.webhook_secret {
+                sets.push("webhook_secret_encrypted = ?");
+                params.push(Box::new(seal_webhook_secret(&state, id, secret)?));
+            }
+            if let Some(ref limits) = body.resource_limits {
+                sets.push("resource_limits = ?");
+                params.push(Box::new(json!(limits).to_string()));
+            }
+            if let Some(ref mounts) = body.mounts {
+                sets.push("mounts = ?");
+                params.push(Box::new(json!(mounts).to_string()));
+            }
+            if let Some(ref networks) = body.networks {
+                sets.push("networks = ?");
+                params.push(Box::new(json!(networks).to_string()));
+            }
+            if adopted != current.adopted {
+                sets.push("adopted = ?");
+                params.push(Box::new(json!(adopted).to_string()));
+            }
 
             if sets.is_empty() {
                 anyhow::bail!("No fields to update");
@@ -279,6 +475,84 @@ async fn delete_repository(
         })
 }
 
+/// Persistent mounts of every environment with their current size on disk.
+async fn list_repository_volumes(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let repo = find_repository(&state, id)?;
+
+    let envs = state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM environments WHERE repo_id = ?1 ORDER BY name",
+                Environment::COLUMNS
+            ))?;
+            let envs = stmt
+                .query_map([id], Environment::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(envs)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    let usage = state.docker.volume_usage().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    let mut volumes = Vec::new();
+    for env in &envs {
+        for mount in env.mounts(&repo) {
+            let (size_bytes, ref_count) = match mount.kind {
+                MountKind::Volume => usage
+                    .get(&mount.source)
+                    .map(|u| (Some(u.size).filter(|s| *s >= 0), Some(u.ref_count)))
+                    .unwrap_or((None, None)),
+                MountKind::Bind => {
+                    let path = mount.source.clone();
+                    let size = tokio::task::spawn_blocking(move || dir_size(std::path::Path::new(&path)))
+                        .await
+                        .ok()
+                        .flatten();
+                    (size, None)
+                }
+            };
+            volumes.push(json!({
+                "environment": env.name,
+                "kind": mount.kind,
+                "source": mount.source,
+                "target": mount.target,
+                "read_only": mount.read_only,
+                "size_bytes": size_bytes,
+                "ref_count": ref_count,
+            }));
+        }
+    }
+
+    Ok(Json(json!(volumes)))
+}
+
+/// Total size of regular files under `path` (symlinks are not followed).
+fn dir_size(path: &std::path::Path) -> Option<i64> {
+    let meta = std::fs::symlink_metadata(path).ok()?;
+    if !meta.is_dir() {
+        return Some(meta.len() as i64);
+    }
+    let mut total = 0i64;
+    for entry in std::fs::read_dir(path).ok()?.flatten() {
+        total += dir_size(&entry.path()).unwrap_or(0);
+    }
+    Some(total)
+}
+
 async fn get_filesystem_status(
     State(state): State<Arc<AppState>>,
     Path(id): Path<i64>,
@@ -338,6 +612,16 @@ async fn get_readme(
     })))
 }
 
+/// Resolve the environment a compose request targets (production when omitted).
+fn load_environment(
+    state: &AppState,
+    repo_id: i64,
+    name: Option<&str>,
+) -> Result<Environment, (StatusCode, Json<Value>)> {
+    crate::services::worker::load_environment(state, repo_id, name.unwrap_or(DEFAULT_ENVIRONMENT))
+        .map_err(|e| (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()}))))
+}
+
 fn override_dir(data_dir: &str, repo_id: i64) -> String {
     format!("{}/compose-overrides/{}", data_dir, repo_id)
 }
@@ -370,16 +654,16 @@ async fn get_compose_files(
         paths.sort();
 
         for cp in paths {
-            if let Ok(c) = std::fs::read_to_string(&cp) {
-                if let Some(name) = cp.file_name().and_then(|n| n.to_str()) {
-                    let override_content =
-                        std::fs::read_to_string(format!("{}/{}", ovr_dir, name)).ok();
-                    files.push(json!({
-                        "path": name,
-                        "content": c,
-                        "override_content": override_content
-                    }));
-                }
+            if let Ok(c) = std::fs::read_to_string(&cp)
+                && let Some(name) = cp.file_name().and_then(|n| n.to_str())
+            {
+                let override_content =
+                    std::fs::read_to_string(format!("{}/{}", ovr_dir, name)).ok();
+                files.push(json!({
+                    "path": name,
+                    "content": c,
+                    "override_content": override_content
+                }));
             }
         }
     }
@@ -391,7 +675,7 @@ async fn clone_repository(
     State(state): State<Arc<AppState>>,
     Path(id): Path<i64>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    let repo = get_repository(State(state.clone()), Path(id)).await?.0;
+    let repo = find_repository(&state, id)?;
     
     let repo_dir = format!("{}/repos/{}", state.config.data_dir, id);
     let _ = std::fs::remove_dir_all(&repo_dir);
@@ -399,26 +683,14 @@ async fn clone_repository(
     
     let mut cmd = tokio::process::Command::new(&state.config.git_bin);
 
-    let mut temp_key_path = None;
-    if let Some(ssh_key) = &repo.ssh_password {
-        if !ssh_key.trim().is_empty() {
-            let key_path = format!("{}/repos/{}_id_rsa", state.config.data_dir, id);
-            std::fs::write(&key_path, ssh_key.trim()).unwrap();
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&key_path).unwrap().permissions();
-                perms.set_mode(0o600);
-                std::fs::set_permissions(&key_path, perms).unwrap();
-            }
-            cmd.env("GIT_SSH_COMMAND", format!("ssh -i {} -o StrictHostKeyChecking=no", key_path));
-            temp_key_path = Some(key_path);
-        } else {
-            cmd.env("GIT_SSH_COMMAND", "ssh -o StrictHostKeyChecking=no");
-        }
-    } else {
-        cmd.env("GIT_SSH_COMMAND", "ssh -o StrictHostKeyChecking=no");
-    }
+    let (git_ssh_command, temp_key_path) =
+        crate::services::worker::git_ssh_command(&state, &repo, &id.to_string()).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("{:#}", e)})),
+            )
+        })?;
+    cmd.env("GIT_SSH_COMMAND", git_ssh_command);
 
     tracing::info!(
         git_bin = %state.config.git_bin,
@@ -456,63 +728,250 @@ async fn clone_repository(
     Ok(Json(json!({"message": "Repository cloned successfully"})))
 }
 
-fn find_compose_bin() -> &'static str {
-    if std::path::Path::new("/usr/bin/podman-compose").exists() {
-        "/usr/bin/podman-compose"
-    } else if std::path::Path::new("/usr/local/bin/podman-compose").exists() {
-        "/usr/local/bin/podman-compose"
-    } else if std::path::Path::new("/usr/bin/docker-compose").exists() {
-        "/usr/bin/docker-compose"
-    } else if std::path::Path::new("/usr/local/bin/docker-compose").exists() {
-        "/usr/local/bin/docker-compose"
+/// Compose file names picked up by `docker compose` when no `-f` is given.
+const DEFAULT_COMPOSE_FILES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// Override files `docker compose` merges into the default file, in the
+/// order it looks for them.
+const DEFAULT_COMPOSE_OVERRIDE_FILES: [&str; 4] = [
+    "compose.override.yaml",
+    "compose.override.yml",
+    "docker-compose.override.yaml",
+    "docker-compose.override.yml",
+];
+
+/// What dockyy applies to every compose service of an environment.
+#[derive(Clone, Default)]
+struct ServiceOverrides {
+    limits: ResourceLimits,
+    labels: Vec<(String, String)>,
+    mounts: Vec<MountSpec>,
+}
+
+impl ServiceOverrides {
+    fn new(repo: &Repository, env: &Environment) -> Self {
+        Self {
+            limits: env.resource_limits(repo),
+            labels: env.labels(),
+            mounts: env.mounts(repo),
+        }
+    }
+}
+
+/// Render a compose override applying `overrides` to every service in
+/// `compose_yaml`. Named volumes are created by dockyy beforehand, so they
+/// are declared external.
+fn compose_override(compose_yaml: &str, overrides: &ServiceOverrides) -> anyhow::Result<String> {
+    let limits = &overrides.limits;
+    let labels = &overrides.labels;
+    let doc: serde_yaml::Value = serde_yaml::from_str(compose_yaml)?;
+    let services = doc
+        .get("services")
+        .and_then(|s| s.as_mapping())
+        .ok_or_else(|| anyhow::anyhow!("Compose file has no services"))?;
+
+    let mut service = serde_yaml::Mapping::new();
+    let mut set = |key: &str, value: serde_yaml::Value| {
+        service.insert(key.into(), value);
+    };
+    if let Some(v) = limits.memory_limit {
+        set("mem_limit", v.into());
+    }
+    if let Some(v) = limits.memory_reservation {
+        set("mem_reservation", v.into());
+    }
+    if let Some(v) = limits.cpus {
+        set("cpus", v.into());
+    }
+    if let Some(v) = limits.cpu_shares {
+        set("cpu_shares", v.into());
+    }
+    if let Some(v) = limits.cpu_quota {
+        set("cpu_quota", v.into());
+    }
+    if let Some(v) = limits.cpu_period {
+        set("cpu_period", v.into());
+    }
+    if let Some(v) = limits.pids_limit {
+        set("pids_limit", v.into());
+    }
+    if let Some(ref v) = limits.restart_policy {
+        set("restart", v.clone().into());
+    }
+    if let Some(ref driver) = limits.log_driver {
+        let mut logging = serde_yaml::Mapping::new();
+        logging.insert("driver".into(), driver.clone().into());
+        if !limits.log_options.is_empty() {
+            logging.insert("options".into(), serde_yaml::to_value(&limits.log_options)?);
+        }
+        set("logging", logging.into());
+    }
+    if !labels.is_empty() {
+        let labels: serde_yaml::Mapping = labels
+            .iter()
+            .map(|(k, v)| (k.clone().into(), v.clone().into()))
+            .collect();
+        set("labels", labels.into());
+    }
+    let mut volumes = serde_yaml::Mapping::new();
+    if !overrides.mounts.is_empty() {
+        let mounts: Vec<serde_yaml::Value> = overrides
+            .mounts
+            .iter()
+            .map(|m| {
+                let mode = if m.read_only { ":ro" } else { "" };
+                format!("{}:{}{}", m.source, m.target, mode).into()
+            })
+            .collect();
+        set("volumes", mounts.into());
+        for mount in overrides.mounts.iter().filter(|m| m.kind == MountKind::Volume) {
+            let mut volume = serde_yaml::Mapping::new();
+            volume.insert("external".into(), true.into());
+            volumes.insert(mount.source.clone().into(), volume.into());
+        }
+    }
+
+    let mut services_override = serde_yaml::Mapping::new();
+    for name in services.keys() {
+        services_override.insert(name.clone(), service.clone().into());
+    }
+    let mut root = serde_yaml::Mapping::new();
+    root.insert("services".into(), services_override.into());
+    if !volumes.is_empty() {
+        root.insert("volumes".into(), volumes.into());
+    }
+
+    Ok(serde_yaml::to_string(&root)?)
+}
+
+/// First of `names` present in `dir`.
+fn existing_file(dir: &str, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find(|f| std::path::Path::new(dir).join(f).exists())
+        .map(|f| f.to_string())
+}
+
+/// Pass `file` to compose with `-f`, substituting the dashboard's override of
+/// it when one was saved. Returns the content compose will read.
+fn add_compose_file(
+    cmd: &mut tokio::process::Command,
+    temp_files: &mut Vec<String>,
+    repo_dir: &str,
+    ovr_dir: &str,
+    file: &str,
+) -> std::io::Result<Option<String>> {
+    let override_path = format!("{}/{}", ovr_dir, file);
+    if std::path::Path::new(&override_path).exists() {
+        let tmp_name = format!(".dockyy-override-{}", file);
+        let tmp_path = format!("{}/{}", repo_dir, tmp_name);
+        let content = std::fs::read_to_string(&override_path)?;
+        std::fs::write(&tmp_path, &content)?;
+        cmd.arg("-f").arg(&tmp_name);
+        temp_files.push(tmp_path);
+        Ok(Some(content))
     } else {
-        "docker-compose"
+        cmd.arg("-f").arg(file);
+        Ok(std::fs::read_to_string(format!("{}/{}", repo_dir, file)).ok())
     }
 }
 
+/// Build the compose command. Returns the command plus temporary files written
+/// into `repo_dir` that must be removed once compose has finished.
 fn setup_compose_cmd(
     compose_bin: &str,
+    docker_host: &str,
     container_name: &str,
     repo_dir: &str,
     ovr_dir: &str,
     compose_file: Option<&str>,
-) -> Result<(tokio::process::Command, Option<String>), (StatusCode, Json<Value>)> {
+    overrides: &ServiceOverrides,
+) -> Result<(tokio::process::Command, Vec<String>), (StatusCode, Json<Value>)> {
     let mut cmd = tokio::process::Command::new(compose_bin);
+    cmd.env("DOCKER_HOST", docker_host);
     cmd.arg("-p").arg(container_name);
 
-    let mut temp_override_path: Option<String> = None;
+    let mut temp_files: Vec<String> = Vec::new();
+    let internal = |e: std::io::Error| {
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
+    };
+
+    // Resolve the base file up front: dockyy's override must come after it.
+    // Compose only merges `*.override.yml` on its own without `-f`, so once
+    // the base file is named the override has to be as well.
+    let (compose_file, auto_override) = match compose_file {
+        Some(file) => (Some(file.to_string()), None),
+        None => (
+            existing_file(repo_dir, &DEFAULT_COMPOSE_FILES),
+            existing_file(repo_dir, &DEFAULT_COMPOSE_OVERRIDE_FILES),
+        ),
+    };
 
-    if let Some(file) = compose_file {
+    let mut base_content = None;
+    if let Some(file) = compose_file.as_deref() {
         if file.contains('/') || file.contains('\\') {
             return Err((
                 StatusCode::BAD_REQUEST,
                 Json(json!({"error": "Invalid compose file name"})),
             ));
         }
-        let override_path = format!("{}/{}", ovr_dir, file);
-        if std::path::Path::new(&override_path).exists() {
-            let tmp_name = format!(".dockyy-override-{}", file);
-            let tmp_path = format!("{}/{}", repo_dir, tmp_name);
-            let content = std::fs::read_to_string(&override_path).map_err(|e| {
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
-            })?;
-            std::fs::write(&tmp_path, &content).map_err(|e| {
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
-            })?;
-            cmd.arg("-f").arg(&tmp_name);
-            temp_override_path = Some(tmp_path);
-        } else {
-            cmd.arg("-f").arg(file);
+        base_content = add_compose_file(&mut cmd, &mut temp_files, repo_dir, ovr_dir, file).map_err(internal)?;
+        if let Some(file) = auto_override.as_deref() {
+            add_compose_file(&mut cmd, &mut temp_files, repo_dir, ovr_dir, file).map_err(internal)?;
         }
     }
 
+    // Labels let stats and repository grants attribute the services'
+    // containers, which compose names after the project, not the repository.
+    if let Some(content) = base_content {
+        let mut labels = overrides.labels.clone();
+        labels.push(("dockyy.managed".to_string(), "true".to_string()));
+        let overrides = ServiceOverrides { labels, ..overrides.clone() };
+        let rendered = compose_override(&content, &overrides).map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("Cannot apply labels, mounts and resource limits: {}", e)})),
+            )
+        })?;
+        let tmp_name = ".dockyy-compose.yml";
+        let tmp_path = format!("{}/{}", repo_dir, tmp_name);
+        std::fs::write(&tmp_path, rendered).map_err(internal)?;
+        cmd.arg("-f").arg(tmp_name);
+        temp_files.push(tmp_path);
+    }
+
     cmd.arg("up")
         .arg("-d")
         .arg("--build")
         .current_dir(repo_dir)
         .stdin(Stdio::null());
 
-    Ok((cmd, temp_override_path))
+    Ok((cmd, temp_files))
+}
+
+/// Create the volumes the compose services mount, cleaning up the compose
+/// command's temporary files when that fails.
+async fn prepare_compose_mounts(
+    state: &AppState,
+    repo: &Repository,
+    overrides: &ServiceOverrides,
+    temp_files: &[String],
+) -> Result<(), (StatusCode, Json<Value>)> {
+    let prepared = crate::services::worker::prepare_attachments(state, repo, &overrides.mounts, &[]).await;
+    prepared.map_err(|e| {
+        for tmp in temp_files {
+            let _ = std::fs::remove_file(tmp);
+        }
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Cannot attach mounts: {}", e)})),
+        )
+    })
 }
 
 async fn docker_compose_up(
@@ -520,21 +979,31 @@ async fn docker_compose_up(
     Path(id): Path<i64>,
     Json(body): Json<DockerComposeUpRequest>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    let repo = get_repository(State(state.clone()), Path(id)).await?.0;
-    let repo_dir = format!("{}/repos/{}", state.config.data_dir, id);
+    let repo = find_repository(&state, id)?;
+    let env = load_environment(&state, id, body.environment.as_deref())?;
+    let repo_dir = env.checkout_dir(&state.config.data_dir);
     let ovr_dir = override_dir(&state.config.data_dir, id);
-    let container_name = format!("dockyy-{}", repo.name.to_lowercase().replace("/", "-"));
+    let container_name = env.container_name(&repo);
 
-    let compose_bin = find_compose_bin();
-    let (mut cmd, temp_override_path) = setup_compose_cmd(
-        compose_bin, &container_name, &repo_dir, &ovr_dir, body.compose_file.as_deref(),
+    let compose_bin = state.docker.compose_bin().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let overrides = ServiceOverrides::new(&repo, &env);
+    let (mut cmd, temp_files) = setup_compose_cmd(
+        compose_bin, state.docker.docker_host(), &container_name, &repo_dir, &ovr_dir, body.compose_file.as_deref(),
+        &overrides,
     )?;
+    cmd.envs(env.env_vars());
+    prepare_compose_mounts(&state, &repo, &overrides, &temp_files).await?;
 
     // Create deployment record
     let deployment_id = state.db.with_conn(|conn| {
         conn.execute(
-            "INSERT INTO deployments (repo_id, status, domain, port) VALUES (?1, 'building', ?2, ?3)",
-            rusqlite::params![id, repo.domain, repo.proxy_port],
+            "INSERT INTO deployments (repo_id, environment_id, status, domain, port) VALUES (?1, ?2, 'building', ?3, ?4)",
+            rusqlite::params![id, env.id, env.domain(&repo), env.proxy_port(&repo)],
         )?;
         Ok(conn.last_insert_rowid())
     }).map_err(|e: anyhow::Error| {
@@ -545,7 +1014,7 @@ async fn docker_compose_up(
         (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e.to_string()})))
     })?;
 
-    if let Some(tmp) = temp_override_path {
+    for tmp in temp_files {
         let _ = std::fs::remove_file(tmp);
     }
 
@@ -583,6 +1052,7 @@ async fn docker_compose_up(
 #[derive(Deserialize)]
 struct ComposeStreamQuery {
     compose_file: Option<String>,
+    environment: Option<String>,
 }
 
 async fn docker_compose_up_stream(
@@ -590,23 +1060,33 @@ async fn docker_compose_up_stream(
     Path(id): Path<i64>,
     Query(query): Query<ComposeStreamQuery>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Value>)> {
-    let repo = get_repository(State(state.clone()), Path(id)).await?.0;
-    let repo_dir = format!("{}/repos/{}", state.config.data_dir, id);
+    let repo = find_repository(&state, id)?;
+    let env = load_environment(&state, id, query.environment.as_deref())?;
+    let repo_dir = env.checkout_dir(&state.config.data_dir);
     let ovr_dir = override_dir(&state.config.data_dir, id);
-    let container_name = format!("dockyy-{}", repo.name.to_lowercase().replace("/", "-"));
+    let container_name = env.container_name(&repo);
 
-    let compose_bin = find_compose_bin();
-    let (mut cmd, temp_override_path) = setup_compose_cmd(
-        compose_bin, &container_name, &repo_dir, &ovr_dir, query.compose_file.as_deref(),
+    let compose_bin = state.docker.compose_bin().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let overrides = ServiceOverrides::new(&repo, &env);
+    let (mut cmd, temp_files) = setup_compose_cmd(
+        compose_bin, state.docker.docker_host(), &container_name, &repo_dir, &ovr_dir, query.compose_file.as_deref(),
+        &overrides,
     )?;
+    cmd.envs(env.env_vars());
+    prepare_compose_mounts(&state, &repo, &overrides, &temp_files).await?;
 
     // Create deployment record
-    let domain = repo.domain.clone();
-    let proxy_port = repo.proxy_port;
+    let domain = env.domain(&repo).map(str::to_string);
+    let proxy_port = env.proxy_port(&repo);
     let deployment_id = state.db.with_conn(|conn| {
         conn.execute(
-            "INSERT INTO deployments (repo_id, status, domain, port) VALUES (?1, 'building', ?2, ?3)",
-            rusqlite::params![id, domain, proxy_port],
+            "INSERT INTO deployments (repo_id, environment_id, status, domain, port) VALUES (?1, ?2, 'building', ?3, ?4)",
+            rusqlite::params![id, env.id, domain, proxy_port],
         )?;
         Ok(conn.last_insert_rowid())
     }).map_err(|e: anyhow::Error| {
@@ -658,7 +1138,7 @@ async fn docker_compose_up_stream(
 
         let status = child.wait().await;
 
-        if let Some(tmp) = temp_override_path {
+        for tmp in temp_files {
             let _ = std::fs::remove_file(tmp);
         }
 
@@ -751,7 +1231,7 @@ async fn pull_repository(
     State(state): State<Arc<AppState>>,
     Path(id): Path<i64>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    let repo = get_repository(State(state.clone()), Path(id)).await?.0;
+    let repo = find_repository(&state, id)?;
     
     let repo_dir = format!("{}/repos/{}", state.config.data_dir, id);
     if !std::path::Path::new(&repo_dir).join(".git").exists() {
@@ -761,26 +1241,14 @@ async fn pull_repository(
     let mut cmd = tokio::process::Command::new(&state.config.git_bin);
     cmd.current_dir(&repo_dir);
     
-    let mut temp_key_path = None;
-    if let Some(ssh_key) = &repo.ssh_password {
-        if !ssh_key.trim().is_empty() {
-            let key_path = format!("{}/repos/{}_id_rsa", state.config.data_dir, id);
-            std::fs::write(&key_path, ssh_key.trim()).unwrap();
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&key_path).unwrap().permissions();
-                perms.set_mode(0o600);
-                std::fs::set_permissions(&key_path, perms).unwrap();
-            }
-            cmd.env("GIT_SSH_COMMAND", format!("ssh -i {} -o StrictHostKeyChecking=no", key_path));
-            temp_key_path = Some(key_path);
-        } else {
-            cmd.env("GIT_SSH_COMMAND", "ssh -o StrictHostKeyChecking=no");
-        }
-    } else {
-        cmd.env("GIT_SSH_COMMAND", "ssh -o StrictHostKeyChecking=no");
-    }
+    let (git_ssh_command, temp_key_path) =
+        crate::services::worker::git_ssh_command(&state, &repo, &id.to_string()).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("{:#}", e)})),
+            )
+        })?;
+    cmd.env("GIT_SSH_COMMAND", git_ssh_command);
 
     let output = cmd
         .arg("pull")
@@ -803,7 +1271,7 @@ async fn fetch_repository(
     State(state): State<Arc<AppState>>,
     Path(id): Path<i64>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    let repo = get_repository(State(state.clone()), Path(id)).await?.0;
+    let repo = find_repository(&state, id)?;
     
     let repo_dir = format!("{}/repos/{}", state.config.data_dir, id);
     if !std::path::Path::new(&repo_dir).join(".git").exists() {
@@ -813,26 +1281,14 @@ async fn fetch_repository(
     let mut cmd = tokio::process::Command::new(&state.config.git_bin);
     cmd.current_dir(&repo_dir);
     
-    let mut temp_key_path = None;
-    if let Some(ssh_key) = &repo.ssh_password {
-        if !ssh_key.trim().is_empty() {
-            let key_path = format!("{}/repos/{}_id_rsa", state.config.data_dir, id);
-            std::fs::write(&key_path, ssh_key.trim()).unwrap();
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&key_path).unwrap().permissions();
-                perms.set_mode(0o600);
-                std::fs::set_permissions(&key_path, perms).unwrap();
-            }
-            cmd.env("GIT_SSH_COMMAND", format!("ssh -i {} -o StrictHostKeyChecking=no", key_path));
-            temp_key_path = Some(key_path);
-        } else {
-            cmd.env("GIT_SSH_COMMAND", "ssh -o StrictHostKeyChecking=no");
-        }
-    } else {
-        cmd.env("GIT_SSH_COMMAND", "ssh -o StrictHostKeyChecking=no");
-    }
+    let (git_ssh_command, temp_key_path) =
+        crate::services::worker::git_ssh_command(&state, &repo, &id.to_string()).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("{:#}", e)})),
+            )
+        })?;
+    cmd.env("GIT_SSH_COMMAND", git_ssh_command);
 
     let output = cmd
         .arg("fetch")
@@ -850,3 +1306,113 @@ async fn fetch_repository(
     
     Ok(Json(json!({"message": "Repository fetched successfully"})))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides() -> ServiceOverrides {
+        ServiceOverrides {
+            labels: vec![
+                ("dockyy.repo_id".to_string(), "3".to_string()),
+                ("dockyy.environment".to_string(), "staging".to_string()),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compose_override_covers_every_service() {
+        let compose = "services:\n  web:\n    image: nginx\n  worker:\n    build: .\n";
+        let limits = ResourceLimits {
+            memory_limit: Some(256 * 1024 * 1024),
+            cpus: Some(0.5),
+            restart_policy: Some("on-failure:3".to_string()),
+            log_driver: Some("json-file".to_string()),
+            log_options: [("max-size".to_string(), "10m".to_string())].into(),
+            ..Default::default()
+        };
+
+        let mounts = vec![
+            MountSpec {
+                kind: MountKind::Volume,
+                source: "shop-data-staging".to_string(),
+                target: "/data".to_string(),
+                read_only: false,
+            },
+            MountSpec {
+                kind: MountKind::Bind,
+                source: "/srv/shop/config".to_string(),
+                target: "/etc/shop".to_string(),
+                read_only: true,
+            },
+        ];
+
+        let rendered = compose_override(compose, &ServiceOverrides { limits, mounts, ..overrides() }).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&rendered).unwrap();
+        assert_eq!(doc["volumes"]["shop-data-staging"]["external"].as_bool(), Some(true));
+        assert!(doc["volumes"].get("/srv/shop/config").is_none());
+        for service in ["web", "worker"] {
+            let s = &doc["services"][service];
+            assert_eq!(s["mem_limit"].as_i64(), Some(256 * 1024 * 1024));
+            assert_eq!(s["cpus"].as_f64(), Some(0.5));
+            assert_eq!(s["restart"].as_str(), Some("on-failure:3"));
+            assert_eq!(s["logging"]["driver"].as_str(), Some("json-file"));
+            assert_eq!(s["logging"]["options"]["max-size"].as_str(), Some("10m"));
+            assert_eq!(s["labels"]["dockyy.repo_id"].as_str(), Some("3"));
+            assert_eq!(s["labels"]["dockyy.environment"].as_str(), Some("staging"));
+            assert_eq!(
+                s["volumes"],
+                serde_yaml::from_str::<serde_yaml::Value>("[shop-data-staging:/data, /srv/shop/config:/etc/shop:ro]").unwrap()
+            );
+            assert!(s.get("pids_limit").is_none());
+            assert!(s.get("image").is_none());
+        }
+    }
+
+    #[test]
+    fn compose_names_the_override_file_before_dockyys() {
+        let repo_dir = crate::test_support::temp_dir();
+        let ovr_dir = crate::test_support::temp_dir();
+        let repo = repo_dir.to_str().unwrap();
+        std::fs::write(repo_dir.join("docker-compose.yml"), "services:\n  web:\n    image: nginx\n").unwrap();
+        std::fs::write(repo_dir.join("docker-compose.override.yml"), "services:\n  web:\n    ports: ['80:80']\n").unwrap();
+
+        let (cmd, temp_files) = setup_compose_cmd(
+            "docker-compose", "unix:///var/run/docker.sock", "dockyy-web", repo, ovr_dir.to_str().unwrap(), None,
+            &overrides(),
+        )
+        .unwrap();
+        let args: Vec<String> = cmd.as_std().get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(
+            args,
+            [
+                "-p", "dockyy-web",
+                "-f", "docker-compose.yml",
+                "-f", "docker-compose.override.yml",
+                "-f", ".dockyy-compose.yml",
+                "up", "-d", "--build",
+            ]
+        );
+        assert_eq!(temp_files, [format!("{}/.dockyy-compose.yml", repo)]);
+        let rendered = std::fs::read_to_string(&temp_files[0]).unwrap();
+        assert!(rendered.contains("dockyy.repo_id") && rendered.contains("dockyy.managed"), "{}", rendered);
+
+        // An explicitly chosen file isn't merged with the default override.
+        let (cmd, _) = setup_compose_cmd(
+            "docker-compose", "unix:///var/run/docker.sock", "dockyy-web", repo, ovr_dir.to_str().unwrap(), Some("docker-compose.yml"),
+            &overrides(),
+        )
+        .unwrap();
+        assert!(!cmd.as_std().get_args().any(|a| a == "docker-compose.override.yml"));
+
+        let _ = std::fs::remove_dir_all(repo_dir);
+        let _ = std::fs::remove_dir_all(ovr_dir);
+    }
+
+    #[test]
+    fn compose_override_needs_services() {
+        assert!(compose_override("version: '3'\n", &ServiceOverrides::default()).is_err());
+        assert!(compose_override("services: [", &ServiceOverrides::default()).is_err());
+    }
+}
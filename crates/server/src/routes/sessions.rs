@@ -0,0 +1,133 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::auth::{Claims, Role};
+use crate::db::models::Session;
+use crate::AppState;
+
+/// Logout and session management, open to every role.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/auth/logout", post(logout))
+        .route("/auth/sessions", get(list_sessions))
+        .route("/auth/sessions/{id}", delete(revoke_session))
+}
+
+/// The caller's session id; API tokens have none and are revoked instead.
+fn session_id(claims: &Claims) -> Result<&str, (StatusCode, Json<Value>)> {
+    claims.sid.as_deref().ok_or((
+        StatusCode::FORBIDDEN,
+        Json(json!({"error": "API tokens have no session; revoke the token instead"})),
+    ))
+}
+
+#[derive(Deserialize)]
+struct LogoutQuery {
+    /// Also end every other session of the caller.
+    all: Option<bool>,
+}
+
+/// Revoke the current session, or all of the caller's sessions with `?all=true`.
+async fn logout(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Query(query): Query<LogoutQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let sid = session_id(&claims)?;
+    let all = query.all.unwrap_or(false);
+
+    let revoked = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.execute(
+                "UPDATE sessions SET revoked_at = datetime('now')
+                 WHERE revoked_at IS NULL
+                   AND (id = ?1
+                        OR (?2 AND user_id = (SELECT id FROM users WHERE username = ?3)))",
+                rusqlite::params![sid, all, claims.sub],
+            )?)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    Ok(Json(json!({"message": "Logged out", "revoked": revoked})))
+}
+
+/// Active sessions of the caller; admins see everyone's.
+async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<Session>>, (StatusCode, Json<Value>)> {
+    let sid = session_id(&claims)?;
+    let everyone = claims.role == Role::Admin;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM sessions s JOIN users u ON u.id = s.user_id
+                 WHERE s.revoked_at IS NULL AND s.expires_at > datetime('now')
+                   AND (?1 OR u.username = ?2)
+                 ORDER BY s.last_used_at DESC",
+                Session::COLUMNS
+            ))?;
+            let sessions = stmt
+                .query_map(rusqlite::params![everyone, claims.sub], |row| {
+                    Session::from_row(row, Some(sid))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(sessions)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Revoke one of the caller's sessions; admins can revoke any.
+async fn revoke_session(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    session_id(&claims)?;
+    let everyone = claims.role == Role::Admin;
+
+    let revoked = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.execute(
+                "UPDATE sessions SET revoked_at = datetime('now')
+                 WHERE id = ?1 AND revoked_at IS NULL
+                   AND (?2 OR user_id = (SELECT id FROM users WHERE username = ?3))",
+                rusqlite::params![id, everyone, claims.sub],
+            )?)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+    if revoked == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Session not found"})),
+        ));
+    }
+    Ok(Json(json!({"message": "Session revoked"})))
+}
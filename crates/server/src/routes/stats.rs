@@ -0,0 +1,125 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Extension, Json, Router,
+};
+use futures_util::stream::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::auth::Access;
+use crate::db::models::{AppUsage, ContainerStatsSeries};
+use crate::routes::containers::parse_log_time;
+use crate::services::container_stats;
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/repositories/{id}/stats", get(repository_stats))
+        .route("/stats/apps", get(app_usage))
+        .route("/containers/{id}/stats/stream", get(stream_stats))
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    /// Relative window such as `1h`, `24h` or `7d`. Defaults to `24h`.
+    range: Option<String>,
+    /// Explicit bounds (Unix, RFC 3339 or relative); override `range`.
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl RangeQuery {
+    fn bounds(&self) -> Result<(i64, i64), (StatusCode, Json<Value>)> {
+        let bad = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+        let from = match (&self.from, &self.range) {
+            (Some(from), _) => parse_log_time(from).map_err(bad)?,
+            (None, Some(range)) => parse_log_time(range).map_err(bad)?,
+            (None, None) => chrono::Utc::now().timestamp() - 24 * 3600,
+        };
+        let to = match &self.to {
+            Some(to) => parse_log_time(to).map_err(bad)?,
+            None => chrono::Utc::now().timestamp(),
+        };
+        if from > to {
+            return Err(bad("'from' must be before 'to'".to_string()));
+        }
+        Ok((from, to))
+    }
+}
+
+/// Resource history of a repository's containers, one series per container.
+async fn repository_stats(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Query(query): Query<RangeQuery>,
+) -> Result<Json<Vec<ContainerStatsSeries>>, (StatusCode, Json<Value>)> {
+    let (from, to) = query.bounds()?;
+    crate::services::worker::load_repository(&state, id).map_err(|e| {
+        (StatusCode::NOT_FOUND, Json(json!({"error": e.to_string()})))
+    })?;
+
+    container_stats::repository_series(&state.db, id, from, to)
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Average and peak usage per application over the range, heaviest first.
+async fn app_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Query(query): Query<RangeQuery>,
+) -> Result<Json<Vec<AppUsage>>, (StatusCode, Json<Value>)> {
+    let (from, to) = query.bounds()?;
+
+    container_stats::app_usage(&state.db, from, to)
+        .map(|apps| Json(apps.into_iter().filter(|a| access.can_see(a.repo_id)).collect()))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Live stats for one container as server-sent events, roughly one per second.
+async fn stream_stats(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Value>)> {
+    state.docker.inspect_container(&id).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    let stats = state.docker.stream_stats(&id);
+    let stream = async_stream::stream! {
+        futures_util::pin_mut!(stats);
+        while let Some(item) = stats.next().await {
+            match item {
+                Ok(s) => match Event::default().json_data(&s) {
+                    Ok(event) => yield Ok::<_, Infallible>(event),
+                    Err(e) => tracing::debug!("Skipping unserialisable stats sample: {}", e),
+                },
+                Err(e) => {
+                    yield Ok(Event::default().event("error").data(e.to_string()));
+                    return;
+                }
+            }
+        }
+        yield Ok(Event::default().event("done").data("Stats stream ended"));
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
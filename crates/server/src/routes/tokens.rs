@@ -0,0 +1,254 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Extension, Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::auth::{api_tokens, Access, Claims, Role};
+use crate::db::models::{ApiToken, CreateApiToken, TokenScope};
+use crate::AppState;
+
+/// Self-service API token management, open to every role.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/auth/tokens", get(list_tokens).post(create_token))
+        .route("/auth/tokens/{id}", delete(revoke_token))
+}
+
+/// Tokens can't mint or revoke tokens, so a leaked one can't entrench itself.
+fn require_login(access: &Access) -> Result<(), (StatusCode, Json<Value>)> {
+    if access.token_id.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "API tokens cannot manage API tokens"})),
+        ));
+    }
+    Ok(())
+}
+
+/// The caller's tokens; admins see everyone's.
+async fn list_tokens(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<ApiToken>>, (StatusCode, Json<Value>)> {
+    let everyone = claims.role == Role::Admin;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM api_tokens t JOIN users u ON u.id = t.user_id
+                 WHERE ?1 OR u.username = ?2
+                 ORDER BY t.id DESC",
+                ApiToken::COLUMNS
+            ))?;
+            let tokens = stmt
+                .query_map(rusqlite::params![everyone, claims.sub], ApiToken::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(tokens)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Create a token for the caller. The plaintext token is only returned here.
+async fn create_token(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Extension(access): Extension<Access>,
+    Json(body): Json<CreateApiToken>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    require_login(&access)?;
+
+    let scope = body.scope.unwrap_or(TokenScope::Read);
+    if scope.role() > claims.role {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": format!("A {} cannot create '{}' tokens", claims.role.as_str(), scope.as_str())
+            })),
+        ));
+    }
+    if body.name.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Token name is required"})),
+        ));
+    }
+    if let Some(ref ids) = body.repo_ids {
+        if ids.is_empty() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "repo_ids must not be empty; omit it for all repositories"})),
+            ));
+        }
+        if scope == TokenScope::Admin {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Admin tokens cannot be limited to repositories"})),
+            ));
+        }
+        if let Some(id) = ids.iter().find(|id| !access.can_see(**id)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("No access to repository {}", id)})),
+            ));
+        }
+    }
+    if body.expires_in_days.is_some_and(|days| days < 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "expires_in_days must be at least 1"})),
+        ));
+    }
+
+    let new = api_tokens::generate().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    let repo_ids = body.repo_ids.as_ref().map(|ids| json!(ids).to_string());
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO api_tokens (user_id, name, token_hash, prefix, scope, repo_ids, expires_at)
+                 SELECT id, ?2, ?3, ?4, ?5, ?6,
+                        CASE WHEN ?7 IS NULL THEN NULL ELSE datetime('now', '+' || ?7 || ' days') END
+                 FROM users WHERE username = ?1",
+                rusqlite::params![
+                    claims.sub,
+                    body.name.trim(),
+                    new.token_hash,
+                    new.display_prefix,
+                    scope.as_str(),
+                    repo_ids,
+                    body.expires_in_days,
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map(|id| {
+            (
+                StatusCode::CREATED,
+                Json(json!({
+                    "id": id,
+                    "token": new.token,
+                    "message": "Token created; it will not be shown again",
+                })),
+            )
+        })
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Revoke one of the caller's tokens; admins can revoke any.
+async fn revoke_token(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Extension(access): Extension<Access>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    require_login(&access)?;
+    let everyone = claims.role == Role::Admin;
+
+    let removed = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.execute(
+                "DELETE FROM api_tokens
+                 WHERE id = ?1
+                   AND (?2 OR user_id = (SELECT id FROM users WHERE username = ?3))",
+                rusqlite::params![id, everyone, claims.sub],
+            )?)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+    if removed == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Token not found"})),
+        ));
+    }
+    Ok(Json(json!({"message": "Token revoked"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::auth::Role;
+    use crate::test_support::{self, call, MockDocker};
+
+    #[tokio::test]
+    async fn admin_tokens_cannot_be_limited_to_repositories() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let repo = test_support::add_repo(&state, "shop");
+        test_support::add_user(&state, "root", Role::Admin, None);
+        let session = test_support::login(&state, "root");
+
+        let (status, _) = call(
+            &app,
+            Method::POST,
+            "/api/auth/tokens",
+            Some(&session),
+            Some(json!({"name": "ci", "scope": "admin", "repo_ids": [repo]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, created) = call(
+            &app,
+            Method::POST,
+            "/api/auth/tokens",
+            Some(&session),
+            Some(json!({"name": "ci", "scope": "admin"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let token = created["token"].as_str().unwrap();
+        for path in ["/api/users", "/api/notifications/channels", "/api/audit"] {
+            let (status, _) = call(&app, Method::GET, path, Some(token), None).await;
+            assert_eq!(status, StatusCode::OK, "{}", path);
+        }
+
+        // Tokens limited to repositories before this was refused can't reach
+        // endpoints that span every repository.
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute("UPDATE api_tokens SET repo_ids = ?1", [json!([repo]).to_string()])?;
+                Ok(())
+            })
+            .unwrap();
+        for path in ["/api/users", "/api/notifications/channels", "/api/audit"] {
+            let (status, _) = call(&app, Method::GET, path, Some(token), None).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", path);
+        }
+        let (status, _) = call(&app, Method::GET, &format!("/api/repositories/{}", repo), Some(token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
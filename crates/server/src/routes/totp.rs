@@ -0,0 +1,241 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::auth::{self, totp, Claims};
+use crate::db::models::{DisableTotpRequest, TotpCodeRequest};
+use crate::AppState;
+
+/// Self-service two-factor enrollment, open to every role.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/auth/totp", get(totp_status))
+        .route("/auth/totp/setup", post(setup_totp))
+        .route("/auth/totp/enable", post(enable_totp))
+        .route("/auth/totp/disable", post(disable_totp))
+        .route("/auth/totp/recovery-codes", post(regenerate_recovery_codes))
+}
+
+struct TotpUser {
+    id: i64,
+    password_hash: String,
+    enabled: bool,
+    pending: bool,
+}
+
+/// The calling user. API tokens can't change how their owner logs in.
+fn current_user(state: &AppState, claims: &Claims) -> Result<TotpUser, (StatusCode, Json<Value>)> {
+    if claims.sid.is_none() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "API tokens cannot manage two-factor authentication"})),
+        ));
+    }
+    state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.query_row(
+                "SELECT id, password_hash, totp_enabled, totp_secret IS NOT NULL
+                 FROM users WHERE username = ?1",
+                [&claims.sub],
+                |row| {
+                    Ok(TotpUser {
+                        id: row.get(0)?,
+                        password_hash: row.get(1)?,
+                        enabled: row.get(2)?,
+                        pending: row.get(3)?,
+                    })
+                },
+            )?)
+        })
+        .map_err(|_| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "User not found"})),
+            )
+        })
+}
+
+fn internal(e: anyhow::Error) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"error": e.to_string()})),
+    )
+}
+
+async fn totp_status(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = current_user(&state, &claims)?;
+    let remaining: i64 = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.query_row(
+                "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ?1 AND used_at IS NULL",
+                [user.id],
+                |row| row.get(0),
+            )?)
+        })
+        .map_err(internal)?;
+
+    Ok(Json(json!({
+        "enabled": user.enabled,
+        "pending": user.pending && !user.enabled,
+        "recovery_codes_remaining": remaining,
+    })))
+}
+
+/// Start enrollment with a fresh secret. It only takes effect once a code
+/// from it is confirmed at `/auth/totp/enable`.
+async fn setup_totp(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = current_user(&state, &claims)?;
+    if user.enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Two-factor authentication is already enabled"})),
+        ));
+    }
+    // Single sign-on logins skip the TOTP step; the provider handles MFA.
+    if user.password_hash.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Single sign-on accounts use the provider's two-factor authentication"})),
+        ));
+    }
+
+    let secret = totp::generate_secret().map_err(internal)?;
+    let uri = totp::provisioning_uri(&secret, &claims.sub);
+    let qr_svg = totp::qr_svg(&uri).map_err(internal)?;
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "UPDATE users SET totp_secret = ?2, totp_last_step = NULL,
+                                  updated_at = datetime('now')
+                 WHERE id = ?1",
+                rusqlite::params![user.id, secret],
+            )?;
+            Ok(())
+        })
+        .map_err(internal)?;
+
+    Ok(Json(json!({
+        "secret": secret,
+        "otpauth_uri": uri,
+        "qr_svg": qr_svg,
+    })))
+}
+
+/// Confirm enrollment with a code from the authenticator app. The recovery
+/// codes are only returned here.
+async fn enable_totp(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<TotpCodeRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = current_user(&state, &claims)?;
+    if user.enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Two-factor authentication is already enabled"})),
+        ));
+    }
+    if !user.pending {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Call /auth/totp/setup first"})),
+        ));
+    }
+    if !totp::check_user_code(&state, user.id, &body.code, false).map_err(internal)? {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid code"})),
+        ));
+    }
+
+    let recovery_codes = totp::reset_recovery_codes(&state, user.id).map_err(internal)?;
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "UPDATE users SET totp_enabled = 1, updated_at = datetime('now') WHERE id = ?1",
+                [user.id],
+            )?;
+            Ok(())
+        })
+        .map_err(internal)?;
+    tracing::info!(username = %claims.sub, "Two-factor authentication enabled");
+
+    Ok(Json(json!({
+        "message": "Two-factor authentication enabled; store the recovery codes safely",
+        "recovery_codes": recovery_codes,
+    })))
+}
+
+/// Turn two-factor authentication off. Needs the password, so a stolen
+/// session alone can't weaken the account.
+async fn disable_totp(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<DisableTotpRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = current_user(&state, &claims)?;
+    if !auth::verify_password(&user.password_hash, &body.password) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Invalid password"})),
+        ));
+    }
+
+    clear_totp(&state, user.id).map_err(internal)?;
+    tracing::info!(username = %claims.sub, "Two-factor authentication disabled");
+    Ok(Json(json!({"message": "Two-factor authentication disabled"})))
+}
+
+/// Remove a user's secret and recovery codes. Also used by admins to reset
+/// a locked-out user.
+pub fn clear_totp(state: &AppState, user_id: i64) -> anyhow::Result<()> {
+    state.db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE users SET totp_secret = NULL, totp_enabled = 0, totp_last_step = NULL,
+                              updated_at = datetime('now')
+             WHERE id = ?1",
+            [user_id],
+        )?;
+        conn.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])?;
+        Ok(())
+    })
+}
+
+/// Replace the recovery codes, invalidating the old ones.
+async fn regenerate_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<TotpCodeRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = current_user(&state, &claims)?;
+    if !user.enabled {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Two-factor authentication is not enabled"})),
+        ));
+    }
+    if !totp::check_user_code(&state, user.id, &body.code, false).map_err(internal)? {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid code"})),
+        ));
+    }
+
+    let recovery_codes = totp::reset_recovery_codes(&state, user.id).map_err(internal)?;
+    Ok(Json(json!({"recovery_codes": recovery_codes})))
+}
@@ -0,0 +1,376 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, put},
+    Extension, Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::auth::{self, Claims, Role};
+use crate::db::models::{
+    CreateUser, RepositoryGrant, SetRepositoryGrant, UpdateUser, User, MIN_PASSWORD_LEN,
+};
+use crate::AppState;
+
+/// Admin-only account management.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/users", get(list_users).post(create_user))
+        .route(
+            "/users/{id}",
+            get(get_user).put(update_user).delete(delete_user),
+        )
+        .route("/users/{id}/totp", delete(reset_totp))
+        .route("/users/{id}/grants", get(list_grants))
+        .route(
+            "/users/{id}/grants/{repo_id}",
+            put(set_grant).delete(delete_grant),
+        )
+}
+
+fn load_user(state: &AppState, id: i64) -> Result<User, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let user = conn.query_row(
+                &format!("SELECT {} FROM users WHERE id = ?1", User::COLUMNS),
+                [id],
+                User::from_row,
+            )?;
+            Ok(user)
+        })
+        .map_err(|_| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "User not found"})),
+            )
+        })
+}
+
+pub(crate) fn validate_password(password: &str) -> Result<(), (StatusCode, Json<Value>)> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Password must be at least {} characters", MIN_PASSWORD_LEN)
+            })),
+        ));
+    }
+    Ok(())
+}
+
+fn hash(password: &str) -> Result<String, (StatusCode, Json<Value>)> {
+    auth::hash_password(password).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })
+}
+
+/// Refuse changes that would leave no admin able to manage the server.
+fn ensure_other_admin(state: &AppState, user: &User) -> Result<(), (StatusCode, Json<Value>)> {
+    if user.role != Role::Admin {
+        return Ok(());
+    }
+    let admins: i64 = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.query_row(
+                "SELECT COUNT(*) FROM users WHERE role = 'admin'",
+                [],
+                |row| row.get(0),
+            )?)
+        })
+        .unwrap_or(0);
+    if admins <= 1 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Cannot remove the last admin"})),
+        ));
+    }
+    Ok(())
+}
+
+async fn list_users(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<User>>, (StatusCode, Json<Value>)> {
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM users ORDER BY username",
+                User::COLUMNS
+            ))?;
+            let users = stmt
+                .query_map([], User::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(users)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn get_user(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<User>, (StatusCode, Json<Value>)> {
+    load_user(&state, id).map(Json)
+}
+
+async fn create_user(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<CreateUser>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    if body.username.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Username is required"})),
+        ));
+    }
+    validate_password(&body.password)?;
+    let password_hash = hash(&body.password)?;
+    let role = body.role.unwrap_or(Role::Viewer);
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO users (username, password_hash, role, scoped) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![
+                    body.username.trim(),
+                    password_hash,
+                    role.as_str(),
+                    body.scoped.unwrap_or(false),
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .map(|id| {
+            (
+                StatusCode::CREATED,
+                Json(json!({"id": id, "message": "User created"})),
+            )
+        })
+        .map_err(|e| {
+            let msg = e.to_string();
+            let status = if msg.contains("UNIQUE") {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            (status, Json(json!({"error": msg})))
+        })
+}
+
+/// Change a user's password, role or scoping.
+async fn update_user(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Json(body): Json<UpdateUser>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = load_user(&state, id)?;
+    if body.role.is_some_and(|role| role != Role::Admin) {
+        ensure_other_admin(&state, &user)?;
+    }
+    let password_hash = match body.password {
+        Some(ref password) => {
+            validate_password(password)?;
+            Some(hash(password)?)
+        }
+        None => None,
+    };
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut sets = Vec::new();
+            let mut params: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
+
+            if let Some(hash) = password_hash {
+                sets.push("password_hash = ?");
+                params.push(Box::new(hash));
+            }
+            if let Some(role) = body.role {
+                sets.push("role = ?");
+                params.push(Box::new(role.as_str()));
+            }
+            if let Some(scoped) = body.scoped {
+                sets.push("scoped = ?");
+                params.push(Box::new(scoped));
+            }
+
+            if sets.is_empty() {
+                anyhow::bail!("No fields to update");
+            }
+
+            sets.push("updated_at = datetime('now')");
+            params.push(Box::new(id));
+
+            let sql = format!("UPDATE users SET {} WHERE id = ?", sets.join(", "));
+            let param_refs: Vec<&dyn rusqlite::types::ToSql> =
+                params.iter().map(|p| p.as_ref()).collect();
+            conn.execute(&sql, param_refs.as_slice())?;
+
+            // A new password logs the user out everywhere.
+            if body.password.is_some() {
+                conn.execute(
+                    "UPDATE sessions SET revoked_at = datetime('now')
+                     WHERE user_id = ?1 AND revoked_at IS NULL",
+                    [id],
+                )?;
+            }
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "User updated"})))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Turn off a user's two-factor authentication, e.g. after they lost both
+/// their device and recovery codes.
+async fn reset_totp(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = load_user(&state, id)?;
+    crate::routes::totp::clear_totp(&state, user.id).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    tracing::info!(username = %user.username, "Two-factor authentication reset by an admin");
+    Ok(Json(json!({"message": "Two-factor authentication reset"})))
+}
+
+async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<i64>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let user = load_user(&state, id)?;
+    if user.username == claims.sub {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Cannot delete your own account"})),
+        ));
+    }
+    ensure_other_admin(&state, &user)?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute("DELETE FROM users WHERE id = ?1", [id])?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "User deleted"})))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+async fn list_grants(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<RepositoryGrant>>, (StatusCode, Json<Value>)> {
+    load_user(&state, id)?;
+
+    state
+        .db
+        .with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM repository_grants g
+                 JOIN repositories r ON r.id = g.repo_id
+                 WHERE g.user_id = ?1 ORDER BY r.name",
+                RepositoryGrant::COLUMNS
+            ))?;
+            let grants = stmt
+                .query_map([id], RepositoryGrant::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(grants)
+        })
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+/// Grant (or change) a user's role on a repository.
+async fn set_grant(
+    State(state): State<Arc<AppState>>,
+    Path((id, repo_id)): Path<(i64, i64)>,
+    Json(body): Json<SetRepositoryGrant>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    load_user(&state, id)?;
+    if body.role == Role::Admin {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Repository grants can be viewer or deployer"})),
+        ));
+    }
+
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO repository_grants (user_id, repo_id, role) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(user_id, repo_id) DO UPDATE SET role = excluded.role",
+                rusqlite::params![id, repo_id, body.role.as_str()],
+            )?;
+            Ok(())
+        })
+        .map(|_| Json(json!({"message": "Grant saved"})))
+        .map_err(|e| {
+            let msg = e.to_string();
+            let status = if msg.contains("FOREIGN KEY") {
+                StatusCode::NOT_FOUND
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            (status, Json(json!({"error": msg})))
+        })
+}
+
+async fn delete_grant(
+    State(state): State<Arc<AppState>>,
+    Path((id, repo_id)): Path<(i64, i64)>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let removed = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.execute(
+                "DELETE FROM repository_grants WHERE user_id = ?1 AND repo_id = ?2",
+                [id, repo_id],
+            )?)
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+    if removed == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Grant not found"})),
+        ));
+    }
+    Ok(Json(json!({"message": "Grant removed"})))
+}
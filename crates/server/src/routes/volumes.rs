@@ -0,0 +1,130 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::auth::Access;
+use crate::db::models::{validate_labels, validate_volume_name, CreateVolumeRequest, VolumeInfo};
+use crate::AppState;
+
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/volumes", get(list_volumes).post(create_volume))
+        .route("/volumes/{name}", get(inspect_volume).delete(remove_volume))
+}
+
+/// Scoped users only see volumes created for their repositories.
+async fn list_volumes(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+) -> Result<Json<Vec<VolumeInfo>>, (StatusCode, Json<Value>)> {
+    let mut volumes = state.docker.list_volumes().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    volumes.retain(|v| access.can_see_labels(&v.labels));
+    Ok(Json(volumes))
+}
+
+async fn inspect_volume(
+    State(state): State<Arc<AppState>>,
+    Extension(access): Extension<Access>,
+    Path(name): Path<String>,
+) -> Result<Json<VolumeInfo>, (StatusCode, Json<Value>)> {
+    state
+        .docker
+        .inspect_volume(&name)
+        .await
+        .ok()
+        .filter(|v| access.can_see_labels(&v.labels))
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": format!("No such volume: {}", name)})),
+            )
+        })
+}
+
+async fn create_volume(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<CreateVolumeRequest>,
+) -> Result<(StatusCode, Json<VolumeInfo>), (StatusCode, Json<Value>)> {
+    validate_volume_name(&body.name)
+        .and_then(|_| validate_labels(&body.labels))
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+
+    state
+        .docker
+        .create_volume(body)
+        .await
+        .map(|v| (StatusCode::CREATED, Json(v)))
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": e.to_string()})),
+            )
+        })
+}
+
+#[derive(Deserialize)]
+struct RemoveQuery {
+    force: Option<bool>,
+}
+
+/// Remove a volume. Volumes that are mounted by a container or were created by
+/// dockyy for an application are refused unless `?force=true`.
+async fn remove_volume(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<RemoveQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let force = query.force.unwrap_or(false);
+    let volume = state.docker.inspect_volume(&name).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+
+    if !force {
+        if !volume.containers.is_empty() {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "error": format!("Volume is in use by: {}", volume.containers.join(", ")),
+                    "containers": volume.containers,
+                })),
+            ));
+        }
+        if volume.managed {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(json!({"error": "Volume holds dockyy application data; pass force=true to remove it"})),
+            ));
+        }
+    }
+
+    // Even when forced, the engine still refuses volumes mounted by an existing
+    // container; its error is passed through as-is.
+    state
+        .docker
+        .remove_volume(&name, force)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+
+    tracing::info!(volume = %name, force, "Volume removed");
+    Ok(Json(json!({"status": "removed", "volume": name})))
+}
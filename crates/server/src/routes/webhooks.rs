@@ -1,13 +1,17 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
+    body::Bytes,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
     routing::post,
     Json, Router,
 };
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+use crate::db::models::{Repository, DEFAULT_ENVIRONMENT};
+use crate::services::{audit, crypto};
 use crate::AppState;
 
 pub fn routes() -> Router<Arc<AppState>> {
@@ -15,11 +19,13 @@ pub fn routes() -> Router<Arc<AppState>> {
 }
 
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
 struct GitHubPushEvent {
     #[serde(rename = "ref")]
     git_ref: Option<String>,
     after: Option<String>,
+    /// Set when the push deleted the ref.
+    #[serde(default)]
+    deleted: bool,
     repository: Option<GitHubRepo>,
 }
 
@@ -31,30 +37,174 @@ struct GitHubRepo {
     ssh_url: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct GitHubPullRequestEvent {
+    action: String,
+    number: i64,
+    pull_request: GitHubPullRequest,
+    repository: Option<GitHubRepo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubPullRequest {
+    head: GitHubPullRequestHead,
+    base: GitHubPullRequestHead,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubPullRequestHead {
+    #[serde(rename = "ref")]
+    git_ref: Option<String>,
+    sha: Option<String>,
+    /// `None` when the fork has been deleted.
+    repo: Option<GitHubRepo>,
+}
+
 async fn github_webhook(
     State(state): State<Arc<AppState>>,
-    Json(body): Json<GitHubPushEvent>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
-    let commit_sha = body.after.unwrap_or_default();
-    let repo_name = body
-        .repository
-        .as_ref()
+    let event = headers
+        .get("X-GitHub-Event")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("push");
+    let delivery = Delivery {
+        ip: addr.ip().to_string(),
+        signature: headers
+            .get("X-Hub-Signature-256")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body,
+    };
+
+    let result = handle_event(state.clone(), event, &delivery).await;
+    let status = match &result {
+        Ok((status, _)) | Err((status, _)) => status.as_u16(),
+    };
+    // The header is client-controlled; keep the label set bounded.
+    let label = match event {
+        "push" | "pull_request" | "ping" => event,
+        _ => "other",
+    };
+    state.telemetry.record_webhook(label, status);
+    result
+}
+
+/// A webhook request as received, kept raw so its signature can be checked.
+struct Delivery {
+    ip: String,
+    signature: Option<String>,
+    body: Bytes,
+}
+
+impl Delivery {
+    /// Check `X-Hub-Signature-256` against the repository's webhook secret.
+    /// Returns whether the payload was signed; fails when a secret is set
+    /// and the signature is missing or wrong.
+    fn verify(&self, state: &AppState, repo: &Repository) -> Result<bool, (StatusCode, Json<Value>)> {
+        let Some(ref sealed) = repo.webhook_secret_encrypted else {
+            return Ok(false);
+        };
+        let secret = state
+            .keyring
+            .decrypt(sealed, &crypto::webhook_secret_context(repo.id))
+            .map_err(|e| {
+                tracing::error!(repo_id = repo.id, "Cannot decrypt webhook secret: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to decrypt the webhook secret"})),
+                )
+            })?;
+        if !signature_matches(&secret, self.signature.as_deref(), &self.body) {
+            tracing::warn!(repo_id = repo.id, ip = %self.ip, "Rejected webhook with an invalid signature");
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"error": "Invalid webhook signature"})),
+            ));
+        }
+        Ok(true)
+    }
+}
+
+/// Whether `signature` (`sha256=<hex>`) is the HMAC of `body` under `secret`.
+fn signature_matches(secret: &str, signature: Option<&str>, body: &[u8]) -> bool {
+    let Some(tag) = signature
+        .and_then(|s| s.strip_prefix("sha256="))
+        .and_then(|hex| data_encoding::HEXLOWER_PERMISSIVE.decode(hex.as_bytes()).ok())
+    else {
+        return false;
+    };
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    ring::hmac::verify(&key, body, &tag).is_ok()
+}
+
+async fn handle_event(
+    state: Arc<AppState>,
+    event: &str,
+    delivery: &Delivery,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    match event {
+        "push" => {
+            let body: GitHubPushEvent =
+                serde_json::from_slice(&delivery.body).map_err(bad_payload)?;
+            handle_push(state, body, delivery).await
+        }
+        "pull_request" => {
+            let body: GitHubPullRequestEvent =
+                serde_json::from_slice(&delivery.body).map_err(bad_payload)?;
+            handle_pull_request(state, body, delivery).await
+        }
+        "ping" => Ok((StatusCode::OK, Json(json!({"message": "pong"})))),
+        other => Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({"message": format!("Event '{}' ignored", other)})),
+        )),
+    }
+}
+
+/// A delivery that was understood but has nothing to deploy.
+fn ignored(message: String) -> (StatusCode, Json<Value>) {
+    tracing::info!("{}", message);
+    (StatusCode::OK, Json(json!({"message": message})))
+}
+
+fn bad_payload(e: serde_json::Error) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": format!("Invalid webhook payload: {}", e)})),
+    )
+}
+
+/// Record jobs queued by a webhook in the audit log.
+fn audit_webhook(state: &AppState, ip: &str, action: &str, repo_id: i64, summary: Value) {
+    audit::record(
+        state,
+        audit::Event {
+            ip: Some(ip),
+            action,
+            target: Some(&format!("/api/repositories/{}", repo_id)),
+            summary: Some(summary),
+            status: Some(StatusCode::ACCEPTED),
+            ..Default::default()
+        },
+    );
+}
+
+/// Find the repository a GitHub payload refers to, by clone URL or `owner/name`.
+fn find_repository(
+    state: &AppState,
+    repository: Option<&GitHubRepo>,
+) -> Result<Option<i64>, (StatusCode, Json<Value>)> {
+    let repo_name = repository
         .and_then(|r| r.full_name.clone())
         .unwrap_or_default();
-    let clone_url = body
-        .repository
-        .as_ref()
+    let clone_url = repository
         .and_then(|r| r.clone_url.clone())
         .unwrap_or_default();
 
-    tracing::info!(
-        "Received webhook for {} commit {}",
-        repo_name,
-        &commit_sha[..7.min(commit_sha.len())]
-    );
-
-    // Find matching repository
-    let repo_id = state
+    state
         .db
         .with_conn(|conn| {
             let id: Option<i64> = conn
@@ -71,35 +221,252 @@ async fn github_webhook(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"error": e.to_string()})),
             )
-        })?;
+        })
+}
 
-    let repo_id = match repo_id {
-        Some(id) => id,
-        None => {
-            tracing::warn!("No matching repository found for webhook: {}", repo_name);
+/// `owner/name` of a repository, for comparing a pull request's head and base.
+fn full_name(repo: Option<&GitHubRepo>) -> Option<String> {
+    repo.and_then(|r| r.full_name.as_deref()).map(str::to_ascii_lowercase)
+}
+
+async fn handle_pull_request(
+    state: Arc<AppState>,
+    body: GitHubPullRequestEvent,
+    delivery: &Delivery,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let job_type = match body.action.as_str() {
+        "opened" | "synchronize" | "reopened" => "preview_deploy",
+        "closed" => "preview_teardown",
+        _ => {
             return Ok((
                 StatusCode::ACCEPTED,
-                Json(json!({"message": "No matching repository found, ignoring"})),
+                Json(json!({"message": format!("Pull request action '{}' ignored", body.action)})),
             ));
         }
     };
 
-    // Create deployment job
+    let Some(repo_id) = find_repository(&state, body.repository.as_ref())? else {
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({"message": "No matching repository found, ignoring"})),
+        ));
+    };
+
+    let repo = crate::services::worker::load_repository(&state, repo_id).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    if !repo.previews_enabled {
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({"message": "Previews are disabled for this repository, ignoring"})),
+        ));
+    }
+    // Previews build and run the pull request's code, so only signed
+    // deliveries may trigger them.
+    if !delivery.verify(&state, &repo)? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Previews require a webhook secret on the repository"})),
+        ));
+    }
+
+    let pr_number = body.number;
+    let head_repo = full_name(body.pull_request.head.repo.as_ref());
+    let is_fork = head_repo.is_none() || head_repo != full_name(body.pull_request.base.repo.as_ref());
+    if job_type == "preview_deploy" && is_fork && !repo.preview_forks {
+        tracing::info!("Not previewing {} PR #{}: opened from a fork", repo.name, pr_number);
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({"message": "Pull request is from a fork and fork previews are disabled, ignoring"})),
+        ));
+    }
+    let container_name = crate::services::worker::preview_container_name(&repo, pr_number);
+    let head = body.pull_request.head;
+
+    tracing::info!(
+        "Received pull_request '{}' for {} PR #{}",
+        body.action,
+        repo.name,
+        pr_number
+    );
+
+    let max_previews = state.config.max_previews;
     let job_id = state
         .db
         .with_conn(|conn| {
+            if job_type == "preview_deploy" {
+                // Updates to a PR that already has a preview don't count.
+                let active: i64 = conn.query_row(
+                    "SELECT COUNT(*) FROM previews
+                     WHERE status IN ('pending', 'building', 'running')
+                       AND NOT (repo_id = ?1 AND pr_number = ?2)",
+                    [repo_id, pr_number],
+                    |row| row.get(0),
+                )?;
+                if max_previews > 0 && active >= max_previews {
+                    return Ok(None);
+                }
+                conn.execute(
+                    "INSERT INTO previews (repo_id, pr_number, head_ref, head_sha, container_name, status)
+                     VALUES (?1, ?2, ?3, ?4, ?5, 'pending')
+                     ON CONFLICT (repo_id, pr_number) DO UPDATE SET
+                        head_ref = excluded.head_ref,
+                        head_sha = excluded.head_sha,
+                        status = 'pending',
+                        updated_at = datetime('now')",
+                    rusqlite::params![repo_id, pr_number, head.git_ref, head.sha, container_name],
+                )?;
+            }
+
             let payload = json!({
                 "repo_id": repo_id,
-                "commit_sha": commit_sha,
-                "clone_url": clone_url,
+                "pr_number": pr_number,
+                "head_sha": head.sha,
             })
             .to_string();
-
             conn.execute(
-                "INSERT INTO jobs (job_type, payload, status) VALUES ('deploy', ?1, 'pending')",
-                [&payload],
+                "INSERT INTO jobs (job_type, payload, status) VALUES (?1, ?2, 'pending')",
+                rusqlite::params![job_type, payload],
             )?;
-            Ok(conn.last_insert_rowid())
+            Ok(Some(conn.last_insert_rowid()))
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+    let Some(job_id) = job_id else {
+        tracing::warn!("Not previewing {} PR #{}: {} previews already active", repo.name, pr_number, max_previews);
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({"error": format!("The limit of {} active previews is reached", max_previews)})),
+        ));
+    };
+
+    tracing::info!("Created {} job {} for repo {} PR #{}", job_type, job_id, repo_id, pr_number);
+    audit_webhook(
+        &state,
+        &delivery.ip,
+        "webhook.preview",
+        repo_id,
+        json!({
+            "pr_number": pr_number,
+            "pr_action": body.action,
+            "job_type": job_type,
+            "job_id": job_id,
+            "head_sha": head.sha,
+        }),
+    );
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "Preview job queued",
+            "job_id": job_id,
+            "repo_id": repo_id,
+            "pr_number": pr_number
+        })),
+    ))
+}
+
+async fn handle_push(
+    state: Arc<AppState>,
+    body: GitHubPushEvent,
+    delivery: &Delivery,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let commit_sha = body.after.unwrap_or_default();
+    let repo_name = body
+        .repository
+        .as_ref()
+        .and_then(|r| r.full_name.clone())
+        .unwrap_or_default();
+    let clone_url = body
+        .repository
+        .as_ref()
+        .and_then(|r| r.clone_url.clone())
+        .unwrap_or_default();
+
+    tracing::info!(
+        "Received webhook for {} commit {}",
+        repo_name,
+        &commit_sha[..7.min(commit_sha.len())]
+    );
+
+    // Find matching repository
+    let repo_id = find_repository(&state, body.repository.as_ref())?;
+
+    let repo_id = match repo_id {
+        Some(id) => id,
+        None => {
+            tracing::warn!("No matching repository found for webhook: {}", repo_name);
+            return Ok((
+                StatusCode::ACCEPTED,
+                Json(json!({"message": "No matching repository found, ignoring"})),
+            ));
+        }
+    };
+    let repo = crate::services::worker::load_repository(&state, repo_id).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        )
+    })?;
+    delivery.verify(&state, &repo)?;
+
+    // Deploy every environment tracking the pushed branch. Pushes without a
+    // ref fall back to the default environment; tags and deleted branches
+    // deploy nothing.
+    let branch = match body.git_ref.as_deref() {
+        None => None,
+        Some(git_ref) => {
+            let Some(branch) = git_ref.strip_prefix("refs/heads/") else {
+                return Ok(ignored(format!("Ref '{}' is not a branch, ignoring", git_ref)));
+            };
+            let zero_sha = !commit_sha.is_empty() && commit_sha.bytes().all(|b| b == b'0');
+            if body.deleted || zero_sha {
+                return Ok(ignored(format!("Branch '{}' was deleted, ignoring", branch)));
+            }
+            Some(branch.to_string())
+        }
+    };
+
+    let job_ids = state
+        .db
+        .with_conn(|conn| {
+            let envs: Vec<String> = match branch {
+                Some(ref branch) => {
+                    let mut stmt = conn.prepare(
+                        "SELECT e.name FROM environments e
+                         JOIN repositories r ON r.id = e.repo_id
+                         WHERE e.repo_id = ?1 AND COALESCE(e.branch, r.default_branch) = ?2",
+                    )?;
+                    stmt.query_map(rusqlite::params![repo_id, branch], |row| row.get(0))?
+                        .collect::<Result<Vec<_>, _>>()?
+                }
+                None => vec![DEFAULT_ENVIRONMENT.to_string()],
+            };
+
+            let mut job_ids = Vec::new();
+            for env in envs {
+                let payload = json!({
+                    "repo_id": repo_id,
+                    "environment": env,
+                    "commit_sha": commit_sha,
+                    "clone_url": clone_url,
+                })
+                .to_string();
+
+                conn.execute(
+                    "INSERT INTO jobs (job_type, payload, status) VALUES ('deploy', ?1, 'pending')",
+                    [&payload],
+                )?;
+                job_ids.push((env, conn.last_insert_rowid()));
+            }
+            Ok(job_ids)
         })
         .map_err(|e| {
             (
@@ -108,14 +475,225 @@ async fn github_webhook(
             )
         })?;
 
-    tracing::info!("Created deploy job {} for repo {}", job_id, repo_id);
+    let Some((_, job_id)) = job_ids.first() else {
+        tracing::info!(
+            "No environment of repo {} tracks branch {}, ignoring",
+            repo_id,
+            branch.as_deref().unwrap_or("?")
+        );
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({"message": "No environment tracks this branch, ignoring"})),
+        ));
+    };
+
+    for (env, id) in &job_ids {
+        tracing::info!("Created deploy job {} for repo {} ({})", id, repo_id, env);
+    }
+    audit_webhook(
+        &state,
+        &delivery.ip,
+        "webhook.deploy",
+        repo_id,
+        json!({
+            "ref": body.git_ref,
+            "commit_sha": commit_sha,
+            "jobs": job_ids.iter().map(|(env, id)| json!({"environment": env, "job_id": id})).collect::<Vec<_>>(),
+        }),
+    );
 
     Ok((
         StatusCode::ACCEPTED,
         Json(json!({
             "message": "Deployment queued",
             "job_id": job_id,
-            "repo_id": repo_id
+            "repo_id": repo_id,
+            "environments": job_ids.iter().map(|(env, _)| env).collect::<Vec<_>>(),
         })),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+
+    use crate::test_support::{self, MockDocker};
+
+    async fn push(app: &axum::Router, payload: Value) -> (StatusCode, Value) {
+        let req = Request::post("/api/webhooks/github")
+            .header("X-GitHub-Event", "push")
+            .header("Content-Type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        test_support::send(app, req).await
+    }
+
+    fn queued_environments(state: &crate::AppState) -> Vec<String> {
+        state
+            .db
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT payload FROM jobs WHERE job_type = 'deploy' ORDER BY id")?;
+                let payloads = stmt
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(payloads
+                    .iter()
+                    .map(|p| serde_json::from_str::<Value>(p).unwrap()["environment"].as_str().unwrap().to_string())
+                    .collect())
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pushes_deploy_only_branches() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        test_support::add_repo(&state, "shop");
+        let repository = json!({
+            "full_name": "acme/shop",
+            "clone_url": "https://github.com/acme/shop.git",
+        });
+        let sha = "5e1f0c3a9b8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f";
+
+        let (status, body) = push(&app, json!({"ref": "refs/tags/v1.0.0", "after": sha, "repository": repository})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["message"].as_str().unwrap().contains("not a branch"));
+
+        let zero = "0000000000000000000000000000000000000000";
+        let (status, body) = push(&app, json!({"ref": "refs/heads/main", "after": zero, "deleted": true, "repository": repository})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["message"].as_str().unwrap().contains("deleted"));
+        let (status, _) = push(&app, json!({"ref": "refs/heads/main", "after": zero, "repository": repository})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(queued_environments(&state).is_empty());
+
+        let (status, body) = push(&app, json!({"ref": "refs/heads/feature", "after": sha, "repository": repository})).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(body["message"].as_str().unwrap().contains("No environment tracks"));
+        assert!(queued_environments(&state).is_empty());
+
+        let (status, body) = push(&app, json!({"ref": "refs/heads/main", "after": sha, "repository": repository})).await;
+        assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+        assert_eq!(body["environments"], json!(["production"]));
+
+        // Only a push without any ref falls back to the default environment.
+        let (status, _) = push(&app, json!({"after": sha, "repository": repository})).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(queued_environments(&state), ["production", "production"]);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    /// Deliver a `pull_request` event for acme/shop, signed with `secret`.
+    async fn pull_request(app: &axum::Router, action: &str, number: i64, secret: Option<&str>) -> (StatusCode, Value) {
+        let repository = json!({"full_name": "acme/shop", "clone_url": "https://github.com/acme/shop.git"});
+        let payload = json!({
+            "action": action,
+            "number": number,
+            "pull_request": {
+                "head": {"ref": format!("feature-{}", number), "sha": "5e1f0c3a", "repo": repository},
+                "base": {"ref": "main", "sha": "0a1b2c3d", "repo": repository},
+            },
+            "repository": repository,
+        })
+        .to_string();
+        let mut req = Request::post("/api/webhooks/github")
+            .header("X-GitHub-Event", "pull_request")
+            .header("Content-Type", "application/json");
+        if let Some(secret) = secret {
+            let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+            let tag = ring::hmac::sign(&key, payload.as_bytes());
+            let signature = format!("sha256={}", data_encoding::HEXLOWER.encode(tag.as_ref()));
+            req = req.header("X-Hub-Signature-256", signature);
+        }
+        test_support::send(app, req.body(Body::from(payload)).unwrap()).await
+    }
+
+    /// `(pr_number, status)` of the repository's previews and the preview
+    /// job types queued, in order.
+    fn previews(state: &crate::AppState) -> (Vec<(i64, String)>, Vec<String>) {
+        state
+            .db
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT pr_number, status FROM previews ORDER BY pr_number")?;
+                let previews = stmt
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut stmt = conn.prepare("SELECT job_type FROM jobs WHERE job_type LIKE 'preview_%' ORDER BY id")?;
+                let jobs = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<_>, _>>()?;
+                Ok((previews, jobs))
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_pull_requests_drive_previews() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let repo_id = test_support::add_repo(&state, "shop");
+        let secret = "webhook-secret";
+        let sealed = state
+            .keyring
+            .encrypt(secret, &crate::services::crypto::webhook_secret_context(repo_id))
+            .unwrap();
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute(
+                    "UPDATE repositories SET previews_enabled = 1, webhook_secret_encrypted = ?2 WHERE id = ?1",
+                    rusqlite::params![repo_id, sealed],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+
+        let (status, _) = pull_request(&app, "opened", 1, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = pull_request(&app, "opened", 1, Some("wrong-secret")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(previews(&state), (vec![], vec![]));
+
+        let (status, _) = pull_request(&app, "opened", 1, Some(secret)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, _) = pull_request(&app, "synchronize", 1, Some(secret)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, _) = pull_request(&app, "labeled", 1, Some(secret)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (previews_now, jobs) = previews(&state);
+        assert_eq!(previews_now, [(1, "pending".to_string())]);
+        assert_eq!(jobs, ["preview_deploy", "preview_deploy"]);
+
+        // The cap (5 in tests) counts active previews across repositories,
+        // but updates to a PR that already has one still go through.
+        for number in 2..=5 {
+            let (status, _) = pull_request(&app, "opened", number, Some(secret)).await;
+            assert_eq!(status, StatusCode::ACCEPTED);
+        }
+        let (status, _) = pull_request(&app, "opened", 6, Some(secret)).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let (status, _) = pull_request(&app, "synchronize", 5, Some(secret)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(previews(&state).0.len(), 5);
+
+        let (status, _) = pull_request(&app, "closed", 1, Some(secret)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(previews(&state).1.last().unwrap(), "preview_teardown");
+
+        // Once torn down, a closed preview frees its slot.
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute("UPDATE previews SET status = 'closed' WHERE pr_number = 1", [])?;
+                Ok(())
+            })
+            .unwrap();
+        let (status, _) = pull_request(&app, "opened", 6, Some(secret)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
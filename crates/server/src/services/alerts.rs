@@ -0,0 +1,389 @@
+//! Threshold alerting, evaluated by the health monitor after each sample.
+//!
+//! A rule breaching for a subject (`host` or a container name) first becomes
+//! pending; once it has held for `duration_secs` an alert row is inserted in
+//! the `firing` state and a notification sent. When the condition clears the
+//! row is marked `resolved` and a second notification sent. The partial
+//! unique index on firing alerts keeps one open alert per rule and subject,
+//! so repeated breaches don't re-notify.
+
+use anyhow::Result;
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use crate::db::models::{Alert, AlertRule};
+use crate::services::container_stats::StatsCollector;
+use crate::services::monitor::SystemMetrics;
+use crate::services::notifications::{self, Notification};
+use crate::AppState;
+
+pub const HOST_SUBJECT: &str = "host";
+
+#[derive(Default)]
+pub struct AlertEvaluator {
+    /// First time each (rule, subject) was seen breaching, for `duration_secs`.
+    pending: HashMap<(i64, String), i64>,
+    /// Recent `(ts, restart count)` readings per container.
+    restarts: HashMap<String, VecDeque<(i64, i64)>>,
+}
+
+fn pct(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        used as f64 / total as f64 * 100.0
+    }
+}
+
+fn breaches(rule: &AlertRule, value: f64) -> bool {
+    match rule.metric.as_str() {
+        // Boolean metrics: 1 means down, whatever the threshold.
+        "docker_down" | "traefik_down" => value >= 1.0,
+        _ if rule.operator == "lt" => value < rule.threshold,
+        _ => value > rule.threshold,
+    }
+}
+
+fn describe(rule: &AlertRule, subject: &str, value: f64) -> String {
+    match rule.metric.as_str() {
+        "docker_down" => "Docker engine is unreachable".to_string(),
+        "traefik_down" => "Traefik reverse proxy is not running".to_string(),
+        "container_restarts" => format!(
+            "{} restarted {} times in the last {}s",
+            subject, value, rule.duration_secs
+        ),
+        metric => format!(
+            "{} {} is {:.1} ({} {})",
+            subject,
+            metric,
+            value,
+            if rule.operator == "lt" { "below" } else { "above" },
+            rule.threshold
+        ),
+    }
+}
+
+impl AlertEvaluator {
+    /// Evaluate all enabled rules against the latest readings.
+    pub async fn evaluate(
+        &mut self,
+        state: &Arc<AppState>,
+        metrics: &SystemMetrics,
+        stats: &StatsCollector,
+    ) -> Result<()> {
+        let rules: Vec<AlertRule> = state.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM alert_rules WHERE enabled = 1",
+                AlertRule::COLUMNS
+            ))?;
+            let rules = stmt
+                .query_map([], AlertRule::from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rules)
+        })?;
+
+        let now = chrono::Utc::now().timestamp();
+        let traefik_down = if rules.iter().any(|r| r.metric == "traefik_down") {
+            !state.traefik.is_running().await.unwrap_or(false)
+        } else {
+            false
+        };
+        if metrics.docker_ok && rules.iter().any(|r| r.metric == "container_restarts") {
+            self.track_restarts(state, now).await;
+        }
+
+        // (rule, subject) pairs with a current reading; firing alerts for
+        // evaluated rules whose subject vanished are resolved below.
+        let mut observed: HashSet<(i64, String)> = HashSet::new();
+        let mut evaluated: HashSet<i64> = HashSet::new();
+
+        for rule in &rules {
+            let readings: Vec<(String, f64)> = match rule.metric.as_str() {
+                "cpu_pct" => vec![(HOST_SUBJECT.to_string(), metrics.cpu_usage_pct as f64)],
+                "mem_pct" => vec![(
+                    HOST_SUBJECT.to_string(),
+                    pct(metrics.mem_used_bytes, metrics.mem_total_bytes),
+                )],
+                "swap_pct" => vec![(
+                    HOST_SUBJECT.to_string(),
+                    pct(metrics.swap_used_bytes, metrics.swap_total_bytes),
+                )],
+                "disk_pct" => vec![(
+                    HOST_SUBJECT.to_string(),
+                    pct(metrics.disk_used_bytes, metrics.disk_total_bytes),
+                )],
+                "load_1" => vec![(HOST_SUBJECT.to_string(), metrics.load_avg_1)],
+                "docker_down" => vec![(HOST_SUBJECT.to_string(), (!metrics.docker_ok) as u8 as f64)],
+                "traefik_down" => vec![(HOST_SUBJECT.to_string(), traefik_down as u8 as f64)],
+                // Container readings are stale while Docker is unreachable;
+                // leave those alerts as they are until it's back.
+                _ if !metrics.docker_ok => continue,
+                "container_cpu_pct" => stats
+                    .latest()
+                    .map(|(name, s)| (name.to_string(), s.cpu_pct))
+                    .collect(),
+                "container_mem_pct" => stats
+                    .latest()
+                    .map(|(name, s)| (name.to_string(), pct(s.mem_bytes, s.mem_limit)))
+                    .collect(),
+                "container_restarts" => self
+                    .restarts
+                    .iter()
+                    .map(|(name, history)| (name.clone(), restarts_within(history, now, rule.duration_secs)))
+                    .collect(),
+                _ => continue,
+            };
+            evaluated.insert(rule.id);
+
+            for (subject, value) in readings {
+                if rule.container.as_deref().is_some_and(|c| c != subject) {
+                    continue;
+                }
+                let key = (rule.id, subject.clone());
+                observed.insert(key.clone());
+
+                if !breaches(rule, value) {
+                    self.pending.remove(&key);
+                    self.resolve(state, rule.id, &subject)?;
+                    continue;
+                }
+
+                let since = *self.pending.entry(key).or_insert(now);
+                // Restart rules already count over their window.
+                let hold = if rule.metric == "container_restarts" { 0 } else { rule.duration_secs };
+                if now - since >= hold {
+                    self.fire(state, rule, &subject, value)?;
+                }
+            }
+        }
+
+        // Resolve alerts whose rule was disabled or deleted, or whose subject
+        // (a removed container) is gone.
+        let firing = firing_alerts(state)?;
+        for alert in firing {
+            let Some(rule_id) = alert.rule_id else {
+                self.resolve(state, 0, &alert.subject)?;
+                continue;
+            };
+            let rule_active = rules.iter().any(|r| r.id == rule_id);
+            let key = (rule_id, alert.subject.clone());
+            if !rule_active || (evaluated.contains(&rule_id) && !observed.contains(&key)) {
+                self.pending.remove(&key);
+                self.resolve(state, rule_id, &alert.subject)?;
+            }
+        }
+        self.pending.retain(|key, _| observed.contains(key));
+
+        Ok(())
+    }
+
+    /// Track restart counts of managed containers, including ones currently
+    /// restarting, which aren't listed as running.
+    async fn track_restarts(&mut self, state: &AppState, now: i64) {
+        let containers = match state.docker.list_containers(true).await {
+            Ok(containers) => containers,
+            Err(e) => {
+                tracing::debug!("Could not list containers for restart tracking: {}", e);
+                return;
+            }
+        };
+
+        let mut seen = HashSet::new();
+        for c in containers
+            .iter()
+            .filter(|c| c.labels.get("dockyy.managed").map(String::as_str) == Some("true"))
+        {
+            let Ok(info) = state.docker.inspect_container(&c.id).await else {
+                continue;
+            };
+            let count = info.restart_count.unwrap_or(0);
+            let history = self.restarts.entry(c.name.clone()).or_default();
+            history.push_back((now, count));
+            // Longest window a rule may use; older readings are useless.
+            while history.front().is_some_and(|(ts, _)| now - ts > 24 * 3600) {
+                history.pop_front();
+            }
+            seen.insert(c.name.clone());
+        }
+        self.restarts.retain(|name, _| seen.contains(name));
+    }
+
+    fn fire(&self, state: &Arc<AppState>, rule: &AlertRule, subject: &str, value: f64) -> Result<()> {
+        let message = describe(rule, subject, value);
+        let inserted = state.db.with_conn(|conn| {
+            let changed = conn.execute(
+                "INSERT OR IGNORE INTO alerts (rule_id, rule_name, metric, subject, severity, value, message)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                rusqlite::params![rule.id, rule.name, rule.metric, subject, rule.severity, value, message],
+            )?;
+            Ok(changed > 0)
+        })?;
+        if !inserted {
+            return Ok(());
+        }
+
+        tracing::warn!(rule = %rule.name, subject, value, "Alert firing: {}", message);
+        notifications::dispatch(
+            state,
+            Notification::new(
+                "alert.firing",
+                &rule.severity,
+                format!("[{}] {}", rule.severity.to_uppercase(), rule.name),
+                message,
+                json!({"rule_id": rule.id, "metric": rule.metric, "subject": subject, "value": value}),
+            ),
+        );
+        Ok(())
+    }
+
+    /// Resolve the firing alert for `(rule_id, subject)`, if any. A `rule_id`
+    /// of 0 matches alerts whose rule was deleted.
+    fn resolve(&self, state: &Arc<AppState>, rule_id: i64, subject: &str) -> Result<()> {
+        let resolved: Option<Alert> = state.db.with_conn(|conn| {
+            let alert = conn
+                .query_row(
+                    &format!(
+                        "UPDATE alerts SET state = 'resolved', resolved_at = datetime('now')
+                         WHERE state = 'firing' AND COALESCE(rule_id, 0) = ?1 AND subject = ?2
+                         RETURNING {}",
+                        Alert::COLUMNS
+                    ),
+                    rusqlite::params![rule_id, subject],
+                    Alert::from_row,
+                )
+                .ok();
+            Ok(alert)
+        })?;
+
+        if let Some(alert) = resolved {
+            tracing::info!(rule = %alert.rule_name, subject, "Alert resolved");
+            notifications::dispatch(
+                state,
+                Notification::new(
+                    "alert.resolved",
+                    "resolved",
+                    format!("[RESOLVED] {}", alert.rule_name),
+                    format!("Resolved: {}", alert.message),
+                    json!({"rule_id": alert.rule_id, "metric": alert.metric, "subject": alert.subject}),
+                ),
+            );
+        }
+        Ok(())
+    }
+}
+
+fn restarts_within(history: &VecDeque<(i64, i64)>, now: i64, window: i64) -> f64 {
+    let Some((_, latest)) = history.back() else {
+        return 0.0;
+    };
+    let baseline = history
+        .iter()
+        .find(|(ts, _)| now - ts <= window)
+        .map(|(_, count)| *count)
+        .unwrap_or(*latest);
+    (latest - baseline).max(0) as f64
+}
+
+pub fn firing_alerts(state: &AppState) -> Result<Vec<Alert>> {
+    state.db.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM alerts WHERE state = 'firing' ORDER BY id",
+            Alert::COLUMNS
+        ))?;
+        let alerts = stmt
+            .query_map([], Alert::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(alerts)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, MockDocker};
+
+    fn add_rule(state: &AppState, metric: &str, threshold: f64, duration_secs: i64) -> i64 {
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute(
+                    "INSERT INTO alert_rules (name, metric, threshold, duration_secs) VALUES (?1, ?1, ?2, ?3)",
+                    rusqlite::params![metric, threshold, duration_secs],
+                )?;
+                Ok(conn.last_insert_rowid())
+            })
+            .unwrap()
+    }
+
+    /// `(metric, state)` of every alert, oldest first.
+    fn alerts(state: &AppState) -> Vec<(String, String)> {
+        state
+            .db
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT metric, state FROM alerts ORDER BY id")?;
+                let alerts = stmt
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(alerts)
+            })
+            .unwrap()
+    }
+
+    fn alert(metric: &str, state: &str) -> (String, String) {
+        (metric.to_string(), state.to_string())
+    }
+
+    #[tokio::test]
+    async fn fires_once_while_breaching_and_resolves() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let stats = StatsCollector::default();
+        let mut evaluator = AlertEvaluator::default();
+        add_rule(&state, "cpu_pct", 80.0, 0);
+        // Has to hold for 5 minutes first.
+        add_rule(&state, "mem_pct", 50.0, 300);
+        let docker_rule = add_rule(&state, "docker_down", 0.0, 0);
+
+        let busy = SystemMetrics {
+            cpu_usage_pct: 95.0,
+            mem_used_bytes: 90,
+            mem_total_bytes: 100,
+            docker_ok: true,
+            ..Default::default()
+        };
+        evaluator.evaluate(&state, &busy, &stats).await.unwrap();
+        evaluator.evaluate(&state, &busy, &stats).await.unwrap();
+        assert_eq!(alerts(&state), [alert("cpu_pct", "firing")]);
+
+        let idle = SystemMetrics {
+            cpu_usage_pct: 5.0,
+            docker_ok: false,
+            ..busy
+        };
+        evaluator.evaluate(&state, &idle, &stats).await.unwrap();
+        assert_eq!(alerts(&state), [alert("cpu_pct", "resolved"), alert("docker_down", "firing")]);
+
+        // Alerts of deleted rules are resolved too.
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute("DELETE FROM alert_rules WHERE id = ?1", [docker_rule])?;
+                Ok(())
+            })
+            .unwrap();
+        evaluator.evaluate(&state, &idle, &stats).await.unwrap();
+        assert_eq!(alerts(&state), [alert("cpu_pct", "resolved"), alert("docker_down", "resolved")]);
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    #[test]
+    fn counts_restarts_within_the_window() {
+        let history = VecDeque::from([(0, 1), (100, 3), (200, 6)]);
+        assert_eq!(restarts_within(&history, 200, 150), 3.0);
+        assert_eq!(restarts_within(&history, 200, 300), 5.0);
+        assert_eq!(restarts_within(&history, 200, 0), 0.0);
+        assert_eq!(restarts_within(&VecDeque::new(), 200, 300), 0.0);
+    }
+}
@@ -0,0 +1,344 @@
+//! Audit trail of everything that changes state.
+//!
+//! [`audit_middleware`] records each authenticated API request that isn't a
+//! read. Actions outside it (logins, webhook deploys, terminal sessions)
+//! are recorded by their handlers through [`record`]. Request parameters are
+//! kept as a summary with secret-looking fields replaced by [`REDACTED`].
+//! Entries older than `AUDIT_RETENTION_DAYS` are purged hourly.
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, OriginalUri, Query, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+
+use crate::auth::{Access, Claims};
+use crate::db::models::REDACTED;
+use crate::AppState;
+
+const RETENTION_INTERVAL_SECS: u64 = 3600;
+
+/// Largest request body buffered for the summary; matches axum's default
+/// JSON limit, so handlers would refuse anything bigger anyway.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Summaries longer than this are cut down to a string prefix.
+const MAX_SUMMARY_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Outcome {
+    #[default]
+    Success,
+    /// Refused for lack of permission, bad credentials or rate limiting.
+    Denied,
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Denied => "denied",
+            Outcome::Failure => "failure",
+        }
+    }
+
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => {
+                Outcome::Denied
+            }
+            s if s.is_client_error() || s.is_server_error() => Outcome::Failure,
+            _ => Outcome::Success,
+        }
+    }
+}
+
+/// An action to record.
+#[derive(Debug, Default)]
+pub struct Event<'a> {
+    pub username: Option<&'a str>,
+    pub token_id: Option<i64>,
+    pub ip: Option<&'a str>,
+    pub action: &'a str,
+    pub target: Option<&'a str>,
+    pub summary: Option<Value>,
+    pub outcome: Outcome,
+    pub status: Option<StatusCode>,
+}
+
+/// Write an entry. Failures are logged rather than failing the action.
+pub fn record(state: &AppState, event: Event<'_>) {
+    let summary = event.summary.map(|mut summary| {
+        redact(&mut summary);
+        let text = summary.to_string();
+        if text.len() > MAX_SUMMARY_LEN {
+            let cut = (0..=MAX_SUMMARY_LEN)
+                .rev()
+                .find(|i| text.is_char_boundary(*i))
+                .unwrap_or(0);
+            Value::String(format!("{}…", &text[..cut])).to_string()
+        } else {
+            text
+        }
+    });
+    let result = state.db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO audit_log
+                (username, token_id, ip_address, action, target, summary, outcome, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                event.username,
+                event.token_id,
+                event.ip,
+                event.action,
+                event.target,
+                summary,
+                event.outcome.as_str(),
+                event.status.map(|s| s.as_u16()),
+            ],
+        )?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        tracing::error!(action = event.action, "Failed to write audit entry: {}", e);
+    }
+}
+
+/// Whether a field name suggests its value is a credential.
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    ["password", "secret", "token", "ssh", "private_key", "credential", "authorization"]
+        .iter()
+        .any(|word| key.contains(word))
+        || key == "key"
+        || key.ends_with("_key")
+        || key == "code"
+        // Environment variables and notification channel settings (webhook
+        // URLs and headers double as credentials).
+        || key == "env"
+        || key.ends_with("_env")
+        || key == "env_vars"
+        || key == "config"
+        || key == "headers"
+}
+
+/// Replace the values of sensitive fields, at any depth.
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive(key) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Query parameters and body of a request, as recorded.
+fn summarize(req: &Request, body: &[u8]) -> Option<Value> {
+    let mut summary = serde_json::Map::new();
+    if let Ok(Query(query)) = Query::<HashMap<String, String>>::try_from_uri(req.uri())
+        && !query.is_empty()
+    {
+        summary.insert("query".into(), json!(query));
+    }
+    if !body.is_empty() {
+        let body = serde_json::from_slice(body).unwrap_or_else(|_| json!({"bytes": body.len()}));
+        summary.insert("body".into(), body);
+    }
+    (!summary.is_empty()).then_some(Value::Object(summary))
+}
+
+/// Layer recording every authenticated request except reads. Runs after
+/// [`crate::auth::auth_middleware`] so the caller is known.
+pub async fn audit_middleware(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let req = Request::from_parts(parts, axum::body::Body::from(body.clone()));
+
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(req.uri().path(), |p| p.as_str());
+    let action = format!("{} {}", req.method(), route);
+    // The URI seen here has the `/api` prefix stripped by nesting.
+    let target = req
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(req.uri().path(), |uri| uri.path())
+        .to_string();
+    let summary = summarize(&req, &body);
+    let username = req.extensions().get::<Claims>().map(|c| c.sub.clone());
+    let token_id = req.extensions().get::<Access>().and_then(|a| a.token_id);
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip().to_string());
+
+    let response = next.run(req).await;
+    record(
+        &state,
+        Event {
+            username: username.as_deref(),
+            token_id,
+            ip: ip.as_deref(),
+            action: &action,
+            target: Some(&target),
+            summary,
+            outcome: Outcome::from_status(response.status()),
+            status: Some(response.status()),
+        },
+    );
+    response
+}
+
+/// Purge entries past the retention window (never when it's 0).
+pub async fn run_retention(state: Arc<AppState>) {
+    let days = state.config.audit_retention_days;
+    if days == 0 {
+        return;
+    }
+    loop {
+        let purged = state.db.with_conn(|conn| {
+            Ok(conn.execute(
+                "DELETE FROM audit_log WHERE created_at < datetime('now', '-' || ?1 || ' days')",
+                [days],
+            )?)
+        });
+        match purged {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Purged {} audit entries older than {} days", n, days),
+            Err(e) => tracing::warn!("Audit log purge failed: {}", e),
+        }
+        sleep(Duration::from_secs(RETENTION_INTERVAL_SECS)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+    use crate::test_support::{self, call, MockDocker};
+
+    #[derive(Debug)]
+    struct Entry {
+        username: Option<String>,
+        token_id: Option<i64>,
+        action: String,
+        target: Option<String>,
+        summary: Option<Value>,
+        outcome: String,
+    }
+
+    fn entries(state: &AppState) -> Vec<Entry> {
+        state
+            .db
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT username, token_id, action, target, summary, outcome FROM audit_log ORDER BY id",
+                )?;
+                let rows = stmt.query_map([], |row| {
+                    let summary: Option<String> = row.get(4)?;
+                    Ok(Entry {
+                        username: row.get(0)?,
+                        token_id: row.get(1)?,
+                        action: row.get(2)?,
+                        target: row.get(3)?,
+                        summary: summary.and_then(|s| serde_json::from_str(&s).ok()),
+                        outcome: row.get(5)?,
+                    })
+                })?;
+                Ok(rows.collect::<Result<Vec<_>, _>>()?)
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_mutations_with_the_caller() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        let shop = test_support::add_repo(&state, "shop");
+        docker.add_container("shop", &[("dockyy.repo_id", &shop.to_string())]);
+        test_support::add_user(&state, "root", Role::Admin, None);
+        test_support::add_user(&state, "viewer", Role::Viewer, None);
+        let root = test_support::login(&state, "root");
+
+        // Reads aren't recorded.
+        let (status, _) = call(&app, Method::GET, "/api/repositories", Some(&root), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(entries(&state).is_empty());
+
+        let body = json!({"username": "ann", "password": "longpassword", "role": "viewer"});
+        let (status, _) = call(&app, Method::POST, "/api/users", Some(&root), Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let body = json!({"name": "ci", "scope": "deploy"});
+        let (_, created) = call(&app, Method::POST, "/api/auth/tokens", Some(&root), Some(body)).await;
+        let token = created["token"].as_str().unwrap();
+        let token_id = created["id"].as_i64().unwrap();
+        let (status, _) = call(&app, Method::POST, "/api/containers/shop/restart", Some(token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let recorded = entries(&state);
+        assert_eq!(recorded.len(), 3, "{:?}", recorded);
+        let user = &recorded[0];
+        assert_eq!(user.username.as_deref(), Some("root"));
+        assert_eq!(user.token_id, None);
+        assert_eq!(user.action, "POST /api/users");
+        let summary = user.summary.as_ref().unwrap();
+        assert_eq!(summary["body"]["username"], "ann");
+        assert_eq!(summary["body"]["password"], REDACTED);
+        assert_eq!(user.outcome, "success");
+        let restart = &recorded[2];
+        assert_eq!(restart.username.as_deref(), Some("root"));
+        assert_eq!(restart.token_id, Some(token_id));
+        assert_eq!(restart.target.as_deref(), Some("/api/containers/shop/restart"));
+
+        // Refused mutations are recorded as denied; requests that fail
+        // authentication never reach the layer.
+        let viewer = test_support::login(&state, "viewer");
+        let (status, _) = call(&app, Method::DELETE, "/api/containers/shop", Some(&viewer), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&app, Method::DELETE, "/api/containers/shop", Some("dky_bogus"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let recorded = entries(&state);
+        assert_eq!(recorded.len(), 4, "{:?}", recorded);
+        assert_eq!(recorded[3].username.as_deref(), Some("viewer"));
+        assert_eq!(recorded[3].outcome, "denied");
+
+        // Failed sign-ins are recorded by the login handler.
+        let body = json!({"username": "root", "password": "wrong-password"});
+        let (status, _) = call(&app, Method::POST, "/api/auth/login", None, Some(body)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let recorded = entries(&state);
+        let login = recorded.last().unwrap();
+        assert_eq!((login.action.as_str(), login.outcome.as_str()), ("login", "denied"));
+        assert_eq!(login.username.as_deref(), Some("root"));
+        assert_eq!(login.summary.as_ref().unwrap()["reason"], "invalid_password");
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
@@ -0,0 +1,244 @@
+//! Docker build contexts: `.dockerignore` handling and tar packing.
+//!
+//! Patterns follow Docker's rules: paths are relative to the context root,
+//! `*`, `?` and `[...]` match within one path segment, `**` matches any number
+//! of segments, a pattern matching a directory excludes everything under it,
+//! and a later `!pattern` re-includes what earlier patterns excluded.
+
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+struct Pattern {
+    segments: Vec<String>,
+    exclude: bool,
+}
+
+/// Parsed `.dockerignore` of a build context.
+#[derive(Default)]
+pub struct DockerIgnore {
+    patterns: Vec<Pattern>,
+}
+
+impl DockerIgnore {
+    pub fn parse(content: &str) -> Self {
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (exclude, pattern) = match line.strip_prefix('!') {
+                    Some(rest) => (false, rest.trim()),
+                    None => (true, line),
+                };
+                let segments: Vec<String> = pattern
+                    .split('/')
+                    .filter(|s| !s.is_empty() && *s != ".")
+                    .map(str::to_string)
+                    .collect();
+                (!segments.is_empty()).then_some(Pattern { segments, exclude })
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// The `.dockerignore` at the root of `dir`, or no patterns without one.
+    pub fn load(dir: &Path) -> Result<Self> {
+        match std::fs::read_to_string(dir.join(".dockerignore")) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether `path` (relative, `/`-separated) is left out of the context.
+    pub fn is_excluded(&self, path: &str) -> bool {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut excluded = false;
+        for pattern in &self.patterns {
+            // A pattern also applies to everything under a directory it matches.
+            let matches = (1..=segments.len()).any(|n| match_segments(&pattern.segments, &segments[..n]));
+            if matches {
+                excluded = pattern.exclude;
+            }
+        }
+        excluded
+    }
+
+    /// Whether an excluded directory may still contain re-included paths,
+    /// in which case it has to be walked.
+    fn has_exceptions(&self) -> bool {
+        self.patterns.iter().any(|p| !p.exclude)
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                match_glob(first.as_bytes(), name.as_bytes()) && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Shell-style match of one path segment: `*`, `?`, `[a-z]`, `[^abc]` and
+/// `\` escapes.
+fn match_glob(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_glob(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_glob(rest, &name[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, name_rest)) = name.split_first() else {
+                return false;
+            };
+            let (negated, class) = match rest.first() {
+                Some(b'^') | Some(b'!') => (true, &rest[1..]),
+                _ => (false, rest),
+            };
+            let Some(end) = class.iter().position(|&b| b == b']') else {
+                // Unterminated class: treat `[` literally.
+                return c == b'[' && match_glob(rest, name_rest);
+            };
+            let mut matched = false;
+            let mut i = 0;
+            while i < end {
+                if i + 2 < end && class[i + 1] == b'-' {
+                    matched |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+            matched != negated && match_glob(&class[end + 1..], name_rest)
+        }
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            name.first() == Some(&rest[0]) && match_glob(&rest[1..], &name[1..])
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && match_glob(rest, &name[1..]),
+    }
+}
+
+/// Write `dir` as an uncompressed tar to `out`, leaving out `.git` and
+/// whatever `.dockerignore` excludes. The Dockerfile and `.dockerignore`
+/// are always sent, as the daemon needs them.
+pub fn write_tar<W: Write>(dir: &Path, dockerfile: &str, out: W) -> Result<W> {
+    let ignore = DockerIgnore::load(dir)?;
+    let mut builder = tar::Builder::new(out);
+    builder.follow_symlinks(false);
+    let always = [dockerfile.trim_start_matches("./"), ".dockerignore"];
+    append_dir(&mut builder, dir, "", &ignore, &always)?;
+    Ok(builder.into_inner()?)
+}
+
+fn append_dir<W: Write>(
+    builder: &mut tar::Builder<W>,
+    dir: &Path,
+    prefix: &str,
+    ignore: &DockerIgnore,
+    always: &[&str],
+) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    // Deterministic order keeps the context (and so the build cache) stable.
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let rel = format!("{}{}", prefix, name);
+        if rel == ".git" {
+            continue;
+        }
+        let path = entry.path();
+        let is_dir = entry.file_type()?.is_dir();
+        let excluded = ignore.is_excluded(&rel) && !always.contains(&rel.as_str());
+
+        if is_dir {
+            let holds_always = always.iter().any(|p| p.starts_with(&format!("{}/", rel)));
+            if excluded && !ignore.has_exceptions() && !holds_always {
+                continue;
+            }
+            if !excluded {
+                builder.append_path_with_name(&path, &rel)?;
+            }
+            append_dir(builder, &path, &format!("{}/", rel), ignore, always)?;
+        } else if !excluded {
+            builder.append_path_with_name(&path, &rel)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn matches_dockerignore_patterns() {
+        let ignore = DockerIgnore::parse(
+            "# comment\n\nnode_modules\n*.log\n**/tmp\nbuild/[a-c]*.o\n!important.log\n/dist/\n",
+        );
+        assert!(ignore.is_excluded("node_modules"));
+        assert!(ignore.is_excluded("node_modules/left-pad/index.js"));
+        assert!(ignore.is_excluded("debug.log"));
+        assert!(!ignore.is_excluded("logs/debug.log"));
+        assert!(!ignore.is_excluded("important.log"));
+        assert!(ignore.is_excluded("tmp"));
+        assert!(ignore.is_excluded("src/deep/tmp/file"));
+        assert!(ignore.is_excluded("build/b1.o"));
+        assert!(!ignore.is_excluded("build/d1.o"));
+        assert!(ignore.is_excluded("dist/app.js"));
+        assert!(!ignore.is_excluded("src/main.rs"));
+    }
+
+    #[test]
+    fn later_patterns_win() {
+        let ignore = DockerIgnore::parse("*.md\n!README*.md\nREADME-secret.md\n");
+        assert!(ignore.is_excluded("CHANGELOG.md"));
+        assert!(!ignore.is_excluded("README.md"));
+        assert!(ignore.is_excluded("README-secret.md"));
+    }
+
+    #[test]
+    fn glob_classes_and_escapes() {
+        assert!(match_glob(b"file?.txt", b"file1.txt"));
+        assert!(!match_glob(b"file?.txt", b"file.txt"));
+        assert!(match_glob(b"[^a]*", b"bcd"));
+        assert!(!match_glob(b"[!a]*", b"abc"));
+        assert!(match_glob(b"\\*", b"*"));
+        assert!(!match_glob(b"\\*", b"a"));
+        assert!(match_glob(b"[x", b"[x"));
+    }
+
+    #[test]
+    fn tar_leaves_out_ignored_paths_but_keeps_the_dockerfile() {
+        let dir = test_support::temp_dir();
+        std::fs::create_dir_all(dir.join("docker")).unwrap();
+        std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join("docker/Dockerfile"), "FROM scratch\n").unwrap();
+        std::fs::write(dir.join("docker/notes.txt"), "notes").unwrap();
+        std::fs::write(dir.join("target/debug/app"), "bin").unwrap();
+        std::fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.join(".git/config"), "").unwrap();
+        std::fs::write(dir.join(".dockerignore"), "target\ndocker\n").unwrap();
+
+        let tar = write_tar(&dir, "./docker/Dockerfile", Vec::new()).unwrap();
+        let mut names: Vec<String> = tar::Archive::new(&tar[..])
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, [".dockerignore", "docker/Dockerfile", "main.rs"]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
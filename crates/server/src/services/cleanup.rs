@@ -0,0 +1,137 @@
+//! Scheduled image cleanup.
+//!
+//! Every deployment tags its image `dockyy-<slug>:d<deployment id>`. The
+//! cleanup keeps the newest `IMAGE_RETENTION` of those tags per repository (the
+//! rollback window) and untags the rest, letting the engine delete images that
+//! are no longer referenced. When disk usage reported by the health monitor
+//! exceeds `IMAGE_PRUNE_DISK_PCT`, dangling images and the build cache are
+//! pruned as well.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+
+use crate::db::models::{PruneReport, Repository};
+use crate::AppState;
+
+const CLEANUP_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug, Serialize, Default)]
+pub struct CleanupReport {
+    /// Deployment image tags removed because they fell outside the retention window.
+    pub removed: Vec<String>,
+    /// Set when the disk threshold was exceeded (or a prune was forced).
+    pub pruned: Option<PruneReport>,
+    pub disk_used_pct: Option<f64>,
+}
+
+pub async fn run_cleanup(state: Arc<AppState>) {
+    tracing::info!(
+        retention = state.config.image_retention,
+        prune_disk_pct = state.config.image_prune_disk_pct,
+        "Starting image cleanup (interval: {}s)",
+        CLEANUP_INTERVAL_SECS
+    );
+
+    loop {
+        sleep(Duration::from_secs(CLEANUP_INTERVAL_SECS)).await;
+
+        match run_once(&state, false).await {
+            Ok(report) => tracing::info!(
+                removed = report.removed.len(),
+                pruned = report.pruned.is_some(),
+                "Image cleanup finished"
+            ),
+            Err(e) => tracing::warn!("Image cleanup failed: {}", e),
+        }
+    }
+}
+
+/// Tag given to the image of deployment `deployment_id`.
+pub fn deployment_image_tag(repo: &Repository, deployment_id: i64) -> String {
+    format!("dockyy-{}:d{}", repo.slug(), deployment_id)
+}
+
+/// Apply the retention window, then prune when the disk is over the threshold
+/// or `force_prune` is set.
+pub async fn run_once(state: &AppState, force_prune: bool) -> Result<CleanupReport> {
+    let mut report = CleanupReport {
+        removed: enforce_retention(state).await?,
+        ..Default::default()
+    };
+
+    let metrics = state.metrics.read().await.clone();
+    if metrics.disk_total_bytes > 0 {
+        report.disk_used_pct =
+            Some(metrics.disk_used_bytes as f64 / metrics.disk_total_bytes as f64 * 100.0);
+    }
+
+    let over_threshold = report
+        .disk_used_pct
+        .is_some_and(|pct| pct >= state.config.image_prune_disk_pct);
+
+    if force_prune || over_threshold {
+        tracing::info!(
+            disk_used_pct = report.disk_used_pct,
+            "Pruning dangling images and build cache"
+        );
+        let mut pruned = state.docker.prune_images(false).await?;
+        match state.docker.prune_build_cache().await {
+            Ok(summary) => pruned.build_cache = Some(summary),
+            Err(e) => tracing::warn!("Could not prune build cache: {}", e),
+        }
+        report.pruned = Some(pruned);
+    }
+
+    Ok(report)
+}
+
+/// Untag all but the newest `image_retention` deployment images of each
+/// repository. Tags whose image is still used by a container are left alone by
+/// the engine (non-forced removal) and skipped.
+async fn enforce_retention(state: &AppState) -> Result<Vec<String>> {
+    let retention = state.config.image_retention;
+    if retention == 0 {
+        return Ok(Vec::new());
+    }
+
+    let repos: Vec<Repository> = state.db.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM repositories", Repository::COLUMNS))?;
+        let repos = stmt
+            .query_map([], Repository::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(repos)
+    })?;
+
+    let images = state.docker.list_images().await?;
+    let mut removed = Vec::new();
+
+    for repo in repos {
+        let prefix = format!("dockyy-{}:d", repo.slug());
+        let mut tags: Vec<(i64, &String)> = images
+            .iter()
+            .flat_map(|i| i.tags.iter())
+            .filter_map(|t| {
+                t.strip_prefix(&prefix)
+                    .and_then(|id| id.parse::<i64>().ok())
+                    .map(|id| (id, t))
+            })
+            .collect();
+
+        // Newest deployment first.
+        tags.sort_by_key(|(id, _)| std::cmp::Reverse(*id));
+
+        for (_, tag) in tags.into_iter().skip(retention) {
+            match state.docker.delete_image(tag, false).await {
+                Ok(_) => removed.push(tag.clone()),
+                Err(e) => tracing::debug!("Keeping image {}: {}", tag, e),
+            }
+        }
+    }
+
+    if !removed.is_empty() {
+        tracing::info!("Removed {} expired deployment image(s)", removed.len());
+    }
+    Ok(removed)
+}
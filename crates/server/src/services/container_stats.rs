@@ -0,0 +1,284 @@
+//! Per-container resource history.
+//!
+//! The health monitor samples every running dockyy-managed container through
+//! the Docker stats API. Raw samples are kept for 24 hours and averaged into
+//! 5-minute buckets kept for 7 days. Network and block I/O are stored as
+//! throughput, derived from the cumulative counters of consecutive samples.
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use std::collections::HashMap;
+
+use crate::db::models::{AppUsage, ContainerStats, ContainerStatsSeries, StatsSample};
+use crate::db::Database;
+use crate::AppState;
+
+/// Seconds between raw samples (the monitor interval).
+pub const RAW_RESOLUTION: i64 = 30;
+pub const ROLLUP_RESOLUTION: i64 = 300;
+const RAW_RETENTION_SECS: i64 = 24 * 3600;
+const ROLLUP_RETENTION_SECS: i64 = 7 * 24 * 3600;
+
+/// Keeps the previous reading of each container to turn counters into rates.
+#[derive(Default)]
+pub struct StatsCollector {
+    previous: HashMap<String, (i64, ContainerStats)>,
+}
+
+impl StatsCollector {
+    /// Readings from the last round, by container name.
+    pub fn latest(&self) -> impl Iterator<Item = (&str, &ContainerStats)> {
+        self.previous
+            .iter()
+            .map(|(name, (_, stats))| (name.as_str(), stats))
+    }
+
+    /// Sample all running managed containers and store one row each.
+    pub async fn sample(&mut self, state: &AppState) -> Result<usize> {
+        let containers: Vec<_> = state
+            .docker
+            .list_containers(false)
+            .await?
+            .into_iter()
+            .filter(|c| c.labels.get("dockyy.managed").map(String::as_str) == Some("true"))
+            .collect();
+
+        // Stats calls block for about a second each, so run them concurrently.
+        let readings = join_all(containers.iter().map(|c| state.docker.container_stats(&c.id))).await;
+        let ts = chrono::Utc::now().timestamp();
+
+        let mut rows = Vec::new();
+        let mut seen = HashMap::new();
+        for (container, reading) in containers.iter().zip(readings) {
+            let stats = match reading {
+                Ok(stats) => stats,
+                Err(e) => {
+                    tracing::debug!("Could not read stats for {}: {}", container.name, e);
+                    continue;
+                }
+            };
+
+            let rate = |now: u64, before: u64, elapsed: i64| {
+                // Counters reset when the container is recreated.
+                if elapsed > 0 && now >= before {
+                    (now - before) as f64 / elapsed as f64
+                } else {
+                    0.0
+                }
+            };
+            let sample = match self.previous.get(&container.name) {
+                Some((prev_ts, prev)) => {
+                    let elapsed = ts - prev_ts;
+                    StatsSample {
+                        ts,
+                        cpu_pct: stats.cpu_pct,
+                        mem_bytes: stats.mem_bytes as i64,
+                        mem_limit: stats.mem_limit as i64,
+                        net_rx_bps: rate(stats.net_rx_bytes, prev.net_rx_bytes, elapsed),
+                        net_tx_bps: rate(stats.net_tx_bytes, prev.net_tx_bytes, elapsed),
+                        blk_read_bps: rate(stats.blk_read_bytes, prev.blk_read_bytes, elapsed),
+                        blk_write_bps: rate(stats.blk_write_bytes, prev.blk_write_bytes, elapsed),
+                    }
+                }
+                None => StatsSample {
+                    ts,
+                    cpu_pct: stats.cpu_pct,
+                    mem_bytes: stats.mem_bytes as i64,
+                    mem_limit: stats.mem_limit as i64,
+                    net_rx_bps: 0.0,
+                    net_tx_bps: 0.0,
+                    blk_read_bps: 0.0,
+                    blk_write_bps: 0.0,
+                },
+            };
+
+            let repo_id = container
+                .labels
+                .get("dockyy.repo_id")
+                .and_then(|id| id.parse::<i64>().ok());
+            let environment = container
+                .labels
+                .get("dockyy.environment")
+                .cloned()
+                .or_else(|| container.labels.get("dockyy.preview").map(|pr| format!("pr-{}", pr)));
+
+            rows.push((container.name.clone(), repo_id, environment, sample));
+            seen.insert(container.name.clone(), (ts, stats));
+        }
+
+        // Forget containers that are gone so a later container with the same
+        // name starts from fresh counters.
+        self.previous = seen;
+
+        let count = rows.len();
+        state.db.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "INSERT OR REPLACE INTO container_stats (ts, resolution, container_name, repo_id,
+                    environment, cpu_pct, mem_bytes, mem_limit, net_rx_bps, net_tx_bps,
+                    blk_read_bps, blk_write_bps)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for (name, repo_id, environment, s) in &rows {
+                stmt.execute(rusqlite::params![
+                    s.ts,
+                    RAW_RESOLUTION,
+                    name,
+                    repo_id,
+                    environment,
+                    s.cpu_pct,
+                    s.mem_bytes,
+                    s.mem_limit,
+                    s.net_rx_bps,
+                    s.net_tx_bps,
+                    s.blk_read_bps,
+                    s.blk_write_bps,
+                ])?;
+            }
+            Ok(())
+        })?;
+
+        Ok(count)
+    }
+}
+
+/// Average raw samples into 5-minute buckets and expire old rows. The last
+/// hour of buckets is recomputed each time, so running this often is harmless.
+pub fn rollup(db: &Database) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    let current_bucket = now - now % ROLLUP_RESOLUTION;
+
+    db.with_conn(|conn| {
+        conn.execute(
+            "INSERT OR REPLACE INTO container_stats (ts, resolution, container_name, repo_id,
+                environment, cpu_pct, mem_bytes, mem_limit, net_rx_bps, net_tx_bps,
+                blk_read_bps, blk_write_bps)
+             SELECT (ts / ?3) * ?3, ?3, container_name, MAX(repo_id), MAX(environment),
+                    AVG(cpu_pct), CAST(AVG(mem_bytes) AS INTEGER), MAX(mem_limit),
+                    AVG(net_rx_bps), AVG(net_tx_bps), AVG(blk_read_bps), AVG(blk_write_bps)
+             FROM container_stats
+             WHERE resolution = ?4 AND ts >= ?1 AND ts < ?2
+             GROUP BY (ts / ?3) * ?3, container_name",
+            rusqlite::params![
+                current_bucket - 3600,
+                current_bucket,
+                ROLLUP_RESOLUTION,
+                RAW_RESOLUTION
+            ],
+        )?;
+
+        conn.execute(
+            "DELETE FROM container_stats WHERE resolution = ?1 AND ts < ?2",
+            rusqlite::params![RAW_RESOLUTION, now - RAW_RETENTION_SECS],
+        )?;
+        conn.execute(
+            "DELETE FROM container_stats WHERE resolution = ?1 AND ts < ?2",
+            rusqlite::params![ROLLUP_RESOLUTION, now - ROLLUP_RETENTION_SECS],
+        )?;
+        Ok(())
+    })
+}
+
+/// Raw samples while the whole range is still within raw retention, 5-minute
+/// buckets otherwise.
+pub fn resolution_for(from: i64) -> i64 {
+    if from >= chrono::Utc::now().timestamp() - RAW_RETENTION_SECS {
+        RAW_RESOLUTION
+    } else {
+        ROLLUP_RESOLUTION
+    }
+}
+
+/// Per-container series of one repository between `from` and `to`.
+pub fn repository_series(
+    db: &Database,
+    repo_id: i64,
+    from: i64,
+    to: i64,
+) -> Result<Vec<ContainerStatsSeries>> {
+    let resolution = resolution_for(from);
+
+    db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT container_name, environment, ts, cpu_pct, mem_bytes, mem_limit,
+                    net_rx_bps, net_tx_bps, blk_read_bps, blk_write_bps
+             FROM container_stats
+             WHERE repo_id = ?1 AND resolution = ?2 AND ts >= ?3 AND ts <= ?4
+             ORDER BY container_name, ts",
+        )?;
+
+        let mut series: Vec<ContainerStatsSeries> = Vec::new();
+        let rows = stmt.query_map(rusqlite::params![repo_id, resolution, from, to], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                StatsSample {
+                    ts: row.get(2)?,
+                    cpu_pct: row.get(3)?,
+                    mem_bytes: row.get(4)?,
+                    mem_limit: row.get(5)?,
+                    net_rx_bps: row.get(6)?,
+                    net_tx_bps: row.get(7)?,
+                    blk_read_bps: row.get(8)?,
+                    blk_write_bps: row.get(9)?,
+                },
+            ))
+        })?;
+
+        for row in rows {
+            let (name, environment, sample) = row?;
+            match series.last_mut() {
+                Some(s) if s.container_name == name => s.samples.push(sample),
+                _ => series.push(ContainerStatsSeries {
+                    container_name: name,
+                    environment,
+                    resolution,
+                    samples: vec![sample],
+                }),
+            }
+        }
+        Ok(series)
+    })
+}
+
+/// Usage of every application between `from` and `to`, heaviest CPU user first. Samples
+/// of an app's containers taken in the same round are summed before averaging.
+pub fn app_usage(db: &Database, from: i64, to: i64) -> Result<Vec<AppUsage>> {
+    let resolution = resolution_for(from);
+
+    db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT t.repo_id, r.name,
+                    AVG(t.cpu), MAX(t.cpu), CAST(AVG(t.mem) AS INTEGER), MAX(t.mem),
+                    AVG(t.rx), AVG(t.tx), AVG(t.rd), AVG(t.wr)
+             FROM (
+                SELECT repo_id, ts, SUM(cpu_pct) AS cpu, SUM(mem_bytes) AS mem,
+                       SUM(net_rx_bps) AS rx, SUM(net_tx_bps) AS tx,
+                       SUM(blk_read_bps) AS rd, SUM(blk_write_bps) AS wr
+                FROM container_stats
+                WHERE resolution = ?1 AND ts >= ?2 AND ts <= ?3 AND repo_id IS NOT NULL
+                GROUP BY repo_id, ts
+             ) t
+             JOIN repositories r ON r.id = t.repo_id
+             GROUP BY t.repo_id
+             ORDER BY AVG(t.cpu) DESC",
+        )?;
+
+        let usage = stmt
+            .query_map(rusqlite::params![resolution, from, to], |row| {
+                Ok(AppUsage {
+                    repo_id: row.get(0)?,
+                    name: row.get(1)?,
+                    avg_cpu_pct: row.get(2)?,
+                    max_cpu_pct: row.get(3)?,
+                    avg_mem_bytes: row.get(4)?,
+                    max_mem_bytes: row.get(5)?,
+                    avg_net_rx_bps: row.get(6)?,
+                    avg_net_tx_bps: row.get(7)?,
+                    avg_blk_read_bps: row.get(8)?,
+                    avg_blk_write_bps: row.get(9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(usage)
+    })
+}
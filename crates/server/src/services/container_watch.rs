@@ -0,0 +1,151 @@
+//! Container lifecycle events, detected by diffing managed container states
+//! between health monitor iterations.
+//!
+//! A running container that is found exited with a non-zero code emits
+//! `container.died`; one whose health check turns unhealthy emits
+//! `healthcheck.failed`. Containers seen for the first time only establish a
+//! baseline, so a server restart doesn't replay old failures.
+
+use anyhow::Result;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::db::models::ContainerInfo;
+use crate::services::notifications::{self, Notification};
+use crate::AppState;
+
+struct Seen {
+    running: bool,
+    unhealthy: bool,
+}
+
+#[derive(Default)]
+pub struct ContainerWatcher {
+    /// Last observed state per container id.
+    seen: HashMap<String, Seen>,
+}
+
+/// Exit code from a status such as `Exited (137) 2 seconds ago`.
+fn exit_code(status: &str) -> Option<i64> {
+    let rest = status.strip_prefix("Exited (")?;
+    rest[..rest.find(')')?].parse().ok()
+}
+
+fn is_managed(c: &ContainerInfo) -> bool {
+    c.labels.get("dockyy.managed").map(String::as_str) == Some("true")
+}
+
+impl ContainerWatcher {
+    pub async fn check(&mut self, state: &Arc<AppState>) -> Result<()> {
+        let containers = state.docker.list_containers(true).await?;
+
+        let mut seen = HashMap::new();
+        for c in containers.iter().filter(|c| is_managed(c)) {
+            let now = Seen {
+                running: c.state == "running",
+                unhealthy: c.status.contains("(unhealthy)"),
+            };
+
+            if let Some(before) = self.seen.get(&c.id) {
+                if before.running
+                    && !now.running
+                    && let Some(code) = exit_code(&c.status).filter(|code| *code != 0)
+                {
+                    tracing::warn!(container = %c.name, code, "Container died");
+                    notifications::dispatch(
+                        state,
+                        Notification::new(
+                            "container.died",
+                            "critical",
+                            format!("Container {} died", c.name),
+                            format!("{} exited with code {}", c.name, code),
+                            json!({"container": c.name, "container_id": c.id, "exit_code": code,
+                                   "image": c.image}),
+                        ),
+                    );
+                }
+                if now.unhealthy && !before.unhealthy {
+                    tracing::warn!(container = %c.name, "Container health check failing");
+                    notifications::dispatch(
+                        state,
+                        Notification::new(
+                            "healthcheck.failed",
+                            "warning",
+                            format!("Health check failing for {}", c.name),
+                            format!("{} is reporting unhealthy ({})", c.name, c.status),
+                            json!({"container": c.name, "container_id": c.id, "image": c.image}),
+                        ),
+                    );
+                }
+            }
+            seen.insert(c.id.clone(), now);
+        }
+        self.seen = seen;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, MockDocker};
+
+    /// Events of every recorded notification delivery, oldest first.
+    fn events(state: &AppState) -> Vec<String> {
+        state
+            .db
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT event FROM notification_deliveries ORDER BY id")?;
+                let events = stmt
+                    .query_map([], |row| row.get(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(events)
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn notifies_when_managed_containers_die_or_turn_unhealthy() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        state
+            .db
+            .with_conn(|conn| {
+                // Nothing listens here; deliveries are recorded before they're attempted.
+                conn.execute(
+                    "INSERT INTO notification_channels (name, kind, config) VALUES ('ops', 'webhook', ?1)",
+                    [json!({"url": "http://127.0.0.1:1/hook"}).to_string()],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+        let mut watcher = ContainerWatcher::default();
+        docker.add_container("web", &[("dockyy.managed", "true")]);
+        docker.add_container("job", &[("dockyy.managed", "true")]);
+        docker.add_container("other", &[]);
+
+        // Failures seen on the first pass are only a baseline.
+        docker.set_status("job", "Exited (1) 3 hours ago");
+        watcher.check(&state).await.unwrap();
+        watcher.check(&state).await.unwrap();
+        assert!(events(&state).is_empty());
+
+        docker.set_status("web", "Up 2 minutes (unhealthy)");
+        docker.set_status("other", "Exited (137) 1 second ago");
+        watcher.check(&state).await.unwrap();
+        watcher.check(&state).await.unwrap();
+        assert_eq!(events(&state), ["healthcheck.failed"]);
+
+        docker.set_status("web", "Exited (137) 1 second ago");
+        watcher.check(&state).await.unwrap();
+        assert_eq!(events(&state), ["healthcheck.failed", "container.died"]);
+
+        // A clean exit isn't a death.
+        docker.set_status("job", "Up 1 second");
+        watcher.check(&state).await.unwrap();
+        docker.set_status("job", "Exited (0) 1 second ago");
+        watcher.check(&state).await.unwrap();
+        assert_eq!(events(&state), ["healthcheck.failed", "container.died"]);
+    }
+}
@@ -0,0 +1,263 @@
+//! Encryption of secrets stored in the database (repository SSH keys and
+//! webhook secrets).
+//!
+//! Values are sealed with AES-256-GCM and stored as
+//! `v1:<key id>:<base64 nonce + ciphertext>`. The key comes from
+//! `ENCRYPTION_KEY`, or is generated once into `data_dir`. To rotate it, set
+//! a new `ENCRYPTION_KEY` and list the old one in
+//! `PREVIOUS_ENCRYPTION_KEYS`; [`reencrypt_secrets`] moves everything to the
+//! new key on startup.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::SecureRandom;
+
+use crate::db::Database;
+
+const FORMAT: &str = "v1";
+
+struct Key {
+    id: String,
+    key: LessSafeKey,
+}
+
+impl Key {
+    /// Keys are derived from the configured secret, so any string works.
+    fn derive(secret: &str) -> Result<Self> {
+        let bytes = ring::digest::digest(&ring::digest::SHA256, secret.trim().as_bytes());
+        let id = ring::digest::digest(&ring::digest::SHA256, bytes.as_ref());
+        Ok(Self {
+            id: id.as_ref()[..4].iter().map(|b| format!("{:02x}", b)).collect(),
+            key: LessSafeKey::new(
+                UnboundKey::new(&AES_256_GCM, bytes.as_ref())
+                    .map_err(|_| anyhow!("Invalid encryption key"))?,
+            ),
+        })
+    }
+}
+
+/// The current key, plus earlier ones still accepted for decryption.
+pub struct Keyring {
+    current: Key,
+    previous: Vec<Key>,
+}
+
+impl Keyring {
+    pub fn new(current: &str, previous: &[String]) -> Result<Self> {
+        Ok(Self {
+            current: Key::derive(current)?,
+            previous: previous
+                .iter()
+                .map(|secret| Key::derive(secret))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Seal `plaintext`. `context` (such as `repository:3`) must be given
+    /// again to decrypt, so a value can't be moved to another row.
+    pub fn encrypt(&self, plaintext: &str, context: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        ring::rand::SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate nonce"))?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.current
+            .key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(context.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow!("Encryption failed"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&sealed);
+        Ok(format!(
+            "{}:{}:{}",
+            FORMAT,
+            self.current.id,
+            base64::engine::general_purpose::STANDARD.encode(payload)
+        ))
+    }
+
+    pub fn decrypt(&self, stored: &str, context: &str) -> Result<String> {
+        let mut parts = stored.splitn(3, ':');
+        let (Some(FORMAT), Some(id), Some(payload)) = (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Unrecognized encrypted value");
+        };
+        let key = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|k| k.id == id)
+            .ok_or_else(|| {
+                anyhow!("Value is encrypted with unknown key {}; add it to PREVIOUS_ENCRYPTION_KEYS", id)
+            })?;
+
+        let mut payload = base64::engine::general_purpose::STANDARD
+            .decode(payload)
+            .context("Corrupt encrypted value")?;
+        if payload.len() < NONCE_LEN {
+            bail!("Corrupt encrypted value");
+        }
+        let mut sealed = payload.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&payload)
+            .map_err(|_| anyhow!("Corrupt encrypted value"))?;
+        let plaintext = key
+            .key
+            .open_in_place(nonce, Aad::from(context.as_bytes()), &mut sealed)
+            .map_err(|_| anyhow!("Decryption failed"))?;
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+
+    /// Whether `stored` was sealed with an older key.
+    fn is_stale(&self, stored: &str) -> bool {
+        stored
+            .split(':')
+            .nth(1)
+            .is_some_and(|id| id != self.current.id)
+    }
+}
+
+/// Context binding a repository's SSH key to its row.
+pub fn ssh_key_context(repo_id: i64) -> String {
+    format!("repository:{}", repo_id)
+}
+
+/// Context binding a repository's webhook secret to its row.
+pub fn webhook_secret_context(repo_id: i64) -> String {
+    format!("repository:{}:webhook", repo_id)
+}
+
+/// `SHA256:...` fingerprint of an OpenSSH private key, as `ssh-keygen -l`
+/// shows it. `None` for other formats (such as legacy PEM keys).
+pub fn ssh_key_fingerprint(private_key: &str) -> Option<String> {
+    ssh_key::PrivateKey::from_openssh(private_key.trim())
+        .ok()
+        .map(|key| key.fingerprint(ssh_key::HashAlg::Sha256).to_string())
+}
+
+/// Context a sealed value is bound to, given its row id.
+type ContextFn = fn(i64) -> String;
+
+/// Encrypted columns of `repositories`, with the context each is bound to.
+const SEALED_COLUMNS: &[(&str, ContextFn)] = &[
+    ("ssh_key_encrypted", ssh_key_context),
+    ("webhook_secret_encrypted", webhook_secret_context),
+];
+
+/// Encrypt SSH keys still stored in plaintext and re-encrypt secrets sealed
+/// with a previous key. Runs on startup.
+pub fn reencrypt_secrets(db: &Database, keyring: &Keyring) -> Result<()> {
+    db.with_conn(|conn| {
+        let has_plaintext: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM pragma_table_info('repositories') WHERE name = 'ssh_password')",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_plaintext {
+            let mut stmt = conn.prepare(
+                "SELECT id, ssh_password FROM repositories
+                 WHERE ssh_password IS NOT NULL AND trim(ssh_password) != ''",
+            )?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            drop(stmt);
+
+            let tx = conn.unchecked_transaction()?;
+            for (id, key) in &rows {
+                tx.execute(
+                    "UPDATE repositories SET ssh_key_encrypted = ?2, ssh_key_fingerprint = ?3
+                     WHERE id = ?1",
+                    rusqlite::params![
+                        id,
+                        keyring.encrypt(key.trim(), &ssh_key_context(*id))?,
+                        ssh_key_fingerprint(key),
+                    ],
+                )?;
+            }
+            tx.execute("ALTER TABLE repositories DROP COLUMN ssh_password", [])?;
+            tx.commit()?;
+            if !rows.is_empty() {
+                // Don't leave the plaintext behind in free pages, nor in the
+                // main file until the WAL is next checkpointed.
+                conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+                tracing::info!("Encrypted {} plaintext repository SSH keys", rows.len());
+            }
+        }
+
+        let mut rotated = 0;
+        for (column, context) in SEALED_COLUMNS {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, {column} FROM repositories WHERE {column} IS NOT NULL"
+            ))?;
+            let stale = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|(_, stored)| keyring.is_stale(stored))
+                .collect::<Vec<_>>();
+            for (id, stored) in stale {
+                let context = context(id);
+                match keyring.decrypt(&stored, &context) {
+                    Ok(secret) => {
+                        conn.execute(
+                            &format!("UPDATE repositories SET {column} = ?2 WHERE id = ?1"),
+                            rusqlite::params![id, keyring.encrypt(&secret, &context)?],
+                        )?;
+                        rotated += 1;
+                    }
+                    Err(e) => tracing::warn!(repo_id = id, column, "Cannot re-encrypt secret: {}", e),
+                }
+            }
+        }
+        if rotated > 0 {
+            tracing::info!("Re-encrypted {} repository secrets with the current key", rotated);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_within_its_context() {
+        let keyring = Keyring::new("current", &[]).unwrap();
+        let sealed = keyring.encrypt("hunter2", &ssh_key_context(1)).unwrap();
+        assert!(sealed.starts_with("v1:"));
+        assert!(!sealed.contains("hunter2"));
+        assert_eq!(keyring.decrypt(&sealed, &ssh_key_context(1)).unwrap(), "hunter2");
+
+        assert!(keyring.decrypt(&sealed, &ssh_key_context(2)).is_err());
+        assert!(keyring.decrypt(&sealed, &webhook_secret_context(1)).is_err());
+    }
+
+    #[test]
+    fn previous_keys_still_decrypt() {
+        let old = Keyring::new("old", &[]).unwrap();
+        let sealed = old.encrypt("hunter2", "ctx").unwrap();
+
+        let rotated = Keyring::new("new", &["old".to_string()]).unwrap();
+        assert!(rotated.is_stale(&sealed));
+        assert_eq!(rotated.decrypt(&sealed, "ctx").unwrap(), "hunter2");
+        assert!(!rotated.is_stale(&rotated.encrypt("hunter2", "ctx").unwrap()));
+
+        let forgotten = Keyring::new("new", &[]).unwrap();
+        assert!(forgotten.decrypt(&sealed, "ctx").is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_values() {
+        let keyring = Keyring::new("current", &[]).unwrap();
+        let sealed = keyring.encrypt("hunter2", "ctx").unwrap();
+        let mut tampered = sealed.clone().into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(keyring.decrypt(&String::from_utf8(tampered).unwrap(), "ctx").is_err());
+        assert!(keyring.decrypt("plaintext", "ctx").is_err());
+        assert!(ssh_key_fingerprint("not a key").is_none());
+    }
+}
@@ -1,23 +1,198 @@
 use anyhow::Result;
 use bollard::container::{
-    ListContainersOptions, LogsOptions, RemoveContainerOptions, RestartContainerOptions,
-    StartContainerOptions, StopContainerOptions,
+    Config, CreateContainerOptions, ListContainersOptions, LogOutput, LogsOptions,
+    MemoryStatsStats, RemoveContainerOptions, RestartContainerOptions, StartContainerOptions,
+    Stats, StatsOptions, StopContainerOptions,
 };
+use bollard::exec::{CreateExecOptions, ResizeExecOptions, StartExecOptions, StartExecResults};
+use bollard::image::{
+    CreateImageOptions, ListImagesOptions, PruneImagesOptions, RemoveImageOptions,
+    TagImageOptions,
+};
+use bollard::models::{
+    BuildInfo, EndpointSettings, HostConfig, HostConfigLogConfig, ImageSummary, Ipam, IpamConfig, Mount,
+    MountTypeEnum, Network, RestartPolicy, RestartPolicyNameEnum, Volume, VolumeUsageData,
+};
+use bollard::network::{
+    ConnectNetworkOptions, CreateNetworkOptions, DisconnectNetworkOptions, InspectNetworkOptions,
+};
+use bollard::volume::{CreateVolumeOptions, RemoveVolumeOptions};
 use bollard::Docker;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::Write;
+use std::pin::Pin;
+use tokio::io::AsyncWrite;
 
-use crate::db::models::{ContainerInfo, PortMapping};
+use crate::db::models::{
+    ContainerInfo, ContainerStats, CreateNetworkRequest, CreateVolumeRequest, ImageInfo, LogLine, MountKind,
+    MountSpec, NetworkInfo, PortMapping, PruneReport, ResourceLimits, VolumeInfo,
+};
+use crate::services::build_context;
+use crate::services::engine::{self, DockerEndpoint, EngineCapabilities, EngineConnection};
 
 pub struct DockerService {
     client: Docker,
+    endpoint: DockerEndpoint,
+    /// Address the engine is reached at (the tunnel's socket for `ssh://`).
+    docker_host: String,
+    /// Keeps an SSH tunnel alive for `ssh://` endpoints.
+    connection: Option<EngineConnection>,
+}
+
+/// Options for [`DockerService::stream_logs`].
+#[derive(Debug, Clone, Default)]
+pub struct LogStreamOptions {
+    pub follow: bool,
+    /// Unix timestamps bounding the range (`None` = unbounded).
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Only the last `tail` lines of the range.
+    pub tail: Option<usize>,
+    pub timestamps: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+/// An attached exec instance: TTY output from the process and its stdin.
+pub struct ExecStream {
+    pub id: String,
+    pub output: Pin<Box<dyn Stream<Item = Result<LogOutput, bollard::errors::Error>> + Send>>,
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
+/// Everything needed to create an application container through the Docker API.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerSpec {
+    pub name: String,
+    pub image: String,
+    pub env: Vec<(String, String)>,
+    pub labels: HashMap<String, String>,
+    /// Network the container is attached to at creation time.
+    pub network: Option<String>,
+    /// Additional networks connected before the container starts.
+    pub extra_networks: Vec<String>,
+    pub limits: ResourceLimits,
+    pub mounts: Vec<MountSpec>,
+}
+
+impl ContainerSpec {
+    fn into_config(self) -> Result<Config<String>> {
+        let env = self
+            .env
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+
+        let limits = self.limits;
+        let (policy, retries) = limits.restart_policy().map_err(anyhow::Error::msg)?;
+        let restart_policy = RestartPolicy {
+            name: Some(match policy {
+                "no" => RestartPolicyNameEnum::NO,
+                "unless-stopped" => RestartPolicyNameEnum::UNLESS_STOPPED,
+                "on-failure" => RestartPolicyNameEnum::ON_FAILURE,
+                _ => RestartPolicyNameEnum::ALWAYS,
+            }),
+            maximum_retry_count: retries,
+        };
+
+        let log_config = limits.log_driver.map(|driver| HostConfigLogConfig {
+            typ: Some(driver),
+            config: Some(limits.log_options),
+        });
+
+        let mounts = self
+            .mounts
+            .into_iter()
+            .map(|m| Mount {
+                typ: Some(match m.kind {
+                    MountKind::Volume => MountTypeEnum::VOLUME,
+                    MountKind::Bind => MountTypeEnum::BIND,
+                }),
+                source: Some(m.source),
+                target: Some(m.target),
+                read_only: Some(m.read_only),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Config {
+            image: Some(self.image),
+            env: Some(env),
+            labels: Some(self.labels),
+            host_config: Some(HostConfig {
+                network_mode: self.network,
+                restart_policy: Some(restart_policy),
+                memory: limits.memory_limit,
+                memory_reservation: limits.memory_reservation,
+                nano_cpus: limits.cpus.map(|c| (c * 1_000_000_000.0) as i64),
+                cpu_shares: limits.cpu_shares,
+                cpu_quota: limits.cpu_quota,
+                cpu_period: limits.cpu_period,
+                pids_limit: limits.pids_limit,
+                log_config,
+                mounts: Some(mounts),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
 }
 
 impl DockerService {
-    pub async fn new() -> Result<Self> {
-        let client = Docker::connect_with_local_defaults()?;
+    /// Connect to the engine described by `endpoint` (see [`DockerEndpoint`]).
+    pub async fn new(endpoint: DockerEndpoint, data_dir: &str) -> Result<Self> {
+        let connection = engine::connect(&endpoint, data_dir).await?;
+        let mut service = Self::from_client(connection.client.clone(), endpoint).await?;
+        service.docker_host = connection.docker_host.clone();
+        service.connection = Some(connection);
+        Ok(service)
+    }
+
+    /// Wrap an existing client (e.g. one pointed at a mock Docker API server).
+    pub async fn from_client(client: Docker, endpoint: DockerEndpoint) -> Result<Self> {
         // Verify connection
         client.ping().await?;
-        Ok(Self { client })
+        let docker_host = match &endpoint {
+            DockerEndpoint::Unix(path) => format!("unix://{}", path),
+            DockerEndpoint::Tcp { addr, .. } => format!("tcp://{}", addr),
+            DockerEndpoint::Ssh { .. } => "unix:///var/run/docker.sock".to_string(),
+        };
+        Ok(Self {
+            client,
+            endpoint,
+            docker_host,
+            connection: None,
+        })
+    }
+
+    /// Shared client for other services talking to the same engine.
+    pub fn client(&self) -> Docker {
+        self.client.clone()
+    }
+
+    pub fn endpoint(&self) -> &DockerEndpoint {
+        &self.endpoint
+    }
+
+    /// `DOCKER_HOST` value for child processes such as compose.
+    pub fn docker_host(&self) -> &str {
+        &self.docker_host
+    }
+
+    /// Compose binary that can drive the connected engine.
+    pub async fn compose_bin(&self) -> Result<&'static str> {
+        let version = self.client.version().await?;
+        Ok(engine::find_compose_bin(engine::is_podman(&version), self.endpoint.is_remote()))
+    }
+
+    /// Probe which features the connected engine supports.
+    pub async fn capabilities(&self) -> Result<EngineCapabilities> {
+        engine::probe(&self.client, &self.endpoint).await
     }
 
     pub async fn list_containers(&self, all: bool) -> Result<Vec<ContainerInfo>> {
@@ -63,6 +238,7 @@ impl DockerService {
                     state: c.state.unwrap_or_default(),
                     ports,
                     created: c.created.unwrap_or(0),
+                    labels: c.labels.unwrap_or_default(),
                 }
             })
             .collect();
@@ -104,8 +280,648 @@ impl DockerService {
         Ok(())
     }
 
+    pub async fn remove_image(&self, name: &str) -> Result<()> {
+        self.client
+            .remove_image(
+                name,
+                Some(RemoveImageOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Build `tag` from `context_dir` through the Docker API. `dockerfile` is
+    /// relative to the context. The context (minus `.dockerignore`d paths) is
+    /// streamed as a tar while it is packed, since bollard only sends bodies it
+    /// holds in memory. Returns the build output; on failure the error carries
+    /// the output collected so far.
+    pub async fn build_image(&self, context_dir: &str, dockerfile: &str, tag: &str) -> Result<String> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Bytes>(CONTEXT_CHANNEL_CHUNKS);
+        let dir = std::path::PathBuf::from(context_dir);
+        let file = dockerfile.to_string();
+        let packer = tokio::task::spawn_blocking(move || {
+            build_context::write_tar(&dir, &file, ChunkWriter::new(tx))?.flush()?;
+            anyhow::Ok(())
+        });
+        let body = StreamBody::new(async_stream::stream! {
+            while let Some(chunk) = rx.recv().await {
+                yield Ok::<_, Infallible>(Frame::data(chunk));
+            }
+        });
+
+        let query = [
+            ("dockerfile", dockerfile),
+            ("t", tag),
+            ("rm", "1"),
+            ("forcerm", "1"),
+        ]
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, percent_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+        let request = hyper::Request::post(self.api_path(&format!("/build?{}", query)))
+            .header(hyper::header::CONTENT_TYPE, "application/x-tar")
+            .body(body)?;
+
+        // A packing error (unreadable file, ...) explains a failed build better
+        // than the daemon's view of a truncated tar.
+        let response = match self.send_raw(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                let e = packing_failure(packer).await.unwrap_or(e);
+                anyhow::bail!("Docker build failed: {}", e);
+            }
+        };
+        let status = response.status();
+        let mut body = response.into_body();
+        if !status.is_success() {
+            if let Some(e) = packing_failure(packer).await {
+                anyhow::bail!("Docker build failed: {}", e);
+            }
+            anyhow::bail!("Docker build failed: {}", error_message(body).await?);
+        }
+
+        // The daemon reports progress as JSON objects, one per line.
+        let mut log = String::new();
+        let mut pending = Vec::new();
+        while let Some(frame) = body.frame().await {
+            let Ok(chunk) = frame?.into_data() else {
+                continue;
+            };
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                build_progress(&line, &mut log)?;
+            }
+        }
+        build_progress(&pending, &mut log)?;
+        if let Some(e) = packing_failure(packer).await {
+            anyhow::bail!("Docker build failed: {}\n{}", e, log);
+        }
+
+        Ok(log)
+    }
+
+    /// Create and start a container from `spec`. Returns the container id.
+    /// The container is removed again when it can't be connected to its
+    /// networks or started, so a failed deploy leaves nothing behind.
+    pub async fn run_container(&self, spec: ContainerSpec) -> Result<String> {
+        let name = spec.name.clone();
+        let extra_networks = spec.extra_networks.clone();
+        let created = self
+            .client
+            .create_container(
+                Some(CreateContainerOptions {
+                    name: name.as_str(),
+                    platform: None,
+                }),
+                spec.into_config()?,
+            )
+            .await?;
+
+        let started = async {
+            for network in &extra_networks {
+                self.connect_network(network, &created.id, Vec::new()).await?;
+            }
+            self.client
+                .start_container(&created.id, None::<StartContainerOptions<String>>)
+                .await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = started {
+            if let Err(cleanup) = self.remove_container(&created.id, true).await {
+                tracing::warn!(container = %name, "Failed to remove container after a failed start: {}", cleanup);
+            }
+            return Err(e);
+        }
+
+        Ok(created.id)
+    }
+
+    /// Create the named volume with `labels` unless it already exists.
+    pub async fn ensure_volume(&self, name: &str, labels: HashMap<String, String>) -> Result<()> {
+        if self.client.inspect_volume(name).await.is_ok() {
+            return Ok(());
+        }
+        self.client
+            .create_volume(CreateVolumeOptions {
+                name: name.to_string(),
+                labels,
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Labels of the named volume, or `None` when it doesn't exist.
+    pub async fn volume_labels(&self, name: &str) -> Result<Option<HashMap<String, String>>> {
+        match self.client.inspect_volume(name).await {
+            Ok(volume) => Ok(Some(volume.labels)),
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List volumes with their size and the containers mounting them.
+    pub async fn list_volumes(&self) -> Result<Vec<VolumeInfo>> {
+        let list = self.client.list_volumes::<String>(None).await?;
+        let usage = self.volume_usage().await.unwrap_or_default();
+        let users = self.volume_users().await?;
+
+        let mut volumes: Vec<VolumeInfo> = list
+            .volumes
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| volume_info(v, &usage, &users))
+            .collect();
+        volumes.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(volumes)
+    }
+
+    pub async fn inspect_volume(&self, name: &str) -> Result<VolumeInfo> {
+        let volume = self.client.inspect_volume(name).await?;
+        let usage = self.volume_usage().await.unwrap_or_default();
+        let users = self.volume_users().await?;
+        Ok(volume_info(volume, &usage, &users))
+    }
+
+    pub async fn create_volume(&self, req: CreateVolumeRequest) -> Result<VolumeInfo> {
+        let volume = self
+            .client
+            .create_volume(CreateVolumeOptions {
+                name: req.name,
+                driver: req.driver.unwrap_or_else(|| "local".to_string()),
+                driver_opts: req.driver_opts,
+                labels: req.labels,
+            })
+            .await?;
+        Ok(volume_info(volume, &HashMap::new(), &HashMap::new()))
+    }
+
+    pub async fn remove_volume(&self, name: &str, force: bool) -> Result<()> {
+        self.client
+            .remove_volume(name, Some(RemoveVolumeOptions { force }))
+            .await?;
+        Ok(())
+    }
+
+    /// Map of volume name to the names of containers that mount it.
+    async fn volume_users(&self) -> Result<HashMap<String, Vec<String>>> {
+        let containers = self
+            .client
+            .list_containers(Some(ListContainersOptions::<String> {
+                all: true,
+                ..Default::default()
+            }))
+            .await?;
+
+        let mut users: HashMap<String, Vec<String>> = HashMap::new();
+        for c in containers {
+            let name = c
+                .names
+                .and_then(|n| n.first().cloned())
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string();
+            for mount in c.mounts.unwrap_or_default() {
+                if let Some(volume) = mount.name {
+                    users.entry(volume).or_default().push(name.clone());
+                }
+            }
+        }
+        Ok(users)
+    }
+
+    /// List networks with the containers attached to them.
+    pub async fn list_networks(&self) -> Result<Vec<NetworkInfo>> {
+        let networks = self.client.list_networks::<String>(None).await?;
+        let members = self.network_members().await?;
+
+        let mut result: Vec<NetworkInfo> = networks
+            .into_iter()
+            .map(|n| network_info(n, &members))
+            .collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(result)
+    }
+
+    pub async fn inspect_network(&self, name: &str) -> Result<NetworkInfo> {
+        let network = self
+            .client
+            .inspect_network(name, None::<InspectNetworkOptions<String>>)
+            .await?;
+        let members = self.network_members().await?;
+        Ok(network_info(network, &members))
+    }
+
+    pub async fn create_network(&self, req: CreateNetworkRequest) -> Result<NetworkInfo> {
+        let ipam = if req.subnet.is_some() || req.gateway.is_some() {
+            Ipam {
+                config: Some(vec![IpamConfig {
+                    subnet: req.subnet,
+                    gateway: req.gateway,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+        } else {
+            Ipam::default()
+        };
+
+        self.client
+            .create_network(CreateNetworkOptions {
+                name: req.name.clone(),
+                driver: req.driver.unwrap_or_else(|| "bridge".to_string()),
+                internal: req.internal,
+                ipam,
+                labels: req.labels,
+                ..Default::default()
+            })
+            .await?;
+
+        self.inspect_network(&req.name).await
+    }
+
+    /// Labels of the named network, or `None` when it doesn't exist.
+    pub async fn network_labels(&self, name: &str) -> Result<Option<HashMap<String, String>>> {
+        match self
+            .client
+            .inspect_network(name, None::<InspectNetworkOptions<String>>)
+            .await
+        {
+            Ok(network) => Ok(Some(network.labels.unwrap_or_default())),
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Create the named bridge network with `labels` unless it already exists.
+    pub async fn ensure_network(&self, name: &str, labels: HashMap<String, String>) -> Result<()> {
+        if self
+            .client
+            .inspect_network(name, None::<InspectNetworkOptions<String>>)
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+        tracing::info!("Creating Docker network '{}'", name);
+        self.client
+            .create_network(CreateNetworkOptions {
+                name: name.to_string(),
+                driver: "bridge".to_string(),
+                labels,
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_network(&self, name: &str) -> Result<()> {
+        self.client.remove_network(name).await?;
+        Ok(())
+    }
+
+    /// Connect a container to `network`, optionally with DNS `aliases`.
+    pub async fn connect_network(
+        &self,
+        network: &str,
+        container: &str,
+        aliases: Vec<String>,
+    ) -> Result<()> {
+        self.client
+            .connect_network(
+                network,
+                ConnectNetworkOptions {
+                    container,
+                    endpoint_config: EndpointSettings {
+                        aliases: (!aliases.is_empty()).then_some(aliases),
+                        ..Default::default()
+                    },
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn disconnect_network(&self, network: &str, container: &str, force: bool) -> Result<()> {
+        self.client
+            .disconnect_network(network, DisconnectNetworkOptions { container, force })
+            .await?;
+        Ok(())
+    }
+
+    /// Map of network name to the names of containers attached to it.
+    async fn network_members(&self) -> Result<HashMap<String, Vec<String>>> {
+        let containers = self
+            .client
+            .list_containers(Some(ListContainersOptions::<String> {
+                all: true,
+                ..Default::default()
+            }))
+            .await?;
+
+        let mut members: HashMap<String, Vec<String>> = HashMap::new();
+        for c in containers {
+            let name = c
+                .names
+                .and_then(|n| n.first().cloned())
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string();
+            let networks = c
+                .network_settings
+                .and_then(|s| s.networks)
+                .unwrap_or_default();
+            for network in networks.into_keys() {
+                members.entry(network).or_default().push(name.clone());
+            }
+        }
+        Ok(members)
+    }
+
+    /// Disk usage of every volume, keyed by name (via `GET /system/df`).
+    pub async fn volume_usage(&self) -> Result<HashMap<String, VolumeUsageData>> {
+        let df = self.client.df().await?;
+        Ok(df
+            .volumes
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|v| v.usage_data.map(|u| (v.name, u)))
+            .collect())
+    }
+
+    /// Tag `source` (name or id) as `target` (`repo:tag`).
+    pub async fn tag_image(&self, source: &str, target: &str) -> Result<()> {
+        let (repo, tag) = target.rsplit_once(':').unwrap_or((target, "latest"));
+        self.client
+            .tag_image(source, Some(TagImageOptions { repo, tag }))
+            .await?;
+        Ok(())
+    }
+
+    /// List images (newest first) with the containers created from them.
+    /// `deployments` is left empty for the caller to fill in.
+    pub async fn list_images(&self) -> Result<Vec<ImageInfo>> {
+        let images = self
+            .client
+            .list_images(Some(ListImagesOptions::<String> {
+                all: false,
+                ..Default::default()
+            }))
+            .await?;
+        let users = self.image_users().await?;
+
+        let mut result: Vec<ImageInfo> = images
+            .into_iter()
+            .map(|i| image_info(i, &users))
+            .collect();
+        result.sort_by_key(|i| std::cmp::Reverse(i.created));
+        Ok(result)
+    }
+
+    pub async fn inspect_image(&self, name: &str) -> Result<ImageInfo> {
+        let image = self.client.inspect_image(name).await?;
+        let id = image.id.unwrap_or_default();
+        let users = self.image_users().await?;
+        let tags = image.repo_tags.unwrap_or_default();
+
+        Ok(ImageInfo {
+            containers: users.get(&id).cloned().unwrap_or_default(),
+            dangling: tags.is_empty() || tags.iter().all(|t| t == "<none>:<none>"),
+            tags,
+            digests: image.repo_digests.unwrap_or_default(),
+            size_bytes: image.size.unwrap_or(0),
+            created: image
+                .created
+                .and_then(|c| chrono::DateTime::parse_from_rfc3339(&c).ok())
+                .map(|c| c.timestamp())
+                .unwrap_or(0),
+            labels: image
+                .config
+                .and_then(|c| c.labels)
+                .unwrap_or_default(),
+            deployments: Vec::new(),
+            id,
+        })
+    }
+
+    /// Pull `reference` (`name[:tag]` or `name@digest`). Returns the pull output.
+    pub async fn pull_image(&self, reference: &str) -> Result<String> {
+        // Only treat a ':' after the last '/' as a tag separator so that
+        // registry ports (`host:5000/app`) are kept intact.
+        let (from_image, tag) = if reference.contains('@') {
+            (reference, "")
+        } else {
+            match reference.rsplit_once(':') {
+                Some((name, tag)) if !tag.contains('/') => (name, tag),
+                _ => (reference, "latest"),
+            }
+        };
+
+        let mut stream = self.client.create_image(
+            Some(CreateImageOptions {
+                from_image,
+                tag,
+                ..Default::default()
+            }),
+            None,
+            None,
+        );
+        let mut log = String::new();
+
+        while let Some(item) = stream.next().await {
+            let info = item?;
+            if let Some(err) = info.error {
+                anyhow::bail!("Pull failed: {}", err);
+            }
+            // Skip per-layer progress ticks; keep one line per status change.
+            if let Some(status) = info.status
+                && info.progress.is_none()
+            {
+                if let Some(id) = info.id {
+                    log.push_str(&id);
+                    log.push_str(": ");
+                }
+                log.push_str(&status);
+                log.push('\n');
+            }
+        }
+
+        Ok(log)
+    }
+
+    /// Remove an image or one of its tags. Without `force`, the engine refuses
+    /// images used by a container; removing one tag of a multi-tag image only
+    /// untags it. Returns the untagged references and deleted layer ids.
+    pub async fn delete_image(&self, name: &str, force: bool) -> Result<Vec<String>> {
+        let items = self
+            .client
+            .remove_image(
+                name,
+                Some(RemoveImageOptions {
+                    force,
+                    ..Default::default()
+                }),
+                None,
+            )
+            .await?;
+
+        Ok(items
+            .into_iter()
+            .filter_map(|i| i.untagged.or(i.deleted))
+            .collect())
+    }
+
+    /// Prune dangling images (or, with `all`, every image without a container).
+    pub async fn prune_images(&self, all: bool) -> Result<PruneReport> {
+        let mut filters = HashMap::new();
+        filters.insert(
+            "dangling".to_string(),
+            vec![if all { "false" } else { "true" }.to_string()],
+        );
+
+        let response = self
+            .client
+            .prune_images(Some(PruneImagesOptions { filters }))
+            .await?;
+
+        Ok(PruneReport {
+            images_deleted: response
+                .images_deleted
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|i| i.deleted.or(i.untagged))
+                .collect(),
+            space_reclaimed: response.space_reclaimed.unwrap_or(0),
+            build_cache: None,
+        })
+    }
+
+    /// Prune dangling build cache through `POST /build/prune`, which the
+    /// Docker API client doesn't wrap. Returns the reclaimed-space summary.
+    pub async fn prune_build_cache(&self) -> Result<String> {
+        let request = hyper::Request::post(self.api_path("/build/prune"))
+            .body(http_body_util::Empty::<Bytes>::new())?;
+        let response = self.send_raw(request).await?;
+        let status = response.status();
+        if status == hyper::StatusCode::NOT_FOUND {
+            anyhow::bail!("The engine does not support pruning the build cache");
+        }
+        if !status.is_success() {
+            anyhow::bail!("Build cache prune failed: {}", error_message(response.into_body()).await?);
+        }
+
+        let bytes = response.into_body().collect().await?.to_bytes();
+        let pruned: serde_json::Value = serde_json::from_slice(&bytes)?;
+        Ok(format!(
+            "Total reclaimed space: {}",
+            human_size(pruned["SpaceReclaimed"].as_i64().unwrap_or(0))
+        ))
+    }
+
+    /// `path` under the API version the client negotiated.
+    fn api_path(&self, path: &str) -> String {
+        let version = self.client.client_version();
+        format!("/v{}.{}{}", version.major_version, version.minor_version, path)
+    }
+
+    /// Send `request` on a fresh engine connection, for endpoints the Docker
+    /// API client doesn't cover or can't stream.
+    async fn send_raw<B>(&self, mut request: hyper::Request<B>) -> Result<hyper::Response<hyper::body::Incoming>>
+    where
+        B: hyper::body::Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        request
+            .headers_mut()
+            .insert(hyper::header::HOST, hyper::header::HeaderValue::from_static("docker"));
+        let io = engine::dial(&self.endpoint, self.docker_host()).await?;
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(io)).await?;
+        tokio::spawn(connection);
+        Ok(sender.send_request(request).await?)
+    }
+
+    /// Map of image id to the names of containers created from it.
+    async fn image_users(&self) -> Result<HashMap<String, Vec<String>>> {
+        let containers = self
+            .client
+            .list_containers(Some(ListContainersOptions::<String> {
+                all: true,
+                ..Default::default()
+            }))
+            .await?;
+
+        let mut users: HashMap<String, Vec<String>> = HashMap::new();
+        for c in containers {
+            let name = c
+                .names
+                .and_then(|n| n.first().cloned())
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string();
+            if let Some(image_id) = c.image_id {
+                users.entry(image_id).or_default().push(name);
+            }
+        }
+        Ok(users)
+    }
+
+    /// Stream container output line by line. Frames are split on newlines per
+    /// stream, holding back partial lines until they are completed.
+    pub fn stream_logs(
+        &self,
+        id: &str,
+        opts: LogStreamOptions,
+    ) -> impl Stream<Item = Result<LogLine>> + Send + 'static {
+        let client = self.client.clone();
+        let id = id.to_string();
+
+        async_stream::try_stream! {
+            let options = LogsOptions::<String> {
+                follow: opts.follow,
+                stdout: opts.stdout,
+                stderr: opts.stderr,
+                since: opts.since.unwrap_or(0),
+                until: opts.until.unwrap_or(0),
+                timestamps: opts.timestamps,
+                tail: opts.tail.map(|t| t.to_string()).unwrap_or_else(|| "all".to_string()),
+            };
+            let mut frames = client.logs(&id, Some(options));
+            let mut partial: HashMap<&'static str, String> = HashMap::new();
+
+            while let Some(frame) = frames.next().await {
+                let (stream, message) = match frame? {
+                    LogOutput::StdOut { message } => ("stdout", message),
+                    LogOutput::StdErr { message } => ("stderr", message),
+                    LogOutput::Console { message } => ("console", message),
+                    LogOutput::StdIn { .. } => continue,
+                };
+
+                let buf = partial.entry(stream).or_default();
+                buf.push_str(&String::from_utf8_lossy(&message));
+                while let Some(pos) = buf.find('\n') {
+                    let raw: String = buf.drain(..=pos).collect();
+                    yield log_line(stream, &raw, opts.timestamps);
+                }
+            }
+
+            for (stream, rest) in partial {
+                if !rest.is_empty() {
+                    yield log_line(stream, &rest, opts.timestamps);
+                }
+            }
+        }
+    }
+
     pub async fn get_container_logs(&self, id: &str, tail: usize) -> Result<String> {
-        use bollard::container::LogOutput;
         use futures_util::TryStreamExt;
 
         let options = LogsOptions::<String> {
@@ -130,6 +946,97 @@ impl DockerService {
         Ok(output)
     }
 
+    /// One stats reading. Waits for the engine's second CPU sample so the
+    /// CPU percentage is meaningful (about a second).
+    pub async fn container_stats(&self, id: &str) -> Result<ContainerStats> {
+        let mut stream = self.client.stats(
+            id,
+            Some(StatsOptions {
+                stream: false,
+                one_shot: false,
+            }),
+        );
+        match stream.next().await {
+            Some(stats) => Ok(container_stats(stats?)),
+            None => anyhow::bail!("No stats returned for {}", id),
+        }
+    }
+
+    /// Live stats, one reading per second, until the container stops.
+    pub fn stream_stats(&self, id: &str) -> impl Stream<Item = Result<ContainerStats>> + Send + use<> {
+        self.client
+            .stats(
+                id,
+                Some(StatsOptions {
+                    stream: true,
+                    one_shot: false,
+                }),
+            )
+            .map(|s| s.map(container_stats).map_err(anyhow::Error::from))
+    }
+
+    /// Start an interactive exec instance with a TTY running `cmd` in `container`.
+    pub async fn exec_interactive(&self, container: &str, cmd: Vec<String>) -> Result<ExecStream> {
+        let exec = self
+            .client
+            .create_exec(
+                container,
+                CreateExecOptions {
+                    attach_stdin: Some(true),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    tty: Some(true),
+                    env: Some(vec!["TERM=xterm-256color".to_string()]),
+                    cmd: Some(cmd),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        match self
+            .client
+            .start_exec(
+                &exec.id,
+                Some(StartExecOptions {
+                    detach: false,
+                    tty: true,
+                    output_capacity: None,
+                }),
+            )
+            .await?
+        {
+            StartExecResults::Attached { output, input } => Ok(ExecStream {
+                id: exec.id,
+                output,
+                input,
+            }),
+            StartExecResults::Detached => anyhow::bail!("Exec instance started detached"),
+        }
+    }
+
+    pub async fn resize_exec(&self, exec_id: &str, cols: u16, rows: u16) -> Result<()> {
+        self.client
+            .resize_exec(
+                exec_id,
+                ResizeExecOptions {
+                    width: cols,
+                    height: rows,
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Exit code of a finished exec instance (`None` while it is still running).
+    pub async fn exec_exit_code(&self, exec_id: &str) -> Result<Option<i64>> {
+        let info = self.client.inspect_exec(exec_id).await?;
+        Ok(if info.running == Some(true) {
+            None
+        } else {
+            info.exit_code
+        })
+    }
+
     pub async fn inspect_container(
         &self,
         id: &str,
@@ -138,3 +1045,309 @@ impl DockerService {
         Ok(info)
     }
 }
+
+fn volume_info(
+    v: Volume,
+    usage: &HashMap<String, VolumeUsageData>,
+    users: &HashMap<String, Vec<String>>,
+) -> VolumeInfo {
+    let size_bytes = usage
+        .get(&v.name)
+        .or(v.usage_data.as_ref())
+        .map(|u| u.size)
+        .filter(|s| *s >= 0);
+
+    VolumeInfo {
+        managed: v.labels.get("dockyy.managed").map(String::as_str) == Some("true"),
+        containers: users.get(&v.name).cloned().unwrap_or_default(),
+        size_bytes,
+        scope: v.scope.map(|s| s.to_string()),
+        created_at: v.created_at,
+        driver: v.driver,
+        mountpoint: v.mountpoint,
+        labels: v.labels,
+        name: v.name,
+    }
+}
+
+fn container_stats(s: Stats) -> ContainerStats {
+    let cpu_delta = s
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(s.precpu_stats.cpu_usage.total_usage);
+    let system_delta = s
+        .cpu_stats
+        .system_cpu_usage
+        .unwrap_or(0)
+        .saturating_sub(s.precpu_stats.system_cpu_usage.unwrap_or(0));
+    let cpus = s.cpu_stats.online_cpus.filter(|n| *n > 0).unwrap_or_else(|| {
+        s.cpu_stats
+            .cpu_usage
+            .percpu_usage
+            .as_ref()
+            .map(|p| p.len() as u64)
+            .unwrap_or(1)
+    });
+    let cpu_pct = if system_delta > 0 {
+        cpu_delta as f64 / system_delta as f64 * cpus as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    // Same as `docker stats`: page cache that can be reclaimed doesn't count.
+    let cache = match &s.memory_stats.stats {
+        Some(MemoryStatsStats::V1(v1)) => v1.total_inactive_file,
+        Some(MemoryStatsStats::V2(v2)) => v2.inactive_file,
+        None => 0,
+    };
+    let mem_bytes = s.memory_stats.usage.unwrap_or(0).saturating_sub(cache);
+
+    let (net_rx_bytes, net_tx_bytes) = s
+        .networks
+        .as_ref()
+        .map(|nets| {
+            nets.values()
+                .fold((0, 0), |(rx, tx), n| (rx + n.rx_bytes, tx + n.tx_bytes))
+        })
+        .unwrap_or((0, 0));
+
+    let (blk_read_bytes, blk_write_bytes) = s
+        .blkio_stats
+        .io_service_bytes_recursive
+        .as_ref()
+        .map(|entries| {
+            entries.iter().fold((0, 0), |(r, w), e| match e.op.to_lowercase().as_str() {
+                "read" => (r + e.value, w),
+                "write" => (r, w + e.value),
+                _ => (r, w),
+            })
+        })
+        .unwrap_or((0, 0));
+
+    ContainerStats {
+        cpu_pct,
+        mem_bytes,
+        mem_limit: s.memory_stats.limit.unwrap_or(0),
+        net_rx_bytes,
+        net_tx_bytes,
+        blk_read_bytes,
+        blk_write_bytes,
+        pids: s.pids_stats.current.unwrap_or(0),
+    }
+}
+
+/// Split the RFC 3339 prefix Docker adds with `timestamps=true` off a raw line.
+fn log_line(stream: &'static str, raw: &str, timestamps: bool) -> LogLine {
+    let raw = raw.trim_end_matches(['\n', '\r']);
+    let (timestamp, line) = match raw.split_once(' ') {
+        Some((ts, rest))
+            if timestamps && chrono::DateTime::parse_from_rfc3339(ts).is_ok() =>
+        {
+            (Some(ts.to_string()), rest)
+        }
+        _ => (None, raw),
+    };
+
+    LogLine {
+        stream,
+        timestamp,
+        line: line.to_string(),
+    }
+}
+
+fn network_info(n: Network, members: &HashMap<String, Vec<String>>) -> NetworkInfo {
+    let name = n.name.unwrap_or_default();
+    let ipam_config = n.ipam.and_then(|i| i.config).unwrap_or_default();
+    let labels = n.labels.unwrap_or_default();
+
+    NetworkInfo {
+        id: n.id.unwrap_or_default(),
+        driver: n.driver,
+        scope: n.scope,
+        internal: n.internal.unwrap_or(false),
+        subnets: ipam_config.iter().filter_map(|c| c.subnet.clone()).collect(),
+        gateways: ipam_config.iter().filter_map(|c| c.gateway.clone()).collect(),
+        created: n.created,
+        managed: labels.get("dockyy.managed").map(String::as_str) == Some("true"),
+        labels,
+        containers: members.get(&name).cloned().unwrap_or_default(),
+        name,
+    }
+}
+
+fn image_info(i: ImageSummary, users: &HashMap<String, Vec<String>>) -> ImageInfo {
+    let tags: Vec<String> = i
+        .repo_tags
+        .into_iter()
+        .filter(|t| t != "<none>:<none>")
+        .collect();
+
+    ImageInfo {
+        containers: users.get(&i.id).cloned().unwrap_or_default(),
+        dangling: tags.is_empty(),
+        tags,
+        digests: i.repo_digests,
+        size_bytes: i.size,
+        created: i.created,
+        labels: i.labels,
+        deployments: Vec::new(),
+        id: i.id,
+    }
+}
+
+/// Why packing the build context failed, unless it only stopped because the
+/// daemon stopped reading it.
+async fn packing_failure(packer: tokio::task::JoinHandle<Result<()>>) -> Option<anyhow::Error> {
+    match packer.await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            let upload_stopped = e
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe);
+            (!upload_stopped).then_some(e)
+        }
+        Err(e) => Some(e.into()),
+    }
+}
+
+/// Append one line of `/build` progress to `log`, failing on a build error.
+fn build_progress(line: &[u8], log: &mut String) -> Result<()> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+    let info: BuildInfo = serde_json::from_slice(line)?;
+    if let Some(line) = info.stream {
+        log.push_str(&line);
+    }
+    if let Some(status) = info.status {
+        log.push_str(&status);
+        log.push('\n');
+    }
+    if let Some(err) = info.error {
+        anyhow::bail!("Docker build failed: {}\n{}", err, log);
+    }
+    Ok(())
+}
+
+/// Chunks of the build context buffered between packing and upload.
+const CONTEXT_CHANNEL_CHUNKS: usize = 16;
+const CONTEXT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Feeds the tar writer's output to the upload in fixed-size chunks.
+struct ChunkWriter {
+    tx: tokio::sync::mpsc::Sender<Bytes>,
+    buf: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(tx: tokio::sync::mpsc::Sender<Bytes>) -> Self {
+        Self {
+            tx,
+            buf: Vec::with_capacity(CONTEXT_CHUNK_BYTES),
+        }
+    }
+}
+
+impl std::io::Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CONTEXT_CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(CONTEXT_CHUNK_BYTES),
+        ));
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Build upload stopped"))
+    }
+}
+
+/// The `message` of an engine error response, or its raw body.
+async fn error_message(body: hyper::body::Incoming) -> Result<String> {
+    let bytes = body.collect().await?.to_bytes();
+    Ok(serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|v| v["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(&bytes).to_string()))
+}
+
+/// `bytes` in decimal units, as the docker CLI prints sizes (`1.5GB`).
+fn human_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    let size = format!("{:.2}", size);
+    format!("{}{}", size.trim_end_matches('0').trim_end_matches('.'), UNITS[unit])
+}
+
+/// Percent-encode a query parameter value.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockDocker;
+    use axum::http::Method;
+
+    #[tokio::test]
+    async fn prunes_build_cache_through_the_api() {
+        let docker = MockDocker::start().await;
+        let service = docker.service().await;
+
+        assert_eq!(service.prune_build_cache().await.unwrap(), "Total reclaimed space: 1.54MB");
+        assert!(docker.find(Method::POST, "/build/prune").is_some());
+    }
+
+    #[tokio::test]
+    async fn removes_the_container_when_a_network_is_missing() {
+        let docker = MockDocker::start().await;
+        let service = docker.service().await;
+
+        let spec = ContainerSpec {
+            name: "dockyy-shop".to_string(),
+            image: "dockyy-shop:latest".to_string(),
+            env: Vec::new(),
+            labels: HashMap::new(),
+            network: None,
+            extra_networks: vec!["missing".to_string()],
+            limits: ResourceLimits::default(),
+            mounts: Vec::new(),
+        };
+        assert!(service.run_container(spec).await.is_err());
+
+        let path = format!("/containers/{}", crate::test_support::MOCK_CONTAINER_ID);
+        let removed = docker.find(Method::DELETE, &path).expect("container removed");
+        assert!(removed.query.contains("force=true"));
+        assert!(docker.find(Method::POST, &format!("{}/start", path)).is_none());
+    }
+
+    #[test]
+    fn sizes_use_decimal_units() {
+        assert_eq!(human_size(0), "0B");
+        assert_eq!(human_size(999), "999B");
+        assert_eq!(human_size(1000), "1kB");
+        assert_eq!(human_size(2_500_000_000), "2.5GB");
+    }
+}
@@ -0,0 +1,426 @@
+//! Container engine endpoint resolution (Docker or Podman, local or remote).
+//!
+//! The endpoint is configured with a `DOCKER_HOST`-style URL:
+//!
+//! - `unix:///path/to/socket` — local socket (Docker or Podman)
+//! - `tcp://host:2376` — remote daemon; TLS client certs are used when
+//!   `DOCKER_TLS_VERIFY=1`, loaded from `DOCKER_CERT_PATH` (`ca.pem`,
+//!   `cert.pem`, `key.pem`)
+//! - `ssh://user@host[:port][/path/to/remote.sock]` — tunnelled over `ssh`
+//!
+//! When `DOCKER_HOST` is unset the Docker socket is preferred, falling back to
+//! the well-known Podman socket locations.
+
+use anyhow::{Context, Result};
+use bollard::system::Version;
+use bollard::{Docker, API_DEFAULT_VERSION};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::process::{Child, Command};
+use tokio_rustls::rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+const CONNECT_TIMEOUT_SECS: u64 = 120;
+/// 100ms polls for the SSH tunnel's forwarded socket before giving up.
+const TUNNEL_WAIT_ATTEMPTS: u32 = 50;
+/// Compose implementations in order of preference.
+const COMPOSE_BINS: [&str; 4] = [
+    "/usr/bin/podman-compose",
+    "/usr/local/bin/podman-compose",
+    "/usr/bin/docker-compose",
+    "/usr/local/bin/docker-compose",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DockerEndpoint {
+    Unix(String),
+    Tcp {
+        addr: String,
+        /// Directory holding `ca.pem`, `cert.pem` and `key.pem` when TLS is on.
+        tls_cert_path: Option<PathBuf>,
+    },
+    Ssh {
+        /// `user@host` (or just `host`) passed to `ssh`.
+        destination: String,
+        port: Option<u16>,
+        remote_socket: String,
+    },
+}
+
+impl DockerEndpoint {
+    /// Resolve the endpoint from `DOCKER_HOST`, `DOCKER_TLS_VERIFY` and
+    /// `DOCKER_CERT_PATH`, auto-detecting a local socket when unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("DOCKER_HOST") {
+            Ok(url) if !url.trim().is_empty() => Self::parse(url.trim()),
+            _ => Ok(Self::Unix(detect_local_socket())),
+        }
+    }
+
+    pub fn parse(url: &str) -> Result<Self> {
+        if let Some(path) = url.strip_prefix("unix://") {
+            return Ok(Self::Unix(path.to_string()));
+        }
+
+        if let Some(addr) = url.strip_prefix("tcp://") {
+            let tls = std::env::var("DOCKER_TLS_VERIFY")
+                .map(|v| !v.is_empty() && v != "0")
+                .unwrap_or(false);
+            let tls_cert_path = tls.then(|| {
+                std::env::var("DOCKER_CERT_PATH")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|_| {
+                        let home = std::env::var("HOME").unwrap_or_else(|_| ".".into());
+                        PathBuf::from(home).join(".docker")
+                    })
+            });
+            return Ok(Self::Tcp {
+                addr: addr.to_string(),
+                tls_cert_path,
+            });
+        }
+
+        if let Some(rest) = url.strip_prefix("ssh://") {
+            let (authority, path) = match rest.find('/') {
+                Some(i) => (&rest[..i], &rest[i..]),
+                None => (rest, ""),
+            };
+            // Only split a port off the host part, never off `user@`.
+            let (destination, port) = match authority.rsplit_once(':') {
+                Some((dest, port)) if !port.contains('@') => (
+                    dest.to_string(),
+                    Some(port.parse().with_context(|| format!("Invalid SSH port in {}", url))?),
+                ),
+                _ => (authority.to_string(), None),
+            };
+            if destination.is_empty() {
+                anyhow::bail!("Missing host in {}", url);
+            }
+            let remote_socket = if path.is_empty() || path == "/" {
+                DEFAULT_DOCKER_SOCKET.to_string()
+            } else {
+                path.to_string()
+            };
+            return Ok(Self::Ssh {
+                destination,
+                port,
+                remote_socket,
+            });
+        }
+
+        anyhow::bail!(
+            "Unsupported DOCKER_HOST '{}' (expected unix://, tcp:// or ssh://)",
+            url
+        )
+    }
+
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, Self::Unix(_))
+    }
+
+    /// Path of the engine socket *on the host running the containers*, which is
+    /// what sidecars such as Traefik need to bind-mount.
+    pub fn host_socket_path(&self) -> &str {
+        match self {
+            Self::Unix(path) => path,
+            Self::Ssh { remote_socket, .. } => remote_socket,
+            Self::Tcp { .. } => DEFAULT_DOCKER_SOCKET,
+        }
+    }
+
+    /// Human-readable form for logs and the capabilities endpoint.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Unix(path) => format!("unix://{}", path),
+            Self::Tcp { addr, tls_cert_path } => format!(
+                "tcp://{}{}",
+                addr,
+                if tls_cert_path.is_some() { " (tls)" } else { "" }
+            ),
+            Self::Ssh {
+                destination, port, ..
+            } => match port {
+                Some(p) => format!("ssh://{}:{}", destination, p),
+                None => format!("ssh://{}", destination),
+            },
+        }
+    }
+}
+
+/// Prefer the Docker socket, then rootful and rootless Podman sockets.
+fn detect_local_socket() -> String {
+    let mut candidates = vec![DEFAULT_DOCKER_SOCKET.to_string()];
+    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+        candidates.push(format!("{}/podman/podman.sock", runtime_dir));
+    }
+    candidates.push("/run/podman/podman.sock".to_string());
+
+    candidates
+        .iter()
+        .find(|p| std::path::Path::new(p).exists())
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_DOCKER_SOCKET.to_string())
+}
+
+/// A live connection to the engine. For SSH endpoints this owns the `ssh`
+/// process forwarding a local socket to the remote one.
+pub struct EngineConnection {
+    pub client: Docker,
+    /// `DOCKER_HOST` value that child processes (compose) should use.
+    pub docker_host: String,
+    _tunnel: Option<Child>,
+}
+
+pub async fn connect(endpoint: &DockerEndpoint, data_dir: &str) -> Result<EngineConnection> {
+    match endpoint {
+        DockerEndpoint::Unix(path) => Ok(EngineConnection {
+            client: Docker::connect_with_unix(path, CONNECT_TIMEOUT_SECS, API_DEFAULT_VERSION)?,
+            docker_host: format!("unix://{}", path),
+            _tunnel: None,
+        }),
+        DockerEndpoint::Tcp {
+            addr,
+            tls_cert_path: Some(certs),
+        } => Ok(EngineConnection {
+            client: Docker::connect_with_ssl(
+                addr,
+                &certs.join("key.pem"),
+                &certs.join("cert.pem"),
+                &certs.join("ca.pem"),
+                CONNECT_TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            )?,
+            docker_host: format!("tcp://{}", addr),
+            _tunnel: None,
+        }),
+        DockerEndpoint::Tcp {
+            addr,
+            tls_cert_path: None,
+        } => Ok(EngineConnection {
+            client: Docker::connect_with_http(addr, CONNECT_TIMEOUT_SECS, API_DEFAULT_VERSION)?,
+            docker_host: format!("tcp://{}", addr),
+            _tunnel: None,
+        }),
+        DockerEndpoint::Ssh {
+            destination,
+            port,
+            remote_socket,
+        } => {
+            let local_socket = format!("{}/docker-ssh.sock", data_dir);
+            let _ = std::fs::remove_file(&local_socket);
+
+            let mut cmd = Command::new("ssh");
+            cmd.arg("-nNT")
+                .arg("-o")
+                .arg("ExitOnForwardFailure=yes")
+                .arg("-o")
+                .arg("ServerAliveInterval=30")
+                .arg("-o")
+                .arg("StreamLocalBindUnlink=yes")
+                .arg("-L")
+                .arg(format!("{}:{}", local_socket, remote_socket));
+            if let Some(port) = port {
+                cmd.arg("-p").arg(port.to_string());
+            }
+            cmd.arg(destination)
+                .stdin(std::process::Stdio::null())
+                .kill_on_drop(true);
+
+            let mut tunnel = cmd.spawn().context("Failed to spawn ssh for DOCKER_HOST tunnel")?;
+
+            // Wait for the forwarded socket to appear (or ssh to die).
+            let mut attempts = 0;
+            while !std::path::Path::new(&local_socket).exists() {
+                if let Some(status) = tunnel.try_wait()? {
+                    anyhow::bail!("ssh tunnel to {} exited with {}", destination, status);
+                }
+                attempts += 1;
+                if attempts == TUNNEL_WAIT_ATTEMPTS {
+                    anyhow::bail!(
+                        "ssh tunnel to {} did not come up within {}s",
+                        destination,
+                        TUNNEL_WAIT_ATTEMPTS / 10
+                    );
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+
+            Ok(EngineConnection {
+                client: Docker::connect_with_unix(
+                    &local_socket,
+                    CONNECT_TIMEOUT_SECS,
+                    API_DEFAULT_VERSION,
+                )?,
+                docker_host: format!("unix://{}", local_socket),
+                _tunnel: Some(tunnel),
+            })
+        }
+    }
+}
+
+pub trait EngineIo: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> EngineIo for T {}
+
+/// Open a raw connection to the engine at `docker_host` (as returned by
+/// [`EngineConnection::docker_host`]), for requests bollard can only send
+/// buffered, such as build contexts. TCP endpoints with TLS present the same
+/// client certificates bollard uses.
+pub async fn dial(endpoint: &DockerEndpoint, docker_host: &str) -> Result<Box<dyn EngineIo>> {
+    if let Some(path) = docker_host.strip_prefix("unix://") {
+        let stream = tokio::net::UnixStream::connect(path)
+            .await
+            .with_context(|| format!("Failed to connect to {}", docker_host))?;
+        return Ok(Box::new(stream));
+    }
+    let Some(addr) = docker_host.strip_prefix("tcp://") else {
+        anyhow::bail!("Unsupported engine address '{}'", docker_host);
+    };
+    let stream = tokio::net::TcpStream::connect(addr)
+        .await
+        .with_context(|| format!("Failed to connect to {}", docker_host))?;
+    match endpoint {
+        DockerEndpoint::Tcp {
+            tls_cert_path: Some(certs),
+            ..
+        } => {
+            let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+            let name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())
+                .context("Invalid engine host name")?;
+            Ok(Box::new(tls_connector(certs)?.connect(name, stream).await?))
+        }
+        _ => Ok(Box::new(stream)),
+    }
+}
+
+/// TLS client for `DOCKER_CERT_PATH` (`ca.pem`, `cert.pem`, `key.pem`).
+fn tls_connector(certs: &Path) -> Result<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(certs.join("ca.pem"))? {
+        roots.add(cert?)?;
+    }
+    let chain = CertificateDer::pem_file_iter(certs.join("cert.pem"))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(certs.join("key.pem"))?;
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(chain, key)?;
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// What the connected engine supports, as reported by the capability probe.
+#[derive(Debug, Serialize, Clone)]
+pub struct EngineCapabilities {
+    /// `docker` or `podman`
+    pub engine: String,
+    pub version: Option<String>,
+    pub api_version: Option<String>,
+    pub endpoint: String,
+    pub remote: bool,
+    /// User-defined networks can be listed/created (needed for Traefik routing).
+    pub networks: bool,
+    /// Compose binary dockyy would use, when one is installed and compatible.
+    pub compose: Option<String>,
+}
+
+pub async fn probe(client: &Docker, endpoint: &DockerEndpoint) -> Result<EngineCapabilities> {
+    let version = client.version().await?;
+    let is_podman = is_podman(&version);
+
+    let networks = client.list_networks::<String>(None).await.is_ok();
+
+    let compose_bin = find_compose_bin(is_podman, endpoint.is_remote());
+    let compose_ok = compose_bin.starts_with('/');
+
+    Ok(EngineCapabilities {
+        engine: if is_podman { "podman" } else { "docker" }.to_string(),
+        version: version.version,
+        api_version: version.api_version,
+        endpoint: endpoint.describe(),
+        remote: endpoint.is_remote(),
+        networks,
+        compose: compose_ok.then(|| compose_bin.to_string()),
+    })
+}
+
+/// Whether the engine reporting `version` is Podman.
+pub fn is_podman(version: &Version) -> bool {
+    version
+        .components
+        .as_ref()
+        .map(|cs| cs.iter().any(|c| c.name.to_lowercase().contains("podman")))
+        .unwrap_or(false)
+        || version
+            .platform
+            .as_ref()
+            .map(|p| p.name.to_lowercase().contains("podman"))
+            .unwrap_or(false)
+}
+
+/// Locate a compose implementation for the engine. `podman-compose` only talks
+/// to a local Podman, so it's preferred there and skipped otherwise, since
+/// docker-compose honours `DOCKER_HOST`. Falls back to the bare name
+/// `docker-compose` (resolved via `PATH`) when none is found.
+pub fn find_compose_bin(is_podman: bool, remote: bool) -> &'static str {
+    COMPOSE_BINS
+        .into_iter()
+        .filter(|bin| (is_podman && !remote) || !bin.contains("podman-compose"))
+        .find(|bin| Path::new(bin).exists())
+        .unwrap_or("docker-compose")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_unix_and_tcp_endpoints() {
+        assert_eq!(
+            DockerEndpoint::parse("unix:///run/podman/podman.sock").unwrap(),
+            DockerEndpoint::Unix("/run/podman/podman.sock".to_string())
+        );
+        let tcp = DockerEndpoint::parse("tcp://10.0.0.5:2375").unwrap();
+        assert!(matches!(tcp, DockerEndpoint::Tcp { ref addr, .. } if addr == "10.0.0.5:2375"));
+        assert!(tcp.is_remote());
+        assert_eq!(tcp.host_socket_path(), DEFAULT_DOCKER_SOCKET);
+    }
+
+    #[test]
+    fn parses_ssh_endpoints() {
+        assert_eq!(
+            DockerEndpoint::parse("ssh://deploy@build.example.com:2222/run/user/1000/docker.sock").unwrap(),
+            DockerEndpoint::Ssh {
+                destination: "deploy@build.example.com".to_string(),
+                port: Some(2222),
+                remote_socket: "/run/user/1000/docker.sock".to_string(),
+            }
+        );
+        let plain = DockerEndpoint::parse("ssh://build.example.com").unwrap();
+        assert_eq!(
+            plain,
+            DockerEndpoint::Ssh {
+                destination: "build.example.com".to_string(),
+                port: None,
+                remote_socket: DEFAULT_DOCKER_SOCKET.to_string(),
+            }
+        );
+        assert_eq!(plain.describe(), "ssh://build.example.com");
+    }
+
+    #[test]
+    fn only_uses_podman_compose_for_a_local_podman() {
+        assert!(!find_compose_bin(false, false).contains("podman-compose"));
+        assert!(!find_compose_bin(true, true).contains("podman-compose"));
+        assert!(!find_compose_bin(false, true).contains("podman-compose"));
+    }
+
+    #[test]
+    fn rejects_bad_endpoints() {
+        assert!(DockerEndpoint::parse("http://localhost:2375").is_err());
+        assert!(DockerEndpoint::parse("ssh://host:port").is_err());
+        assert!(DockerEndpoint::parse("ssh://:22").is_err());
+    }
+}
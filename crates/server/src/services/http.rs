@@ -0,0 +1,102 @@
+//! Outbound HTTP(S) client shared by notifications and single sign-on.
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+
+type HttpsClient = Client<hyper_rustls::HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+fn http_client() -> Result<&'static HttpsClient> {
+    static CLIENT: OnceLock<HttpsClient> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .context("Failed to load system root certificates")?
+        .https_or_http()
+        .enable_http1()
+        .build();
+    Ok(CLIENT.get_or_init(|| Client::builder(TokioExecutor::new()).build(connector)))
+}
+
+/// Send `req` and return the response body, failing on non-2xx responses.
+async fn send(req: hyper::http::request::Builder, body: Vec<u8>) -> Result<Bytes> {
+    let req = req
+        .header("User-Agent", concat!("dockyy/", env!("CARGO_PKG_VERSION")))
+        .body(Full::new(Bytes::from(body)))?;
+
+    let resp = tokio::time::timeout(
+        Duration::from_secs(REQUEST_TIMEOUT_SECS),
+        http_client()?.request(req),
+    )
+    .await
+    .context("Request timed out")??;
+
+    let status = resp.status();
+    let body = resp.into_body().collect().await.map(|b| b.to_bytes()).unwrap_or_default();
+    if !status.is_success() {
+        let body = String::from_utf8_lossy(&body);
+        bail!("HTTP {}: {}", status, body.chars().take(200).collect::<String>());
+    }
+    Ok(body)
+}
+
+/// POST `body` to `url`, failing on non-2xx responses.
+pub async fn post(
+    url: &str,
+    content_type: &str,
+    body: Vec<u8>,
+    headers: &HashMap<String, String>,
+) -> Result<()> {
+    let mut req = hyper::Request::post(url).header("Content-Type", content_type);
+    for (name, value) in headers {
+        req = req.header(name.as_str(), value.as_str());
+    }
+    send(req, body).await?;
+    Ok(())
+}
+
+/// GET `url` and decode the JSON response, optionally with a bearer token.
+pub async fn get_json<T: DeserializeOwned>(url: &str, bearer: Option<&str>) -> Result<T> {
+    let mut req = hyper::Request::get(url).header("Accept", "application/json");
+    if let Some(token) = bearer {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    }
+    let body = send(req, Vec::new()).await?;
+    serde_json::from_slice(&body).with_context(|| format!("Invalid JSON from {}", url))
+}
+
+/// POST a form to `url` and decode the JSON response.
+pub async fn post_form<T: DeserializeOwned>(url: &str, form: &[(&str, &str)]) -> Result<T> {
+    let body = form
+        .iter()
+        .map(|(k, v)| format!("{}={}", form_encode(k), form_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let req = hyper::Request::post(url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json");
+    let body = send(req, body.into_bytes()).await?;
+    serde_json::from_slice(&body).with_context(|| format!("Invalid JSON from {}", url))
+}
+
+/// Percent-encode for `application/x-www-form-urlencoded` and query strings.
+pub fn form_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
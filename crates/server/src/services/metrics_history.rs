@@ -0,0 +1,234 @@
+//! Host metrics history.
+//!
+//! Every monitor sample is stored at 30s resolution. Rollups average raw rows
+//! into 5-minute buckets and 5-minute buckets into hourly ones; each level is
+//! trimmed to its retention window, so the table stays a fixed-size ring.
+
+use anyhow::{bail, Result};
+
+use crate::db::models::{HostMetricsHistory, HostMetricsSample};
+use crate::db::Database;
+use crate::services::monitor::SystemMetrics;
+
+pub const RAW_RESOLUTION: i64 = 30;
+pub const FIVE_MINUTE_RESOLUTION: i64 = 300;
+pub const HOURLY_RESOLUTION: i64 = 3600;
+
+/// `(resolution, retention in seconds)`, finest first.
+const LEVELS: [(i64, i64); 3] = [
+    (RAW_RESOLUTION, 24 * 3600),
+    (FIVE_MINUTE_RESOLUTION, 7 * 24 * 3600),
+    (HOURLY_RESOLUTION, 90 * 24 * 3600),
+];
+
+const COLUMNS: &str = "ts, cpu_pct, mem_used_bytes, mem_total_bytes, swap_used_bytes,
+    swap_total_bytes, disk_used_bytes, disk_total_bytes, load_avg_1, load_avg_5,
+    load_avg_15, net_rx_bps, net_tx_bps";
+
+/// Store one raw sample.
+pub fn record(db: &Database, m: &SystemMetrics) -> Result<()> {
+    let ts = chrono::Utc::now().timestamp();
+
+    db.with_conn(|conn| {
+        conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO host_metrics (resolution, {})
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                COLUMNS
+            ),
+            rusqlite::params![
+                RAW_RESOLUTION,
+                ts,
+                m.cpu_usage_pct as f64,
+                m.mem_used_bytes as i64,
+                m.mem_total_bytes as i64,
+                m.swap_used_bytes as i64,
+                m.swap_total_bytes as i64,
+                m.disk_used_bytes as i64,
+                m.disk_total_bytes as i64,
+                m.load_avg_1,
+                m.load_avg_5,
+                m.load_avg_15,
+                m.net_rx_bps,
+                m.net_tx_bps,
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+/// Build rollups from the level below and expire rows past their retention.
+/// Recent complete buckets are recomputed each time, so running this more
+/// often than the bucket size is harmless.
+pub fn rollup(db: &Database) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+
+    db.with_conn(|conn| {
+        for pair in LEVELS.windows(2) {
+            let (source, _) = pair[0];
+            let (target, _) = pair[1];
+            let current_bucket = now - now % target;
+
+            conn.execute(
+                "INSERT OR REPLACE INTO host_metrics (ts, resolution, cpu_pct, mem_used_bytes,
+                    mem_total_bytes, swap_used_bytes, swap_total_bytes, disk_used_bytes,
+                    disk_total_bytes, load_avg_1, load_avg_5, load_avg_15, net_rx_bps, net_tx_bps)
+                 SELECT (ts / ?3) * ?3, ?3, AVG(cpu_pct),
+                        CAST(AVG(mem_used_bytes) AS INTEGER), MAX(mem_total_bytes),
+                        CAST(AVG(swap_used_bytes) AS INTEGER), MAX(swap_total_bytes),
+                        CAST(AVG(disk_used_bytes) AS INTEGER), MAX(disk_total_bytes),
+                        AVG(load_avg_1), AVG(load_avg_5), AVG(load_avg_15),
+                        AVG(net_rx_bps), AVG(net_tx_bps)
+                 FROM host_metrics
+                 WHERE resolution = ?4 AND ts >= ?1 AND ts < ?2
+                 GROUP BY (ts / ?3) * ?3",
+                rusqlite::params![current_bucket - 2 * target, current_bucket, target, source],
+            )?;
+        }
+
+        for (resolution, retention) in LEVELS {
+            conn.execute(
+                "DELETE FROM host_metrics WHERE resolution = ?1 AND ts < ?2",
+                rusqlite::params![resolution, now - retention],
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/// Parse a requested resolution: seconds, or `raw`, `5m`, `1h`.
+pub fn parse_resolution(value: &str) -> Result<i64> {
+    let resolution = match value {
+        "raw" | "30" | "30s" => RAW_RESOLUTION,
+        "5m" | "300" => FIVE_MINUTE_RESOLUTION,
+        "1h" | "3600" => HOURLY_RESOLUTION,
+        _ => bail!("Invalid resolution '{}' (expected raw, 5m or 1h)", value),
+    };
+    Ok(resolution)
+}
+
+/// The finest resolution whose retention still covers `from`.
+fn resolution_for(from: i64) -> i64 {
+    let now = chrono::Utc::now().timestamp();
+    LEVELS
+        .iter()
+        .find(|(_, retention)| from >= now - retention)
+        .map(|(resolution, _)| *resolution)
+        .unwrap_or(HOURLY_RESOLUTION)
+}
+
+/// Samples between `from` and `to`, at `resolution` or chosen from the range.
+pub fn history(
+    db: &Database,
+    from: i64,
+    to: i64,
+    resolution: Option<i64>,
+) -> Result<HostMetricsHistory> {
+    let resolution = resolution.unwrap_or_else(|| resolution_for(from));
+
+    db.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM host_metrics
+             WHERE resolution = ?1 AND ts >= ?2 AND ts <= ?3
+             ORDER BY ts",
+            COLUMNS
+        ))?;
+
+        let samples = stmt
+            .query_map(rusqlite::params![resolution, from, to], |row| {
+                Ok(HostMetricsSample {
+                    ts: row.get(0)?,
+                    cpu_pct: row.get(1)?,
+                    mem_used_bytes: row.get(2)?,
+                    mem_total_bytes: row.get(3)?,
+                    swap_used_bytes: row.get(4)?,
+                    swap_total_bytes: row.get(5)?,
+                    disk_used_bytes: row.get(6)?,
+                    disk_total_bytes: row.get(7)?,
+                    load_avg_1: row.get(8)?,
+                    load_avg_5: row.get(9)?,
+                    load_avg_15: row.get(10)?,
+                    net_rx_bps: row.get(11)?,
+                    net_tx_bps: row.get(12)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(HostMetricsHistory {
+            from,
+            to,
+            resolution,
+            samples,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Database {
+        let db = Database::new(":memory:").unwrap();
+        db.run_migrations().unwrap();
+        db
+    }
+
+    fn insert(db: &Database, resolution: i64, ts: i64, cpu_pct: f64, mem_used_bytes: i64) {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO host_metrics (resolution, ts, cpu_pct, mem_used_bytes, mem_total_bytes,
+                    swap_used_bytes, swap_total_bytes, disk_used_bytes, disk_total_bytes,
+                    load_avg_1, load_avg_5, load_avg_15, net_rx_bps, net_tx_bps)
+                 VALUES (?1, ?2, ?3, ?4, 1000, 0, 0, 0, 0, 0, 0, 0, 0, 0)",
+                rusqlite::params![resolution, ts, cpu_pct, mem_used_bytes],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn rolls_up_complete_buckets_and_expires_old_rows() {
+        let db = database();
+        let now = chrono::Utc::now().timestamp();
+        let bucket = now - now % FIVE_MINUTE_RESOLUTION - FIVE_MINUTE_RESOLUTION;
+        insert(&db, RAW_RESOLUTION, bucket, 10.0, 100);
+        insert(&db, RAW_RESOLUTION, bucket + 30, 30.0, 300);
+        // The current bucket is still filling, so it isn't rolled up yet.
+        insert(&db, RAW_RESOLUTION, bucket + FIVE_MINUTE_RESOLUTION, 90.0, 900);
+        insert(&db, RAW_RESOLUTION, now - 25 * 3600, 50.0, 500);
+
+        rollup(&db).unwrap();
+
+        let rolled = history(&db, 0, now, Some(FIVE_MINUTE_RESOLUTION)).unwrap();
+        assert_eq!(rolled.samples.len(), 1);
+        assert_eq!(rolled.samples[0].ts, bucket);
+        assert_eq!(rolled.samples[0].cpu_pct, 20.0);
+        assert_eq!(rolled.samples[0].mem_used_bytes, 200);
+        assert_eq!(rolled.samples[0].mem_total_bytes, 1000);
+
+        // Raw rows past their 24h retention are gone.
+        let raw = history(&db, 0, now, Some(RAW_RESOLUTION)).unwrap();
+        assert_eq!(raw.samples.iter().map(|s| s.ts).collect::<Vec<_>>(), [bucket, bucket + 30, bucket + 300]);
+
+        // Running it again recomputes the same buckets instead of duplicating them.
+        rollup(&db).unwrap();
+        assert_eq!(history(&db, 0, now, Some(FIVE_MINUTE_RESOLUTION)).unwrap().samples.len(), 1);
+    }
+
+    #[test]
+    fn picks_the_finest_resolution_covering_the_range() {
+        let db = database();
+        let now = chrono::Utc::now().timestamp();
+        assert_eq!(history(&db, now - 3600, now, None).unwrap().resolution, RAW_RESOLUTION);
+        assert_eq!(history(&db, now - 3 * 24 * 3600, now, None).unwrap().resolution, FIVE_MINUTE_RESOLUTION);
+        assert_eq!(history(&db, now - 30 * 24 * 3600, now, None).unwrap().resolution, HOURLY_RESOLUTION);
+        assert_eq!(history(&db, 0, now, None).unwrap().resolution, HOURLY_RESOLUTION);
+        assert_eq!(history(&db, 0, now, Some(RAW_RESOLUTION)).unwrap().resolution, RAW_RESOLUTION);
+
+        assert_eq!(parse_resolution("raw").unwrap(), RAW_RESOLUTION);
+        assert_eq!(parse_resolution("5m").unwrap(), FIVE_MINUTE_RESOLUTION);
+        assert_eq!(parse_resolution("3600").unwrap(), HOURLY_RESOLUTION);
+        assert!(parse_resolution("1d").is_err());
+    }
+}
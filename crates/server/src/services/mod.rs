@@ -1,4 +1,19 @@
+pub mod alerts;
+pub mod audit;
+pub mod build_context;
+pub mod cleanup;
+pub mod container_stats;
+pub mod container_watch;
+pub mod crypto;
 pub mod docker;
+pub mod engine;
+pub mod http;
+pub mod metrics_history;
 pub mod monitor;
+pub mod notifications;
+pub mod oidc;
+pub mod secrets;
+pub mod smtp;
+pub mod telemetry;
 pub mod traefik;
 pub mod worker;
@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use sysinfo::{Disks, System};
+use sysinfo::{Disks, Networks, System};
 use tokio::sync::RwLock;
 use tokio::time::{sleep, Duration};
 
+use crate::services::alerts::AlertEvaluator;
+use crate::services::container_stats::{self, StatsCollector};
+use crate::services::container_watch::ContainerWatcher;
+use crate::services::metrics_history;
+
 /// Snapshot of host system health metrics.
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct SystemMetrics {
@@ -16,6 +21,15 @@ pub struct SystemMetrics {
     /// Root filesystem (or largest disk) used bytes
     pub disk_used_bytes: u64,
     pub disk_total_bytes: u64,
+    pub load_avg_1: f64,
+    pub load_avg_5: f64,
+    pub load_avg_15: f64,
+    /// Bytes received/sent on all non-loopback interfaces since boot
+    pub net_rx_bytes: u64,
+    pub net_tx_bytes: u64,
+    /// Throughput since the previous collection, in bytes per second
+    pub net_rx_bps: f64,
+    pub net_tx_bps: f64,
     pub docker_ok: bool,
     /// RFC-3339 timestamp of last successful collection
     pub checked_at: String,
@@ -30,6 +44,11 @@ pub fn new_metrics_state() -> MetricsState {
 pub async fn run_monitor(state: Arc<crate::AppState>) {
     tracing::info!("Starting health monitor (interval: 30s)");
 
+    let mut stats = StatsCollector::default();
+    let mut alerts = AlertEvaluator::default();
+    let mut watcher = ContainerWatcher::default();
+    let mut iteration: u64 = 0;
+
     loop {
         // Collect CPU/RAM/disk on a blocking thread so we don't stall the async runtime.
         let sys_metrics = tokio::task::spawn_blocking(collect_system_metrics)
@@ -39,9 +58,31 @@ pub async fn run_monitor(state: Arc<crate::AppState>) {
         // Check Docker connectivity in async context.
         let docker_ok = state.docker.list_containers(false).await.is_ok();
 
+        let now = chrono::Utc::now();
+        let (net_rx_bps, net_tx_bps) = {
+            let previous = state.metrics.read().await;
+            let elapsed = chrono::DateTime::parse_from_rfc3339(&previous.checked_at)
+                .map(|t| (now - t.with_timezone(&chrono::Utc)).num_milliseconds() as f64 / 1000.0)
+                .unwrap_or(0.0);
+            let rate = |now: u64, before: u64| {
+                // Counters reset when an interface goes away.
+                if elapsed > 0.0 && now >= before {
+                    (now - before) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            };
+            (
+                rate(sys_metrics.net_rx_bytes, previous.net_rx_bytes),
+                rate(sys_metrics.net_tx_bytes, previous.net_tx_bytes),
+            )
+        };
+
         let updated = SystemMetrics {
             docker_ok,
-            checked_at: chrono::Utc::now().to_rfc3339(),
+            net_rx_bps,
+            net_tx_bps,
+            checked_at: now.to_rfc3339(),
             ..sys_metrics
         };
 
@@ -57,7 +98,34 @@ pub async fn run_monitor(state: Arc<crate::AppState>) {
             "Health metrics collected"
         );
 
-        *state.metrics.write().await = updated;
+        if let Err(e) = metrics_history::record(&state.db, &updated) {
+            tracing::warn!("Failed to store host metrics: {}", e);
+        }
+        *state.metrics.write().await = updated.clone();
+
+        if docker_ok {
+            if let Err(e) = stats.sample(&state).await {
+                tracing::warn!("Container stats sampling failed: {}", e);
+            }
+            if let Err(e) = watcher.check(&state).await {
+                tracing::warn!("Container state check failed: {}", e);
+            }
+        }
+
+        if let Err(e) = alerts.evaluate(&state, &updated, &stats).await {
+            tracing::warn!("Alert evaluation failed: {}", e);
+        }
+
+        // Roll raw samples up and expire old rows every 10 iterations (~5 min).
+        iteration += 1;
+        if iteration.is_multiple_of(10) {
+            if let Err(e) = container_stats::rollup(&state.db) {
+                tracing::warn!("Container stats rollup failed: {}", e);
+            }
+            if let Err(e) = metrics_history::rollup(&state.db) {
+                tracing::warn!("Host metrics rollup failed: {}", e);
+            }
+        }
 
         sleep(Duration::from_secs(30)).await;
     }
@@ -99,6 +167,16 @@ fn collect_system_metrics() -> SystemMetrics {
             })
         });
 
+    let load = System::load_average();
+
+    let networks = Networks::new_with_refreshed_list();
+    let (net_rx_bytes, net_tx_bytes) = networks
+        .iter()
+        .filter(|(name, _)| name.as_str() != "lo")
+        .fold((0u64, 0u64), |(rx, tx), (_, data)| {
+            (rx + data.total_received(), tx + data.total_transmitted())
+        });
+
     SystemMetrics {
         cpu_usage_pct,
         mem_used_bytes,
@@ -107,7 +185,14 @@ fn collect_system_metrics() -> SystemMetrics {
         swap_total_bytes,
         disk_used_bytes,
         disk_total_bytes,
+        load_avg_1: load.one,
+        load_avg_5: load.five,
+        load_avg_15: load.fifteen,
+        net_rx_bytes,
+        net_tx_bytes,
         // filled in by the async caller
+        net_rx_bps: 0.0,
+        net_tx_bps: 0.0,
         docker_ok: false,
         checked_at: String::new(),
     }
@@ -0,0 +1,639 @@
+//! Outbound notifications.
+//!
+//! A [`Notification`] is fanned out to every enabled channel subscribed to its
+//! event. Each delivery is recorded in `notification_deliveries` and failed
+//! ones are retried with exponential backoff by [`run_retries`]. Each
+//! [`ChannelConfig`] variant knows how to render and deliver a notification;
+//! adding a channel type means adding a variant and its `send` arm.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::models::{ChannelConfig, NotificationChannel};
+use crate::services::http::post;
+use crate::services::smtp;
+use crate::AppState;
+
+/// Attempts per delivery before it's marked failed.
+const MAX_ATTEMPTS: i64 = 5;
+/// Delay before the first retry; doubled for each further attempt.
+const RETRY_BASE_SECS: i64 = 30;
+const RETRY_INTERVAL_SECS: u64 = 30;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    /// Machine-readable event name, e.g. `deploy.failed`.
+    pub event: String,
+    pub title: String,
+    pub message: String,
+    /// `info`, `success`, `warning`, `critical` or `resolved`.
+    pub severity: String,
+    /// Event-specific details.
+    pub data: Value,
+    pub timestamp: String,
+}
+
+impl Notification {
+    pub fn new(event: &str, severity: &str, title: String, message: String, data: Value) -> Self {
+        Self {
+            event: event.to_string(),
+            title,
+            message,
+            severity: severity.to_string(),
+            data,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    fn text(&self) -> String {
+        format!("{}\n{}", self.title, self.message)
+    }
+}
+
+async fn post_json(url: &str, body: &Value) -> Result<()> {
+    post(url, "application/json", serde_json::to_vec(body)?, &HashMap::new()).await
+}
+
+/// Hex HMAC-SHA256 of `body`, sent as `X-Dockyy-Signature: sha256=<hex>`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    ring::hmac::sign(&key, body)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn discord_color(severity: &str) -> u32 {
+    match severity {
+        "critical" => 0xE74C3C,
+        "warning" => 0xF39C12,
+        "success" | "resolved" => 0x2ECC71,
+        _ => 0x3498DB,
+    }
+}
+
+impl ChannelConfig {
+    pub async fn send(&self, n: &Notification) -> Result<()> {
+        match self {
+            ChannelConfig::Webhook { url, headers, secret } => {
+                let body = serde_json::to_vec(n)?;
+                let mut headers = headers.clone();
+                headers.insert("X-Dockyy-Event".to_string(), n.event.clone());
+                if let Some(secret) = secret.as_deref().filter(|s| !s.is_empty()) {
+                    headers.insert(
+                        "X-Dockyy-Signature".to_string(),
+                        format!("sha256={}", sign(secret, &body)),
+                    );
+                }
+                post(url, "application/json", body, &headers).await
+            }
+            ChannelConfig::Slack { url } => {
+                post_json(url, &json!({"text": format!("*{}*\n{}", n.title, n.message)})).await
+            }
+            ChannelConfig::Mattermost { url } => {
+                post_json(
+                    url,
+                    &json!({"username": "Dockyy", "text": format!("**{}**\n{}", n.title, n.message)}),
+                )
+                .await
+            }
+            ChannelConfig::Discord { url } => {
+                post_json(
+                    url,
+                    &json!({
+                        "username": "Dockyy",
+                        "embeds": [{
+                            "title": n.title,
+                            "description": n.message,
+                            "color": discord_color(&n.severity),
+                            "timestamp": n.timestamp,
+                        }],
+                    }),
+                )
+                .await
+            }
+            ChannelConfig::Telegram { bot_token, chat_id } => {
+                post_json(
+                    &format!("https://api.telegram.org/bot{}/sendMessage", bot_token),
+                    &json!({
+                        "chat_id": chat_id,
+                        "text": n.text(),
+                        "disable_web_page_preview": true,
+                    }),
+                )
+                .await
+            }
+            ChannelConfig::Email {
+                host,
+                port,
+                security,
+                username,
+                password,
+                from,
+                to,
+            } => {
+                let body = format!(
+                    "{}\n\nEvent: {}\nTime: {}\n\n{}\n",
+                    n.message,
+                    n.event,
+                    n.timestamp,
+                    serde_json::to_string_pretty(&n.data).unwrap_or_default()
+                );
+                smtp::send(&smtp::Mail {
+                    host,
+                    port: *port,
+                    security: *security,
+                    username: username.as_deref(),
+                    password: password.as_deref(),
+                    from,
+                    to,
+                    subject: &format!("[Dockyy] {}", n.title),
+                    body: &body,
+                })
+                .await
+            }
+        }
+    }
+}
+
+fn enabled_channels(state: &AppState) -> Result<Vec<NotificationChannel>> {
+    state.db.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM notification_channels WHERE enabled = 1",
+            NotificationChannel::COLUMNS
+        ))?;
+        let channels = stmt
+            .query_map([], NotificationChannel::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(channels)
+    })
+}
+
+fn record_delivery(state: &AppState, channel_id: i64, n: &Notification) -> Result<i64> {
+    let payload = serde_json::to_string(n)?;
+    state.db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO notification_deliveries (channel_id, event, payload) VALUES (?1, ?2, ?3)",
+            rusqlite::params![channel_id, n.event, payload],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+}
+
+/// Make one delivery attempt and record its outcome. Failures are scheduled
+/// for retry unless `retry` is false or attempts are exhausted.
+async fn attempt(
+    state: &AppState,
+    delivery_id: i64,
+    channel: &NotificationChannel,
+    n: &Notification,
+    retry: bool,
+) -> Result<()> {
+    let result = channel.channel.send(n).await;
+
+    let error = result.as_ref().err().map(|e| format!("{:#}", e));
+    state.db.with_conn(|conn| {
+        match &error {
+            None => conn.execute(
+                "UPDATE notification_deliveries
+                 SET status = 'sent', attempts = attempts + 1, last_error = NULL,
+                     next_attempt_at = NULL, delivered_at = datetime('now')
+                 WHERE id = ?1",
+                [delivery_id],
+            )?,
+            Some(error) => conn.execute(
+                "UPDATE notification_deliveries
+                 SET attempts = attempts + 1, last_error = ?2,
+                     status = CASE WHEN ?3 AND attempts + 1 < ?4 THEN 'pending' ELSE 'failed' END,
+                     next_attempt_at = CASE WHEN ?3 AND attempts + 1 < ?4
+                         THEN datetime('now', '+' || (?5 << attempts) || ' seconds')
+                         ELSE NULL END
+                 WHERE id = ?1",
+                rusqlite::params![delivery_id, error, retry, MAX_ATTEMPTS, RETRY_BASE_SECS],
+            )?,
+        };
+        Ok(())
+    })?;
+
+    match error {
+        None => {
+            tracing::debug!(channel = %channel.name, event = %n.event, "Notification sent");
+            Ok(())
+        }
+        Some(error) => {
+            tracing::warn!(
+                channel = %channel.name,
+                event = %n.event,
+                "Notification delivery failed: {}", error
+            );
+            bail!(error)
+        }
+    }
+}
+
+/// Deliver `notification` to every subscribed channel in the background.
+pub fn dispatch(state: &Arc<AppState>, notification: Notification) {
+    let channels = match enabled_channels(state) {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::warn!("Could not load notification channels: {}", e);
+            return;
+        }
+    };
+
+    for channel in channels
+        .into_iter()
+        .filter(|c| c.subscribed(&notification.event))
+    {
+        let delivery_id = match record_delivery(state, channel.id, &notification) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Could not record notification delivery: {}", e);
+                continue;
+            }
+        };
+        let state = state.clone();
+        let notification = notification.clone();
+        tokio::spawn(async move {
+            let _ = attempt(&state, delivery_id, &channel, &notification, true).await;
+        });
+    }
+}
+
+/// Send a test notification to `channel` right away, without retries.
+pub async fn send_test(state: &AppState, channel: &NotificationChannel) -> Result<()> {
+    let n = Notification::new(
+        "test",
+        "info",
+        "Test notification".to_string(),
+        format!("Channel '{}' is configured correctly.", channel.name),
+        json!({"channel_id": channel.id}),
+    );
+    let delivery_id = record_delivery(state, channel.id, &n)?;
+    attempt(state, delivery_id, channel, &n, false).await
+}
+
+/// Retry failed deliveries whose backoff has elapsed.
+pub async fn run_retries(state: Arc<AppState>) {
+    tracing::info!("Starting notification retry loop (interval: {}s)", RETRY_INTERVAL_SECS);
+
+    // Deliveries in flight when the server stopped have no retry time yet.
+    let _ = state.db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE notification_deliveries SET next_attempt_at = datetime('now')
+             WHERE status = 'pending' AND next_attempt_at IS NULL",
+            [],
+        )?;
+        Ok(())
+    });
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(RETRY_INTERVAL_SECS)).await;
+        if let Err(e) = retry_due(&state).await {
+            tracing::warn!("Notification retry pass failed: {}", e);
+        }
+    }
+}
+
+async fn retry_due(state: &AppState) -> Result<()> {
+    let due: Vec<(i64, i64, String)> = state.db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT id, channel_id, payload FROM notification_deliveries
+             WHERE status = 'pending' AND next_attempt_at <= datetime('now')
+             ORDER BY id LIMIT 50",
+        )?;
+        let due = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Claim them so a slow pass doesn't pick the same rows up twice.
+        for (id, _, _) in &due {
+            conn.execute(
+                "UPDATE notification_deliveries SET next_attempt_at = NULL WHERE id = ?1",
+                [id],
+            )?;
+        }
+        Ok(due)
+    })?;
+
+    for (id, channel_id, payload) in due {
+        let channel = state.db.with_conn(|conn| {
+            let channel = conn.query_row(
+                &format!(
+                    "SELECT {} FROM notification_channels WHERE id = ?1",
+                    NotificationChannel::COLUMNS
+                ),
+                [channel_id],
+                NotificationChannel::from_row,
+            )?;
+            Ok(channel)
+        });
+        let n = serde_json::from_str::<Notification>(&payload);
+        match (channel, n) {
+            (Ok(channel), Ok(n)) => {
+                let _ = attempt(state, id, &channel, &n, true).await;
+            }
+            (Err(e), _) => tracing::warn!("Dropping notification {}: {}", id, e),
+            (_, Err(e)) => tracing::warn!("Dropping undecodable notification {}: {}", id, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, Method, StatusCode};
+    use axum::Router;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::Mutex;
+
+    use crate::auth::Role;
+    use crate::db::models::REDACTED;
+    use crate::test_support::{self, MockDocker};
+
+    /// Headers and body of each request a webhook endpoint received.
+    type Received = Arc<Mutex<Vec<(HeaderMap, Vec<u8>)>>>;
+
+    /// What a webhook endpoint received, and the status it answers with.
+    struct Receiver {
+        url: String,
+        received: Received,
+        status: Arc<AtomicU16>,
+    }
+
+    impl Receiver {
+        async fn start() -> Self {
+            let received = Received::default();
+            let status = Arc::new(AtomicU16::new(200));
+            let (log, answer) = (received.clone(), status.clone());
+            let app = Router::new().fallback(move |headers: HeaderMap, body: bytes::Bytes| async move {
+                log.lock().unwrap().push((headers, body.to_vec()));
+                StatusCode::from_u16(answer.load(Ordering::SeqCst)).unwrap()
+            });
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            Self { url, received, status }
+        }
+
+        fn events(&self) -> Vec<String> {
+            self.received
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(headers, _)| headers["x-dockyy-event"].to_str().unwrap().to_string())
+                .collect()
+        }
+
+        /// Wait for `count` requests to have arrived.
+        async fn wait_for(&self, count: usize) {
+            for _ in 0..100 {
+                if self.received.lock().unwrap().len() >= count {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("expected {} webhook requests, got {:?}", count, self.events());
+        }
+    }
+
+    /// `(event, status, attempts)` of every delivery, oldest first.
+    fn deliveries(state: &AppState) -> Vec<(String, String, i64)> {
+        state
+            .db
+            .with_conn(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT event, status, attempts FROM notification_deliveries ORDER BY id")?;
+                let deliveries = stmt
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(deliveries)
+            })
+            .unwrap()
+    }
+
+    fn delivery(event: &str, status: &str, attempts: i64) -> (String, String, i64) {
+        (event.to_string(), status.to_string(), attempts)
+    }
+
+    fn deploy_failed() -> Notification {
+        Notification::new(
+            "deploy.failed",
+            "critical",
+            "Deploy failed".to_string(),
+            "shop could not be built".to_string(),
+            json!({"repo": "shop"}),
+        )
+    }
+
+    #[tokio::test]
+    async fn signs_webhooks_for_subscribed_events_and_redacts_secrets() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let app = test_support::app(&state);
+        test_support::add_user(&state, "admin", Role::Admin, None);
+        let admin = test_support::login(&state, "admin");
+        let receiver = Receiver::start().await;
+
+        let (status, body) = test_support::call(
+            &app,
+            Method::POST,
+            "/api/notifications/channels",
+            Some(&admin),
+            Some(json!({
+                "name": "ops",
+                "kind": "webhook",
+                "config": {"url": receiver.url, "secret": "hook-secret"},
+                "events": ["deploy.failed"],
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let id = body["id"].as_i64().unwrap();
+        let uri = format!("/api/notifications/channels/{}", id);
+
+        // Unknown events and placeholder secrets are refused.
+        let (status, _) = test_support::call(
+            &app,
+            Method::POST,
+            "/api/notifications/channels",
+            Some(&admin),
+            Some(json!({"name": "x", "kind": "slack", "config": {"url": "https://hooks.example"}, "events": ["deploy.maybe"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = test_support::call(
+            &app,
+            Method::POST,
+            "/api/notifications/channels",
+            Some(&admin),
+            Some(json!({"name": "x", "kind": "telegram", "config": {"bot_token": REDACTED, "chat_id": "1"}})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // The secret is never returned, and sending the placeholder back keeps it.
+        let (_, channel) = test_support::call(&app, Method::GET, &uri, Some(&admin), None).await;
+        assert_eq!(channel["config"]["secret"], REDACTED);
+        assert_eq!(channel["config"]["url"], receiver.url);
+        let (status, _) = test_support::call(
+            &app,
+            Method::PUT,
+            &uri,
+            Some(&admin),
+            Some(json!({"kind": "webhook", "config": channel["config"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) =
+            test_support::call(&app, Method::POST, &format!("{}/test", uri), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        receiver.wait_for(1).await;
+        {
+            let received = receiver.received.lock().unwrap();
+            let (headers, body) = &received[0];
+            assert_eq!(
+                headers["x-dockyy-signature"].to_str().unwrap(),
+                format!("sha256={}", sign("hook-secret", body))
+            );
+            let sent: Value = serde_json::from_slice(body).unwrap();
+            assert_eq!(sent["event"], "test");
+        }
+
+        // Only the subscribed event goes out.
+        dispatch(
+            &state,
+            Notification::new("deploy.succeeded", "success", "Deployed".into(), "ok".into(), json!({})),
+        );
+        dispatch(&state, deploy_failed());
+        receiver.wait_for(2).await;
+        assert_eq!(receiver.events(), ["test", "deploy.failed"]);
+
+        // Disabled channels get nothing.
+        let (status, _) =
+            test_support::call(&app, Method::PUT, &uri, Some(&admin), Some(json!({"enabled": false}))).await;
+        assert_eq!(status, StatusCode::OK);
+        dispatch(&state, deploy_failed());
+
+        let (status, log) = test_support::call(
+            &app,
+            Method::GET,
+            &format!("/api/notifications/deliveries?channel={}", id),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let log: Vec<(&str, &str)> = log
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| (d["event"].as_str().unwrap(), d["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(log, [("deploy.failed", "sent"), ("test", "sent")]);
+
+        // Channels are admin-only.
+        test_support::add_user(&state, "dev", Role::Deployer, None);
+        let dev = test_support::login(&state, "dev");
+        let (status, _) = test_support::call(&app, Method::GET, &uri, Some(&dev), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries_with_backoff() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let receiver = Receiver::start().await;
+        receiver.status.store(500, Ordering::SeqCst);
+        let channel = NotificationChannel {
+            id: state
+                .db
+                .with_conn(|conn| {
+                    conn.execute(
+                        "INSERT INTO notification_channels (name, kind, config) VALUES ('ops', 'webhook', ?1)",
+                        [json!({"url": receiver.url}).to_string()],
+                    )?;
+                    Ok(conn.last_insert_rowid())
+                })
+                .unwrap(),
+            name: "ops".to_string(),
+            channel: ChannelConfig::Webhook {
+                url: receiver.url.clone(),
+                headers: HashMap::new(),
+                secret: None,
+            },
+            events: Vec::new(),
+            enabled: true,
+            created_at: String::new(),
+            updated_at: String::new(),
+        };
+
+        // Test sends aren't retried.
+        assert!(send_test(&state, &channel).await.is_err());
+        assert_eq!(deliveries(&state), [delivery("test", "failed", 1)]);
+
+        let delivery_id = record_delivery(&state, channel.id, &deploy_failed()).unwrap();
+        assert!(attempt(&state, delivery_id, &channel, &deploy_failed(), true).await.is_err());
+        let (error, backoff): (String, i64) = state
+            .db
+            .with_conn(|conn| {
+                Ok(conn.query_row(
+                    "SELECT last_error, CAST(strftime('%s', next_attempt_at) - strftime('%s', 'now') AS INTEGER)
+                     FROM notification_deliveries WHERE id = ?1",
+                    [delivery_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?)
+            })
+            .unwrap();
+        assert!(error.contains("500"), "{}", error);
+        assert!((RETRY_BASE_SECS - 2..=RETRY_BASE_SECS).contains(&backoff), "{}", backoff);
+
+        // Not due yet.
+        retry_due(&state).await.unwrap();
+        assert_eq!(deliveries(&state)[1], delivery("deploy.failed", "pending", 1));
+
+        let make_due = || {
+            state
+                .db
+                .with_conn(|conn| {
+                    conn.execute(
+                        "UPDATE notification_deliveries SET next_attempt_at = datetime('now', '-1 second')
+                         WHERE id = ?1",
+                        [delivery_id],
+                    )?;
+                    Ok(())
+                })
+                .unwrap()
+        };
+        for attempts in 2..MAX_ATTEMPTS {
+            make_due();
+            retry_due(&state).await.unwrap();
+            assert_eq!(deliveries(&state)[1], delivery("deploy.failed", "pending", attempts));
+        }
+        // The last attempt gives up.
+        make_due();
+        retry_due(&state).await.unwrap();
+        assert_eq!(deliveries(&state)[1], delivery("deploy.failed", "failed", MAX_ATTEMPTS));
+
+        // A pending delivery is sent once the endpoint recovers.
+        receiver.status.store(200, Ordering::SeqCst);
+        let delivery_id = record_delivery(&state, channel.id, &deploy_failed()).unwrap();
+        let _ = state.db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE notification_deliveries SET next_attempt_at = datetime('now') WHERE id = ?1",
+                [delivery_id],
+            )?;
+            Ok(())
+        });
+        retry_due(&state).await.unwrap();
+        assert_eq!(deliveries(&state)[2], delivery("deploy.failed", "sent", 1));
+        assert_eq!(receiver.events().len(), 1 + MAX_ATTEMPTS as usize + 1);
+    }
+}
@@ -0,0 +1,293 @@
+//! OpenID Connect single sign-on (authorization code flow with PKCE).
+//!
+//! [`begin`] stores a state, nonce and PKCE verifier and returns the
+//! provider's authorization URL. [`complete`] exchanges the returned code,
+//! validates the ID token against the provider's JWKS and maps its claims
+//! to a username and [`Role`]. Endpoints come from the issuer's discovery
+//! document, so any compliant provider (Keycloak, Authentik, Dex, ...)
+//! works.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::auth::{api_tokens, Role};
+use crate::services::http;
+use crate::AppState;
+
+/// Minutes a user has to finish signing in at the provider.
+const STATE_TTL_MINS: i64 = 10;
+
+/// Provider settings, from `OIDC_*` environment variables.
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    /// Omitted for public clients, which rely on PKCE alone.
+    pub client_secret: Option<String>,
+    /// Must point at `/api/auth/oidc/callback` and be registered at the provider.
+    pub redirect_url: String,
+    pub scopes: String,
+    /// Label for the dashboard's sign-in button.
+    pub display_name: String,
+    /// Claim holding the username; falls back to `email`, then `sub`.
+    pub username_claim: String,
+    /// Claim holding groups or roles; a dotted path such as
+    /// `realm_access.roles` reaches into nested objects.
+    pub roles_claim: String,
+    pub admin_values: Vec<String>,
+    pub deployer_values: Vec<String>,
+    pub viewer_values: Vec<String>,
+    /// Role for users matching none of the values; `None` denies them.
+    pub default_role: Option<Role>,
+}
+
+impl OidcConfig {
+    /// `None` unless `OIDC_ISSUER` is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let list = |name: &str| {
+            var(name)
+                .map(|v| {
+                    v.split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let Some(issuer) = var("OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let default_role = match var("OIDC_DEFAULT_ROLE").as_deref() {
+            None => Some(Role::Viewer),
+            Some("none") => None,
+            Some(role) => Some(
+                Role::parse(role)
+                    .ok_or_else(|| anyhow!("OIDC_DEFAULT_ROLE must be viewer, deployer, admin or none"))?,
+            ),
+        };
+
+        Ok(Some(Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id: var("OIDC_CLIENT_ID").context("OIDC_CLIENT_ID is required with OIDC_ISSUER")?,
+            client_secret: var("OIDC_CLIENT_SECRET"),
+            redirect_url: var("OIDC_REDIRECT_URL")
+                .context("OIDC_REDIRECT_URL is required with OIDC_ISSUER")?,
+            scopes: var("OIDC_SCOPES").unwrap_or_else(|| "openid profile email".into()),
+            display_name: var("OIDC_DISPLAY_NAME").unwrap_or_else(|| "SSO".into()),
+            username_claim: var("OIDC_USERNAME_CLAIM").unwrap_or_else(|| "preferred_username".into()),
+            roles_claim: var("OIDC_ROLES_CLAIM").unwrap_or_else(|| "groups".into()),
+            admin_values: list("OIDC_ADMIN_GROUPS"),
+            deployer_values: list("OIDC_DEPLOYER_GROUPS"),
+            viewer_values: list("OIDC_VIEWER_GROUPS"),
+            default_role,
+        }))
+    }
+
+    /// Highest role whose configured values appear in the user's claims.
+    fn map_role(&self, claims: &Value) -> Option<Role> {
+        let values: Vec<&str> = match claim(claims, &self.roles_claim) {
+            Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+            Some(Value::String(s)) => s.split([',', ' ']).filter(|s| !s.is_empty()).collect(),
+            _ => Vec::new(),
+        };
+        let has = |wanted: &[String]| wanted.iter().any(|w| values.contains(&w.as_str()));
+
+        if has(&self.admin_values) {
+            Some(Role::Admin)
+        } else if has(&self.deployer_values) {
+            Some(Role::Deployer)
+        } else if has(&self.viewer_values) {
+            Some(Role::Viewer)
+        } else {
+            self.default_role
+        }
+    }
+}
+
+/// Look up a claim by dotted path.
+fn claim<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(claims, |value, key| value.get(key))
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    userinfo_endpoint: Option<String>,
+}
+
+async fn discover(config: &OidcConfig) -> Result<Discovery> {
+    let url = format!("{}/.well-known/openid-configuration", config.issuer);
+    let discovery: Discovery = http::get_json(&url, None)
+        .await
+        .context("OIDC discovery failed")?;
+    if discovery.issuer.trim_end_matches('/') != config.issuer {
+        bail!("Discovery document is for issuer '{}'", discovery.issuer);
+    }
+    Ok(discovery)
+}
+
+fn sha256_b64(input: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, input.as_bytes());
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest.as_ref())
+}
+
+/// Start a login: remember the state and return the provider URL to
+/// redirect the browser to.
+pub async fn begin(state: &AppState, config: &OidcConfig) -> Result<String> {
+    let discovery = discover(config).await?;
+    let login_state = api_tokens::random_token("")?;
+    let nonce = api_tokens::random_token("")?;
+    let verifier = api_tokens::random_token("")?;
+
+    state.db.with_conn(|conn| {
+        conn.execute(
+            "DELETE FROM oidc_states WHERE created_at < datetime('now', '-' || ?1 || ' minutes')",
+            [STATE_TTL_MINS],
+        )?;
+        conn.execute(
+            "INSERT INTO oidc_states (state, nonce, code_verifier) VALUES (?1, ?2, ?3)",
+            rusqlite::params![login_state, nonce, verifier],
+        )?;
+        Ok(())
+    })?;
+
+    let params = [
+        ("response_type", "code"),
+        ("client_id", config.client_id.as_str()),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("scope", config.scopes.as_str()),
+        ("state", login_state.as_str()),
+        ("nonce", nonce.as_str()),
+        ("code_challenge", &sha256_b64(&verifier)),
+        ("code_challenge_method", "S256"),
+    ];
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, http::form_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let separator = if discovery.authorization_endpoint.contains('?') { '&' } else { '?' };
+    Ok(format!("{}{}{}", discovery.authorization_endpoint, separator, query))
+}
+
+/// A user authenticated by the provider.
+pub struct OidcIdentity {
+    pub subject: String,
+    pub username: String,
+    /// `None` when the claims map to no role and there's no default.
+    pub role: Option<Role>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+    access_token: Option<String>,
+}
+
+/// Finish a login from the provider's callback.
+pub async fn complete(
+    state: &AppState,
+    config: &OidcConfig,
+    code: &str,
+    login_state: &str,
+) -> Result<OidcIdentity> {
+    // Each state is single-use.
+    let (nonce, verifier): (String, String) = state
+        .db
+        .with_conn(|conn| {
+            let row = conn.query_row(
+                "DELETE FROM oidc_states
+                 WHERE state = ?1 AND created_at >= datetime('now', '-' || ?2 || ' minutes')
+                 RETURNING nonce, code_verifier",
+                rusqlite::params![login_state, STATE_TTL_MINS],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            Ok(row)
+        })
+        .map_err(|_| anyhow!("Unknown or expired login attempt; try again"))?;
+
+    let discovery = discover(config).await?;
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("code_verifier", verifier.as_str()),
+    ];
+    if let Some(ref secret) = config.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+    let tokens: TokenResponse = http::post_form(&discovery.token_endpoint, &form)
+        .await
+        .context("Token exchange failed")?;
+
+    let mut claims = validate_id_token(config, &discovery, &tokens.id_token).await?;
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce.as_str()) {
+        bail!("ID token nonce mismatch");
+    }
+    let subject = claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("ID token has no subject"))?
+        .to_string();
+
+    // Providers often leave groups out of the ID token; fill gaps from userinfo.
+    if let (Some(url), Some(access_token)) = (&discovery.userinfo_endpoint, &tokens.access_token) {
+        match http::get_json::<Value>(url, Some(access_token)).await {
+            Ok(Value::Object(info)) if info.get("sub").and_then(Value::as_str) == Some(&subject) => {
+                if let Value::Object(ref mut map) = claims {
+                    for (key, value) in info {
+                        map.entry(key).or_insert(value);
+                    }
+                }
+            }
+            Ok(_) => tracing::warn!("Ignoring userinfo response for a different subject"),
+            Err(e) => tracing::warn!("OIDC userinfo request failed: {}", e),
+        }
+    }
+
+    let username = [config.username_claim.as_str(), "email", "sub"]
+        .iter()
+        .find_map(|name| claim(&claims, name).and_then(Value::as_str))
+        .unwrap_or(&subject)
+        .to_string();
+
+    Ok(OidcIdentity {
+        role: config.map_role(&claims),
+        subject,
+        username,
+    })
+}
+
+async fn validate_id_token(config: &OidcConfig, discovery: &Discovery, token: &str) -> Result<Value> {
+    let header = jsonwebtoken::decode_header(token).context("Malformed ID token")?;
+    // Only asymmetric signatures: the client secret is not a signing key here.
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        bail!("Unsupported ID token algorithm {:?}", header.alg);
+    }
+
+    let jwks: JwkSet = http::get_json(&discovery.jwks_uri, None)
+        .await
+        .context("Failed to fetch provider keys")?;
+    let jwk = match header.kid {
+        Some(ref kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| anyhow!("No provider key matches the ID token"))?;
+    let key = DecodingKey::from_jwk(jwk)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&config.issuer, &discovery.issuer]);
+    validation.set_audience(&[&config.client_id]);
+    let data = jsonwebtoken::decode::<Value>(token, &key, &validation)
+        .context("Invalid ID token")?;
+    Ok(data.claims)
+}
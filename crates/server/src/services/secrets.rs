@@ -0,0 +1,58 @@
+//! Server secrets generated on first start and kept in `data_dir`.
+//!
+//! Each secret is a random token in its own file, readable only by the
+//! owner, so restarts (and upgrades) keep signing tokens valid without the
+//! operator having to invent one.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+
+use crate::auth::api_tokens;
+
+/// Read the secret stored in `data_dir/name`, creating it on first use.
+pub fn load_or_create(data_dir: &str, name: &str) -> Result<String> {
+    let path = Path::new(data_dir).join(name);
+    match std::fs::read_to_string(&path) {
+        Ok(secret) if !secret.trim().is_empty() => {
+            restrict_permissions(&path)?;
+            return Ok(secret.trim().to_string());
+        }
+        Ok(_) => anyhow::bail!("{} is empty; delete it to generate a new secret", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+
+    let secret = api_tokens::random_token("")?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(secret.as_bytes())?;
+    file.sync_all()?;
+    tracing::info!("Generated {}", path.display());
+    Ok(secret)
+}
+
+/// Tighten a secret file that was created or copied with looser permissions.
+fn restrict_permissions(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(path)?.permissions();
+        if perms.mode() & 0o077 != 0 {
+            tracing::warn!("{} was readable by other users; restricting it to 0600", path.display());
+            perms.set_mode(0o600);
+            std::fs::set_permissions(path, perms)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+//! Minimal SMTP submission client for email notifications: implicit TLS or
+//! STARTTLS, optional `AUTH PLAIN`, one plain-text message per connection.
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+const SMTP_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with `STARTTLS` (port 587).
+    #[default]
+    Starttls,
+    /// TLS from the first byte (port 465).
+    Tls,
+    /// No encryption; only for local relays.
+    None,
+}
+
+pub struct Mail<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a str>,
+    pub from: &'a str,
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+fn tls_connector() -> Result<TlsConnector> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(TlsConnector::from(config.clone()));
+    }
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs().certs {
+        let _ = roots.add(cert);
+    }
+    if roots.is_empty() {
+        bail!("No system root certificates found");
+    }
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(CONFIG.get_or_init(|| Arc::new(config)).clone()))
+}
+
+async fn start_tls(stream: Box<dyn Io>, host: &str) -> Result<Box<dyn Io>> {
+    let name = ServerName::try_from(host.to_string()).context("Invalid SMTP host name")?;
+    let tls = tls_connector()?.connect(name, stream).await?;
+    Ok(Box::new(tls))
+}
+
+struct Session {
+    stream: BufReader<Box<dyn Io>>,
+}
+
+impl Session {
+    /// Read a (possibly multi-line) reply and check its code.
+    async fn expect(&mut self, codes: &[u16]) -> Result<String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("SMTP server closed the connection");
+            }
+            reply.push_str(&line);
+            // "250-..." continues, "250 ..." ends the reply.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        if !codes.iter().any(|code| reply.starts_with(&code.to_string())) {
+            bail!("SMTP error: {}", reply.trim());
+        }
+        Ok(reply)
+    }
+
+    async fn command(&mut self, line: &str, codes: &[u16]) -> Result<String> {
+        self.stream.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        self.expect(codes).await
+    }
+}
+
+/// Reject header injection through addresses and subjects.
+fn header_safe(value: &str) -> Result<&str> {
+    if value.contains('\r') || value.contains('\n') {
+        bail!("Invalid characters in email header value");
+    }
+    Ok(value)
+}
+
+/// RFC 2047 encoding for non-ASCII subjects.
+fn encode_subject(subject: &str) -> String {
+    if subject.is_ascii() {
+        subject.to_string()
+    } else {
+        format!(
+            "=?utf-8?B?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(subject)
+        )
+    }
+}
+
+pub async fn send(mail: &Mail<'_>) -> Result<()> {
+    tokio::time::timeout(Duration::from_secs(SMTP_TIMEOUT_SECS), deliver(mail))
+        .await
+        .context("SMTP delivery timed out")?
+}
+
+async fn deliver(mail: &Mail<'_>) -> Result<()> {
+    let tcp = TcpStream::connect((mail.host, mail.port))
+        .await
+        .with_context(|| format!("Could not connect to {}:{}", mail.host, mail.port))?;
+    let mut stream: Box<dyn Io> = Box::new(tcp);
+    if mail.security == SmtpSecurity::Tls {
+        stream = start_tls(stream, mail.host).await?;
+    }
+
+    let mut session = Session { stream: BufReader::new(stream) };
+    session.expect(&[220]).await?;
+    session.command("EHLO dockyy", &[250]).await?;
+
+    if mail.security == SmtpSecurity::Starttls {
+        session.command("STARTTLS", &[220]).await?;
+        let plain = session.stream.into_inner();
+        session = Session {
+            stream: BufReader::new(start_tls(plain, mail.host).await?),
+        };
+        session.command("EHLO dockyy", &[250]).await?;
+    }
+
+    if let Some(username) = mail.username {
+        let token = format!("\0{}\0{}", username, mail.password.unwrap_or(""));
+        let token = base64::engine::general_purpose::STANDARD.encode(token);
+        session.command(&format!("AUTH PLAIN {}", token), &[235]).await?;
+    }
+
+    session
+        .command(&format!("MAIL FROM:<{}>", header_safe(mail.from)?), &[250])
+        .await?;
+    for to in mail.to {
+        // 251: user not local, will forward.
+        session
+            .command(&format!("RCPT TO:<{}>", header_safe(to)?), &[250, 251])
+            .await?;
+    }
+    session.command("DATA", &[354]).await?;
+
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        mail.from,
+        mail.to.join(", "),
+        encode_subject(header_safe(mail.subject)?),
+        chrono::Utc::now().to_rfc2822(),
+    );
+    for line in mail.body.lines() {
+        // Dot-stuffing: a leading '.' would otherwise end the message early.
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message.push_str(".\r\n");
+    session.stream.get_mut().write_all(message.as_bytes()).await?;
+    session.stream.get_mut().flush().await?;
+    session.expect(&[250]).await?;
+
+    let _ = session.command("QUIT", &[221]).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A relay that accepts one message and returns the session transcript.
+    async fn relay() -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut transcript = Vec::new();
+            let mut in_data = false;
+            stream.get_mut().write_all(b"220 relay ready\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches("\r\n").to_string();
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250-relay\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 bye\r\n"
+                } else {
+                    b"250 ok\r\n"
+                };
+                transcript.push(line);
+                stream.get_mut().write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn submits_a_dot_stuffed_message() {
+        let (port, transcript) = relay().await;
+        let to = ["ops@example.com".to_string(), "dev@example.com".to_string()];
+        send(&Mail {
+            host: "127.0.0.1",
+            port,
+            security: SmtpSecurity::None,
+            username: Some("dockyy"),
+            password: Some("hunter2"),
+            from: "dockyy@example.com",
+            to: &to,
+            subject: "Déploiement échoué",
+            body: "first\n.hidden\nlast",
+        })
+        .await
+        .unwrap();
+
+        let transcript = transcript.await.unwrap();
+        let auth = base64::engine::general_purpose::STANDARD.encode("\0dockyy\0hunter2");
+        for line in [
+            "EHLO dockyy".to_string(),
+            format!("AUTH PLAIN {}", auth),
+            "MAIL FROM:<dockyy@example.com>".to_string(),
+            "RCPT TO:<ops@example.com>".to_string(),
+            "RCPT TO:<dev@example.com>".to_string(),
+            "To: ops@example.com, dev@example.com".to_string(),
+            format!("Subject: {}", encode_subject("Déploiement échoué")),
+            "..hidden".to_string(),
+            "QUIT".to_string(),
+        ] {
+            assert!(transcript.contains(&line), "missing {:?} in {:#?}", line, transcript);
+        }
+        assert!(encode_subject("Déploiement échoué").starts_with("=?utf-8?B?"));
+    }
+
+    #[tokio::test]
+    async fn refuses_header_injection() {
+        let (port, _) = relay().await;
+        let to = ["ops@example.com".to_string()];
+        let err = send(&Mail {
+            host: "127.0.0.1",
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "dockyy@example.com",
+            to: &to,
+            subject: "Deploy failed\r\nBcc: everyone@example.com",
+            body: "",
+        })
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("Invalid characters"), "{:#}", err);
+    }
+}
@@ -0,0 +1,227 @@
+//! In-process counters and histograms exported on `/metrics`.
+//!
+//! Label sets are small and bounded (job kinds, statuses, route templates), so
+//! plain maps behind a mutex are enough; nothing here is on a hot path that
+//! would justify a metrics crate.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::AppState;
+
+/// Deployment duration buckets, in seconds.
+const DEPLOY_BUCKETS: &[f64] = &[10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0];
+/// HTTP latency buckets, in seconds.
+const HTTP_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// Non-cumulative count per bucket; the last slot is `+Inf`.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let slot = self
+            .bounds
+            .iter()
+            .position(|b| value <= *b)
+            .unwrap_or(self.bounds.len());
+        self.counts[slot] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let le = match self.bounds.get(i) {
+                Some(b) => b.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, le, cumulative);
+        }
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Default)]
+pub struct Telemetry {
+    /// (job type, status) → duration histogram.
+    deployments: Mutex<BTreeMap<(String, &'static str), Histogram>>,
+    /// (event, HTTP status) → count.
+    webhooks: Mutex<BTreeMap<(String, u16), u64>>,
+    /// reason → count.
+    login_failures: Mutex<BTreeMap<&'static str, u64>>,
+    /// (method, route) → latency histogram.
+    http_latency: Mutex<BTreeMap<(String, String), Histogram>>,
+    /// (method, route, status) → count.
+    http_requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+}
+
+impl Telemetry {
+    pub fn record_deployment(&self, job_type: &str, success: bool, seconds: f64) {
+        let status = if success { "success" } else { "failed" };
+        self.deployments
+            .lock()
+            .unwrap()
+            .entry((job_type.to_string(), status))
+            .or_insert_with(|| Histogram::new(DEPLOY_BUCKETS))
+            .observe(seconds);
+    }
+
+    pub fn record_webhook(&self, event: &str, status: u16) {
+        *self
+            .webhooks
+            .lock()
+            .unwrap()
+            .entry((event.to_string(), status))
+            .or_default() += 1;
+    }
+
+    pub fn record_login_failure(&self, reason: &'static str) {
+        *self.login_failures.lock().unwrap().entry(reason).or_default() += 1;
+    }
+
+    fn record_http(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        self.http_latency
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), route.to_string()))
+            .or_insert_with(|| Histogram::new(HTTP_BUCKETS))
+            .observe(seconds);
+        *self
+            .http_requests
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// Append every metric in Prometheus text format.
+    pub fn render(&self, out: &mut String) {
+        let deployments = self.deployments.lock().unwrap();
+        header(out, "dockyy_deployments_total", "counter", "Finished deployment jobs");
+        for ((job_type, status), h) in deployments.iter() {
+            let _ = writeln!(
+                out,
+                "dockyy_deployments_total{{{}}} {}",
+                labels(&[("type", job_type), ("status", status)]),
+                h.count
+            );
+        }
+        header(out, "dockyy_deployment_duration_seconds", "histogram", "Deployment job duration");
+        for ((job_type, status), h) in deployments.iter() {
+            h.render(
+                out,
+                "dockyy_deployment_duration_seconds",
+                &labels(&[("type", job_type), ("status", status)]),
+            );
+        }
+        drop(deployments);
+
+        header(out, "dockyy_webhook_deliveries_total", "counter", "Received webhook deliveries");
+        for ((event, status), count) in self.webhooks.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "dockyy_webhook_deliveries_total{{{}}} {}",
+                labels(&[("event", event), ("status", &status.to_string())]),
+                count
+            );
+        }
+
+        header(out, "dockyy_login_failures_total", "counter", "Rejected login attempts");
+        for (reason, count) in self.login_failures.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "dockyy_login_failures_total{{{}}} {}",
+                labels(&[("reason", reason)]),
+                count
+            );
+        }
+
+        header(out, "dockyy_http_requests_total", "counter", "API requests by route and status");
+        for ((method, route, status), count) in self.http_requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "dockyy_http_requests_total{{{}}} {}",
+                labels(&[("method", method), ("route", route), ("status", &status.to_string())]),
+                count
+            );
+        }
+        header(out, "dockyy_http_request_duration_seconds", "histogram", "API request latency");
+        for ((method, route), h) in self.http_latency.lock().unwrap().iter() {
+            h.render(
+                out,
+                "dockyy_http_request_duration_seconds",
+                &labels(&[("method", method), ("route", route)]),
+            );
+        }
+    }
+}
+
+/// Write the `# HELP` / `# TYPE` lines for a metric family.
+pub fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Format a label set, escaping values as the exposition format requires.
+pub fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| {
+            let v = v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", k, v)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Route-layer middleware recording latency per matched route template, so
+/// path parameters don't explode label cardinality.
+pub async fn track_http(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string());
+    let method = req.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+
+    if let Some(route) = route {
+        state.telemetry.record_http(
+            &method,
+            &route,
+            response.status().as_u16(),
+            started.elapsed().as_secs_f64(),
+        );
+    }
+    response
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use bollard::container::{Config, CreateContainerOptions, ListContainersOptions, StartContainerOptions};
 use bollard::image::CreateImageOptions;
 use bollard::models::{HostConfig, PortBinding, RestartPolicy, RestartPolicyNameEnum};
-use bollard::network::{ConnectNetworkOptions, CreateNetworkOptions};
+use bollard::network::CreateNetworkOptions;
 use bollard::Docker;
 use futures_util::TryStreamExt;
 use serde::{Deserialize, Serialize};
@@ -19,15 +19,22 @@ pub struct ProxyRoute {
     pub domain: String,
     pub port: u16,
     pub status: String,
+    /// Repository the container was deployed for (`dockyy.repo_id`).
+    pub repo_id: Option<i64>,
 }
 
 pub struct TraefikService {
     docker: Docker,
+    /// Engine socket on the container host, bind-mounted into Traefik.
+    socket_path: String,
 }
 
 impl TraefikService {
-    pub fn new(docker: Docker) -> Self {
-        Self { docker }
+    pub fn new(docker: Docker, socket_path: &str) -> Self {
+        Self {
+            docker,
+            socket_path: socket_path.to_string(),
+        }
     }
 
     /// Create the shared Docker network if it does not exist.
@@ -136,9 +143,10 @@ impl TraefikService {
             cmd: Some(cmd),
             labels: Some(labels),
             host_config: Some(HostConfig {
-                binds: Some(vec![
-                    "/var/run/docker.sock:/var/run/docker.sock:ro".to_string()
-                ]),
+                binds: Some(vec![format!(
+                    "{}:/var/run/docker.sock:ro",
+                    self.socket_path
+                )]),
                 port_bindings: Some(port_bindings),
                 restart_policy: Some(RestartPolicy {
                     name: Some(RestartPolicyNameEnum::ALWAYS),
@@ -196,20 +204,6 @@ impl TraefikService {
         labels
     }
 
-    /// Connect an already-running container to the shared dockyy-net network.
-    pub async fn connect_container(&self, container_id: &str) -> Result<()> {
-        self.docker
-            .connect_network(
-                TRAEFIK_NETWORK,
-                ConnectNetworkOptions {
-                    container: container_id,
-                    ..Default::default()
-                },
-            )
-            .await?;
-        Ok(())
-    }
-
     /// List all running containers that have Traefik routing enabled.
     pub async fn list_routes(&self) -> Result<Vec<ProxyRoute>> {
         let mut filters = HashMap::new();
@@ -261,6 +255,7 @@ impl TraefikService {
                     domain,
                     port,
                     status: c.status.unwrap_or_default(),
+                    repo_id: labels.get("dockyy.repo_id").and_then(|id| id.parse().ok()),
                 });
             }
         }
@@ -1,12 +1,20 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::sync::Arc;
 use tokio::time::{sleep, Duration};
 use tokio::process::Command;
 use std::process::Stdio;
 use crate::AppState;
-use crate::db::models::{Job, Repository};
+use crate::db::models::{
+    Environment, Job, MountKind, MountSpec, Repository, ResourceLimits, DEFAULT_ENVIRONMENT,
+    check_owner, validate_network_name,
+};
+use crate::services::cleanup::deployment_image_tag;
+use crate::services::crypto;
+use crate::services::docker::ContainerSpec;
+use crate::services::notifications::{self, Notification};
 use crate::services::traefik::{TraefikService, TRAEFIK_NETWORK};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 
 pub async fn run_worker(state: Arc<AppState>) {
     tracing::info!("Starting background worker loop");
@@ -59,11 +67,20 @@ async fn process_next_job(state: &Arc<AppState>) -> Result<()> {
     })?;
 
     // 3. Dispatch based on job type
+    let started = std::time::Instant::now();
     let result = match job.job_type.as_str() {
         "deploy" => handle_deploy_job(state, &job).await,
+        "preview_deploy" => handle_preview_deploy_job(state, &job).await,
+        "preview_teardown" => handle_preview_teardown_job(state, &job).await,
         _ => Err(anyhow::anyhow!("Unknown job type: {}", job.job_type)),
     };
 
+    if matches!(job.job_type.as_str(), "deploy" | "preview_deploy") {
+        state
+            .telemetry
+            .record_deployment(&job.job_type, result.is_ok(), started.elapsed().as_secs_f64());
+    }
+
     // 4. Update job status based on result
     match result {
         Ok(_) => {
@@ -91,218 +108,366 @@ async fn process_next_job(state: &Arc<AppState>) -> Result<()> {
     Ok(())
 }
 
-async fn handle_deploy_job(state: &Arc<AppState>, job: &Job) -> Result<()> {
-    let payload: Value = serde_json::from_str(&job.payload)?;
-    let repo_id = payload["repo_id"].as_i64().ok_or_else(|| anyhow::anyhow!("Missing repo_id in payload"))?;
-    
-    // 1. Get repository info
-    let repo = state.db.with_conn(|conn| {
+/// Load a repository row by id.
+pub(crate) fn load_repository(state: &AppState, repo_id: i64) -> Result<Repository> {
+    state.db.with_conn(|conn| {
         let repo = conn.query_row(
-            "SELECT id, name, owner, url, description, webhook_url, filesystem_path,
-                    ssh_password, is_private, default_branch, domain, proxy_port,
-                    created_at, updated_at
-             FROM repositories WHERE id = ?1",
+            &format!("SELECT {} FROM repositories WHERE id = ?1", Repository::COLUMNS),
             [repo_id],
-            |row| {
-                Ok(Repository {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    owner: row.get(2)?,
-                    url: row.get(3)?,
-                    description: row.get(4)?,
-                    webhook_url: row.get(5)?,
-                    filesystem_path: row.get(6)?,
-                    ssh_password: row.get(7)?,
-                    is_private: row.get::<_, i64>(8)? != 0,
-                    default_branch: row.get(9)?,
-                    domain: row.get(10)?,
-                    proxy_port: row.get(11)?,
-                    created_at: row.get(12)?,
-                    updated_at: row.get(13)?,
-                })
-            }
+            Repository::from_row,
         )?;
         Ok(repo)
-    })?;
+    })
+}
 
-    // 2. Create deployment record
-    let deployment_id = state.db.with_conn(|conn| {
-        conn.execute(
-            "INSERT INTO deployments (repo_id, status) VALUES (?1, 'building')",
-            [repo.id]
-        )?;
-        Ok(conn.last_insert_rowid())
-    })?;
+/// Load a repository's environment by name. The default environment is created
+/// on demand so repositories registered before environments existed keep working.
+pub(crate) fn load_environment(state: &AppState, repo_id: i64, name: &str) -> Result<Environment> {
+    if name == DEFAULT_ENVIRONMENT {
+        state.db.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO environments (repo_id, name) VALUES (?1, ?2)",
+                rusqlite::params![repo_id, name],
+            )?;
+            Ok(())
+        })?;
+    }
+    find_environment(state, repo_id, name)
+}
 
-    let repo_dir = format!("{}/repos/{}", state.config.data_dir, repo.id);
-    std::fs::create_dir_all(&repo_dir)?;
-
-    let mut temp_key_path = None;
-    let git_ssh_command = if let Some(ssh_key) = &repo.ssh_password {
-        if !ssh_key.trim().is_empty() {
-            let key_path = format!("{}/repos/{}_id_rsa", state.config.data_dir, repo.id);
-            std::fs::write(&key_path, ssh_key.trim())?;
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&key_path)?.permissions();
-                perms.set_mode(0o600);
-                std::fs::set_permissions(&key_path, perms)?;
-            }
-            temp_key_path = Some(key_path.clone());
-            format!("ssh -i {} -o StrictHostKeyChecking=no", key_path)
-        } else {
-            "ssh -o StrictHostKeyChecking=no".to_string()
-        }
-    } else {
-        "ssh -o StrictHostKeyChecking=no".to_string()
-    };
+/// Look up a repository's environment by name without creating anything.
+pub(crate) fn find_environment(state: &AppState, repo_id: i64, name: &str) -> Result<Environment> {
+    state.db.with_conn(|conn| {
+        let env = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM environments WHERE repo_id = ?1 AND name = ?2",
+                    Environment::COLUMNS
+                ),
+                rusqlite::params![repo_id, name],
+                Environment::from_row,
+            )
+            .map_err(|_| anyhow::anyhow!("Environment '{}' not found", name))?;
+        Ok(env)
+    })
+}
 
-    // 3. Clone or Pull
-    if std::path::Path::new(&format!("{}/.git", repo_dir)).exists() {
-        tracing::info!("Pulling repo {}", repo.name);
-        let output = Command::new(&state.config.git_bin)
-            .env("GIT_SSH_COMMAND", &git_ssh_command)
-            .arg("-C")
-            .arg(&repo_dir)
-            .arg("pull")
-            .arg("origin")
-            .arg(&repo.default_branch)
-            .output().await?;
-            
-        if let Some(key_path) = &temp_key_path {
-            let _ = std::fs::remove_file(key_path);
-        }
-        
-        if !output.status.success() {
-            let err = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Git pull failed: {}", err));
-        }
-    } else {
-        tracing::info!("Cloning repo {} to {}", repo.url, repo_dir);
-        let output = Command::new(&state.config.git_bin)
-            .env("GIT_SSH_COMMAND", &git_ssh_command)
-            .arg("clone")
-            .arg(&repo.url)
-            .arg(&repo_dir)
-            .output().await?;
-            
-        if let Some(key_path) = &temp_key_path {
-            let _ = std::fs::remove_file(key_path);
-        }
-            
-        if !output.status.success() {
-            let err = String::from_utf8_lossy(&output.stderr);
-            return Err(anyhow::anyhow!("Git clone failed: {}", err));
+/// Build the `GIT_SSH_COMMAND` for a repository, decrypting its deploy key
+/// to a temporary file when one is configured. The returned path (if any)
+/// must be removed by the caller once git has finished.
+pub fn git_ssh_command(state: &AppState, repo: &Repository, key_name: &str) -> Result<(String, Option<String>)> {
+    if let Some(sealed) = &repo.ssh_key_encrypted {
+        let ssh_key = state
+            .keyring
+            .decrypt(sealed, &crypto::ssh_key_context(repo.id))
+            .context("Failed to decrypt the repository's SSH key")?;
+        let key_path = format!("{}/repos/{}_id_rsa", state.config.data_dir, key_name);
+        // Key files need a trailing newline for OpenSSH.
+        std::fs::write(&key_path, format!("{}\n", ssh_key.trim()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&key_path)?.permissions();
+            perms.set_mode(0o600);
+            std::fs::set_permissions(&key_path, perms)?;
         }
+        return Ok((
+            format!("ssh -i {} -o StrictHostKeyChecking=no", key_path),
+            Some(key_path),
+        ));
+    }
+    Ok(("ssh -o StrictHostKeyChecking=no".to_string(), None))
+}
+
+/// Run a git command with the repository's SSH credentials.
+async fn run_git(state: &AppState, repo: &Repository, key_name: &str, args: &[&str]) -> Result<()> {
+    let (git_ssh_command, temp_key_path) = git_ssh_command(state, repo, key_name)?;
+
+    let output = Command::new(&state.config.git_bin)
+        .env("GIT_SSH_COMMAND", &git_ssh_command)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await;
+
+    if let Some(key_path) = &temp_key_path {
+        let _ = std::fs::remove_file(key_path);
     }
 
-    // 4. Build with Nixpacks (or just build as a Docker image)
-    // We'll tag the image as dockyy-{repo_name}:latest
-    let image_tag = format!("dockyy-{}:latest", repo.name.to_lowercase().replace("/", "-"));
+    let output = output?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("git {} failed: {}", args.first().copied().unwrap_or(""), err));
+    }
+    Ok(())
+}
+
+/// Build `image_tag` from `context_dir` through the Docker API. A Dockerfile in
+/// the repository wins; otherwise Nixpacks is used only to *generate* one
+/// (`nixpacks build --out`), so no docker CLI is needed. Returns the build log.
+async fn build_image(state: &AppState, context_dir: &str, image_tag: &str) -> Result<String> {
     tracing::info!("Building image {}", image_tag);
-    
-    // We use nixpacks if available, otherwise we assume a Dockerfile exists
-    // Let's try nixpacks first
-    let build_output = Command::new("nixpacks")
+
+    if std::path::Path::new(context_dir).join("Dockerfile").exists() {
+        return state.docker.build_image(context_dir, "Dockerfile", image_tag).await;
+    }
+
+    let plan = Command::new("nixpacks")
         .arg("build")
-        .arg(&repo_dir)
-        .arg("--name")
-        .arg(&image_tag)
+        .arg(context_dir)
+        .arg("--out")
+        .arg(context_dir)
         .stdin(Stdio::null())
-        .output().await;
-
-    let build_log = match build_output {
-        Ok(output) if output.status.success() => {
-            String::from_utf8_lossy(&output.stdout).to_string()
-        },
-        Ok(output) => {
-            let err = String::from_utf8_lossy(&output.stderr);
-            tracing::warn!("Nixpacks build failed: {}. Trying docker build...", err);
-            // Fallback to docker build .
-            let dbuild = Command::new("docker")
-                .arg("build")
-                .arg("-t")
-                .arg(&image_tag)
-                .arg(&repo_dir)
-                .stdin(Stdio::null())
-                .output().await?;
-            if !dbuild.status.success() {
-                return Err(anyhow::anyhow!("Docker build failed: {}", String::from_utf8_lossy(&dbuild.stderr)));
-            }
-            String::from_utf8_lossy(&dbuild.stdout).to_string()
-        },
-        Err(e) => {
-            tracing::warn!("Nixpacks not found: {}. Trying docker build...", e);
-            let dbuild = Command::new("docker")
-                .arg("build")
-                .arg("-t")
-                .arg(&image_tag)
-                .arg(&repo_dir)
-                .stdin(Stdio::null())
-                .output().await?;
-             if !dbuild.status.success() {
-                return Err(anyhow::anyhow!("Docker build failed: {}", String::from_utf8_lossy(&dbuild.stderr)));
-            }
-            String::from_utf8_lossy(&dbuild.stdout).to_string()
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("No Dockerfile found and nixpacks is unavailable: {}", e))?;
+
+    if !plan.status.success() {
+        return Err(anyhow::anyhow!(
+            "Nixpacks failed to generate a build plan: {}",
+            String::from_utf8_lossy(&plan.stderr)
+        ));
+    }
+
+    let mut log = String::from_utf8_lossy(&plan.stdout).to_string();
+    log.push_str(
+        &state
+            .docker
+            .build_image(context_dir, ".nixpacks/Dockerfile", image_tag)
+            .await?,
+    );
+    Ok(log)
+}
+
+/// Everything needed to (re)create an application container.
+struct RunSpec<'a> {
+    repo: &'a Repository,
+    container_name: &'a str,
+    image_tag: &'a str,
+    domain: Option<&'a str>,
+    proxy_port: u16,
+    env: Vec<(String, String)>,
+    labels: Vec<(String, String)>,
+    limits: ResourceLimits,
+    mounts: Vec<MountSpec>,
+    /// Extra networks besides the proxy network, created on first use.
+    networks: Vec<String>,
+}
+
+/// Check mounts against the bind allowlist, which may have changed since they
+/// were saved, and create the volumes and networks `repo`'s containers use.
+pub(crate) async fn prepare_attachments(
+    state: &AppState,
+    repo: &Repository,
+    mounts: &[MountSpec],
+    networks: &[String],
+) -> Result<()> {
+    for mount in mounts {
+        mount
+            .validate(&state.config.bind_allowlist)
+            .map_err(anyhow::Error::msg)?;
+        if mount.kind == MountKind::Volume {
+            ensure_attachment(state, repo, Attachment::Volume, &mount.source).await?;
         }
+    }
+    for network in networks {
+        validate_network_name(network).map_err(anyhow::Error::msg)?;
+        ensure_attachment(state, repo, Attachment::Network, network).await?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Attachment {
+    Volume,
+    Network,
+}
+
+/// Create the volume or network `name` labelled with `repo`, or check the
+/// existing one is `repo`'s to use. Names are free-form, so they may point
+/// at another application's object (or, with an environment suffix, its
+/// staging volume); ones dockyy didn't create for any repository are only
+/// used when an admin attached them.
+async fn ensure_attachment(state: &AppState, repo: &Repository, attachment: Attachment, name: &str) -> Result<()> {
+    let (kind, existing) = match attachment {
+        Attachment::Volume => ("Volume", state.docker.volume_labels(name).await?),
+        Attachment::Network => ("Network", state.docker.network_labels(name).await?),
     };
+    if let Some(existing) = existing {
+        return check_owner(kind, name, &existing, Some(repo.id), repo.adopts(kind, name))
+            .map_err(anyhow::Error::msg);
+    }
 
-    // 5. Deploy / Start container
-    let container_name = format!("dockyy-{}", repo.name.to_lowercase().replace("/", "-"));
-    let _ = state.docker.stop_container(&container_name).await;
-    let _ = state.docker.remove_container(&container_name, true).await;
+    let labels = HashMap::from([
+        ("dockyy.managed".to_string(), "true".to_string()),
+        ("dockyy.repo_id".to_string(), repo.id.to_string()),
+    ]);
+    match attachment {
+        Attachment::Volume => state.docker.ensure_volume(name, labels).await,
+        Attachment::Network => state.docker.ensure_network(name, labels).await,
+    }
+}
+
+/// Replace any existing container called `spec.container_name` with a fresh one
+/// on the proxy network. Returns the new container id.
+async fn run_container(state: &AppState, spec: RunSpec<'_>) -> Result<String> {
+    let _ = state.docker.stop_container(spec.container_name).await;
+    let _ = state.docker.remove_container(spec.container_name, true).await;
 
     // Ensure the shared proxy network exists before running the container
     state.traefik.ensure_network().await?;
 
-    tracing::info!("Starting container {}", container_name);
+    tracing::info!("Starting container {}", spec.container_name);
 
-    let mut run_cmd = Command::new("docker");
-    run_cmd
-        .stdin(Stdio::null())
-        .arg("run")
-        .arg("-d")
-        .arg("--name")
-        .arg(&container_name)
-        .arg("--network")
-        .arg(TRAEFIK_NETWORK)
-        .arg("--restart")
-        .arg("always");
+    let mut labels: HashMap<String, String> = spec.labels.into_iter().collect();
+    labels.insert("dockyy.managed".to_string(), "true".to_string());
+
+    prepare_attachments(state, spec.repo, &spec.mounts, &spec.networks).await?;
 
     // Attach Traefik routing labels when a domain is configured
-    if let Some(ref domain) = repo.domain {
-        let proxy_port = repo.proxy_port.unwrap_or(3000) as u16;
+    if let Some(domain) = spec.domain {
         tracing::info!(
             "Attaching Traefik route: {} -> {}:{}",
             domain,
-            container_name,
-            proxy_port
+            spec.container_name,
+            spec.proxy_port
         );
-        let labels = TraefikService::container_labels(&container_name, domain, proxy_port);
-        for (k, v) in &labels {
-            run_cmd.arg("--label").arg(format!("{}={}", k, v));
-        }
+        labels.extend(TraefikService::container_labels(spec.container_name, domain, spec.proxy_port));
     }
 
-    run_cmd.arg(&image_tag);
+    state
+        .docker
+        .run_container(ContainerSpec {
+            name: spec.container_name.to_string(),
+            image: spec.image_tag.to_string(),
+            env: spec.env,
+            labels,
+            network: Some(TRAEFIK_NETWORK.to_string()),
+            extra_networks: spec.networks,
+            limits: spec.limits,
+            mounts: spec.mounts,
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to start container {}: {}", spec.container_name, e))
+}
 
-    let run_output = run_cmd.output().await?;
+async fn handle_deploy_job(state: &Arc<AppState>, job: &Job) -> Result<()> {
+    let payload: Value = serde_json::from_str(&job.payload)?;
+    let repo_id = payload["repo_id"].as_i64().ok_or_else(|| anyhow::anyhow!("Missing repo_id in payload"))?;
+    let env_name = payload["environment"].as_str().unwrap_or(DEFAULT_ENVIRONMENT);
 
-    if !run_output.status.success() {
-        return Err(anyhow::anyhow!(
-            "Docker run failed: {}",
-            String::from_utf8_lossy(&run_output.stderr)
-        ));
-    }
+    // 1. Get repository and environment info
+    let repo = load_repository(state, repo_id)?;
+    let env = load_environment(state, repo_id, env_name)?;
 
-    let container_id = String::from_utf8_lossy(&run_output.stdout)
-        .trim()
-        .to_string();
+    // 2. Create deployment record
+    let deployment_id = state.db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO deployments (repo_id, environment_id, status) VALUES (?1, ?2, 'building')",
+            [repo.id, env.id]
+        )?;
+        Ok(conn.last_insert_rowid())
+    })?;
+
+    let data = json!({
+        "repo_id": repo.id,
+        "repository": repo.name,
+        "environment": env.name,
+        "deployment_id": deployment_id,
+    });
+    notifications::dispatch(
+        state,
+        Notification::new(
+            "deploy.started",
+            "info",
+            format!("Deploying {} ({})", repo.name, env.name),
+            format!("Deployment #{} of {} to {} started", deployment_id, repo.name, env.name),
+            data.clone(),
+        ),
+    );
+
+    let result = deploy_environment(state, &payload, &repo, &env, deployment_id).await;
+    let notification = match &result {
+        Ok(()) => Notification::new(
+            "deploy.succeeded",
+            "success",
+            format!("Deployed {} ({})", repo.name, env.name),
+            format!("Deployment #{} of {} to {} succeeded", deployment_id, repo.name, env.name),
+            data,
+        ),
+        Err(e) => Notification::new(
+            "deploy.failed",
+            "critical",
+            format!("Deploy of {} ({}) failed", repo.name, env.name),
+            format!("Deployment #{} of {} to {} failed: {}", deployment_id, repo.name, env.name, e),
+            data,
+        ),
+    };
+    notifications::dispatch(state, notification);
+    result
+}
+
+async fn deploy_environment(
+    state: &Arc<AppState>,
+    payload: &Value,
+    repo: &Repository,
+    env: &Environment,
+    deployment_id: i64,
+) -> Result<()> {
+    let image_tag = env.image_tag(repo);
+    let build_log = if let Some(source_image) = payload["image"].as_str() {
+        // Promotion: reuse an image that is already running elsewhere instead of rebuilding.
+        tracing::info!("Promoting image {} to {}", source_image, image_tag);
+        state.docker.tag_image(source_image, &image_tag).await?;
+        format!(
+            "Promoted image {} from environment '{}'",
+            source_image,
+            payload["promoted_from"].as_str().unwrap_or("unknown")
+        )
+    } else {
+        let repo_dir = env.checkout_dir(&state.config.data_dir);
+        std::fs::create_dir_all(&repo_dir)?;
+        let branch = env.branch(repo);
+
+        // 3. Clone or Pull
+        let key_name = format!("{}_{}", repo.id, env.name);
+        if std::path::Path::new(&format!("{}/.git", repo_dir)).exists() {
+            tracing::info!("Pulling repo {} ({})", repo.name, branch);
+            run_git(state, repo, &key_name, &["-C", &repo_dir, "fetch", "origin", branch]).await?;
+            run_git(state, repo, &key_name, &["-C", &repo_dir, "checkout", "--force", "-B", branch, "FETCH_HEAD"]).await?;
+        } else {
+            tracing::info!("Cloning repo {} ({}) to {}", repo.url, branch, repo_dir);
+            run_git(state, repo, &key_name, &["clone", "--branch", branch, &repo.url, &repo_dir]).await?;
+        }
+
+        // 4. Build with Nixpacks (or just build as a Docker image)
+        build_image(state, &repo_dir, &image_tag).await?
+    };
+
+    // Keep a per-deployment tag so older images stay around for rollback until
+    // the cleanup job expires them.
+    let deployment_tag = deployment_image_tag(repo, deployment_id);
+    state.docker.tag_image(&image_tag, &deployment_tag).await?;
+
+    // 5. Deploy / Start container
+    retire_legacy_container(state, repo, env).await;
+    let container_name = env.container_name(repo);
+    let domain = env.domain(repo);
+    let proxy_port = env.proxy_port(repo);
+    let container_id = run_container(state, RunSpec {
+        repo,
+        container_name: &container_name,
+        image_tag: &image_tag,
+        domain,
+        proxy_port: proxy_port.unwrap_or(3000) as u16,
+        env: env.env_vars().into_iter().collect(),
+        labels: env.labels(),
+        limits: env.resource_limits(repo),
+        mounts: env.mounts(repo),
+        networks: repo.networks.clone(),
+    }).await?;
 
     // 6. Update deployment record (persist domain for reference)
-    let domain_val = repo.domain.clone();
-    let port_val = repo.proxy_port;
     state.db.with_conn(|conn| {
         conn.execute(
             "UPDATE deployments
@@ -310,10 +475,317 @@ async fn handle_deploy_job(state: &Arc<AppState>, job: &Job) -> Result<()> {
                  build_log = ?4, domain = ?5, port = ?6,
                  updated_at = datetime('now')
              WHERE id = ?1",
-            rusqlite::params![deployment_id, container_id, image_tag, build_log, domain_val, port_val]
+            rusqlite::params![deployment_id, container_id, deployment_tag, build_log, domain, proxy_port]
         )?;
         Ok(())
     })?;
 
     Ok(())
 }
+
+/// Remove the environment's container under its pre-repository-id name, if
+/// it is still around. The name may belong to another repository, so only a
+/// container labelled with this repository and environment is touched.
+pub(crate) async fn retire_legacy_container(state: &AppState, repo: &Repository, env: &Environment) {
+    let Some(name) = env.legacy_container_name(repo) else {
+        return;
+    };
+    let Ok(info) = state.docker.inspect_container(&name).await else {
+        return;
+    };
+    let labels = info.config.and_then(|c| c.labels).unwrap_or_default();
+    let repo_id = repo.id.to_string();
+    if labels.get("dockyy.repo_id") == Some(&repo_id)
+        && labels.get("dockyy.environment") == Some(&env.name)
+    {
+        tracing::info!("Removing {} (renamed to {})", name, env.container_name(repo));
+        let _ = state.docker.stop_container(&name).await;
+        let _ = state.docker.remove_container(&name, true).await;
+    }
+}
+
+/// Names derived for the preview of pull request `pr_number`.
+pub(crate) fn preview_container_name(repo: &Repository, pr_number: i64) -> String {
+    format!("dockyy-{}-pr-{}", repo.slug(), pr_number)
+}
+
+fn preview_image_tag(repo: &Repository, pr_number: i64) -> String {
+    format!("dockyy-{}:pr-{}", repo.slug(), pr_number)
+}
+
+fn preview_dir(state: &AppState, repo_id: i64, pr_number: i64) -> String {
+    format!("{}/previews/{}/pr-{}", state.config.data_dir, repo_id, pr_number)
+}
+
+fn set_preview_status(state: &AppState, repo_id: i64, pr_number: i64, status: &str, build_log: Option<&str>) -> Result<()> {
+    state.db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE previews SET status = ?3, build_log = COALESCE(?4, build_log),
+                 updated_at = datetime('now')
+             WHERE repo_id = ?1 AND pr_number = ?2",
+            rusqlite::params![repo_id, pr_number, status, build_log],
+        )?;
+        Ok(())
+    })
+}
+
+async fn handle_preview_deploy_job(state: &Arc<AppState>, job: &Job) -> Result<()> {
+    let payload: Value = serde_json::from_str(&job.payload)?;
+    let repo_id = payload["repo_id"].as_i64().ok_or_else(|| anyhow::anyhow!("Missing repo_id in payload"))?;
+    let pr_number = payload["pr_number"].as_i64().ok_or_else(|| anyhow::anyhow!("Missing pr_number in payload"))?;
+
+    let repo = load_repository(state, repo_id)?;
+    set_preview_status(state, repo_id, pr_number, "building", None)?;
+
+    let result = deploy_preview(state, &repo, pr_number).await;
+    if let Err(e) = &result {
+        let _ = set_preview_status(state, repo_id, pr_number, "failed", Some(&e.to_string()));
+    }
+    result
+}
+
+async fn deploy_preview(state: &Arc<AppState>, repo: &Repository, pr_number: i64) -> Result<()> {
+    let dir = preview_dir(state, repo.id, pr_number);
+    std::fs::create_dir_all(&dir)?;
+
+    // Fetch the PR head through GitHub's `pull/<n>/head` ref so forks work too.
+    let key_name = format!("{}_pr_{}", repo.id, pr_number);
+    if !std::path::Path::new(&format!("{}/.git", dir)).exists() {
+        tracing::info!("Cloning repo {} for PR #{}", repo.url, pr_number);
+        run_git(state, repo, &key_name, &["clone", &repo.url, &dir]).await?;
+    }
+    let pr_ref = format!("pull/{}/head", pr_number);
+    run_git(state, repo, &key_name, &["-C", &dir, "fetch", "origin", &pr_ref]).await?;
+    run_git(state, repo, &key_name, &["-C", &dir, "checkout", "--force", "FETCH_HEAD"]).await?;
+
+    let image_tag = preview_image_tag(repo, pr_number);
+    let build_log = build_image(state, &dir, &image_tag).await?;
+
+    let container_name = preview_container_name(repo, pr_number);
+    let domain = repo
+        .preview_domain
+        .as_deref()
+        .map(|base| format!("pr-{}.{}", pr_number, base));
+    let env: Vec<(String, String)> = repo
+        .preview_env
+        .as_deref()
+        .and_then(|raw| serde_json::from_str::<HashMap<String, String>>(raw).ok())
+        .map(|m| m.into_iter().collect())
+        .unwrap_or_default();
+    // Same limits as the default environment, including its overrides.
+    let limits = load_environment(state, repo.id, DEFAULT_ENVIRONMENT)?.resource_limits(repo);
+
+    let container_id = run_container(state, RunSpec {
+        repo,
+        container_name: &container_name,
+        image_tag: &image_tag,
+        domain: domain.as_deref(),
+        proxy_port: repo.proxy_port.unwrap_or(3000) as u16,
+        env,
+        labels: vec![
+            ("dockyy.repo_id".to_string(), repo.id.to_string()),
+            ("dockyy.preview".to_string(), pr_number.to_string()),
+        ],
+        limits,
+        // Previews are throwaway: never attach the app's persistent data or
+        // the private networks its backing services live on.
+        mounts: Vec::new(),
+        networks: Vec::new(),
+    }).await?;
+
+    state.db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE previews
+             SET status = 'running', container_id = ?3, image_name = ?4,
+                 domain = ?5, build_log = ?6, updated_at = datetime('now')
+             WHERE repo_id = ?1 AND pr_number = ?2",
+            rusqlite::params![repo.id, pr_number, container_id, image_tag, domain, build_log],
+        )?;
+        Ok(())
+    })?;
+
+    tracing::info!("Preview for {} PR #{} is running", repo.name, pr_number);
+    Ok(())
+}
+
+async fn handle_preview_teardown_job(state: &Arc<AppState>, job: &Job) -> Result<()> {
+    let payload: Value = serde_json::from_str(&job.payload)?;
+    let repo_id = payload["repo_id"].as_i64().ok_or_else(|| anyhow::anyhow!("Missing repo_id in payload"))?;
+    let pr_number = payload["pr_number"].as_i64().ok_or_else(|| anyhow::anyhow!("Missing pr_number in payload"))?;
+
+    let repo = load_repository(state, repo_id)?;
+    let container_name = preview_container_name(&repo, pr_number);
+    tracing::info!("Tearing down preview {}", container_name);
+
+    // Removing the container also drops its Traefik route (labels live on it).
+    let _ = state.docker.stop_container(&container_name).await;
+    let _ = state.docker.remove_container(&container_name, true).await;
+    if let Err(e) = state.docker.remove_image(&preview_image_tag(&repo, pr_number)).await {
+        tracing::warn!("Could not remove preview image for {}: {}", container_name, e);
+    }
+    let _ = std::fs::remove_dir_all(preview_dir(state, repo_id, pr_number));
+
+    set_preview_status(state, repo_id, pr_number, "closed", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, MockDocker, MOCK_CONTAINER_ID};
+    use axum::http::Method;
+
+    #[tokio::test]
+    async fn builds_and_runs_against_the_engine_api() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+
+        let context = test_support::temp_dir();
+        std::fs::write(context.join("Dockerfile"), "FROM scratch\nCOPY app.txt /\n").unwrap();
+        std::fs::write(context.join("app.txt"), "hello").unwrap();
+        std::fs::write(context.join("secret.env"), "TOKEN=1").unwrap();
+        std::fs::write(context.join(".dockerignore"), "*.env\n").unwrap();
+        std::fs::create_dir_all(context.join(".git")).unwrap();
+        std::fs::write(context.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+
+        let log = build_image(&state, context.to_str().unwrap(), "dockyy-app:1")
+            .await
+            .unwrap();
+        assert!(log.contains("Successfully built feed"), "{}", log);
+
+        let build = docker.find(Method::POST, "/build").expect("build request");
+        assert!(build.query.contains("t=dockyy-app%3A1"), "{}", build.query);
+        let mut archive = tar::Archive::new(&build.body[..]);
+        let mut sent: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        sent.sort();
+        assert_eq!(sent, [".dockerignore", "Dockerfile", "app.txt"]);
+
+        let repo_id = test_support::add_repo(&state, "app");
+        let repo = load_repository(&state, repo_id).unwrap();
+        let id = run_container(
+            &state,
+            RunSpec {
+                repo: &repo,
+                container_name: "dockyy-app",
+                image_tag: "dockyy-app:1",
+                domain: Some("app.example.com"),
+                proxy_port: 8080,
+                env: vec![("PORT".to_string(), "8080".to_string())],
+                labels: vec![("dockyy.repo_id".to_string(), repo_id.to_string())],
+                limits: ResourceLimits::default(),
+                mounts: Vec::new(),
+                networks: Vec::new(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(id, MOCK_CONTAINER_ID);
+
+        assert!(docker.find(Method::POST, "/networks/create").is_some());
+        let create = docker.find(Method::POST, "/containers/create").expect("create request");
+        assert!(create.query.contains("name=dockyy-app"), "{}", create.query);
+        let config: Value = serde_json::from_slice(&create.body).unwrap();
+        assert_eq!(config["Image"], "dockyy-app:1");
+        assert_eq!(config["Env"], json!(["PORT=8080"]));
+        assert_eq!(config["HostConfig"]["NetworkMode"], TRAEFIK_NETWORK);
+        assert_eq!(config["Labels"]["dockyy.managed"], "true");
+        assert_eq!(config["Labels"]["dockyy.repo_id"], repo_id.to_string());
+        assert!(
+            config["Labels"]
+                .as_object()
+                .unwrap()
+                .values()
+                .any(|v| v.as_str().is_some_and(|v| v.contains("app.example.com"))),
+            "{}",
+            config["Labels"]
+        );
+        let start = format!("/containers/{}/start", MOCK_CONTAINER_ID);
+        assert!(docker.find(Method::POST, &start).is_some());
+
+        let _ = std::fs::remove_dir_all(context);
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    #[tokio::test]
+    async fn only_uses_unlabeled_volumes_an_admin_attached() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let repo_id = test_support::add_repo(&state, "shop");
+        let mut repo = load_repository(&state, repo_id).unwrap();
+        let mount = |source: &str| MountSpec {
+            kind: MountKind::Volume,
+            source: source.to_string(),
+            target: "/data".to_string(),
+            read_only: false,
+        };
+        docker.add_volume("pgdata", &[]);
+        docker.add_volume("billing-data", &[("dockyy.repo_id", "99")]);
+
+        let err = prepare_attachments(&state, &repo, &[mount("pgdata")], &[]).await.unwrap_err();
+        assert!(err.to_string().contains("only admins"), "{}", err);
+
+        repo.adopted = vec!["volume:pgdata".to_string(), "volume:billing-data".to_string()];
+        prepare_attachments(&state, &repo, &[mount("pgdata")], &[]).await.unwrap();
+        let err = prepare_attachments(&state, &repo, &[mount("billing-data")], &[]).await.unwrap_err();
+        assert!(err.to_string().contains("another application"), "{}", err);
+
+        prepare_attachments(&state, &repo, &[mount("shop-data")], &[]).await.unwrap();
+        let create = docker.find(Method::POST, "/volumes/create").expect("volume created");
+        let body: Value = serde_json::from_slice(&create.body).unwrap();
+        assert_eq!(body["Name"], "shop-data");
+        assert_eq!(body["Labels"]["dockyy.repo_id"], repo_id.to_string());
+        prepare_attachments(&state, &repo, &[mount("shop-data")], &[]).await.unwrap();
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+
+    #[tokio::test]
+    async fn tears_down_closed_previews() {
+        let docker = MockDocker::start().await;
+        let state = test_support::state(&docker).await;
+        let repo_id = test_support::add_repo(&state, "shop");
+        let repo = load_repository(&state, repo_id).unwrap();
+        let container_name = preview_container_name(&repo, 7);
+        docker.add_container(&container_name, &[("dockyy.repo_id", &repo_id.to_string()), ("dockyy.preview", "7")]);
+        state
+            .db
+            .with_conn(|conn| {
+                conn.execute(
+                    "INSERT INTO previews (repo_id, pr_number, container_name, status) VALUES (?1, 7, ?2, 'running')",
+                    rusqlite::params![repo_id, container_name],
+                )?;
+                Ok(())
+            })
+            .unwrap();
+
+        let job = Job {
+            id: 1,
+            job_type: "preview_teardown".to_string(),
+            payload: json!({"repo_id": repo_id, "pr_number": 7}).to_string(),
+            status: "running".to_string(),
+            result: None,
+            attempts: 1,
+            max_attempts: 3,
+            created_at: String::new(),
+            updated_at: String::new(),
+        };
+        handle_preview_teardown_job(&state, &job).await.unwrap();
+
+        assert!(docker.find(Method::DELETE, &format!("/containers/{}", container_name)).is_some());
+        let status: String = state
+            .db
+            .with_conn(|conn| {
+                Ok(conn.query_row("SELECT status FROM previews WHERE repo_id = ?1 AND pr_number = 7", [repo_id], |row| {
+                    row.get(0)
+                })?)
+            })
+            .unwrap();
+        assert_eq!(status, "closed");
+
+        let _ = std::fs::remove_dir_all(&state.config.data_dir);
+    }
+}
@@ -0,0 +1,389 @@
+//! Shared test fixtures: a mock Docker Engine API and an [`AppState`] wired
+//! to it with an in-memory database.
+
+use axum::body::{Body, Bytes};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{Method, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use bollard::{Docker, API_DEFAULT_VERSION};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+use crate::auth::{self, Role};
+use crate::services::docker::DockerService;
+use crate::services::engine::DockerEndpoint;
+use crate::{db, routes, services, AppConfig, AppState};
+
+/// Id the mock engine gives every container it creates.
+pub const MOCK_CONTAINER_ID: &str = "0123456789ab";
+
+/// A request received by [`MockDocker`], with the API version prefix removed.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: Method,
+    pub path: String,
+    pub query: String,
+    pub body: Bytes,
+}
+
+#[derive(Default)]
+struct Engine {
+    requests: Vec<Recorded>,
+    /// Labels of existing containers, by id.
+    containers: HashMap<String, HashMap<String, String>>,
+    /// `Status` of listed containers, by id; `Up 1 second` when not set.
+    statuses: HashMap<String, String>,
+    /// Labels of existing volumes, by name.
+    volumes: HashMap<String, HashMap<String, String>>,
+}
+
+fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+/// Just enough of the Docker Engine API to build an image, run a container
+/// and prune the build cache. Unknown objects answer 404, as a fresh engine
+/// would.
+pub struct MockDocker {
+    pub addr: SocketAddr,
+    engine: Arc<Mutex<Engine>>,
+}
+
+impl MockDocker {
+    pub async fn start() -> Self {
+        let engine = Arc::new(Mutex::new(Engine::default()));
+        let app = Router::new().fallback(handle).with_state(engine.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self { addr, engine }
+    }
+
+    /// Make a container with `labels` exist; it can be inspected, started,
+    /// stopped, restarted and removed.
+    pub fn add_container(&self, id: &str, labels: &[(&str, &str)]) {
+        self.engine.lock().unwrap().containers.insert(id.to_string(), self::labels(labels));
+    }
+
+    /// Set the status a container is listed with, e.g. `Exited (1) 2 seconds ago`.
+    pub fn set_status(&self, id: &str, status: &str) {
+        self.engine.lock().unwrap().statuses.insert(id.to_string(), status.to_string());
+    }
+
+    /// Make a volume with `labels` exist.
+    pub fn add_volume(&self, name: &str, labels: &[(&str, &str)]) {
+        self.engine.lock().unwrap().volumes.insert(name.to_string(), self::labels(labels));
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.engine.lock().unwrap().requests.clone()
+    }
+
+    /// The first request matching `method` and `path`.
+    pub fn find(&self, method: Method, path: &str) -> Option<Recorded> {
+        self.requests()
+            .into_iter()
+            .find(|r| r.method == method && r.path == path)
+    }
+
+    pub async fn service(&self) -> DockerService {
+        let client = Docker::connect_with_http(&self.addr.to_string(), 10, API_DEFAULT_VERSION).unwrap();
+        let endpoint = DockerEndpoint::Tcp {
+            addr: self.addr.to_string(),
+            tls_cert_path: None,
+        };
+        DockerService::from_client(client, endpoint).await.unwrap()
+    }
+}
+
+async fn handle(
+    State(engine): State<Arc<Mutex<Engine>>>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    // Clients prefix paths with the API version (`/v1.47/...`).
+    let path = match uri.path().strip_prefix("/v") {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+            rest.find('/').map_or(uri.path(), |i| &rest[i..])
+        }
+        _ => uri.path(),
+    }
+    .to_string();
+    let mut engine = engine.lock().unwrap();
+    engine.requests.push(Recorded {
+        method: method.clone(),
+        path: path.clone(),
+        query: uri.query().unwrap_or_default().to_string(),
+        body,
+    });
+
+    if let Some(rest) = path.strip_prefix("/containers/") {
+        let (id, action) = rest.split_once('/').unwrap_or((rest, ""));
+        if let Some(labels) = engine.containers.get(id) {
+            return match (method.as_str(), action) {
+                ("GET", "json") => Json(json!({
+                    "Id": id,
+                    "Name": format!("/{}", id),
+                    "State": {"Status": "running", "Running": true},
+                    "Config": {"Image": "app:latest", "Labels": labels},
+                }))
+                .into_response(),
+                ("POST", "stop" | "restart" | "start") => StatusCode::NO_CONTENT.into_response(),
+                ("DELETE", "") => {
+                    engine.containers.remove(id);
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                _ => (StatusCode::NOT_FOUND, Json(json!({"message": "No such object"}))).into_response(),
+            };
+        }
+    }
+
+    if let Some(name) = path.strip_prefix("/volumes/")
+        && method == Method::GET
+    {
+        return match engine.volumes.get(name) {
+            Some(labels) => Json(volume(name, labels)).into_response(),
+            None => (StatusCode::NOT_FOUND, Json(json!({"message": "No such volume"}))).into_response(),
+        };
+    }
+
+    match (method.as_str(), path.as_str()) {
+        ("GET" | "HEAD", "/_ping") => "OK".into_response(),
+        ("GET", "/containers/json") => Json(
+            engine
+                .containers
+                .iter()
+                .map(|(id, labels)| {
+                    let status = engine.statuses.get(id).map_or("Up 1 second", String::as_str);
+                    json!({
+                        "Id": id,
+                        "Names": [format!("/{}", id)],
+                        "Image": "app:latest",
+                        "State": if status.starts_with("Up") { "running" } else { "exited" },
+                        "Status": status,
+                        "Labels": labels,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        ("POST", "/volumes/create") => {
+            let body: Value = serde_json::from_slice(&engine.requests.last().unwrap().body).unwrap();
+            let name = body["Name"].as_str().unwrap().to_string();
+            let labels: HashMap<String, String> = serde_json::from_value(body["Labels"].clone()).unwrap_or_default();
+            let created = volume(&name, &labels);
+            engine.volumes.insert(name, labels);
+            (StatusCode::CREATED, Json(created)).into_response()
+        }
+        ("POST", "/build") => [
+            json!({"stream": "Step 1/1 : FROM scratch\n"}),
+            json!({"aux": {"ID": "sha256:feed"}}),
+            json!({"stream": "Successfully built feed\n"}),
+        ]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect::<String>()
+        .into_response(),
+        ("POST", "/build/prune") => Json(json!({
+            "CachesDeleted": ["k0", "k1"],
+            "SpaceReclaimed": 1_536_000,
+        }))
+        .into_response(),
+        ("GET", "/networks") => Json(json!([])).into_response(),
+        ("POST", "/networks/create") => {
+            (StatusCode::CREATED, Json(json!({"Id": "net0", "Warning": ""}))).into_response()
+        }
+        // Every network exists, but only containers added with `add_container` can join.
+        ("POST", p) if p.starts_with("/networks/") && p.ends_with("/connect") => {
+            let body: Value = serde_json::from_slice(&engine.requests.last().unwrap().body).unwrap();
+            match body["Container"].as_str() {
+                Some(id) if engine.containers.contains_key(id) => StatusCode::OK.into_response(),
+                _ => (StatusCode::NOT_FOUND, Json(json!({"message": "No such container"}))).into_response(),
+            }
+        }
+        ("POST", "/containers/create") => (
+            StatusCode::CREATED,
+            Json(json!({"Id": MOCK_CONTAINER_ID, "Warnings": []})),
+        )
+            .into_response(),
+        ("POST", p) if p == format!("/containers/{}/start", MOCK_CONTAINER_ID) => {
+            StatusCode::NO_CONTENT.into_response()
+        }
+        _ => (StatusCode::NOT_FOUND, Json(json!({"message": "No such object"}))).into_response(),
+    }
+}
+
+fn volume(name: &str, labels: &HashMap<String, String>) -> Value {
+    json!({
+        "Name": name,
+        "Driver": "local",
+        "Mountpoint": format!("/var/lib/docker/volumes/{}/_data", name),
+        "Labels": labels,
+        "Scope": "local",
+        "Options": {},
+    })
+}
+
+/// A fresh directory under the system temp dir.
+pub fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dockyy-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// App state on an in-memory database, talking to `docker`.
+pub async fn state(docker: &MockDocker) -> Arc<AppState> {
+    let database = db::Database::new(":memory:").unwrap();
+    database.run_migrations().unwrap();
+    let docker = docker.service().await;
+    let traefik = services::traefik::TraefikService::new(docker.client(), "/var/run/docker.sock");
+    let data_dir = temp_dir().to_string_lossy().to_string();
+
+    Arc::new(AppState {
+        db: database,
+        docker,
+        traefik,
+        config: AppConfig {
+            jwt_secret: "test-secret".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            bind_allowlist: vec![format!("{}/binds", data_dir)],
+            data_dir,
+            traefik_http_port: 80,
+            disable_rate_limit: true,
+            git_bin: "git".to_string(),
+            image_retention: 5,
+            image_prune_disk_pct: 80.0,
+            exec_shell: "sh".to_string(),
+            metrics_token: None,
+            access_token_ttl_mins: 15,
+            refresh_token_ttl_days: 30,
+            oidc: None,
+            password_login: true,
+            audit_retention_days: 90,
+            max_previews: 5,
+        },
+        metrics: services::monitor::new_metrics_state(),
+        telemetry: services::telemetry::Telemetry::default(),
+        setup_token: std::sync::Mutex::new(None),
+        keyring: services::crypto::Keyring::new("test-key", &[]).unwrap(),
+    })
+}
+
+/// The API as the server mounts it, under `/api`.
+pub fn app(state: &Arc<AppState>) -> Router {
+    Router::new()
+        .nest("/api", routes::api_routes(state.clone()))
+        .with_state(state.clone())
+}
+
+/// Insert a repository at `https://github.com/acme/<name>.git` with its
+/// default environment, as creating one through the API does, and return its
+/// id.
+pub fn add_repo(state: &AppState, name: &str) -> i64 {
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO repositories (name, owner, url) VALUES (?1, 'acme', ?2)",
+                rusqlite::params![name, format!("https://github.com/acme/{}.git", name)],
+            )?;
+            let id = conn.last_insert_rowid();
+            conn.execute(
+                "INSERT INTO environments (repo_id, name) VALUES (?1, ?2)",
+                rusqlite::params![id, db::models::DEFAULT_ENVIRONMENT],
+            )?;
+            Ok(id)
+        })
+        .unwrap()
+}
+
+/// Create a user with `role`. With `grants` the user is scoped to those
+/// repositories.
+pub fn add_user(state: &AppState, username: &str, role: Role, grants: Option<&[(i64, Role)]>) -> i64 {
+    state
+        .db
+        .with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO users (username, password_hash, role, scoped) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![
+                    username,
+                    auth::hash_password("longpassword")?,
+                    role.as_str(),
+                    grants.is_some()
+                ],
+            )?;
+            let user_id = conn.last_insert_rowid();
+            for (repo_id, role) in grants.unwrap_or_default() {
+                conn.execute(
+                    "INSERT INTO repository_grants (user_id, repo_id, role) VALUES (?1, ?2, ?3)",
+                    rusqlite::params![user_id, repo_id, role.as_str()],
+                )?;
+            }
+            Ok(user_id)
+        })
+        .unwrap()
+}
+
+/// An access token for a new login session of `username`.
+pub fn login(state: &AppState, username: &str) -> String {
+    let (user_id, role): (i64, String) = state
+        .db
+        .with_conn(|conn| {
+            Ok(conn.query_row(
+                "SELECT id, role FROM users WHERE username = ?1",
+                [username],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?)
+        })
+        .unwrap();
+    let session = auth::sessions::create(state, user_id, "127.0.0.1", None).unwrap();
+    auth::create_token(
+        &state.config.jwt_secret,
+        username,
+        Role::parse(&role).unwrap(),
+        &session.id,
+        chrono::Duration::minutes(15),
+    )
+    .unwrap()
+}
+
+/// Send a request to `app` as an API client would, returning the status and
+/// the JSON body (`Null` when the body isn't JSON).
+pub async fn call(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut req = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    }
+    let req = match body {
+        Some(body) => req
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => req.body(Body::empty()),
+    };
+    send(app, req.unwrap()).await
+}
+
+/// Send `req` to `app` from a local client, returning the status and the
+/// JSON body (`Null` when the body isn't JSON).
+pub async fn send(app: &Router, mut req: Request<Body>) -> (StatusCode, Value) {
+    req.extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let res = app.clone().oneshot(req).await.unwrap();
+    let status = res.status();
+    let bytes = res.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
@@ -1,6 +1,59 @@
 const API_BASE = "/api";
 
-async function request<T>(path: string, options: RequestInit = {}): Promise<T> {
+/** Paths answering 401 for bad credentials rather than an expired session. */
+const CREDENTIAL_PATHS = [
+  "/auth/login",
+  "/auth/login/mfa",
+  "/auth/setup",
+  "/auth/refresh",
+  "/auth/verify",
+];
+
+export function storeSession(token: string, refreshToken: string) {
+  localStorage.setItem("dockyy_token", token);
+  localStorage.setItem("dockyy_refresh", refreshToken);
+}
+
+export function clearSession() {
+  localStorage.removeItem("dockyy_token");
+  localStorage.removeItem("dockyy_refresh");
+}
+
+let refreshing: Promise<boolean> | null = null;
+
+/**
+ * Swap the stored refresh token for a new token pair. Concurrent callers share
+ * one request, since each refresh token is only accepted once.
+ */
+export function refreshSession(): Promise<boolean> {
+  if (refreshing) return refreshing;
+  refreshing = (async () => {
+    const refreshToken = localStorage.getItem("dockyy_refresh");
+    if (!refreshToken) return false;
+    try {
+      const res = await fetch(`${API_BASE}/auth/refresh`, {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ refresh_token: refreshToken }),
+      });
+      if (!res.ok) return false;
+      const body = await res.json();
+      storeSession(body.token, body.refresh_token);
+      return true;
+    } catch {
+      return false;
+    } finally {
+      refreshing = null;
+    }
+  })();
+  return refreshing;
+}
+
+async function request<T>(
+  path: string,
+  options: RequestInit = {},
+  retry = true,
+): Promise<T> {
   const token = localStorage.getItem("dockyy_token");
   const headers: Record<string, string> = {
     "Content-Type": "application/json",
@@ -13,9 +66,13 @@ async function request<T>(path: string, options: RequestInit = {}): Promise<T> {
   if (!res.ok) {
     const body = await res.json().catch(() => ({}));
 
-    // Clear token and reload on 401 from authenticated routes (not login)
-    if (res.status === 401 && !path.startsWith("/auth/")) {
-      localStorage.removeItem("dockyy_token");
+    // On 401 from authenticated routes, refresh once; clear the session and
+    // reload when that fails too.
+    if (res.status === 401 && !CREDENTIAL_PATHS.includes(path)) {
+      if (retry && (await refreshSession())) {
+        return request<T>(path, options, false);
+      }
+      clearSession();
       window.location.reload();
     }
 
@@ -25,16 +82,56 @@ async function request<T>(path: string, options: RequestInit = {}): Promise<T> {
   return res.json();
 }
 
+export type Role = "viewer" | "deployer" | "admin";
+
+export interface Session {
+  token: string;
+  refresh_token: string;
+  expires_in: number;
+  username: string;
+  role: Role;
+}
+
+/** Users with two-factor authentication get a challenge instead of a session. */
+export type LoginResult =
+  | Session
+  | { mfa_required: true; mfa_token: string; expires_in: number };
+
 export const api = {
   // Auth
   login: (username: string, password: string) =>
-    request<{ token: string; username: string }>("/auth/login", {
+    request<LoginResult>("/auth/login", {
       method: "POST",
       body: JSON.stringify({ username, password }),
     }),
 
+  loginMfa: (mfaToken: string, code: string) =>
+    request<Session>("/auth/login/mfa", {
+      method: "POST",
+      body: JSON.stringify({ mfa_token: mfaToken, code }),
+    }),
+
+  authMethods: () =>
+    request<{
+      password: boolean;
+      setup_required: boolean;
+      oidc: { name: string; login_url: string } | null;
+    }>("/auth/methods"),
+
+  /** Create the first admin with the token from the server log. */
+  setup: (token: string, username: string, password: string) =>
+    request<Session>("/auth/setup", {
+      method: "POST",
+      body: JSON.stringify({ token, username, password }),
+    }),
+
+  logout: (all = false) =>
+    request<{ message: string }>(`/auth/logout${all ? "?all=true" : ""}`, {
+      method: "POST",
+    }),
+
   verify: (token: string) =>
-    request<{ valid: boolean; username: string }>("/auth/verify", {
+    request<{ valid: boolean; username: string; role: Role }>("/auth/verify", {
       method: "POST",
       body: JSON.stringify({ token }),
     }),
@@ -69,12 +166,12 @@ export const api = {
   // Repositories
   listRepositories: () => request<Repository[]>("/repositories"),
   getRepository: (id: number) => request<Repository>(`/repositories/${id}`),
-  createRepository: (data: Partial<Repository>) =>
+  createRepository: (data: RepositoryInput) =>
     request<{ id: number }>("/repositories", {
       method: "POST",
       body: JSON.stringify(data),
     }),
-  updateRepository: (id: number, data: Partial<Repository>) =>
+  updateRepository: (id: number, data: RepositoryInput) =>
     request<{ message: string }>(`/repositories/${id}`, {
       method: "PUT",
       body: JSON.stringify(data),
@@ -235,7 +332,8 @@ export interface Repository {
   description: string | null;
   webhook_url: string | null;
   filesystem_path: string | null;
-  ssh_password: string | null;
+  has_ssh_key: boolean;
+  ssh_key_fingerprint: string | null;
   is_private: boolean;
   default_branch: string;
   domain: string | null;
@@ -244,6 +342,10 @@ export interface Repository {
   updated_at: string;
 }
 
+/** The SSH key is write-only: send it here, it is never returned. An empty
+ *  string removes it. */
+export type RepositoryInput = Partial<Repository> & { ssh_key?: string | null };
+
 export interface ServerMetrics {
   cpu_usage_pct: number;
   mem_used_bytes: number;
@@ -252,6 +354,13 @@ export interface ServerMetrics {
   swap_total_bytes: number;
   disk_used_bytes: number;
   disk_total_bytes: number;
+  load_avg_1: number;
+  load_avg_5: number;
+  load_avg_15: number;
+  net_rx_bytes: number;
+  net_tx_bytes: number;
+  net_rx_bps: number;
+  net_tx_bps: number;
   docker_ok: boolean;
   checked_at: string;
 }